@@ -0,0 +1,83 @@
+#!/usr/bin/env python3
+"""Computes the test vectors of src/building_blocks/crh/rescue.rs.
+
+This is an independent implementation of the Rescue-style sponge of that module, over the scalar field of
+BLS12-381 (the base field of Jubjub), with the fixed parameters of `test_vector_parameters`: the MDS matrix
+1 / (i + j + 3) and the round constants 1, 2, 3, ...
+
+The sponge is NOT Rescue-Prime, so these are regression vectors only, not the reference vectors of
+Rescue-Prime, and the hash has not been analysed.
+"""
+
+from math import comb, gcd
+
+P = 0x73EDA753299D7D483339D80809A1D80553BDA402FFFE5BFEFFFFFFFF00000001
+RATE, CAPACITY = 2, 1
+WIDTH = RATE + CAPACITY
+SECURITY_LEVEL = 128
+
+# The smallest S-box exponent that is a permutation, and its inverse.
+ALPHA = 3
+while gcd(ALPHA, P - 1) != 1:
+    ALPHA += 2
+ALPHA_INV = pow(ALPHA, -1, P - 1)
+
+# The round numbers of `RescueParameters::round_numbers`: the bounds of the statistical and the Groebner
+# basis attacks, at least five, plus a margin of 50%.
+N = P.bit_length()
+STATISTICAL = -(-2 * SECURITY_LEVEL // ((WIDTH + 1) * (N - 1 - (ALPHA - 1).bit_length())))
+GROEBNER = 1
+while True:
+    degree = (ALPHA - 1) * WIDTH * (GROEBNER - 1) // 2 + 2
+    variables = WIDTH * (GROEBNER - 1) + RATE
+    if comb(variables + degree, variables) > 2 ** (SECURITY_LEVEL // 2):
+        break
+    GROEBNER += 1
+ROUNDS = -(-3 * max(STATISTICAL, GROEBNER, 5) // 2)
+
+MDS = [[pow(i + j + 3, -1, P) for j in range(WIDTH)] for i in range(WIDTH)]
+ROUND_CONSTANTS = [[(h * WIDTH + j + 1) % P for j in range(WIDTH)] for h in range(2 * ROUNDS)]
+
+
+def mix(state, half_round):
+    return [
+        (ROUND_CONSTANTS[half_round][i] + sum(MDS[i][j] * state[j] for j in range(WIDTH))) % P
+        for i in range(WIDTH)
+    ]
+
+
+def permute(state):
+    for r in range(ROUNDS):
+        state = mix([pow(x, ALPHA, P) for x in state], 2 * r)
+        state = mix([pow(x, ALPHA_INV, P) for x in state], 2 * r + 1)
+    return state
+
+
+def hash_field_elements(inputs):
+    # The capacity starts with the number of inputs, so the zero padding of the last block is unambiguous.
+    state = [0] * WIDTH
+    state[RATE] = len(inputs) % P
+    if not inputs:
+        state = permute(state)
+    for i in range(0, len(inputs), RATE):
+        for k, x in enumerate(inputs[i : i + RATE]):
+            state[k] = (state[k] + x) % P
+        state = permute(state)
+    return state[0]
+
+
+def hash_bytes(data):
+    # Little-endian bits, packed into field elements of one bit less than the modulus.
+    bits = [(byte >> i) & 1 for byte in data for i in range(8)]
+    chunk = N - 1
+    elements = [
+        sum(bit << k for k, bit in enumerate(bits[i : i + chunk])) for i in range(0, len(bits), chunk)
+    ]
+    return hash_field_elements(elements)
+
+
+if __name__ == "__main__":
+    assert ALPHA == 5 and ROUNDS == 14
+    for length in range(5):
+        print(f"hash_field_elements(1..={length}):", hash_field_elements(list(range(1, length + 1))))
+    print("hash_bytes(0..40):", hash_bytes(bytes(range(40))))
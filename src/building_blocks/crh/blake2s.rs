@@ -0,0 +1,242 @@
+// Note: Blake2s here is for interoperability, since external verifiers can evaluate it without the curves that the
+// algebraic hashes are defined over. It is much more expensive in the circuit than Poseidon or Bowe-Hopwood.
+
+use crate::building_blocks::crh::{CRHforMerkleTree, CRHforMerkleTreeGadget};
+use crate::{Borrow, Error, PhantomData, SynthesisError};
+use ark_crypto_primitives::prf::blake2s::constraints::evaluate_blake2s;
+use ark_ff::{PrimeField, ToBytes};
+use ark_r1cs_std::alloc::{AllocVar, AllocationMode};
+use ark_r1cs_std::bits::{boolean::Boolean, uint8::UInt8, ToBitsGadget, ToBytesGadget};
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_r1cs_std::R1CSVar;
+use ark_relations::r1cs::{ConstraintSystemRef, Namespace};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::io::{Read, Result as IoResult, Write};
+use ark_std::rand::{CryptoRng, Rng};
+use ark_std::{io::Cursor, vec::Vec};
+use blake2::{Blake2s, Digest};
+
+/// the 32-byte output of Blake2s
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Blake2sDigest(pub [u8; 32]);
+
+impl ToBytes for Blake2sDigest {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        writer.write_all(&self.0)
+    }
+}
+
+impl CanonicalSerialize for Blake2sDigest {
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        Ok(writer.write_all(&self.0)?)
+    }
+
+    fn serialized_size(&self) -> usize {
+        32
+    }
+}
+
+impl CanonicalDeserialize for Blake2sDigest {
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut digest = [0u8; 32];
+        reader.read_exact(&mut digest)?;
+        Ok(Blake2sDigest(digest))
+    }
+}
+
+/// Blake2s CRH, with the compressor hashing the concatenation of the children
+///
+/// the setup has no parameters, so the hash is the standard unkeyed Blake2s-256
+pub struct Blake2sCRHforMerkleTree;
+
+impl CRHforMerkleTree for Blake2sCRHforMerkleTree {
+    type Output = Blake2sDigest;
+    type Parameters = ();
+
+    fn setup<R: Rng + CryptoRng>(_: &mut R) -> Result<Self::Parameters, Error> {
+        Ok(())
+    }
+
+    fn hash_bytes(_: &Self::Parameters, input: &[u8]) -> Result<Self::Output, Error> {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&Blake2s::digest(input));
+        Ok(Blake2sDigest(digest))
+    }
+
+    fn two_to_one_compress(
+        parameters: &Self::Parameters,
+        left: &Self::Output,
+        right: &Self::Output,
+    ) -> Result<Self::Output, Error> {
+        let mut writer = Cursor::new(Vec::<u8>::new());
+        left.write(&mut writer)?;
+        right.write(&mut writer)?;
+
+        Self::hash_bytes(parameters, &writer.into_inner())
+    }
+
+    fn four_to_one_compress(
+        parameters: &Self::Parameters,
+        elts: &[Self::Output],
+    ) -> Result<Self::Output, Error> {
+        let mut writer = Cursor::new(Vec::<u8>::new());
+        for elt in elts.iter() {
+            elt.write(&mut writer)?;
+        }
+
+        Self::hash_bytes(parameters, &writer.into_inner())
+    }
+}
+
+/// the variable of the 32-byte output of Blake2s
+#[derive(Clone, Debug)]
+pub struct Blake2sDigestVar<F: PrimeField>(pub Vec<UInt8<F>>);
+
+impl<F: PrimeField> AllocVar<Blake2sDigest, F> for Blake2sDigestVar<F> {
+    fn new_variable<T: Borrow<Blake2sDigest>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let digest = f().map(|digest| *digest.borrow());
+        let mut bytes = Vec::new();
+        for i in 0..32 {
+            bytes.push(UInt8::new_variable(
+                ark_relations::ns!(cs, "byte"),
+                || digest.as_ref().map(|digest| digest.0[i]).map_err(|e| *e),
+                mode,
+            )?);
+        }
+        Ok(Blake2sDigestVar(bytes))
+    }
+}
+
+impl<F: PrimeField> R1CSVar<F> for Blake2sDigestVar<F> {
+    type Value = Blake2sDigest;
+
+    fn cs(&self) -> ConstraintSystemRef<F> {
+        self.0.cs()
+    }
+
+    fn value(&self) -> Result<Self::Value, SynthesisError> {
+        let mut digest = [0u8; 32];
+        for (byte, byte_var) in digest.iter_mut().zip(self.0.iter()) {
+            *byte = byte_var.value()?;
+        }
+        Ok(Blake2sDigest(digest))
+    }
+}
+
+impl<F: PrimeField> EqGadget<F> for Blake2sDigestVar<F> {
+    fn is_eq(&self, other: &Self) -> Result<Boolean<F>, SynthesisError> {
+        self.0.is_eq(&other.0)
+    }
+
+    fn conditional_enforce_equal(
+        &self,
+        other: &Self,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        self.0.conditional_enforce_equal(&other.0, should_enforce)
+    }
+
+    fn conditional_enforce_not_equal(
+        &self,
+        other: &Self,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        self.0
+            .conditional_enforce_not_equal(&other.0, should_enforce)
+    }
+}
+
+impl<F: PrimeField> ToBytesGadget<F> for Blake2sDigestVar<F> {
+    fn to_bytes(&self) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        Ok(self.0.clone())
+    }
+}
+
+impl<F: PrimeField> CondSelectGadget<F> for Blake2sDigestVar<F> {
+    fn conditionally_select(
+        cond: &Boolean<F>,
+        true_value: &Self,
+        false_value: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let mut bytes = Vec::new();
+        for (true_byte, false_byte) in true_value.0.iter().zip(false_value.0.iter()) {
+            bytes.push(UInt8::conditionally_select(cond, true_byte, false_byte)?);
+        }
+        Ok(Blake2sDigestVar(bytes))
+    }
+}
+
+/// Gadgets of the Blake2s CRH, using the Blake2s constraints of crypto-primitives
+pub struct Blake2sCRHforMerkleTreeGadget<F: PrimeField> {
+    _field: PhantomData<F>,
+}
+
+impl<F: PrimeField> CRHforMerkleTreeGadget<Blake2sCRHforMerkleTree, F>
+    for Blake2sCRHforMerkleTreeGadget<F>
+{
+    type OutputVar = Blake2sDigestVar<F>;
+
+    fn hash_bytes(
+        parameters: &<Blake2sCRHforMerkleTree as CRHforMerkleTree>::Parameters,
+        input: &[UInt8<F>],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let cs = input.cs();
+
+        if cs == ConstraintSystemRef::None {
+            let mut bytes = Vec::new();
+
+            for input_elem in input.iter() {
+                bytes.push(input_elem.value()?);
+            }
+
+            let digest = Blake2sCRHforMerkleTree::hash_bytes(parameters, &bytes).unwrap();
+            Ok(Blake2sDigestVar(UInt8::constant_vec(&digest.0)))
+        } else {
+            // Step 1: unpack the bytes into little-endian bits
+            let mut bits = Vec::new();
+
+            for input_elem in input.iter() {
+                bits.extend_from_slice(&input_elem.to_bits_le()?);
+            }
+
+            // Step 2: hash the bits, and pack the little-endian words into bytes
+            let mut bytes = Vec::new();
+            for word in evaluate_blake2s(&bits)?.iter() {
+                bytes.extend_from_slice(&word.to_bytes()?);
+            }
+
+            Ok(Blake2sDigestVar(bytes))
+        }
+    }
+
+    fn two_to_one_compress(
+        parameters: &<Blake2sCRHforMerkleTree as CRHforMerkleTree>::Parameters,
+        left: &Self::OutputVar,
+        right: &Self::OutputVar,
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let mut bytes = left.0.clone();
+        bytes.extend_from_slice(&right.0);
+
+        Self::hash_bytes(parameters, &bytes)
+    }
+
+    fn four_to_one_compress(
+        parameters: &<Blake2sCRHforMerkleTree as CRHforMerkleTree>::Parameters,
+        elts: &[Self::OutputVar],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let mut bytes = Vec::new();
+        for elt in elts.iter() {
+            bytes.extend_from_slice(&elt.0);
+        }
+
+        Self::hash_bytes(parameters, &bytes)
+    }
+}
@@ -1,9 +1,15 @@
-use crate::{Error, SynthesisError};
+use crate::{gadgets::FpVar, Error, SynthesisError};
 use ark_ff::{PrimeField, ToBytes};
 use ark_r1cs_std::eq::EqGadget;
 use ark_r1cs_std::select::CondSelectGadget;
 use ark_r1cs_std::{alloc::AllocVar, bits::uint8::UInt8, R1CSVar, ToBytesGadget};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::io::Cursor;
 use ark_std::rand::{CryptoRng, Rng};
+use ark_std::{vec, vec::Vec};
+
+/// The Blake2s hash, for verifiers outside the circuit
+pub mod blake2s;
 
 /// The Bowe-Hopwood variant of the Pedersen hash
 pub mod bowe_hopwood;
@@ -11,12 +17,34 @@ pub mod bowe_hopwood;
 /// The Poseidon hash
 pub mod poseidon;
 
+/// A Rescue-style hash, which is not Rescue-Prime and has not been analysed
+pub mod rescue;
+
+/// `Send + Sync` with the `parallel` feature, which hashes on several threads, and no bound otherwise
+#[cfg(feature = "parallel")]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(feature = "parallel")]
+impl<T: Send + Sync> MaybeSendSync for T {}
+
+/// `Send + Sync` with the `parallel` feature, which hashes on several threads, and no bound otherwise
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSendSync {}
+#[cfg(not(feature = "parallel"))]
+impl<T> MaybeSendSync for T {}
+
 /// CRH specifically for Merkle trees (with a two-to-one compression method)
 pub trait CRHforMerkleTree {
     /// CRH output
-    type Output: Clone + Eq + core::fmt::Debug + Default + ToBytes;
+    type Output: Clone
+        + Eq
+        + core::fmt::Debug
+        + Default
+        + ToBytes
+        + CanonicalSerialize
+        + CanonicalDeserialize
+        + MaybeSendSync;
     /// CRH parameters
-    type Parameters: Clone;
+    type Parameters: Clone + MaybeSendSync;
 
     /// CRH setup
     fn setup<R: Rng + CryptoRng>(r: &mut R) -> Result<Self::Parameters, Error>;
@@ -24,6 +52,19 @@ pub trait CRHforMerkleTree {
     /// Hash bytes to the CRH output
     fn hash_bytes(parameters: &Self::Parameters, input: &[u8]) -> Result<Self::Output, Error>;
 
+    /// Hash field elements to the CRH output, by default through their bytes
+    fn hash_field_elements<F: PrimeField>(
+        parameters: &Self::Parameters,
+        input: &[F],
+    ) -> Result<Self::Output, Error> {
+        let mut writer = Cursor::new(Vec::<u8>::new());
+        for elem in input.iter() {
+            elem.write(&mut writer)?;
+        }
+
+        Self::hash_bytes(parameters, &writer.into_inner())
+    }
+
     /// Hash two CRH outputs into one output
     fn two_to_one_compress(
         parameters: &Self::Parameters,
@@ -31,11 +72,41 @@ pub trait CRHforMerkleTree {
         right: &Self::Output,
     ) -> Result<Self::Output, Error>;
 
+    /// Hash two CRH outputs into one output, in the domain of the tag, by default by prefixing it to their bytes
+    fn two_to_one_compress_with_tag(
+        parameters: &Self::Parameters,
+        tag: u8,
+        left: &Self::Output,
+        right: &Self::Output,
+    ) -> Result<Self::Output, Error> {
+        let mut writer = Cursor::new(Vec::<u8>::new());
+        tag.write(&mut writer)?;
+        left.write(&mut writer)?;
+        right.write(&mut writer)?;
+
+        Self::hash_bytes(parameters, &writer.into_inner())
+    }
+
     /// Hash four CRH outputs into one output
     fn four_to_one_compress(
         parameters: &Self::Parameters,
         elts: &[Self::Output],
     ) -> Result<Self::Output, Error>;
+
+    /// Hash four CRH outputs into one output, in the domain of the tag, by default by prefixing it to their bytes
+    fn four_to_one_compress_with_tag(
+        parameters: &Self::Parameters,
+        tag: u8,
+        elts: &[Self::Output],
+    ) -> Result<Self::Output, Error> {
+        let mut writer = Cursor::new(Vec::<u8>::new());
+        tag.write(&mut writer)?;
+        for elt in elts.iter() {
+            elt.write(&mut writer)?;
+        }
+
+        Self::hash_bytes(parameters, &writer.into_inner())
+    }
 }
 
 /// CRH gadgets for Merkle trees
@@ -54,6 +125,19 @@ pub trait CRHforMerkleTreeGadget<CRH: CRHforMerkleTree, ConstraintF: PrimeField>
         input: &[UInt8<ConstraintF>],
     ) -> Result<Self::OutputVar, SynthesisError>;
 
+    /// Hash field element variables to the CRH output variable, by default through their bytes
+    fn hash_field_elements(
+        parameters: &CRH::Parameters,
+        input: &[FpVar<ConstraintF>],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let mut bytes = Vec::new();
+        for elem in input.iter() {
+            bytes.extend_from_slice(&elem.to_bytes()?);
+        }
+
+        Self::hash_bytes(parameters, &bytes)
+    }
+
     /// Hash two CRH output variables into one
     fn two_to_one_compress(
         parameters: &CRH::Parameters,
@@ -61,9 +145,276 @@ pub trait CRHforMerkleTreeGadget<CRH: CRHforMerkleTree, ConstraintF: PrimeField>
         right: &Self::OutputVar,
     ) -> Result<Self::OutputVar, SynthesisError>;
 
+    /// Hash two CRH output variables into one, in the domain of the tag, by default by prefixing it to their bytes
+    fn two_to_one_compress_with_tag(
+        parameters: &CRH::Parameters,
+        tag: u8,
+        left: &Self::OutputVar,
+        right: &Self::OutputVar,
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let mut bytes = vec![UInt8::constant(tag)];
+        bytes.extend_from_slice(&left.to_bytes()?);
+        bytes.extend_from_slice(&right.to_bytes()?);
+
+        Self::hash_bytes(parameters, &bytes)
+    }
+
     /// Hash four CRH output variables into one
     fn four_to_one_compress(
         parameters: &CRH::Parameters,
         elts: &[Self::OutputVar],
     ) -> Result<Self::OutputVar, SynthesisError>;
+
+    /// Hash four CRH output variables into one, in the domain of the tag, by default by prefixing it to their bytes
+    fn four_to_one_compress_with_tag(
+        parameters: &CRH::Parameters,
+        tag: u8,
+        elts: &[Self::OutputVar],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let mut bytes = vec![UInt8::constant(tag)];
+        for elt in elts.iter() {
+            bytes.extend_from_slice(&elt.to_bytes()?);
+        }
+
+        Self::hash_bytes(parameters, &bytes)
+    }
+}
+
+/// Returns (p - 1) mod m, where p is the characteristic of F.
+pub(crate) fn modulus_minus_one_mod<F: PrimeField>(m: u64) -> u64 {
+    let mut remainder = 0u128;
+    for limb in F::characteristic().iter().rev() {
+        remainder = ((remainder << 64) | (*limb as u128)) % (m as u128);
+    }
+
+    (remainder as u64 + m - 1) % m
+}
+
+/// Returns the smallest exponent alpha such that x^alpha is a permutation of F, i.e., gcd(alpha, p - 1) = 1.
+pub(crate) fn smallest_sbox_exponent<F: PrimeField>() -> u64 {
+    let mut alpha = 3u64;
+    while gcd(alpha, modulus_minus_one_mod::<F>(alpha)) != 1 {
+        alpha += 2;
+    }
+
+    alpha
+}
+
+/// Returns a width-by-width Cauchy matrix sampled from the RO.
+///
+/// A Cauchy matrix 1 / (x_i + y_j) is MDS as long as the x's and the y's are distinct and no x + y is zero.
+pub(crate) fn sample_cauchy_mds<F: PrimeField, R: Rng>(ro: &mut R, width: usize) -> Vec<Vec<F>> {
+    let mut xs: Vec<F> = Vec::new();
+    while xs.len() < width {
+        let x = F::rand(ro);
+        if !xs.contains(&x) {
+            xs.push(x);
+        }
+    }
+
+    let mut ys: Vec<F> = Vec::new();
+    while ys.len() < width {
+        let y = F::rand(ro);
+        if !ys.contains(&y) && xs.iter().all(|x| !(*x + &y).is_zero()) {
+            ys.push(y);
+        }
+    }
+
+    let mut mds = Vec::new();
+    for x in xs.iter() {
+        let mut row = Vec::new();
+        for y in ys.iter() {
+            row.push((*x + y).inverse().unwrap());
+        }
+        mds.push(row);
+    }
+
+    mds
+}
+
+/// Returns a width-by-width Cauchy matrix sampled from the RO that has no invariant subspace trail, resampling
+/// the matrices that fail the check.
+pub(crate) fn sample_secure_mds<F: PrimeField, R: Rng>(ro: &mut R, width: usize) -> Vec<Vec<F>> {
+    loop {
+        let mds = sample_cauchy_mds::<F, R>(ro, width);
+        if has_no_invariant_subspace_trail(&mds) {
+            return mds;
+        }
+    }
+}
+
+/// Returns whether the matrix M leaves no subspace invariant through the partial rounds of Poseidon.
+///
+/// This checks the sufficient condition of Grassi, Rechberger and Schofnegger ("Proving Resistance Against
+/// Infinitely Long Subspace Trails", ToSC 2020): the minimal polynomial of M^i is irreducible and of maximal
+/// degree, i.e., equal to the characteristic polynomial, for every i from 1 to twice the width.
+pub(crate) fn has_no_invariant_subspace_trail<F: PrimeField>(mds: &[Vec<F>]) -> bool {
+    let mut power = mds.to_vec();
+    for _ in 0..(2 * mds.len()) {
+        if !is_irreducible(&characteristic_polynomial(&power)) {
+            return false;
+        }
+        power = matrix_mul(&power, mds);
+    }
+
+    true
+}
+
+/// Returns the product of two square matrices.
+fn matrix_mul<F: PrimeField>(a: &[Vec<F>], b: &[Vec<F>]) -> Vec<Vec<F>> {
+    let n = a.len();
+    let mut product = vec![vec![F::zero(); n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            for k in 0..n {
+                product[i][j] += a[i][k] * &b[k][j];
+            }
+        }
+    }
+
+    product
+}
+
+/// Returns the coefficients of the characteristic polynomial of a square matrix, from the constant one up, with
+/// the Faddeev-LeVerrier algorithm.
+fn characteristic_polynomial<F: PrimeField>(a: &[Vec<F>]) -> Vec<F> {
+    let n = a.len();
+    let mut coeffs = vec![F::zero(); n + 1];
+    coeffs[n] = F::one();
+
+    let mut m = vec![vec![F::zero(); n]; n];
+    for k in 1..=n {
+        // M_k = A M_(k-1) + c_(n-k+1) I and c_(n-k) = -tr(A M_k) / k.
+        m = matrix_mul(a, &m);
+        for i in 0..n {
+            m[i][i] += coeffs[n - k + 1];
+        }
+
+        let am = matrix_mul(a, &m);
+        let mut trace = F::zero();
+        for i in 0..n {
+            trace += am[i][i];
+        }
+        coeffs[n - k] = -trace * &F::from(k as u64).inverse().unwrap();
+    }
+
+    coeffs
+}
+
+/// Returns whether a monic polynomial over F is irreducible, with Rabin's test: a polynomial f of degree d is
+/// irreducible if and only if x^(p^d) = x mod f and x^(p^(d/q)) - x is coprime with f for each prime q dividing d.
+fn is_irreducible<F: PrimeField>(f: &[F]) -> bool {
+    let degree = f.len() - 1;
+    if degree <= 1 {
+        return true;
+    }
+
+    // x^(p^k) mod f, for k from 1 to the degree.
+    let x = vec![F::zero(), F::one()];
+    let mut frobenius = Vec::with_capacity(degree);
+    let mut power = x.clone();
+    for _ in 0..degree {
+        power = poly_pow_mod(&power, F::characteristic(), f);
+        frobenius.push(power.clone());
+    }
+
+    if !poly_sub(&frobenius[degree - 1], &x).is_empty() {
+        return false;
+    }
+
+    (2..=degree)
+        .filter(|q| degree % q == 0 && (2..*q).all(|r| q % r != 0))
+        .all(|q| poly_gcd(f.to_vec(), poly_sub(&frobenius[degree / q - 1], &x)).len() == 1)
+}
+
+/// Removes the leading zero coefficients of a polynomial, so that zero is the empty polynomial.
+fn poly_trim<F: PrimeField>(mut a: Vec<F>) -> Vec<F> {
+    while a.last().map_or(false, |c| c.is_zero()) {
+        a.pop();
+    }
+
+    a
+}
+
+/// Returns a - b.
+fn poly_sub<F: PrimeField>(a: &[F], b: &[F]) -> Vec<F> {
+    let mut difference = vec![F::zero(); a.len().max(b.len())];
+    for (i, c) in a.iter().enumerate() {
+        difference[i] += c;
+    }
+    for (i, c) in b.iter().enumerate() {
+        difference[i] -= c;
+    }
+
+    poly_trim(difference)
+}
+
+/// Returns a mod b, for a non-zero b.
+fn poly_rem<F: PrimeField>(a: Vec<F>, b: &[F]) -> Vec<F> {
+    let mut remainder = poly_trim(a);
+    let b = poly_trim(b.to_vec());
+    let lead_inverse = b.last().unwrap().inverse().unwrap();
+    while remainder.len() >= b.len() {
+        let shift = remainder.len() - b.len();
+        let factor = *remainder.last().unwrap() * &lead_inverse;
+        for (i, c) in b.iter().enumerate() {
+            remainder[shift + i] -= factor * c;
+        }
+        remainder = poly_trim(remainder);
+    }
+
+    remainder
+}
+
+/// Returns a * b mod f.
+fn poly_mul_mod<F: PrimeField>(a: &[F], b: &[F], f: &[F]) -> Vec<F> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+
+    let mut product = vec![F::zero(); a.len() + b.len() - 1];
+    for (i, c) in a.iter().enumerate() {
+        for (j, d) in b.iter().enumerate() {
+            product[i + j] += *c * d;
+        }
+    }
+
+    poly_rem(product, f)
+}
+
+/// Returns base^exponent mod f, where the exponent is given by its little-endian limbs.
+fn poly_pow_mod<F: PrimeField>(base: &[F], exponent: &[u64], f: &[F]) -> Vec<F> {
+    let mut result = vec![F::one()];
+    for limb in exponent.iter().rev() {
+        for bit in (0..64).rev() {
+            result = poly_mul_mod(&result, &result, f);
+            if (limb >> bit) & 1 == 1 {
+                result = poly_mul_mod(&result, base, f);
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns a greatest common divisor of a and b, which is a non-zero constant if they are coprime.
+fn poly_gcd<F: PrimeField>(mut a: Vec<F>, mut b: Vec<F>) -> Vec<F> {
+    a = poly_trim(a);
+    b = poly_trim(b);
+    while !b.is_empty() {
+        let remainder = poly_rem(a, &b);
+        a = b;
+        b = remainder;
+    }
+
+    a
+}
+
+/// Returns the greatest common divisor of a and b.
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
 }
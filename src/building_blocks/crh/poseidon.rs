@@ -1,12 +1,16 @@
-// Note: Poseidon here is for benchmark purposes.
-// The upstream sponge does not sample random parameters, and the default parameters may be insecure for certain fields.
-// We leave it as a future item. The more stable sponge may be later implemented in the ark-sponge repo.
-
-use crate::building_blocks::crh::{CRHforMerkleTree, CRHforMerkleTreeGadget};
+// Note: the upstream sponge only comes with default parameters, which may be insecure for certain fields.
+// The setup here replaces them with parameters generated for the field: the S-box exponent and the round
+// numbers follow the Poseidon paper for 128-bit security, and the round constants and the MDS matrix are
+// sampled from the RO, seeded by the setup randomness. The MDS matrix is resampled until it passes the check
+// against invariant subspace trails, which a random Cauchy matrix may fail.
+
+use crate::building_blocks::crh::{
+    sample_secure_mds, smallest_sbox_exponent, CRHforMerkleTree, CRHforMerkleTreeGadget,
+};
 use crate::Error;
 use ark_ff::{BigInteger, FpParameters, PrimeField};
 use ark_std::rand::{CryptoRng, Rng, SeedableRng};
-use ark_std::{marker::PhantomData, vec::Vec};
+use ark_std::{io::Cursor, marker::PhantomData, vec::Vec};
 
 use crate::gadgets::{FieldVar, FpVar, ToBitsGadget};
 use ark_marlin::fiat_shamir::constraints::AlgebraicSpongeVar;
@@ -18,7 +22,7 @@ use ark_r1cs_std::R1CSVar;
 use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
 
 /// Poseidon CRH combining with the compressor (taking only the x-coordinate in the affine representation)
-/// The RO is not used now, but should be used later for generating the parameters.
+/// The RO, seeded by the setup randomness, samples the round constants and the MDS matrix.
 pub struct PoseidonCRHforMerkleTree<RO: Rng + CryptoRng + SeedableRng, F: PrimeField> {
     _rand: PhantomData<RO>,
     _field: PhantomData<F>,
@@ -30,8 +34,38 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField> CRHforMerkleTree
     type Output = F;
     type Parameters = PoseidonSponge<F>;
 
-    fn setup<R: Rng + CryptoRng>(_: &mut R) -> Result<Self::Parameters, Error> {
-        Ok(PoseidonSponge::<F>::new())
+    fn setup<R: Rng + CryptoRng>(r: &mut R) -> Result<Self::Parameters, Error> {
+        // Step 1: seed the RO, so that the parameters are reproducible from the setup randomness
+        let mut seed = RO::Seed::default();
+        r.fill_bytes(seed.as_mut());
+        let mut ro = RO::from_seed(seed);
+
+        // Step 2: keep the rate and the capacity of the default sponge, but pick the S-box and the rounds for F
+        let mut sponge = PoseidonSponge::<F>::new();
+        let width = sponge.rate + sponge.capacity;
+        let alpha = Self::sbox_exponent();
+        let (full_rounds, partial_rounds) = Self::round_numbers(alpha, width);
+
+        // Step 3: sample the round constants
+        let mut ark = Vec::new();
+        for _ in 0..(full_rounds + partial_rounds) {
+            let mut round_constants = Vec::new();
+            for _ in 0..width {
+                round_constants.push(F::rand(&mut ro));
+            }
+            ark.push(round_constants);
+        }
+
+        // Step 4: sample the MDS matrix, without invariant subspace trails through the partial rounds
+        let mds = sample_secure_mds::<F, _>(&mut ro, width);
+
+        sponge.full_rounds = full_rounds;
+        sponge.partial_rounds = partial_rounds;
+        sponge.alpha = alpha;
+        sponge.ark = ark;
+        sponge.mds = mds;
+
+        Ok(sponge)
     }
 
     fn hash_bytes(parameters: &Self::Parameters, input: &[u8]) -> Result<Self::Output, Error> {
@@ -73,6 +107,37 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField> CRHforMerkleTree
         Ok(res[0])
     }
 
+    fn hash_field_elements<CF: PrimeField>(
+        parameters: &Self::Parameters,
+        input: &[CF],
+    ) -> Result<Self::Output, Error> {
+        // Step 1: elements of another field go through their bytes
+        if CF::characteristic() != F::characteristic() {
+            let mut writer = Cursor::new(Vec::<u8>::new());
+            for input_elem in input.iter() {
+                input_elem.write(&mut writer)?;
+            }
+
+            return Self::hash_bytes(parameters, &writer.into_inner());
+        }
+
+        // Step 2: otherwise, read each element in the sponge field
+        let mut field_elements = Vec::new();
+        for input_elem in input.iter() {
+            let mut writer = Cursor::new(Vec::<u8>::new());
+            input_elem.write(&mut writer)?;
+            field_elements.push(F::from_le_bytes_mod_order(&writer.into_inner()));
+        }
+
+        // Step 3: clone a freshly new sponge and put the field elements into
+        let mut sponge = parameters.clone();
+        sponge.absorb(&field_elements);
+
+        // Step 4: output one element
+        let res = sponge.squeeze(1);
+        Ok(res[0])
+    }
+
     fn two_to_one_compress(
         parameters: &Self::Parameters,
         left: &Self::Output,
@@ -87,6 +152,21 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField> CRHforMerkleTree
         Ok(res[0])
     }
 
+    fn two_to_one_compress_with_tag(
+        parameters: &Self::Parameters,
+        tag: u8,
+        left: &Self::Output,
+        right: &Self::Output,
+    ) -> Result<Self::Output, Error> {
+        // Step 1: clone a freshly new sponge and put the tag, as a field element, and the field elements into
+        let mut sponge = parameters.clone();
+        sponge.absorb(&[F::from(u64::from(tag)), *left, *right]);
+
+        // Step 2: output one element
+        let res = sponge.squeeze(1);
+        Ok(res[0])
+    }
+
     fn four_to_one_compress(
         parameters: &Self::Parameters,
         elts: &[Self::Output],
@@ -99,6 +179,122 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField> CRHforMerkleTree
         let res = sponge.squeeze(1);
         Ok(res[0])
     }
+
+    fn four_to_one_compress_with_tag(
+        parameters: &Self::Parameters,
+        tag: u8,
+        elts: &[Self::Output],
+    ) -> Result<Self::Output, Error> {
+        // Step 1: clone a freshly new sponge and put the tag, as a field element, and the field elements into
+        let mut sponge = parameters.clone();
+        sponge.absorb(&[F::from(u64::from(tag))]);
+        sponge.absorb(elts);
+
+        // Step 2: output one element
+        let res = sponge.squeeze(1);
+        Ok(res[0])
+    }
+}
+
+/// the security level, in bits, that the Poseidon parameters are generated for
+pub const POSEIDON_SECURITY_LEVEL: u64 = 128;
+
+impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField> PoseidonCRHforMerkleTree<RO, F> {
+    /// the smallest exponent alpha such that x^alpha is a permutation of F, i.e., gcd(alpha, p - 1) = 1
+    pub fn sbox_exponent() -> u64 {
+        smallest_sbox_exponent::<F>()
+    }
+
+    /// the numbers of full rounds and partial rounds for this exponent and this state width
+    ///
+    /// they are the cheapest numbers satisfying the statistical, interpolation, and Gröbner basis bounds of the
+    /// Poseidon paper for `POSEIDON_SECURITY_LEVEL`, plus its security margin of two full rounds and 7.5% of
+    /// partial rounds; as in the published instances, the partial rounds are then rounded up to a multiple of
+    /// the width
+    pub fn round_numbers(alpha: u64, width: usize) -> (u32, u32) {
+        let m = POSEIDON_SECURITY_LEVEL as i64;
+        let n = F::size_in_bits() as i64;
+        let t = width as i64;
+        let log_alpha = log2_fixed(alpha) as i64;
+        let log_t = log2_fixed(width as u64) as i64;
+
+        // The bounds which do not depend on the number of partial rounds.
+        let statistical = if 2 * m <= (2 * (n - 1) - (alpha as i64 - 1)) * (t + 1) {
+            6
+        } else {
+            10
+        };
+        let interpolation =
+            1 + ceil_div(m.min(n) << LOG2_PRECISION, log_alpha) + ceil_div(log_t, log_alpha);
+        let groebner_1 = ceil_div(m.min(n) << LOG2_PRECISION, log_alpha);
+        let groebner_2 = t - 1
+            + ceil_div(
+                (2 * m).min(n * (t + 1)) << LOG2_PRECISION,
+                2 * (t + 1) * log_alpha,
+            );
+        let groebner_3 = t - 2 + ceil_div(m << LOG2_PRECISION, 2 * log_alpha);
+
+        // Search for the partial rounds which minimize the number of S-boxes.
+        let mut best: Option<(i64, i64)> = None;
+        for partial_rounds in 1..=MAX_PARTIAL_ROUNDS {
+            let mut full_rounds = statistical
+                .max(interpolation - partial_rounds)
+                .max(groebner_1 - partial_rounds)
+                .max(groebner_2 - partial_rounds)
+                .max(ceil_div(groebner_3 - partial_rounds, t - 1))
+                .max(2);
+            full_rounds += full_rounds % 2;
+
+            let is_cheaper = match best {
+                Some((best_full, best_partial)) => {
+                    t * full_rounds + partial_rounds < t * best_full + best_partial
+                }
+                None => true,
+            };
+            if is_cheaper {
+                best = Some((full_rounds, partial_rounds));
+            }
+        }
+        let (full_rounds, partial_rounds) = best.unwrap();
+
+        let partial_rounds = ceil_div(partial_rounds * 1075, 1000);
+
+        (
+            (full_rounds + 2) as u32,
+            (ceil_div(partial_rounds, t) * t) as u32,
+        )
+    }
+}
+
+/// The number of fractional bits in the fixed-point logarithms.
+const LOG2_PRECISION: u32 = 16;
+
+/// The largest number of partial rounds to search for.
+const MAX_PARTIAL_ROUNDS: i64 = 500;
+
+/// Returns floor(log2(x) * 2^LOG2_PRECISION), computed by repeated squaring of the mantissa.
+fn log2_fixed(x: u64) -> u64 {
+    assert!(x > 0 && x < (1 << 62));
+
+    let integer_part = 63 - x.leading_zeros() as u64;
+    let mut res = integer_part << LOG2_PRECISION;
+
+    // The mantissa x / 2^integer_part, in [1, 2), with 62 fractional bits.
+    let mut mantissa = (x as u128) << (62 - integer_part);
+    for i in (0..LOG2_PRECISION).rev() {
+        mantissa = (mantissa * mantissa) >> 62;
+        if mantissa >= 2 << 62 {
+            mantissa >>= 1;
+            res |= 1 << i;
+        }
+    }
+
+    res
+}
+
+/// Returns the ceiling of a / b, for a positive b.
+fn ceil_div(a: i64, b: i64) -> i64 {
+    (a + b - 1).div_euclid(b)
 }
 
 /// Gadgets of the Bowe-Hopwood CRH combining with the compressor
@@ -161,6 +357,32 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField>
         }
     }
 
+    fn hash_field_elements(
+        parameters: &<PoseidonCRHforMerkleTree<RO, F> as CRHforMerkleTree>::Parameters,
+        input: &[FpVar<F>],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let cs = input.cs();
+
+        if cs == ConstraintSystemRef::None {
+            let mut vals: Vec<F> = Vec::new();
+            for input_elem in input.iter() {
+                vals.push(input_elem.value()?);
+            }
+
+            Ok(FpVar::<F>::Constant(
+                PoseidonCRHforMerkleTree::<RO, F>::hash_field_elements(parameters, &vals).unwrap(),
+            ))
+        } else {
+            // Step 1: clone a freshly new sponge and put the field elements into, as they are
+            let mut sponge = PoseidonSpongeVar::constant(cs, parameters);
+            sponge.absorb(input)?;
+
+            // Step 2: output one element
+            let res = sponge.squeeze(1)?;
+            Ok(res[0].clone())
+        }
+    }
+
     fn two_to_one_compress(
         parameters: &<PoseidonCRHforMerkleTree<RO, F> as CRHforMerkleTree>::Parameters,
         left: &Self::OutputVar,
@@ -188,6 +410,39 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField>
         }
     }
 
+    fn two_to_one_compress_with_tag(
+        parameters: &<PoseidonCRHforMerkleTree<RO, F> as CRHforMerkleTree>::Parameters,
+        tag: u8,
+        left: &Self::OutputVar,
+        right: &Self::OutputVar,
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let cs = left.cs().or(right.cs());
+
+        if cs == ConstraintSystemRef::None {
+            Ok(FpVar::<F>::Constant(
+                PoseidonCRHforMerkleTree::<RO, F>::two_to_one_compress_with_tag(
+                    parameters,
+                    tag,
+                    &left.value()?,
+                    &right.value()?,
+                )
+                .unwrap(),
+            ))
+        } else {
+            // Step 1: clone a freshly new sponge and put the tag, as a constant, and the field elements into
+            let mut sponge = PoseidonSpongeVar::constant(cs, parameters);
+            sponge.absorb(&[
+                FpVar::<F>::constant(F::from(u64::from(tag))),
+                left.clone(),
+                right.clone(),
+            ])?;
+
+            // Step 2: output one element
+            let res = sponge.squeeze(1)?;
+            Ok(res[0].clone())
+        }
+    }
+
     fn four_to_one_compress(
         parameters: &<PoseidonCRHforMerkleTree<RO, F> as CRHforMerkleTree>::Parameters,
         elts: &[Self::OutputVar],
@@ -213,4 +468,101 @@ impl<RO: Rng + CryptoRng + SeedableRng, F: PrimeField>
             Ok(res[0].clone())
         }
     }
+
+    fn four_to_one_compress_with_tag(
+        parameters: &<PoseidonCRHforMerkleTree<RO, F> as CRHforMerkleTree>::Parameters,
+        tag: u8,
+        elts: &[Self::OutputVar],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let cs = elts.cs();
+
+        if cs == ConstraintSystemRef::None {
+            let mut vals: Vec<F> = Vec::new();
+            for elt in elts.iter() {
+                vals.push(elt.value()?);
+            }
+
+            Ok(FpVar::<F>::Constant(
+                PoseidonCRHforMerkleTree::<RO, F>::four_to_one_compress_with_tag(
+                    parameters, tag, &vals,
+                )
+                .unwrap(),
+            ))
+        } else {
+            // Step 1: clone a freshly new sponge and put the tag, as a constant, and the field elements into
+            let mut sponge = PoseidonSpongeVar::constant(cs, parameters);
+            sponge.absorb(&[FpVar::<F>::constant(F::from(u64::from(tag)))])?;
+            sponge.absorb(elts)?;
+
+            // Step 2: output one element
+            let res = sponge.squeeze(1)?;
+            Ok(res[0].clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand_chacha::ChaChaRng;
+
+    // The known answers are given by the reference script of the Poseidon paper (calc_round_numbers.py) for
+    // 128-bit security, with the partial rounds rounded up to a multiple of the width.
+
+    #[test]
+    fn round_numbers_bls12_381_test() {
+        type H = PoseidonCRHforMerkleTree<ChaChaRng, ark_ed_on_bls12_381::Fq>;
+
+        assert_eq!(H::sbox_exponent(), 5);
+        assert_eq!(H::round_numbers(5, 3), (8, 57));
+        assert_eq!(H::round_numbers(5, 5), (8, 60));
+    }
+
+    #[test]
+    fn round_numbers_mnt4_298_test() {
+        type HFr = PoseidonCRHforMerkleTree<ChaChaRng, ark_ed_on_mnt4_298::Fq>;
+        type HFq = PoseidonCRHforMerkleTree<ChaChaRng, ark_mnt4_298::Fq>;
+
+        assert_eq!(HFr::sbox_exponent(), 5);
+        assert_eq!(HFr::round_numbers(5, 3), (8, 57));
+        assert_eq!(HFr::round_numbers(5, 5), (8, 60));
+
+        assert_eq!(HFq::sbox_exponent(), 11);
+        assert_eq!(HFq::round_numbers(11, 3), (8, 39));
+        assert_eq!(HFq::round_numbers(11, 5), (8, 40));
+    }
+
+    #[test]
+    fn round_numbers_other_widths_test() {
+        type H = PoseidonCRHforMerkleTree<ChaChaRng, ark_ed_on_bls12_381::Fq>;
+
+        let expected = [
+            56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68,
+        ];
+        for (width, partial_rounds) in (2..).zip(expected.iter()) {
+            assert_eq!(H::round_numbers(5, width), (8, *partial_rounds));
+        }
+    }
+
+    #[test]
+    fn mds_subspace_trail_test() {
+        use crate::building_blocks::crh::has_no_invariant_subspace_trail;
+
+        type F = ark_ed_on_bls12_381::Fq;
+        type H = PoseidonCRHforMerkleTree<ChaChaRng, F>;
+
+        let mut rng = ark_std::test_rng();
+        let sponge = H::setup(&mut rng).unwrap();
+        assert!(has_no_invariant_subspace_trail(&sponge.mds));
+
+        // The characteristic polynomial of this matrix is (x - 1)^2 (x - 4), so the span of (1, -1, 0) is
+        // invariant.
+        let (one, two) = (F::from(1u64), F::from(2u64));
+        let reducible = vec![
+            vec![two, one, one],
+            vec![one, two, one],
+            vec![one, one, two],
+        ];
+        assert!(!has_no_invariant_subspace_trail(&reducible));
+    }
 }
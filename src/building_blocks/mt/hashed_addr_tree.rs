@@ -0,0 +1,1266 @@
+//! A sparse Merkle tree keyed by arbitrary addresses, such as 32-byte public keys or field
+//! elements. An address is placed at the leaf whose index is the lowest `HEIGHT - 1` bits of
+//! the CRH of its bytes, and the leaf commits to the address as well as the data.
+//!
+//! Two different addresses may be hashed to the same index. The leaf encoding binds the
+//! address, so a proof for one address can never be used to claim data for another. The tree
+//! refuses to write an address whose index is already taken by a different address, and such an
+//! address can be neither looked up nor proven absent. With `HEIGHT - 1` index bits, a collision
+//! is expected after about `2^((HEIGHT - 1) / 2)` addresses, so the height should be chosen with
+//! the number of accounts in mind. The byte encoding of the addresses must have a fixed length.
+//!
+//! Collisions can also be made on purpose: an attacker who knows the address of a victim can try
+//! addresses of its own until one lands on the index of the victim, which takes about
+//! `2^(HEIGHT - 1)` hashes, and write it first, so that the victim can never be written. With
+//! `N` victims to choose from, it takes about `2^(HEIGHT - 1) / N` hashes to lock one out. The
+//! indices cannot have more than 63 bits, so `setup` requires `HEIGHT` to be
+//! `MIN_HASHED_ADDR_TREE_HEIGHT`, which makes the attack cost about `2^63` hashes per victim; a
+//! ledger that cannot afford to lose an address to this attack should not use this tree.
+//!
+//! A modifying proof also opens the old leaf, which must be empty or hold the same address, so a
+//! write can never evict a colliding address. The tree therefore keeps the data of each leaf.
+//!
+//! The empty leaf is hashed with the tag of the empty leaves, so a proof records whether each
+//! leaf is empty, which selects the tag.
+use crate::building_blocks::crh::{CRHforMerkleTree, CRHforMerkleTreeGadget};
+use crate::building_blocks::mt::{
+    encode_leaf, encode_leaves,
+    merkle_sparse_tree::{
+        constraints::{
+            hash_leaf_or_empty_gadget, MerkleSparseTreeMultiPathVar, MerkleSparseTreePathVar,
+        },
+        hash_leaf_or_empty, MerkleSparseTree, MerkleSparseTreeConfig, MerkleSparseTreeMultiPath,
+        MerkleSparseTreePath,
+    },
+    EncodedLeaf, LookupProofVar, MerkleLeaf, MerkleLeafGadget, ModifyProofType, ModifyProofTypeVar,
+    SparseMTError, MT,
+};
+use crate::{
+    gadgets::{
+        AllocVar, Boolean, CondSelectGadget, EqGadget, ToBitsGadget, ToBytesGadget, UInt64, UInt8,
+    },
+    Borrow, Error, PhantomData, PrimeField, SynthesisError, ToBytes, Vec,
+};
+use ark_r1cs_std::alloc::AllocationMode;
+use ark_relations::r1cs::{ConstraintSystemRef, Namespace};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::collections::BTreeMap;
+use ark_std::rand::{CryptoRng, Rng};
+use ark_std::{fmt::Debug, format};
+use ark_std::{
+    io::{Cursor, Read, Result as IoResult, Write},
+    vec,
+};
+
+/// Sparse Merkle tree keyed by hashed addresses
+pub struct HashedAddrMT<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    Addr,
+> {
+    f_phantom: PhantomData<F>,
+    tree_config_phantom: PhantomData<P>,
+    crh_gadget_phantom: PhantomData<CRHVar>,
+    addr_phantom: PhantomData<Addr>,
+}
+
+/// the tree, together with the address stored at each occupied index
+pub struct HashedAddrTree<P: MerkleSparseTreeConfig, Addr> {
+    /// the underlying sparse Merkle tree
+    pub tree: MerkleSparseTree<P>,
+    /// the address written at each index
+    pub addresses: BTreeMap<u64, Addr>,
+    /// the bytes of the data written at each index
+    pub data: BTreeMap<u64, Vec<u8>>,
+}
+
+/// A lookup proof for one address, which also tells whether the address has never been written
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseTreeConfig"),
+    Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct HashedAddrPath<P: MerkleSparseTreeConfig> {
+    /// whether the leaf is empty, in which case the data must be the default one
+    pub(crate) empty: bool,
+    /// the path of the leaf
+    pub(crate) path: MerkleSparseTreePath<P>,
+}
+
+/// A multi-opening for several addresses
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseTreeConfig"),
+    Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct HashedAddrMultiPath<P: MerkleSparseTreeConfig> {
+    /// whether each leaf is empty
+    pub(crate) empty: Vec<bool>,
+    /// the multi-opening of the leaves
+    pub(crate) multi_path: MerkleSparseTreeMultiPath<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Default for HashedAddrPath<P> {
+    fn default() -> Self {
+        Self {
+            empty: false,
+            path: MerkleSparseTreePath::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for HashedAddrPath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.empty.write(&mut writer)?;
+        self.path.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for HashedAddrMultiPath<P> {
+    fn default() -> Self {
+        Self {
+            empty: Vec::new(),
+            multi_path: MerkleSparseTreeMultiPath::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for HashedAddrMultiPath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        for empty in &self.empty {
+            empty.write(&mut writer)?;
+        }
+        self.multi_path.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+/// A modifying proof for one address, which opens the old leaf, so that it must be empty or hold the same address
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(Clone(bound = "P: MerkleSparseTreeConfig"))]
+pub struct HashedAddrTwoPaths<P: MerkleSparseTreeConfig> {
+    /// whether the old leaf is empty
+    pub(crate) empty: bool,
+    /// the bytes of the old data, which are those of the default data if the old leaf is empty
+    pub(crate) old_data: Vec<u8>,
+    /// the modifying proof of the leaf
+    pub(crate) modify_proof: ModifyProofType<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Default for HashedAddrTwoPaths<P> {
+    fn default() -> Self {
+        Self {
+            empty: false,
+            old_data: Vec::new(),
+            modify_proof: ModifyProofType::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for HashedAddrTwoPaths<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.empty.write(&mut writer)?;
+        self.old_data.write(&mut writer)?;
+        self.modify_proof.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+/// Gadgets for the lookup proof of one address
+pub struct HashedAddrPathVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+> {
+    empty: Boolean<F>,
+    path: MerkleSparseTreePathVar<P, CRHVar, F>,
+}
+
+/// lookup proof gadgets for the hashed-address tree
+pub struct HashedAddrLookupProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<HashedAddrPathVar<F, P, CRHVar>>);
+
+/// Gadgets for the modifying proof of one address
+pub struct HashedAddrTwoPathsVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+> {
+    empty: Boolean<F>,
+    /// The old data is allocated only once the length of the data is known,
+    /// so that a default proof gives the same circuit.
+    old_data: Vec<u8>,
+    modify_proof: ModifyProofTypeVar<P, CRHVar, F>,
+}
+
+/// modifying proof gadgets for the hashed-address tree
+pub struct HashedAddrModifyProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<HashedAddrTwoPathsVar<F, P, CRHVar>>);
+
+/// Gadgets for the multi-opening of several addresses
+pub struct HashedAddrMultiPathVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+> {
+    empty: Vec<Boolean<F>>,
+    multi_path: MerkleSparseTreeMultiPathVar<P, CRHVar, F>,
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<HashedAddrPath<P>, F> for HashedAddrPathVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<HashedAddrPath<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let t = f()?;
+        let proof = t.borrow();
+
+        let empty =
+            Boolean::new_variable(ark_relations::ns!(cs, "empty"), || Ok(proof.empty), mode)?;
+        let path = MerkleSparseTreePathVar::new_variable(
+            ark_relations::ns!(cs, "path"),
+            || Ok(&proof.path),
+            mode,
+        )?;
+
+        Ok(HashedAddrPathVar { empty, path })
+    }
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<HashedAddrPath<P>>, F> for HashedAddrLookupProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<HashedAddrPath<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(HashedAddrPathVar::<F, P, CRHVar>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(HashedAddrLookupProofVar { 0: vec })
+    }
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<HashedAddrTwoPaths<P>, F> for HashedAddrTwoPathsVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<HashedAddrTwoPaths<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let t = f()?;
+        let proof = t.borrow();
+
+        let empty =
+            Boolean::new_variable(ark_relations::ns!(cs, "empty"), || Ok(proof.empty), mode)?;
+        let modify_proof = ModifyProofTypeVar::new_variable(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(&proof.modify_proof),
+            mode,
+        )?;
+
+        Ok(HashedAddrTwoPathsVar {
+            empty,
+            old_data: proof.old_data.clone(),
+            modify_proof,
+        })
+    }
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<HashedAddrTwoPaths<P>>, F> for HashedAddrModifyProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<HashedAddrTwoPaths<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(HashedAddrTwoPathsVar::<F, P, CRHVar>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(HashedAddrModifyProofVar { 0: vec })
+    }
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<HashedAddrMultiPath<P>, F> for HashedAddrMultiPathVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<HashedAddrMultiPath<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let t = f()?;
+        let proof = t.borrow();
+
+        let mut empty = Vec::new();
+        for value in proof.empty.iter() {
+            empty.push(Boolean::new_variable(
+                ark_relations::ns!(cs, "empty"),
+                || Ok(*value),
+                mode,
+            )?);
+        }
+        let multi_path = MerkleSparseTreeMultiPathVar::new_variable(
+            ark_relations::ns!(cs, "multi_path"),
+            || Ok(&proof.multi_path),
+            mode,
+        )?;
+
+        Ok(HashedAddrMultiPathVar { empty, multi_path })
+    }
+}
+
+/// The leaf of the tree, which is `1 || addr || data` if written and `0 || 0...0 || data`
+/// for the empty leaf, so both have the same length.
+struct HashedLeaf<'a, Addr, Data> {
+    addr: Option<&'a Addr>,
+    data: Data,
+}
+
+impl<'a, Addr: ToBytes + Default, Data: Default> Default for HashedLeaf<'a, Addr, Data> {
+    fn default() -> Self {
+        HashedLeaf {
+            addr: None,
+            data: Data::default(),
+        }
+    }
+}
+
+impl<'a, Addr: ToBytes + Default, Data: ToBytes> ToBytes for HashedLeaf<'a, Addr, Data> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        match self.addr {
+            Some(addr) => {
+                1u8.write(&mut writer)?;
+                addr.write(&mut writer)?;
+            }
+            None => {
+                let mut addr_writer = Cursor::new(Vec::<u8>::new());
+                Addr::default().write(&mut addr_writer)?;
+
+                0u8.write(&mut writer)?;
+                for _ in 0..addr_writer.into_inner().len() {
+                    0u8.write(&mut writer)?;
+                }
+            }
+        }
+        self.data.write(&mut writer)
+    }
+}
+
+/// Returns the leaf index of an address.
+pub fn addr_to_index<P: MerkleSparseTreeConfig, Addr: ToBytes>(
+    pp: &<P::H as CRHforMerkleTree>::Parameters,
+    addr: &Addr,
+) -> Result<u64, Error> {
+    let mut addr_writer = Cursor::new(Vec::<u8>::new());
+    addr.write(&mut addr_writer)?;
+    let hash = P::H::hash_bytes(pp, &addr_writer.into_inner())?;
+
+    let mut hash_writer = Cursor::new(Vec::<u8>::new());
+    hash.write(&mut hash_writer)?;
+    let hash_bytes = hash_writer.into_inner();
+
+    // Take the lowest HEIGHT - 1 bits, in the same order as the gadget.
+    let mut index = 0u64;
+    for i in 0..(P::HEIGHT - 1) as usize {
+        let bit = (hash_bytes[i / 8] >> (i % 8)) & 1;
+        index |= (bit as u64) << i;
+    }
+
+    Ok(index)
+}
+
+/// Returns the leaf index of an address, given the bytes of the address.
+pub fn addr_to_index_gadget<F, P, CRHVar>(
+    pp_g: &<P::H as CRHforMerkleTree>::Parameters,
+    addr_bytes_g: &[UInt8<F>],
+) -> Result<UInt64<F>, SynthesisError>
+where
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+{
+    let hash_g = CRHVar::hash_bytes(pp_g, addr_bytes_g)?;
+    let hash_bits_g = hash_g.to_bytes()?.to_bits_le()?;
+
+    let mut index_bits_g = hash_bits_g[..(P::HEIGHT - 1) as usize].to_vec();
+    index_bits_g.resize(64, Boolean::constant(false));
+
+    Ok(UInt64::from_bits_le(&index_bits_g))
+}
+
+/// Returns the bytes of the leaf, where the address is masked out if the leaf is empty.
+fn leaf_bytes_gadget<F: PrimeField>(
+    addr_bytes_g: &[UInt8<F>],
+    data_bytes_g: &[UInt8<F>],
+    empty_g: &Boolean<F>,
+) -> Result<Vec<UInt8<F>>, SynthesisError> {
+    let occupied_g = empty_g.not();
+
+    let mut tag_bits_g = vec![occupied_g.clone()];
+    tag_bits_g.resize(8, Boolean::constant(false));
+
+    let mut leaf_bytes_g = vec![UInt8::from_bits_le(&tag_bits_g)];
+    for byte_g in addr_bytes_g.iter() {
+        let mut bits_g = Vec::with_capacity(8);
+        for bit_g in byte_g.to_bits_le()?.iter() {
+            bits_g.push(bit_g.and(&occupied_g)?);
+        }
+        leaf_bytes_g.push(UInt8::from_bits_le(&bits_g));
+    }
+    leaf_bytes_g.extend_from_slice(data_bytes_g);
+
+    Ok(leaf_bytes_g)
+}
+
+/// Returns the bytes of the data, as they are placed in the leaf.
+fn data_bytes<Data: ToBytes>(data: &Data) -> Result<Vec<u8>, Error> {
+    let mut data_writer = Cursor::new(Vec::<u8>::new());
+    data.write(&mut data_writer)?;
+    Ok(data_writer.into_inner())
+}
+
+/// the smallest height accepted by `HashedAddrMT::setup`, the largest a sparse tree can have
+pub const MIN_HASHED_ADDR_TREE_HEIGHT: u64 = 64;
+
+impl<F, P, CRHVar, Addr> HashedAddrMT<F, P, CRHVar, Addr>
+where
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+{
+    /// like `setup`, for a tree of any height, e.g., to test collisions in a small tree
+    ///
+    /// a tree below `MIN_HASHED_ADDR_TREE_HEIGHT` is open to the grinding attack in the module
+    /// documentation
+    pub fn setup_with_small_height<R: Rng + CryptoRng>(
+        rng: &mut R,
+    ) -> Result<<P::H as CRHforMerkleTree>::Parameters, Error> {
+        <P::H as CRHforMerkleTree>::setup(rng)
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, Addr: Clone> Clone for HashedAddrTree<P, Addr> {
+    fn clone(&self) -> Self {
+        HashedAddrTree {
+            tree: self.tree.clone(),
+            addresses: self.addresses.clone(),
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, Addr: Clone + Eq> HashedAddrTree<P, Addr> {
+    /// Returns an error if the index is taken by a different address.
+    fn check_collision(&self, index: u64, addr: &Addr) -> Result<(), Error> {
+        match self.addresses.get(&index) {
+            Some(x) if x != addr => Err(HashedAddrMTError::AddressCollision(index).into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<F, P, CRHVar, Addr, AddrVar> MT<F, Addr, AddrVar> for HashedAddrMT<F, P, CRHVar, Addr>
+where
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    Addr: ToBytes + Default + Eq + Clone + Ord + CanonicalSerialize + CanonicalDeserialize,
+    AddrVar: AllocVar<Addr, F> + ToBytesGadget<F>,
+{
+    type PublicParameters = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Parameters;
+    type Digest = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output;
+    type T = HashedAddrTree<P, Addr>;
+    type LookupProof = Vec<HashedAddrPath<P>>;
+    type ModifyProof = Vec<HashedAddrTwoPaths<P>>;
+    type AbsenceProof = Vec<MerkleSparseTreePath<P>>;
+    type MultiLookupProof = HashedAddrMultiPath<P>;
+
+    type DigestVar = CRHVar::OutputVar;
+    type LookupProofVar = HashedAddrLookupProofVar<F, P, CRHVar>;
+    type ModifyProofVar = HashedAddrModifyProofVar<F, P, CRHVar>;
+    type AbsenceProofVar = LookupProofVar<F, P, CRHVar>;
+    type MultiLookupProofVar = HashedAddrMultiPathVar<F, P, CRHVar>;
+
+    fn setup<R: Rng + CryptoRng>(rng: &mut R) -> Result<Self::PublicParameters, Error> {
+        if P::HEIGHT < MIN_HASHED_ADDR_TREE_HEIGHT {
+            return Err(HashedAddrMTError::HeightTooSmall(P::HEIGHT).into());
+        }
+
+        Self::setup_with_small_height(rng)
+    }
+
+    fn new<L: Default + MerkleLeaf<F>>(pp: &Self::PublicParameters) -> Result<Self::T, Error> {
+        Ok(HashedAddrTree {
+            tree: MerkleSparseTree::blank::<HashedLeaf<Addr, EncodedLeaf<F, L>>>(pp.clone()),
+            addresses: BTreeMap::new(),
+            data: BTreeMap::new(),
+        })
+    }
+
+    fn root(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<Self::Digest, Error> {
+        Ok(tree.tree.root())
+    }
+
+    fn validate(pp: &Self::PublicParameters, tree: &Self::T) -> Result<bool, Error> {
+        // Every address must be stored at its own index, together with its data.
+        for (index, addr) in tree.addresses.iter() {
+            if addr_to_index::<P, Addr>(pp, addr)? != *index || !tree.data.contains_key(index) {
+                return Ok(false);
+            }
+        }
+        if tree.data.len() != tree.addresses.len() {
+            return Ok(false);
+        }
+
+        tree.tree.validate()
+    }
+
+    fn lookup(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[Addr],
+    ) -> Result<Self::LookupProof, Error> {
+        let mut lookup_proof: Self::LookupProof = Vec::with_capacity(addr.len());
+
+        for addr_item in addr {
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+            tree.check_collision(index, addr_item)?;
+
+            lookup_proof.push(HashedAddrPath {
+                empty: !tree.addresses.contains_key(&index),
+                path: tree.tree.generate_membership_proof(index)?,
+            });
+        }
+
+        Ok(lookup_proof)
+    }
+
+    fn verify_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[Addr],
+        data: &[Data],
+        lookup_proof: &Self::LookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != lookup_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        for ((addr_item, data_item), lookup_proof_item) in
+            addr.iter().zip(data.iter()).zip(lookup_proof.iter())
+        {
+            let leaf = HashedLeaf {
+                addr: if lookup_proof_item.empty {
+                    None
+                } else {
+                    Some(addr_item)
+                },
+                data: data_item.clone(),
+            };
+            let leaf_hash = hash_leaf_or_empty::<P, _>(pp, &leaf, lookup_proof_item.empty)?;
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+
+            if !lookup_proof_item
+                .path
+                .verify_leaf_hash_with_index(pp, rh, leaf_hash, index)?
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn multi_lookup(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[Addr],
+    ) -> Result<Self::MultiLookupProof, Error> {
+        let mut empty = Vec::with_capacity(addr.len());
+        let mut indices = Vec::with_capacity(addr.len());
+
+        for addr_item in addr {
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+            tree.check_collision(index, addr_item)?;
+
+            empty.push(!tree.addresses.contains_key(&index));
+            indices.push(index);
+        }
+
+        Ok(HashedAddrMultiPath {
+            empty,
+            multi_path: tree.tree.generate_multi_membership_proof(&indices)?,
+        })
+    }
+
+    fn verify_multi_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[Addr],
+        data: &[Data],
+        multi_lookup_proof: &Self::MultiLookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != multi_lookup_proof.empty.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        let mut leaf_hashes = Vec::with_capacity(addr.len());
+        let mut indices = Vec::with_capacity(addr.len());
+        for ((addr_item, data_item), empty) in addr
+            .iter()
+            .zip(data.iter())
+            .zip(multi_lookup_proof.empty.iter())
+        {
+            let leaf = HashedLeaf {
+                addr: if *empty { None } else { Some(addr_item) },
+                data: data_item.clone(),
+            };
+            leaf_hashes.push(hash_leaf_or_empty::<P, _>(pp, &leaf, *empty)?);
+            indices.push(addr_to_index::<P, Addr>(pp, addr_item)?);
+        }
+
+        multi_lookup_proof
+            .multi_path
+            .verify_leaf_hashes_with_indices(pp, rh, &leaf_hashes, &indices)
+    }
+
+    fn prove_absence(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[Addr],
+    ) -> Result<Self::AbsenceProof, Error> {
+        let mut absence_proof: Self::AbsenceProof = Vec::with_capacity(addr.len());
+
+        for addr_item in addr {
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+            tree.check_collision(index, addr_item)?;
+
+            absence_proof.push(tree.tree.generate_absence_proof(index)?);
+        }
+
+        Ok(absence_proof)
+    }
+
+    fn verify_absence<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[Addr],
+        absence_proof: &Self::AbsenceProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != absence_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        for (addr_item, absence_proof_item) in addr.iter().zip(absence_proof.iter()) {
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+
+            if !absence_proof_item
+                .verify_absence_with_index::<HashedLeaf<Addr, EncodedLeaf<F, Data>>>(
+                    pp, rh, index,
+                )?
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn verify_modify<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh_old: &Self::Digest,
+        rh_new: &Self::Digest,
+        addr: &[Addr],
+        data: &[Data],
+        modify_proof: &Self::ModifyProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != modify_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        let mut last_hash: Self::Digest = rh_old.clone();
+
+        for ((addr_item, data_item), modify_proof_item) in
+            addr.iter().zip(data.iter()).zip(modify_proof.iter())
+        {
+            let leaf = HashedLeaf {
+                addr: Some(addr_item),
+                data: data_item.clone(),
+            };
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+
+            // The old leaf must be empty or hold the same address, with data of the same length.
+            let old_leaf = HashedLeaf {
+                addr: if modify_proof_item.empty {
+                    None
+                } else {
+                    Some(addr_item)
+                },
+                data: modify_proof_item.old_data.clone(),
+            };
+            if modify_proof_item.old_data.len() != data_bytes(data_item)?.len()
+                || hash_leaf_or_empty::<P, _>(pp, &old_leaf, modify_proof_item.empty)?
+                    != modify_proof_item.modify_proof.modify_proof.old_leaf_hash
+            {
+                return Ok(false);
+            }
+
+            let new_digest = &modify_proof_item.modify_proof.new_digest;
+            if !modify_proof_item
+                .modify_proof
+                .modify_proof
+                .verify(pp, &last_hash, new_digest, &leaf, index)?
+            {
+                return Ok(false);
+            }
+
+            last_hash = new_digest.clone();
+        }
+
+        Ok(last_hash == *rh_new)
+    }
+
+    fn _new_with_map<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        map: &BTreeMap<Addr, Data>,
+    ) -> Result<Self::T, Error> {
+        let mut addresses = BTreeMap::new();
+        let mut data_bytes_map = BTreeMap::new();
+        let mut leaves = BTreeMap::new();
+
+        for (addr, data) in map.iter() {
+            let data = encode_leaf(data)?;
+            let index = addr_to_index::<P, Addr>(pp, addr)?;
+            if addresses.insert(index, addr.clone()).is_some() {
+                return Err(HashedAddrMTError::AddressCollision(index).into());
+            }
+            data_bytes_map.insert(index, data_bytes(&data)?);
+
+            leaves.insert(
+                index,
+                HashedLeaf {
+                    addr: Some(addr),
+                    data,
+                },
+            );
+        }
+
+        Ok(HashedAddrTree {
+            tree: MerkleSparseTree::new(pp.clone(), &leaves)?,
+            addresses,
+            data: data_bytes_map,
+        })
+    }
+
+    fn _modify_and_apply<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        addr: &[Addr],
+        data: &[Data],
+    ) -> Result<(Self::Digest, Self::ModifyProof), Error> {
+        if addr.len() != data.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        // Check all the addresses first, so that a collision leaves the tree untouched.
+        let mut indices = Vec::with_capacity(addr.len());
+        let mut new_addresses: BTreeMap<u64, &Addr> = BTreeMap::new();
+        for addr_item in addr {
+            let index = addr_to_index::<P, Addr>(pp, addr_item)?;
+            tree.check_collision(index, addr_item)?;
+            if let Some(x) = new_addresses.insert(index, addr_item) {
+                if x != addr_item {
+                    return Err(HashedAddrMTError::AddressCollision(index).into());
+                }
+            }
+            indices.push(index);
+        }
+
+        let data = encode_leaves(data)?;
+        let default_data_bytes = data_bytes(&EncodedLeaf::<F, Data>::default())?;
+
+        let mut modify_proof: Self::ModifyProof = Vec::with_capacity(addr.len());
+        for ((addr_item, data_item), index) in addr.iter().zip(data.iter()).zip(indices.iter()) {
+            let leaf = HashedLeaf {
+                addr: Some(addr_item),
+                data: data_item.clone(),
+            };
+
+            let proof = tree.tree.update_and_prove(*index, &leaf)?;
+            tree.addresses.insert(*index, addr_item.clone());
+            let old_data = tree.data.insert(*index, data_bytes(data_item)?);
+            modify_proof.push(HashedAddrTwoPaths {
+                empty: old_data.is_none(),
+                old_data: old_data.unwrap_or_else(|| default_data_bytes.clone()),
+                modify_proof: ModifyProofType {
+                    new_digest: tree.tree.root(),
+                    modify_proof: proof,
+                },
+            });
+        }
+
+        Ok((tree.tree.root(), modify_proof))
+    }
+
+    fn clear(_pp: &Self::PublicParameters, tree: &mut Self::T) -> Result<(), Error> {
+        tree.tree.tree.clear();
+        tree.addresses.clear();
+        tree.data.clear();
+
+        Ok(())
+    }
+
+    fn serialize_tree<W: Write>(tree: &Self::T, mut writer: W) -> Result<(), Error> {
+        tree.tree.serialize_nodes(&mut writer)?;
+
+        (tree.addresses.len() as u64).serialize(&mut writer)?;
+        for (index, addr) in tree.addresses.iter() {
+            index.serialize(&mut writer)?;
+            addr.serialize(&mut writer)?;
+            tree.data
+                .get(index)
+                .ok_or(HashedAddrMTError::MissingData(*index))?
+                .serialize(&mut writer)?;
+        }
+
+        Ok(())
+    }
+
+    fn deserialize_tree<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &Self::PublicParameters,
+        mut reader: R,
+    ) -> Result<Self::T, Error> {
+        let tree = MerkleSparseTree::<P>::deserialize_nodes::<
+            HashedLeaf<Addr, EncodedLeaf<F, L>>,
+            _,
+        >(pp.clone(), &mut reader)?;
+
+        let len = u64::deserialize(&mut reader)?;
+        let mut addresses = BTreeMap::new();
+        let mut data = BTreeMap::new();
+        for _ in 0..len {
+            let index = u64::deserialize(&mut reader)?;
+            let addr = Addr::deserialize(&mut reader)?;
+            addresses.insert(index, addr);
+            data.insert(index, Vec::<u8>::deserialize(&mut reader)?);
+        }
+
+        Ok(HashedAddrTree {
+            tree,
+            addresses,
+            data,
+        })
+    }
+
+    fn verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            lookup_proof_g_wrapped,
+            &Boolean::constant(true),
+        )
+    }
+
+    fn conditionally_verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            lookup_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        let lookup_proof_g = &lookup_proof_g_wrapped.0;
+
+        assert_eq!(
+            addr_g.len(),
+            data_g.len(),
+            "the address len {} does not equal the data len {}",
+            addr_g.len(),
+            data_g.len()
+        );
+        assert_eq!(
+            data_g.len(),
+            active_g.len(),
+            "the data len {} does not equal the flag len {}",
+            data_g.len(),
+            active_g.len()
+        );
+        assert_eq!(
+            data_g.len(),
+            lookup_proof_g.len(),
+            "the data len {} does not equal the proof len {}",
+            data_g.len(),
+            lookup_proof_g.len()
+        );
+
+        for (((addr_g_item, data_g_item), active_g_item), lookup_proof_g_item) in addr_g
+            .iter()
+            .zip(data_g.iter())
+            .zip(active_g.iter())
+            .zip(lookup_proof_g.iter())
+        {
+            let addr_bytes_g = addr_g_item.to_bytes()?;
+            let index_g = addr_to_index_gadget::<F, P, CRHVar>(pp_g, &addr_bytes_g)?;
+            let leaf_bytes_g = leaf_bytes_gadget(
+                &addr_bytes_g,
+                &data_g_item.encode_leaf()?.to_bytes()?,
+                &lookup_proof_g_item.empty,
+            )?;
+            let leaf_hash_g = hash_leaf_or_empty_gadget::<P, CRHVar, F>(
+                pp_g,
+                &leaf_bytes_g,
+                &lookup_proof_g_item.empty,
+            )?;
+
+            let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+            lookup_proof_g_item
+                .path
+                .conditionally_check_leaf_hash_with_index(
+                    pp_g,
+                    &rh_g,
+                    leaf_hash_g,
+                    &index_g,
+                    &should_enforce_item,
+                )?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_multi_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr: &[Addr],
+        data_g: &[DataVar],
+        multi_lookup_proof_g: &Self::MultiLookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        if addr.len() != data_g.len() || data_g.len() != multi_lookup_proof_g.empty.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // The addresses are fixed in the circuit, so the indices are computed natively.
+        let mut leaf_hashes_g = Vec::with_capacity(addr.len());
+        let mut indices = Vec::with_capacity(addr.len());
+        for ((addr_item, data_g_item), empty_g) in addr
+            .iter()
+            .zip(data_g.iter())
+            .zip(multi_lookup_proof_g.empty.iter())
+        {
+            let mut addr_writer = Cursor::new(Vec::<u8>::new());
+            addr_item
+                .write(&mut addr_writer)
+                .map_err(|_| SynthesisError::Unsatisfiable)?;
+            let addr_bytes_g = UInt8::constant_vec(&addr_writer.into_inner());
+
+            let leaf_bytes_g = leaf_bytes_gadget(
+                &addr_bytes_g,
+                &data_g_item.encode_leaf()?.to_bytes()?,
+                empty_g,
+            )?;
+            leaf_hashes_g.push(hash_leaf_or_empty_gadget::<P, CRHVar, F>(
+                pp_g,
+                &leaf_bytes_g,
+                empty_g,
+            )?);
+            indices.push(
+                addr_to_index::<P, Addr>(pp_g, addr_item)
+                    .map_err(|_| SynthesisError::Unsatisfiable)?,
+            );
+        }
+
+        multi_lookup_proof_g
+            .multi_path
+            .conditionally_check_leaf_hashes_with_indices(
+                pp_g,
+                rh_g,
+                &leaf_hashes_g,
+                &indices,
+                &Boolean::constant(true),
+            )
+    }
+
+    fn verify_absence_gadget<Data: MerkleLeaf<F> + Clone + Default>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        absence_proof_g_wrapped: &Self::AbsenceProofVar,
+    ) -> Result<(), SynthesisError> {
+        let absence_proof_g = &absence_proof_g_wrapped.0;
+
+        assert_eq!(
+            addr_g.len(),
+            absence_proof_g.len(),
+            "the address len {} does not equal the proof len {}",
+            addr_g.len(),
+            absence_proof_g.len()
+        );
+
+        for (addr_g_item, absence_proof_g_item) in addr_g.iter().zip(absence_proof_g.iter()) {
+            let index_g = addr_to_index_gadget::<F, P, CRHVar>(pp_g, &addr_g_item.to_bytes()?)?;
+
+            absence_proof_g_item
+                .check_absence_with_index::<HashedLeaf<Addr, EncodedLeaf<F, Data>>>(
+                    pp_g, &rh_g, &index_g,
+                )?;
+        }
+
+        Ok(())
+    }
+
+    fn conditionally_verify_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_modify_gadget(
+            cs,
+            pp_g,
+            rh_old_g,
+            rh_new_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            modify_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        let modify_proof_g = &modify_proof_g_wrapped.0;
+
+        assert_eq!(addr_g.len(), data_g.len());
+        assert_eq!(data_g.len(), active_g.len());
+        assert_eq!(data_g.len(), modify_proof_g.len());
+
+        let mut last_hash_g: Self::DigestVar = rh_old_g.clone();
+
+        for (((addr_g_item, data_g_item), active_g_item), modify_proof_g_item) in addr_g
+            .iter()
+            .zip(data_g.iter())
+            .zip(active_g.iter())
+            .zip(modify_proof_g.iter())
+        {
+            let addr_bytes_g = addr_g_item.to_bytes()?;
+            let index_g = addr_to_index_gadget::<F, P, CRHVar>(pp_g, &addr_bytes_g)?;
+            let data_bytes_g = data_g_item.encode_leaf()?.to_bytes()?;
+            let leaf_bytes_g =
+                leaf_bytes_gadget(&addr_bytes_g, &data_bytes_g, &Boolean::constant(false))?;
+
+            let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+            // The old data has the length of the new data, where a default proof is padded with zeros.
+            let mut old_data = modify_proof_g_item.old_data.clone();
+            old_data.resize(data_bytes_g.len(), 0u8);
+            let mode = if cs == ConstraintSystemRef::None {
+                AllocationMode::Constant
+            } else {
+                AllocationMode::Witness
+            };
+            let mut old_data_bytes_g = Vec::with_capacity(old_data.len());
+            for byte in old_data.iter() {
+                old_data_bytes_g.push(UInt8::new_variable(
+                    ark_relations::ns!(cs, "old_data"),
+                    || Ok(*byte),
+                    mode,
+                )?);
+            }
+
+            // The old leaf must be empty or hold the same address.
+            let old_leaf_bytes_g =
+                leaf_bytes_gadget(&addr_bytes_g, &old_data_bytes_g, &modify_proof_g_item.empty)?;
+            let old_leaf_hash_g = hash_leaf_or_empty_gadget::<P, CRHVar, F>(
+                pp_g,
+                &old_leaf_bytes_g,
+                &modify_proof_g_item.empty,
+            )?;
+
+            let modify_proof_type_g = &modify_proof_g_item.modify_proof;
+            old_leaf_hash_g.conditional_enforce_equal(
+                modify_proof_type_g.modify_proof_g.old_leaf_hash(),
+                &should_enforce_item,
+            )?;
+
+            modify_proof_type_g
+                .modify_proof_g
+                .conditionally_check_update(
+                    pp_g,
+                    &last_hash_g,
+                    &modify_proof_type_g.new_digest_g,
+                    leaf_bytes_g.as_slice(),
+                    &index_g,
+                    &should_enforce_item,
+                )?;
+
+            /* an inactive entry leaves the digest unchanged */
+            last_hash_g = Self::DigestVar::conditionally_select(
+                &active_g_item,
+                &modify_proof_type_g.new_digest_g,
+                &last_hash_g,
+            )?;
+        }
+
+        last_hash_g.conditional_enforce_equal(rh_new_g, &should_enforce)?;
+
+        Ok(())
+    }
+
+    fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(HashedAddrPath::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(HashedAddrTwoPaths::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn pad_lookup_proof(proof: &mut Self::LookupProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(HashedAddrPath::<P>::default());
+        }
+        Ok(())
+    }
+
+    fn pad_modify_proof(proof: &mut Self::ModifyProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(HashedAddrTwoPaths::<P>::default());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// error type for the hashed-address Merkle tree
+pub enum HashedAddrMTError {
+    /// the index is already taken by a different address
+    AddressCollision(u64),
+    /// the data of an occupied index is missing
+    MissingData(u64),
+    /// the height is below `MIN_HASHED_ADDR_TREE_HEIGHT`
+    HeightTooSmall(u64),
+}
+
+impl core::fmt::Display for HashedAddrMTError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            HashedAddrMTError::AddressCollision(index) => {
+                format!("the index {} is taken by a different address", index)
+            }
+            HashedAddrMTError::MissingData(index) => {
+                format!("the data of the index {} is missing", index)
+            }
+            HashedAddrMTError::HeightTooSmall(height) => format!(
+                "the height {} is below {}, which leaves the indices open to grinding",
+                height, MIN_HASHED_ADDR_TREE_HEIGHT
+            ),
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl ark_std::error::Error for HashedAddrMTError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn ark_std::error::Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,1022 @@
+//! An append-only Merkle tree for the history, where every write must be at a location after
+//! all the locations written so far (locations may be skipped).
+//!
+//! Everything to the right of the next location is empty, so an append proof only needs the
+//! siblings on the left (the frontier), and the verifier fills in the empty hashes on the right
+//! itself. The proof also shows that the location was empty, so the history cannot be rewritten.
+//! The empty leaf does not depend on the data type, which lets the gadget use the empty hashes as
+//! constants; written leaves are hashed as `1 || data` and the empty leaf as `0`, so an empty
+//! location cannot be looked up, only proven absent.
+//!
+//! The tree only stores the nodes on the paths of the written locations, so skipping locations
+//! costs nothing.
+//!
+//! The only saving over `SparseMT` is in storage. In the circuit, an append still hashes the old
+//! path from the empty leaf and the new path from the written leaf, both `HEIGHT - 1` levels
+//! high, so it costs as many constraints as a modification of a sparse tree of the same height,
+//! plus one selection per level to fill in the empty siblings on the right.
+use crate::building_blocks::crh::{CRHforMerkleTree, CRHforMerkleTreeGadget};
+use crate::building_blocks::mt::{
+    encode_leaf, encode_leaves,
+    merkle_sparse_tree::{
+        constraints::{hash_inner_node_gadget, hash_leaf_gadget, MerkleSparseTreeMultiPathVar},
+        gen_empty_hashes, hash_inner_node, hash_leaf, MerkleSparseTreeConfig,
+        MerkleSparseTreeError, MerkleSparseTreeMultiPath, MerkleSparseTreePath,
+    },
+    LookupProofVar, MerkleLeaf, MerkleLeafGadget, SparseMTError, MT,
+};
+use crate::{
+    gadgets::{AllocVar, Boolean, CondSelectGadget, EqGadget, ToBytesGadget, UInt64, UInt8},
+    Borrow, Error, PhantomData, PrimeField, SynthesisError, ToBytes, Vec,
+};
+use ark_r1cs_std::alloc::AllocationMode;
+use ark_relations::r1cs::{ConstraintSystemRef, Namespace};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::collections::{BTreeMap, BTreeSet};
+use ark_std::format;
+use ark_std::rand::{CryptoRng, Rng};
+use ark_std::{
+    io::{Read, Result as IoResult, Write},
+    vec,
+};
+
+/// Append-only Merkle tree
+pub struct IncrementalMT<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+> {
+    f_phantom: PhantomData<F>,
+    tree_config_phantom: PhantomData<P>,
+    crh_gadget_phantom: PhantomData<CRHVar>,
+}
+
+/// The empty leaf, which is hashed as `0`
+#[derive(Default)]
+struct EmptyLeaf;
+
+impl ToBytes for EmptyLeaf {
+    fn write<W: Write>(&self, writer: W) -> IoResult<()> {
+        0u8.write(writer)
+    }
+}
+
+/// A written leaf, which is hashed as `1 || data`
+struct AppendLeaf<'a, L>(&'a L);
+
+impl<'a, L: ToBytes> ToBytes for AppendLeaf<'a, L> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        1u8.write(&mut writer)?;
+        self.0.write(&mut writer)
+    }
+}
+
+/// Append-only Merkle tree, storing the nodes on the paths of the written locations
+pub struct MerkleIncrementalTree<P: MerkleSparseTreeConfig> {
+    /// the stored nodes of each level, from the leaves to the root; the others are empty
+    pub levels: Vec<BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>>,
+    parameters: <P::H as CRHforMerkleTree>::Parameters,
+    empty_hashes: Vec<<P::H as CRHforMerkleTree>::Output>,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for MerkleIncrementalTree<P> {
+    fn clone(&self) -> Self {
+        MerkleIncrementalTree {
+            levels: self.levels.clone(),
+            parameters: self.parameters.clone(),
+            empty_hashes: self.empty_hashes.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleIncrementalTree<P> {
+    /// tree height
+    pub const HEIGHT: u64 = P::HEIGHT;
+
+    /// obtain an empty tree
+    pub fn blank(parameters: <P::H as CRHforMerkleTree>::Parameters) -> Result<Self, Error> {
+        let empty_hashes = gen_empty_hashes::<P, EmptyLeaf>(&parameters, EmptyLeaf)?;
+
+        Ok(MerkleIncrementalTree {
+            levels: vec![BTreeMap::new(); P::HEIGHT as usize],
+            parameters,
+            empty_hashes,
+        })
+    }
+
+    /// obtain the root hash
+    pub fn root(&self) -> <P::H as CRHforMerkleTree>::Output {
+        self.node_hash((P::HEIGHT - 1) as usize, 0)
+    }
+
+    /// the first location that can be written, i.e., one after the last written location
+    pub fn next_index(&self) -> u64 {
+        match self.levels[0].keys().next_back() {
+            Some(index) => index + 1,
+            None => 0,
+        }
+    }
+
+    /// write the nodes of the tree, without the CRH parameters
+    pub fn serialize_nodes<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        for level in self.levels.iter() {
+            (level.len() as u64).serialize(&mut writer)?;
+            for (index, hash) in level.iter() {
+                index.serialize(&mut writer)?;
+                hash.serialize(&mut writer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// restore a tree from the nodes written by `serialize_nodes` and the CRH parameters
+    pub fn deserialize_nodes<R: Read>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        mut reader: R,
+    ) -> Result<Self, Error> {
+        let mut levels = Vec::with_capacity(P::HEIGHT as usize);
+        for _ in 0..P::HEIGHT {
+            let len = u64::deserialize(&mut reader)?;
+            let mut level = BTreeMap::new();
+            for _ in 0..len {
+                let index = u64::deserialize(&mut reader)?;
+                let hash = <P::H as CRHforMerkleTree>::Output::deserialize(&mut reader)?;
+                level.insert(index, hash);
+            }
+            levels.push(level);
+        }
+
+        let empty_hashes = gen_empty_hashes::<P, EmptyLeaf>(&parameters, EmptyLeaf)?;
+
+        Ok(MerkleIncrementalTree {
+            levels,
+            parameters,
+            empty_hashes,
+        })
+    }
+
+    /// generate a membership proof (does not check the data point)
+    pub fn generate_membership_proof(&self, index: u64) -> Result<MerkleSparseTreePath<P>, Error> {
+        if index >= 1u64 << (P::HEIGHT - 1) {
+            return Err(IncrementalMTError::IndexOutOfRange(index).into());
+        }
+
+        // The sibling at each level is the other child of the same parent.
+        let mut path = Vec::with_capacity((P::HEIGHT - 1) as usize);
+        for level in 0..(P::HEIGHT - 1) as usize {
+            path.push(self.node_hash(level, (index >> level) ^ 1));
+        }
+
+        Ok(MerkleSparseTreePath { path })
+    }
+
+    /// generate an absence proof, showing that the location has never been written
+    pub fn generate_absence_proof(&self, index: u64) -> Result<MerkleSparseTreePath<P>, Error> {
+        if self.node_hash(0, index) != self.empty_hashes[0] {
+            return Err(MerkleSparseTreeError::NonEmptyLeaf(index).into());
+        }
+
+        self.generate_membership_proof(index)
+    }
+
+    /// generate a multi-opening for several locations (does not check the data points)
+    pub fn generate_multi_membership_proof(
+        &self,
+        indices: &[u64],
+    ) -> Result<MerkleSparseTreeMultiPath<P>, Error> {
+        let mut current_level: BTreeSet<u64> = BTreeSet::new();
+        for index in indices {
+            if *index >= 1u64 << (P::HEIGHT - 1) {
+                return Err(IncrementalMTError::IndexOutOfRange(*index).into());
+            }
+            current_level.insert(*index);
+        }
+
+        // Iterate from the leaves up to the root, in the same order as the sparse tree.
+        let mut auxiliary = Vec::new();
+        for level in 0..(P::HEIGHT - 1) as usize {
+            let mut next_level: BTreeSet<u64> = BTreeSet::new();
+            for current_node in &current_level {
+                let sibling_node = current_node ^ 1;
+                if !current_level.contains(&sibling_node) {
+                    auxiliary.push(self.node_hash(level, sibling_node));
+                }
+
+                next_level.insert(current_node >> 1);
+            }
+            current_level = next_level;
+        }
+
+        Ok(MerkleSparseTreeMultiPath { auxiliary })
+    }
+
+    /// append a leaf at a location after all the written ones, and provide the siblings
+    pub fn append_and_prove<L: ToBytes>(
+        &mut self,
+        index: u64,
+        new_leaf: &L,
+    ) -> Result<MerkleSparseTreePath<P>, Error> {
+        if index < self.next_index() {
+            return Err(IncrementalMTError::NotAppend(index).into());
+        }
+
+        let path = self.generate_membership_proof(index)?;
+
+        // The skipped locations stay empty, so only the path of the new leaf is stored.
+        let leaf_hash = hash_leaf::<P, _>(&self.parameters, &AppendLeaf(new_leaf))?;
+        self.set_node(0, index, leaf_hash);
+
+        let mut current_node = index;
+        for level in 0..(P::HEIGHT - 1) as usize {
+            let left_node = current_node & !1;
+            let hash = hash_inner_node::<P>(
+                &self.parameters,
+                &self.node_hash(level, left_node),
+                &self.node_hash(level, left_node + 1),
+            )?;
+
+            current_node >>= 1;
+            self.set_node(level + 1, current_node, hash);
+        }
+
+        Ok(path)
+    }
+
+    /// check if the tree is structurally valid
+    pub fn validate(&self) -> Result<bool, Error> {
+        if self.levels.len() != P::HEIGHT as usize {
+            return Ok(false);
+        }
+
+        if self.levels[0]
+            .keys()
+            .any(|index| *index >= 1u64 << (P::HEIGHT - 1))
+        {
+            return Ok(false);
+        }
+
+        for level in 0..(P::HEIGHT - 1) as usize {
+            // Each level stores exactly the parents of the stored nodes of the level below.
+            let parent_nodes: BTreeSet<u64> =
+                self.levels[level].keys().map(|index| index >> 1).collect();
+            if !self.levels[level + 1].keys().eq(parent_nodes.iter()) {
+                return Ok(false);
+            }
+
+            for (parent_node, parent_hash) in self.levels[level + 1].iter() {
+                let left_node = 2 * parent_node;
+                let hash = hash_inner_node::<P>(
+                    &self.parameters,
+                    &self.node_hash(level, left_node),
+                    &self.node_hash(level, left_node + 1),
+                )?;
+
+                if hash != *parent_hash {
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// clear the tree
+    pub fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            level.clear();
+        }
+    }
+
+    /// Returns the hash of a node, which is the empty hash of its level if not stored.
+    fn node_hash(&self, level: usize, index: u64) -> <P::H as CRHforMerkleTree>::Output {
+        match self.levels[level].get(&index) {
+            Some(x) => x.clone(),
+            None => self.empty_hashes[level].clone(),
+        }
+    }
+
+    /// Sets a node.
+    fn set_node(&mut self, level: usize, index: u64, hash: <P::H as CRHforMerkleTree>::Output) {
+        self.levels[level].insert(index, hash);
+    }
+}
+
+/// A single append proof
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct AppendProofType<P: MerkleSparseTreeConfig> {
+    /// the new digest
+    new_digest: <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output,
+    /// the siblings, of which only the left ones are used
+    path: MerkleSparseTreePath<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for AppendProofType<P> {
+    fn clone(&self) -> Self {
+        AppendProofType {
+            new_digest: self.new_digest.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for AppendProofType<P> {
+    fn default() -> Self {
+        AppendProofType {
+            new_digest: <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output::default(),
+            path: MerkleSparseTreePath::<P>::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for AppendProofType<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.new_digest.write(&mut writer)?;
+        self.path.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> AppendProofType<P> {
+    /// verify the append proof, given the old digest
+    fn verify<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        empty_hashes: &[<P::H as CRHforMerkleTree>::Output],
+        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        index: u64,
+    ) -> Result<bool, Error> {
+        if self.path.path.len() != (P::HEIGHT - 1) as usize {
+            return Ok(false);
+        }
+
+        // Replace the right siblings by the empty hashes.
+        let mut path = Vec::with_capacity(self.path.path.len());
+        for (level, sibling) in self.path.path.iter().enumerate() {
+            if (index >> level) & 1 == 1 {
+                path.push(sibling.clone());
+            } else {
+                path.push(empty_hashes[level].clone());
+            }
+        }
+        let path = MerkleSparseTreePath::<P> { path };
+
+        Ok(
+            path.verify_with_index(parameters, &self.new_digest, &AppendLeaf(leaf), index)?
+                && path.verify_absence_with_index::<EmptyLeaf>(parameters, old_root_hash, index)?,
+        )
+    }
+}
+
+struct AppendProofTypeVar<
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    F: PrimeField,
+> {
+    new_digest_g: CRHVar::OutputVar,
+    path_g: Vec<CRHVar::OutputVar>,
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AllocVar<AppendProofType<P>, F> for AppendProofTypeVar<P, CRHVar, F>
+{
+    fn new_variable<T: Borrow<AppendProofType<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let val = f()?;
+        let append_proof_type = val.borrow().clone();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let new_digest_g = CRHVar::OutputVar::new_variable(
+            ark_relations::ns!(cs, "append_proof_type_gadget_new_digest"),
+            || Ok(&append_proof_type.new_digest),
+            mode,
+        )?;
+
+        let mut path_g = Vec::new();
+        for sibling in append_proof_type.path.path.iter() {
+            path_g.push(CRHVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "sibling"),
+                || Ok(sibling.clone()),
+                mode,
+            )?);
+        }
+
+        Ok(AppendProofTypeVar {
+            new_digest_g,
+            path_g,
+        })
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AppendProofTypeVar<P, CRHVar, F>
+{
+    /// conditionally check the append proof, given the old digest
+    ///
+    /// the old and the new paths share their siblings but not their nodes, so both are hashed
+    fn conditionally_check_append(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        empty_hashes_g: &[CRHVar::OutputVar],
+        old_root: &CRHVar::OutputVar,
+        new_leaf_hash: CRHVar::OutputVar,
+        index: &UInt64<F>,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        assert_eq!(self.path_g.len(), (P::HEIGHT - 1) as usize);
+
+        // Both paths start from the same location, the old one from the empty leaf.
+        let mut new_hash = new_leaf_hash;
+        let mut old_hash = empty_hashes_g[0].clone();
+        let index_bits = index.to_bits_le();
+        for (i, sibling_hash) in self.path_g.iter().enumerate() {
+            // A right sibling is always empty, so only a left sibling comes from the proof.
+            let previous_is_left = index_bits[i].not();
+            let sibling_hash = CRHVar::OutputVar::conditionally_select(
+                &previous_is_left,
+                &empty_hashes_g[i],
+                sibling_hash,
+            )?;
+
+            new_hash =
+                Self::hash_with_sibling(parameters, &previous_is_left, &new_hash, &sibling_hash)?;
+            old_hash =
+                Self::hash_with_sibling(parameters, &previous_is_left, &old_hash, &sibling_hash)?;
+        }
+
+        self.new_digest_g
+            .conditional_enforce_equal(&new_hash, should_enforce)?;
+        old_root.conditional_enforce_equal(&old_hash, should_enforce)
+    }
+
+    /// Returns the hash of the parent, given a node and its sibling.
+    fn hash_with_sibling(
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        previous_is_left: &Boolean<F>,
+        previous_hash: &CRHVar::OutputVar,
+        sibling_hash: &CRHVar::OutputVar,
+    ) -> Result<CRHVar::OutputVar, SynthesisError> {
+        let left_hash =
+            CRHVar::OutputVar::conditionally_select(previous_is_left, previous_hash, sibling_hash)?;
+        let right_hash =
+            CRHVar::OutputVar::conditionally_select(previous_is_left, sibling_hash, previous_hash)?;
+
+        hash_inner_node_gadget::<P, CRHVar, F>(parameters, &left_hash, &right_hash)
+    }
+}
+
+/// append proof gadgets
+pub struct AppendProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<AppendProofTypeVar<P, CRHVar, F>>);
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<AppendProofType<P>>, F> for AppendProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<AppendProofType<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(AppendProofTypeVar::<P, CRHVar, F>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(AppendProofVar { 0: vec })
+    }
+}
+
+/// Returns the bytes of a written leaf in the circuit.
+fn append_leaf_bytes_gadget<F: PrimeField, DataVar: MerkleLeafGadget<F>>(
+    data_g: &DataVar,
+) -> Result<Vec<UInt8<F>>, SynthesisError> {
+    let mut leaf_bytes_g = vec![UInt8::constant(1u8)];
+    leaf_bytes_g.extend_from_slice(&data_g.encode_leaf()?.to_bytes()?);
+
+    Ok(leaf_bytes_g)
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    MT<F, u64, UInt64<F>> for IncrementalMT<F, P, CRHVar>
+{
+    type PublicParameters = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Parameters;
+    type Digest = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output;
+    type T = MerkleIncrementalTree<P>;
+    type LookupProof = Vec<MerkleSparseTreePath<P>>;
+    type ModifyProof = Vec<AppendProofType<P>>;
+    type AbsenceProof = Vec<MerkleSparseTreePath<P>>;
+    type MultiLookupProof = MerkleSparseTreeMultiPath<P>;
+
+    type DigestVar = CRHVar::OutputVar;
+    type LookupProofVar = LookupProofVar<F, P, CRHVar>;
+    type ModifyProofVar = AppendProofVar<F, P, CRHVar>;
+    type AbsenceProofVar = LookupProofVar<F, P, CRHVar>;
+    type MultiLookupProofVar = MerkleSparseTreeMultiPathVar<P, CRHVar, F>;
+
+    fn setup<R: Rng + CryptoRng>(rng: &mut R) -> Result<Self::PublicParameters, Error> {
+        <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::setup(rng)
+    }
+
+    fn new<L: Default + MerkleLeaf<F>>(pp: &Self::PublicParameters) -> Result<Self::T, Error> {
+        MerkleIncrementalTree::blank(pp.clone())
+    }
+
+    fn root(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<Self::Digest, Error> {
+        Ok(tree.root())
+    }
+
+    fn validate(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<bool, Error> {
+        tree.validate()
+    }
+
+    fn lookup(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::LookupProof, Error> {
+        let mut lookup_proof: Self::LookupProof = Vec::with_capacity(addr.len());
+
+        for i in addr {
+            lookup_proof.push(tree.generate_membership_proof(*i)?);
+        }
+
+        Ok(lookup_proof)
+    }
+
+    fn verify_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        lookup_proof: &Self::LookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != lookup_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        for ((addr_item, data_item), lookup_proof_item) in
+            addr.iter().zip(data.iter()).zip(lookup_proof.iter())
+        {
+            if !lookup_proof_item.verify_with_index(pp, rh, &AppendLeaf(data_item), *addr_item)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn multi_lookup(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::MultiLookupProof, Error> {
+        tree.generate_multi_membership_proof(addr)
+    }
+
+    fn verify_multi_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        multi_lookup_proof: &Self::MultiLookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        let data = encode_leaves(data)?;
+        let leaves: Vec<AppendLeaf<_>> = data.iter().map(AppendLeaf).collect();
+        multi_lookup_proof.verify_with_indices(pp, rh, &leaves, addr)
+    }
+
+    fn prove_absence(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::AbsenceProof, Error> {
+        let mut absence_proof: Self::AbsenceProof = Vec::with_capacity(addr.len());
+
+        for i in addr {
+            absence_proof.push(tree.generate_absence_proof(*i)?);
+        }
+
+        Ok(absence_proof)
+    }
+
+    fn verify_absence<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        absence_proof: &Self::AbsenceProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != absence_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        // The empty leaf does not depend on the data type.
+        for (addr_item, absence_proof_item) in addr.iter().zip(absence_proof.iter()) {
+            if !absence_proof_item.verify_absence_with_index::<EmptyLeaf>(pp, rh, *addr_item)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn verify_modify<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh_old: &Self::Digest,
+        rh_new: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        modify_proof: &Self::ModifyProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != modify_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        let empty_hashes = gen_empty_hashes::<P, EmptyLeaf>(pp, EmptyLeaf)?;
+        let mut last_hash: Self::Digest = rh_old.clone();
+
+        for ((addr_item, data_item), modify_proof_item) in
+            addr.iter().zip(data.iter()).zip(modify_proof.iter())
+        {
+            if !modify_proof_item.verify(pp, &empty_hashes, &last_hash, data_item, *addr_item)? {
+                return Ok(false);
+            }
+
+            last_hash = modify_proof_item.new_digest.clone();
+        }
+
+        Ok(last_hash == *rh_new)
+    }
+
+    fn _new_with_map<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        map: &BTreeMap<u64, Data>,
+    ) -> Result<Self::T, Error> {
+        let mut tree = MerkleIncrementalTree::blank(pp.clone())?;
+
+        // The map is ordered, so the locations are appended in order.
+        for (index, data) in map.iter() {
+            tree.append_and_prove(*index, &encode_leaf(data)?)?;
+        }
+
+        Ok(tree)
+    }
+
+    fn _modify_and_apply<Data: MerkleLeaf<F> + Clone + Default>(
+        _pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        addr: &[u64],
+        data: &[Data],
+    ) -> Result<(Self::Digest, Self::ModifyProof), Error> {
+        if addr.len() != data.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        // Check the order first, so that a rejected batch leaves the tree untouched.
+        let mut next_index = tree.next_index();
+        for addr_item in addr {
+            if *addr_item < next_index {
+                return Err(IncrementalMTError::NotAppend(*addr_item).into());
+            }
+            next_index = *addr_item + 1;
+        }
+        let data = encode_leaves(data)?;
+
+        let mut modify_proof: Self::ModifyProof = Vec::with_capacity(addr.len());
+        for (addr_item, data_item) in addr.iter().zip(data.iter()) {
+            let path = tree.append_and_prove(*addr_item, data_item)?;
+            modify_proof.push(AppendProofType {
+                new_digest: tree.root(),
+                path,
+            });
+        }
+
+        Ok((tree.root(), modify_proof))
+    }
+
+    fn clear(_pp: &Self::PublicParameters, tree: &mut Self::T) -> Result<(), Error> {
+        tree.clear();
+
+        Ok(())
+    }
+
+    fn serialize_tree<W: Write>(tree: &Self::T, writer: W) -> Result<(), Error> {
+        tree.serialize_nodes(writer)?;
+
+        Ok(())
+    }
+
+    fn deserialize_tree<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &Self::PublicParameters,
+        reader: R,
+    ) -> Result<Self::T, Error> {
+        MerkleIncrementalTree::<P>::deserialize_nodes(pp.clone(), reader)
+    }
+
+    fn verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            lookup_proof_g_wrapped,
+            &Boolean::constant(true),
+        )
+    }
+
+    fn conditionally_verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            lookup_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        let lookup_proof_g = &lookup_proof_g_wrapped.0;
+
+        assert_eq!(
+            addr_g.len(),
+            data_g.len(),
+            "the address len {} does not equal the data len {}",
+            addr_g.len(),
+            data_g.len()
+        );
+        assert_eq!(
+            data_g.len(),
+            active_g.len(),
+            "the data len {} does not equal the flag len {}",
+            data_g.len(),
+            active_g.len()
+        );
+        assert_eq!(
+            data_g.len(),
+            lookup_proof_g.len(),
+            "the data len {} does not equal the proof len {}",
+            data_g.len(),
+            lookup_proof_g.len()
+        );
+
+        for (((addr_g_item, data_g_item), active_g_item), lookup_proof_g_item) in addr_g
+            .iter()
+            .zip(data_g.iter())
+            .zip(active_g.iter())
+            .zip(lookup_proof_g.iter())
+        {
+            let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+            lookup_proof_g_item.conditionally_check_membership_with_index(
+                pp_g,
+                rh_g,
+                append_leaf_bytes_gadget(data_g_item)?.as_slice(),
+                addr_g_item,
+                &should_enforce_item,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_multi_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr: &[u64],
+        data_g: &[DataVar],
+        multi_lookup_proof_g: &Self::MultiLookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        assert_eq!(
+            addr.len(),
+            data_g.len(),
+            "the address len {} does not equal the data len {}",
+            addr.len(),
+            data_g.len()
+        );
+
+        let mut leaves_bytes_g = Vec::with_capacity(data_g.len());
+        for data_g_item in data_g.iter() {
+            leaves_bytes_g.push(append_leaf_bytes_gadget(data_g_item)?);
+        }
+
+        multi_lookup_proof_g.check_membership_with_indices(pp_g, rh_g, &leaves_bytes_g, addr)
+    }
+
+    fn verify_absence_gadget<Data: MerkleLeaf<F> + Clone + Default>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        absence_proof_g_wrapped: &Self::AbsenceProofVar,
+    ) -> Result<(), SynthesisError> {
+        let absence_proof_g = &absence_proof_g_wrapped.0;
+
+        assert_eq!(
+            addr_g.len(),
+            absence_proof_g.len(),
+            "the address len {} does not equal the proof len {}",
+            addr_g.len(),
+            absence_proof_g.len()
+        );
+
+        for (addr_g_item, absence_proof_g_item) in addr_g.iter().zip(absence_proof_g.iter()) {
+            absence_proof_g_item.check_absence_with_index::<EmptyLeaf>(pp_g, rh_g, addr_g_item)?;
+        }
+
+        Ok(())
+    }
+
+    fn conditionally_verify_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_modify_gadget(
+            cs,
+            pp_g,
+            rh_old_g,
+            rh_new_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            modify_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        let modify_proof_g = &modify_proof_g_wrapped.0;
+
+        assert_eq!(addr_g.len(), data_g.len());
+        assert_eq!(data_g.len(), active_g.len());
+        assert_eq!(data_g.len(), modify_proof_g.len());
+
+        // The empty hashes are fixed by the parameters, so they are constants.
+        let empty_hashes = gen_empty_hashes::<P, EmptyLeaf>(pp_g, EmptyLeaf)
+            .map_err(|_| SynthesisError::Unsatisfiable)?;
+        let mut empty_hashes_g = Vec::with_capacity(empty_hashes.len());
+        for empty_hash in empty_hashes.iter() {
+            empty_hashes_g.push(CRHVar::OutputVar::new_constant(
+                ConstraintSystemRef::None,
+                empty_hash,
+            )?);
+        }
+
+        let mut last_hash_g: Self::DigestVar = rh_old_g.clone();
+
+        for (((addr_g_item, data_g_item), active_g_item), modify_proof_g_item) in addr_g
+            .iter()
+            .zip(data_g.iter())
+            .zip(active_g.iter())
+            .zip(modify_proof_g.iter())
+        {
+            let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+            let new_leaf_hash_g =
+                hash_leaf_gadget::<P, CRHVar, F>(pp_g, &append_leaf_bytes_gadget(data_g_item)?)?;
+            modify_proof_g_item.conditionally_check_append(
+                pp_g,
+                &empty_hashes_g,
+                &last_hash_g,
+                new_leaf_hash_g,
+                addr_g_item,
+                &should_enforce_item,
+            )?;
+
+            // An inactive entry leaves the digest unchanged.
+            last_hash_g = Self::DigestVar::conditionally_select(
+                &active_g_item,
+                &modify_proof_g_item.new_digest_g,
+                &last_hash_g,
+            )?;
+        }
+
+        last_hash_g.conditional_enforce_equal(rh_new_g, &should_enforce)?;
+
+        Ok(())
+    }
+
+    fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(MerkleSparseTreePath::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(AppendProofType::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn pad_lookup_proof(proof: &mut Self::LookupProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(MerkleSparseTreePath::<P>::default());
+        }
+        Ok(())
+    }
+
+    fn pad_modify_proof(proof: &mut Self::ModifyProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(AppendProofType::<P>::default());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// error type for the append-only Merkle tree
+pub enum IncrementalMTError {
+    /// the location is not after the last written one
+    NotAppend(u64),
+    /// the location is outside the tree
+    IndexOutOfRange(u64),
+}
+
+impl core::fmt::Display for IncrementalMTError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            IncrementalMTError::NotAppend(index) => {
+                format!("index {} is not after the last written one", index)
+            }
+            IncrementalMTError::IndexOutOfRange(index) => {
+                format!("index {} is outside the tree", index)
+            }
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl ark_std::error::Error for IncrementalMTError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn ark_std::error::Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,1519 @@
+//! A Merkle mountain range for the history, where the locations are written in increasing order
+//! (locations may be skipped).
+//!
+//! The leaves are kept in the order they are written, and each leaf is hashed together with its
+//! location. The leaves form perfect binary trees of decreasing heights, whose roots are the
+//! peaks; the root bags the peaks together with the number of leaves and the next location that
+//! can be written. The path from a leaf to its peak never changes when more leaves are appended,
+//! so an old lookup proof is a prefix of the new one and can be extended with the missing
+//! siblings and the new peaks (see `MerkleMountainRange::extend_membership_proof`).
+//!
+//! A skipped location has no leaf, so it can neither be looked up nor proven absent; absence can
+//! only be proven for the locations after the last written one.
+use crate::building_blocks::crh::{CRHforMerkleTree, CRHforMerkleTreeGadget};
+use crate::building_blocks::mt::{
+    encode_leaf, encode_leaves, encode_leaves_gadget,
+    merkle_sparse_tree::{
+        constraints::{hash_inner_node_gadget, hash_leaf_gadget as hash_leaf_bytes_gadget},
+        hash_empty, hash_inner_node, hash_leaf, MerkleSparseTreeConfig,
+    },
+    MerkleLeaf, MerkleLeafGadget, SparseMTError, MT,
+};
+use crate::{
+    gadgets::{AllocVar, Boolean, CondSelectGadget, EqGadget, FpVar, ToBytesGadget, UInt64, UInt8},
+    Borrow, Error, PhantomData, PrimeField, SynthesisError, ToBytes, Vec,
+};
+use ark_r1cs_std::alloc::AllocationMode;
+use ark_relations::r1cs::{ConstraintSystemRef, Namespace};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::cmp::Ordering;
+use ark_std::collections::BTreeMap;
+use ark_std::rand::{CryptoRng, Rng};
+use ark_std::{fmt::Debug, format, string::ToString};
+use ark_std::{
+    io::{Read, Result as IoResult, Write},
+    vec,
+};
+
+/// Merkle mountain range
+pub struct MountainRangeMT<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+> {
+    f_phantom: PhantomData<F>,
+    tree_config_phantom: PhantomData<P>,
+    crh_gadget_phantom: PhantomData<CRHVar>,
+}
+
+/// The bag of no peaks, which is hashed as `0`
+#[derive(Default)]
+struct EmptyBag;
+
+impl ToBytes for EmptyBag {
+    fn write<W: Write>(&self, writer: W) -> IoResult<()> {
+        0u8.write(writer)
+    }
+}
+
+/// A leaf, which is hashed as `1 || location || data`
+struct MountainRangeLeaf<'a, L> {
+    location: u64,
+    data: &'a L,
+}
+
+impl<'a, L: ToBytes> ToBytes for MountainRangeLeaf<'a, L> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        1u8.write(&mut writer)?;
+        self.location.write(&mut writer)?;
+        self.data.write(&mut writer)
+    }
+}
+
+/// The size and the next location, which are hashed as `2 || size || next_location`
+struct MountainRangeMeta {
+    size: u64,
+    next_location: u64,
+}
+
+impl ToBytes for MountainRangeMeta {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        2u8.write(&mut writer)?;
+        self.size.write(&mut writer)?;
+        self.next_location.write(&mut writer)
+    }
+}
+
+/// The peaks of a Merkle mountain range, which open its root
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseTreeConfig"),
+    Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct MerkleMountainRangePeaks<P: MerkleSparseTreeConfig> {
+    pub(crate) size: u64,
+    pub(crate) next_location: u64,
+    /// the peaks, from the lowest to the highest
+    pub(crate) peaks: Vec<<P::H as CRHforMerkleTree>::Output>,
+}
+
+impl<P: MerkleSparseTreeConfig> Default for MerkleMountainRangePeaks<P> {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            next_location: 0,
+            peaks: Vec::new(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for MerkleMountainRangePeaks<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.size.write(&mut writer)?;
+        self.next_location.write(&mut writer)?;
+        for peak in &self.peaks {
+            peak.write(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleMountainRangePeaks<P> {
+    /// verify that the peaks open the root
+    pub fn verify(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+    ) -> Result<bool, Error> {
+        // The circuit only has a slot for each height below the tree height.
+        if self.size >> P::HEIGHT != 0 || self.peaks.len() != self.size.count_ones() as usize {
+            return Ok(false);
+        }
+
+        Ok(self.root_hash(parameters)? == *root_hash)
+    }
+
+    /// Returns the root hash, assuming that there is one peak for each bit of the size.
+    fn root_hash(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        let mut bag = hash_empty::<P, EmptyBag>(parameters, EmptyBag)?;
+        for peak in self.peaks.iter() {
+            bag = hash_inner_node::<P>(parameters, peak, &bag)?;
+        }
+
+        let meta_hash = hash_leaf::<P, _>(
+            parameters,
+            &MountainRangeMeta {
+                size: self.size,
+                next_location: self.next_location,
+            },
+        )?;
+
+        hash_inner_node::<P>(parameters, &meta_hash, &bag)
+    }
+
+    /// Returns the peak of the given height, if there is one.
+    fn peak(&self, height: u64) -> Option<&<P::H as CRHforMerkleTree>::Output> {
+        if (self.size >> height) & 1 == 0 {
+            return None;
+        }
+
+        let lower_peaks = (self.size & ((1u64 << height) - 1)).count_ones() as usize;
+        self.peaks.get(lower_peaks)
+    }
+
+    /// Returns the peaks after appending a leaf, where the lowest peaks merge with the new leaf.
+    fn append<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        location: u64,
+        leaf: &L,
+    ) -> Result<Self, Error> {
+        if location < self.next_location {
+            return Err(MountainRangeError::NotAppend(location).into());
+        }
+        let next_location = location.checked_add(1).ok_or(MountainRangeError::Full)?;
+        if (self.size + 1) >> P::HEIGHT != 0 {
+            return Err(MountainRangeError::Full.into());
+        }
+
+        let mut carry = hash_leaf::<P, _>(
+            parameters,
+            &MountainRangeLeaf {
+                location,
+                data: leaf,
+            },
+        )?;
+
+        // The peaks of heights 0, 1, ... up to the lowest unset bit of the size are merged.
+        let merged = self.size.trailing_ones() as usize;
+        for peak in self.peaks[..merged].iter() {
+            carry = hash_inner_node::<P>(parameters, peak, &carry)?;
+        }
+
+        let mut peaks = vec![carry];
+        peaks.extend_from_slice(&self.peaks[merged..]);
+
+        Ok(Self {
+            size: self.size + 1,
+            next_location,
+            peaks,
+        })
+    }
+}
+
+/// A lookup proof, consisting of the position of the leaf, the siblings from the leaf to its
+/// peak, and the peaks
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseTreeConfig"),
+    Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct MerkleMountainRangePath<P: MerkleSparseTreeConfig> {
+    pub(crate) position: u64,
+    pub(crate) path: Vec<<P::H as CRHforMerkleTree>::Output>,
+    pub(crate) peaks: MerkleMountainRangePeaks<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Default for MerkleMountainRangePath<P> {
+    fn default() -> Self {
+        Self {
+            position: 0,
+            path: Vec::new(),
+            peaks: MerkleMountainRangePeaks::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for MerkleMountainRangePath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.position.write(&mut writer)?;
+        for p in &self.path {
+            p.write(&mut writer)?;
+        }
+        self.peaks.write(&mut writer)
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleMountainRangePath<P> {
+    /// verify the lookup proof, given the location
+    pub fn verify_with_location<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        location: u64,
+    ) -> Result<bool, Error> {
+        if !self.peaks.verify(parameters, root_hash)? {
+            return Ok(false);
+        }
+
+        let height = match peak_height(self.position, self.peaks.size) {
+            Some(height) => height,
+            None => return Ok(false),
+        };
+        if self.path.len() != height as usize {
+            return Ok(false);
+        }
+
+        let mut current_hash = hash_leaf::<P, _>(
+            parameters,
+            &MountainRangeLeaf {
+                location,
+                data: leaf,
+            },
+        )?;
+        for (level, sibling_hash) in self.path.iter().enumerate() {
+            current_hash = if (self.position >> level) & 1 == 0 {
+                hash_inner_node::<P>(parameters, &current_hash, sibling_hash)?
+            } else {
+                hash_inner_node::<P>(parameters, sibling_hash, &current_hash)?
+            };
+        }
+
+        Ok(self.peaks.peak(height) == Some(&current_hash))
+    }
+}
+
+/// A single append proof, consisting of the new digest and the peaks before the append
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MerkleMountainRangeAppendProof<P: MerkleSparseTreeConfig> {
+    new_digest: <P::H as CRHforMerkleTree>::Output,
+    old_peaks: MerkleMountainRangePeaks<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for MerkleMountainRangeAppendProof<P> {
+    fn clone(&self) -> Self {
+        MerkleMountainRangeAppendProof {
+            new_digest: self.new_digest.clone(),
+            old_peaks: self.old_peaks.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for MerkleMountainRangeAppendProof<P> {
+    fn default() -> Self {
+        MerkleMountainRangeAppendProof {
+            new_digest: <P::H as CRHforMerkleTree>::Output::default(),
+            old_peaks: MerkleMountainRangePeaks::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for MerkleMountainRangeAppendProof<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.new_digest.write(&mut writer)?;
+        self.old_peaks.write(&mut writer)
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleMountainRangeAppendProof<P> {
+    /// verify the append proof, given the old digest
+    pub fn verify<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        location: u64,
+    ) -> Result<bool, Error> {
+        if !self.old_peaks.verify(parameters, old_root_hash)? {
+            return Ok(false);
+        }
+
+        let new_peaks = match self.old_peaks.append(parameters, location, leaf) {
+            Ok(new_peaks) => new_peaks,
+            Err(_) => return Ok(false),
+        };
+
+        Ok(new_peaks.root_hash(parameters)? == self.new_digest)
+    }
+}
+
+/// Merkle mountain range, storing the complete nodes of each height and the location of each leaf
+///
+/// there is a peak for each height below the tree height, so it holds up to `2^HEIGHT - 1` leaves
+pub struct MerkleMountainRange<P: MerkleSparseTreeConfig> {
+    /// the complete nodes of each height, from the leaves up
+    pub levels: Vec<Vec<<P::H as CRHforMerkleTree>::Output>>,
+    /// the location of each leaf, in the order they are written
+    pub locations: Vec<u64>,
+    parameters: <P::H as CRHforMerkleTree>::Parameters,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for MerkleMountainRange<P> {
+    fn clone(&self) -> Self {
+        MerkleMountainRange {
+            levels: self.levels.clone(),
+            locations: self.locations.clone(),
+            parameters: self.parameters.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleMountainRange<P> {
+    /// obtain an empty tree
+    pub fn blank(parameters: <P::H as CRHforMerkleTree>::Parameters) -> Self {
+        MerkleMountainRange {
+            levels: vec![Vec::new(); P::HEIGHT as usize],
+            locations: Vec::new(),
+            parameters,
+        }
+    }
+
+    /// the number of leaves
+    pub fn size(&self) -> u64 {
+        self.locations.len() as u64
+    }
+
+    /// the first location that can be written, i.e., one after the last written location
+    pub fn next_location(&self) -> u64 {
+        match self.locations.last() {
+            Some(location) => location + 1,
+            None => 0,
+        }
+    }
+
+    /// obtain the peaks
+    pub fn peaks(&self) -> MerkleMountainRangePeaks<P> {
+        let size = self.size();
+
+        // A set bit of the size means that the last node of that height is a peak.
+        let mut peaks = Vec::new();
+        for height in 0..P::HEIGHT as usize {
+            if (size >> height) & 1 == 1 {
+                peaks.push(self.levels[height][(size >> height) as usize - 1].clone());
+            }
+        }
+
+        MerkleMountainRangePeaks {
+            size,
+            next_location: self.next_location(),
+            peaks,
+        }
+    }
+
+    /// obtain the root hash
+    pub fn root(&self) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        self.peaks().root_hash(&self.parameters)
+    }
+
+    /// write the nodes and the locations, without the CRH parameters
+    pub fn serialize_nodes<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        self.levels.serialize(&mut writer)?;
+        self.locations.serialize(&mut writer)
+    }
+
+    /// restore a tree from the nodes written by `serialize_nodes` and the CRH parameters
+    pub fn deserialize_nodes<R: Read>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        mut reader: R,
+    ) -> Result<Self, SerializationError> {
+        let levels = Vec::<Vec<<P::H as CRHforMerkleTree>::Output>>::deserialize(&mut reader)?;
+        let locations = Vec::<u64>::deserialize(&mut reader)?;
+
+        if levels.len() != P::HEIGHT as usize {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(MerkleMountainRange {
+            levels,
+            locations,
+            parameters,
+        })
+    }
+
+    /// generate a membership proof for a location (does not check the data point)
+    pub fn generate_membership_proof(
+        &self,
+        location: u64,
+    ) -> Result<MerkleMountainRangePath<P>, Error> {
+        let position = self
+            .locations
+            .binary_search(&location)
+            .map_err(|_| MountainRangeError::LocationNotFound(location))?;
+
+        Ok(self.generate_path(position as u64))
+    }
+
+    /// extend a membership proof made for an earlier version of the tree to the current one
+    ///
+    /// the siblings in the old proof are kept, and the siblings up to the current peak and the
+    /// current peaks are added
+    pub fn extend_membership_proof(
+        &self,
+        proof: &MerkleMountainRangePath<P>,
+    ) -> Result<MerkleMountainRangePath<P>, Error> {
+        if proof.position >= self.size() {
+            return Err(MountainRangeError::IncorrectProof.into());
+        }
+
+        let extended_proof = self.generate_path(proof.position);
+        if !extended_proof.path.starts_with(&proof.path) {
+            return Err(MountainRangeError::IncorrectProof.into());
+        }
+
+        Ok(extended_proof)
+    }
+
+    /// generate an absence proof for a location after the last written one
+    pub fn generate_absence_proof(
+        &self,
+        location: u64,
+    ) -> Result<MerkleMountainRangePeaks<P>, Error> {
+        if location < self.next_location() {
+            return Err(MountainRangeError::NotAppend(location).into());
+        }
+
+        Ok(self.peaks())
+    }
+
+    /// append a leaf at a location after all the written ones, and provide the peaks before
+    pub fn append_and_prove<L: ToBytes>(
+        &mut self,
+        location: u64,
+        new_leaf: &L,
+    ) -> Result<MerkleMountainRangePeaks<P>, Error> {
+        let old_peaks = self.peaks();
+
+        // Check the location and the capacity before changing the tree.
+        old_peaks.append(&self.parameters, location, new_leaf)?;
+
+        let leaf_hash = hash_leaf::<P, _>(
+            &self.parameters,
+            &MountainRangeLeaf {
+                location,
+                data: new_leaf,
+            },
+        )?;
+        self.levels[0].push(leaf_hash);
+        self.locations.push(location);
+
+        // A level of even length has a new complete parent for its last two nodes.
+        let mut height = 0;
+        while self.levels[height].len() % 2 == 0 && height + 1 < P::HEIGHT as usize {
+            let len = self.levels[height].len();
+            let hash = hash_inner_node::<P>(
+                &self.parameters,
+                &self.levels[height][len - 2],
+                &self.levels[height][len - 1],
+            )?;
+            self.levels[height + 1].push(hash);
+            height += 1;
+        }
+
+        Ok(old_peaks)
+    }
+
+    /// check if the tree is structurally valid
+    pub fn validate(&self) -> Result<bool, Error> {
+        if self.levels.len() != P::HEIGHT as usize
+            || self.size() >> P::HEIGHT != 0
+            || self.levels[0].len() != self.locations.len()
+        {
+            return Ok(false);
+        }
+
+        // The locations must be strictly increasing.
+        for window in self.locations.windows(2) {
+            if window[0] >= window[1] {
+                return Ok(false);
+            }
+        }
+
+        for height in 0..(P::HEIGHT - 1) as usize {
+            if self.levels[height + 1].len() != self.levels[height].len() / 2 {
+                return Ok(false);
+            }
+
+            for (parent_node, parent_hash) in self.levels[height + 1].iter().enumerate() {
+                let hash = hash_inner_node::<P>(
+                    &self.parameters,
+                    &self.levels[height][2 * parent_node],
+                    &self.levels[height][2 * parent_node + 1],
+                )?;
+
+                if hash != *parent_hash {
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// clear the tree
+    pub fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            level.clear();
+        }
+        self.locations.clear();
+    }
+
+    /// Returns the path of a position, which must be less than the size.
+    fn generate_path(&self, position: u64) -> MerkleMountainRangePath<P> {
+        let height = peak_height(position, self.size()).unwrap();
+
+        let mut path = Vec::with_capacity(height as usize);
+        for level in 0..height as usize {
+            path.push(self.levels[level][((position >> level) ^ 1) as usize].clone());
+        }
+
+        MerkleMountainRangePath {
+            position,
+            path,
+            peaks: self.peaks(),
+        }
+    }
+}
+
+/// Returns the height of the peak above a position, which is the highest bit where the position
+/// and the size differ, if the position is less than the size.
+fn peak_height(position: u64, size: u64) -> Option<u64> {
+    if position >= size {
+        return None;
+    }
+
+    Some(63 - (position ^ size).leading_zeros() as u64)
+}
+
+/// Returns the bytes of a UInt64 in the circuit, in the same order as `u64::write`.
+fn uint64_to_bytes_gadget<F: PrimeField>(value_g: &UInt64<F>) -> Vec<UInt8<F>> {
+    value_g
+        .to_bits_le()
+        .chunks(8)
+        .map(UInt8::from_bits_le)
+        .collect()
+}
+
+/// Conditionally enforces that the location is not before the next location.
+fn conditionally_enforce_not_before<F: PrimeField>(
+    location_g: &UInt64<F>,
+    next_location_g: &UInt64<F>,
+    should_enforce: &Boolean<F>,
+) -> Result<(), SynthesisError> {
+    let location_fp: FpVar<F> = Boolean::le_bits_to_fp_var(&location_g.to_bits_le())?;
+    let next_location_fp: FpVar<F> = Boolean::le_bits_to_fp_var(&next_location_g.to_bits_le())?;
+
+    location_fp
+        .is_cmp(&next_location_fp, Ordering::Greater, true)?
+        .conditional_enforce_equal(&Boolean::constant(true), should_enforce)
+}
+
+/// Returns the hash of a leaf in the circuit.
+fn hash_leaf_gadget<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    DataVar: ToBytesGadget<F>,
+>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    location_g: &UInt64<F>,
+    data_g: &DataVar,
+) -> Result<CRHVar::OutputVar, SynthesisError> {
+    let mut leaf_bytes_g = vec![UInt8::constant(1u8)];
+    leaf_bytes_g.extend_from_slice(&uint64_to_bytes_gadget(location_g));
+    leaf_bytes_g.extend_from_slice(&data_g.to_bytes()?);
+
+    hash_leaf_bytes_gadget::<P, CRHVar, F>(parameters, &leaf_bytes_g)
+}
+
+struct MerkleMountainRangePeaksVar<
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    F: PrimeField,
+> {
+    size_g: UInt64<F>,
+    next_location_g: UInt64<F>,
+    /// one slot for each height, which is only used if the bit of the size is set
+    peaks_g: Vec<CRHVar::OutputVar>,
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AllocVar<MerkleMountainRangePeaks<P>, F> for MerkleMountainRangePeaksVar<P, CRHVar, F>
+{
+    fn new_variable<T: Borrow<MerkleMountainRangePeaks<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let val = f()?;
+        let peaks = val.borrow();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let size_g = UInt64::new_variable(ark_relations::ns!(cs, "size"), || Ok(peaks.size), mode)?;
+        let next_location_g = UInt64::new_variable(
+            ark_relations::ns!(cs, "next_location"),
+            || Ok(peaks.next_location),
+            mode,
+        )?;
+
+        let mut peaks_g = Vec::with_capacity(P::HEIGHT as usize);
+        for height in 0..P::HEIGHT {
+            let peak = peaks.peak(height).cloned().unwrap_or_default();
+            peaks_g.push(CRHVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "peak"),
+                || Ok(peak),
+                mode,
+            )?);
+        }
+
+        Ok(MerkleMountainRangePeaksVar {
+            size_g,
+            next_location_g,
+            peaks_g,
+        })
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    MerkleMountainRangePeaksVar<P, CRHVar, F>
+{
+    /// Returns the root, bagging only the slots whose bit of the size is set.
+    fn root(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    ) -> Result<CRHVar::OutputVar, SynthesisError> {
+        let size_bits = self.size_g.to_bits_le();
+
+        // The empty bag is fixed by the parameters, so it is a constant.
+        let empty_bag = hash_empty::<P, EmptyBag>(parameters, EmptyBag)
+            .map_err(|_| SynthesisError::Unsatisfiable)?;
+        let mut bag_g = CRHVar::OutputVar::new_constant(ConstraintSystemRef::None, empty_bag)?;
+        for (height, peak_g) in self.peaks_g.iter().enumerate() {
+            let hash_g = hash_inner_node_gadget::<P, CRHVar, F>(parameters, peak_g, &bag_g)?;
+            bag_g = CRHVar::OutputVar::conditionally_select(&size_bits[height], &hash_g, &bag_g)?;
+        }
+
+        let mut meta_bytes_g = vec![UInt8::constant(2u8)];
+        meta_bytes_g.extend_from_slice(&uint64_to_bytes_gadget(&self.size_g));
+        meta_bytes_g.extend_from_slice(&uint64_to_bytes_gadget(&self.next_location_g));
+        let meta_hash_g = hash_leaf_bytes_gadget::<P, CRHVar, F>(parameters, &meta_bytes_g)?;
+
+        hash_inner_node_gadget::<P, CRHVar, F>(parameters, &meta_hash_g, &bag_g)
+    }
+}
+
+struct MerkleMountainRangePathVar<
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    F: PrimeField,
+> {
+    position_g: UInt64<F>,
+    /// the siblings, padded to the tree height minus one
+    path_g: Vec<CRHVar::OutputVar>,
+    peaks_g: MerkleMountainRangePeaksVar<P, CRHVar, F>,
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AllocVar<MerkleMountainRangePath<P>, F> for MerkleMountainRangePathVar<P, CRHVar, F>
+{
+    fn new_variable<T: Borrow<MerkleMountainRangePath<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let val = f()?;
+        let path = val.borrow();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let position_g = UInt64::new_variable(
+            ark_relations::ns!(cs, "position"),
+            || Ok(path.position),
+            mode,
+        )?;
+
+        let mut path_g = Vec::with_capacity((P::HEIGHT - 1) as usize);
+        for level in 0..(P::HEIGHT - 1) as usize {
+            let sibling = path.path.get(level).cloned().unwrap_or_default();
+            path_g.push(CRHVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "sibling"),
+                || Ok(sibling),
+                mode,
+            )?);
+        }
+
+        let peaks_g = MerkleMountainRangePeaksVar::new_variable(
+            ark_relations::ns!(cs, "peaks"),
+            || Ok(&path.peaks),
+            mode,
+        )?;
+
+        Ok(MerkleMountainRangePathVar {
+            position_g,
+            path_g,
+            peaks_g,
+        })
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    MerkleMountainRangePathVar<P, CRHVar, F>
+{
+    /// Conditionally checks the lookup proof, given the location.
+    fn conditionally_check_membership_with_location<DataVar: ToBytesGadget<F>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_g: &CRHVar::OutputVar,
+        data_g: &DataVar,
+        location_g: &UInt64<F>,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        self.peaks_g
+            .root(parameters)?
+            .conditional_enforce_equal(root_g, should_enforce)?;
+
+        // The peak is at the highest bit where the position and the size differ, which must be
+        // set in the size (i.e., the position is less than the size) and have a slot.
+        let position_bits = self.position_g.to_bits_le();
+        let size_bits = self.peaks_g.size_g.to_bits_le();
+
+        let mut found = Boolean::constant(false);
+        let mut is_peak_height = vec![Boolean::constant(false); 64];
+        for i in (0..64).rev() {
+            let differ = position_bits[i].xor(&size_bits[i])?;
+            is_peak_height[i] = differ.and(&found.not())?;
+            found = found.or(&differ)?;
+        }
+        found.conditional_enforce_equal(&Boolean::constant(true), should_enforce)?;
+
+        for (i, is_peak_height_item) in is_peak_height.iter().enumerate() {
+            let is_invalid = if i < P::HEIGHT as usize {
+                is_peak_height_item.and(&size_bits[i].not())?
+            } else {
+                is_peak_height_item.clone()
+            };
+            is_invalid.conditional_enforce_equal(&Boolean::constant(false), should_enforce)?;
+        }
+
+        // Hash up to every height and compare with the peak at the height of the position.
+        let mut current_hash_g =
+            hash_leaf_gadget::<F, P, CRHVar, DataVar>(parameters, location_g, data_g)?;
+        for (height, peak_g) in self.peaks_g.peaks_g.iter().enumerate() {
+            let should_enforce_height = should_enforce.and(&is_peak_height[height])?;
+            current_hash_g.conditional_enforce_equal(peak_g, &should_enforce_height)?;
+
+            if height < self.path_g.len() {
+                let sibling_g = &self.path_g[height];
+                let left_hash_g = CRHVar::OutputVar::conditionally_select(
+                    &position_bits[height],
+                    sibling_g,
+                    &current_hash_g,
+                )?;
+                let right_hash_g = CRHVar::OutputVar::conditionally_select(
+                    &position_bits[height],
+                    &current_hash_g,
+                    sibling_g,
+                )?;
+
+                current_hash_g = hash_inner_node_gadget::<P, CRHVar, F>(
+                    parameters,
+                    &left_hash_g,
+                    &right_hash_g,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct MerkleMountainRangeAppendProofVar<
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    F: PrimeField,
+> {
+    new_digest_g: CRHVar::OutputVar,
+    old_peaks_g: MerkleMountainRangePeaksVar<P, CRHVar, F>,
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AllocVar<MerkleMountainRangeAppendProof<P>, F>
+    for MerkleMountainRangeAppendProofVar<P, CRHVar, F>
+{
+    fn new_variable<T: Borrow<MerkleMountainRangeAppendProof<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let val = f()?;
+        let append_proof = val.borrow();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let new_digest_g = CRHVar::OutputVar::new_variable(
+            ark_relations::ns!(cs, "new_digest"),
+            || Ok(&append_proof.new_digest),
+            mode,
+        )?;
+        let old_peaks_g = MerkleMountainRangePeaksVar::new_variable(
+            ark_relations::ns!(cs, "old_peaks"),
+            || Ok(&append_proof.old_peaks),
+            mode,
+        )?;
+
+        Ok(MerkleMountainRangeAppendProofVar {
+            new_digest_g,
+            old_peaks_g,
+        })
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    MerkleMountainRangeAppendProofVar<P, CRHVar, F>
+{
+    /// Conditionally checks the append proof, given the old digest.
+    fn conditionally_check_append<DataVar: ToBytesGadget<F>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root_g: &CRHVar::OutputVar,
+        data_g: &DataVar,
+        location_g: &UInt64<F>,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        self.old_peaks_g
+            .root(parameters)?
+            .conditional_enforce_equal(old_root_g, should_enforce)?;
+        conditionally_enforce_not_before(
+            location_g,
+            &self.old_peaks_g.next_location_g,
+            should_enforce,
+        )?;
+
+        // Merge the new leaf with the peaks of heights 0, 1, ... up to the lowest unset bit of
+        // the size, which is also how the size is incremented.
+        let size_bits = self.old_peaks_g.size_g.to_bits_le();
+        let mut new_size_bits = size_bits.clone();
+        let mut new_peaks_g = Vec::with_capacity(self.old_peaks_g.peaks_g.len());
+
+        let mut carry_g =
+            hash_leaf_gadget::<F, P, CRHVar, DataVar>(parameters, location_g, data_g)?;
+        let mut carrying = Boolean::constant(true);
+        for (height, peak_g) in self.old_peaks_g.peaks_g.iter().enumerate() {
+            let merge = carrying.and(&size_bits[height])?;
+            let place = carrying.and(&size_bits[height].not())?;
+
+            let merged_g = hash_inner_node_gadget::<P, CRHVar, F>(parameters, peak_g, &carry_g)?;
+            new_peaks_g.push(CRHVar::OutputVar::conditionally_select(
+                &place, &carry_g, peak_g,
+            )?);
+            new_size_bits[height] = size_bits[height].xor(&carrying)?;
+
+            carry_g = CRHVar::OutputVar::conditionally_select(&merge, &merged_g, &carry_g)?;
+            carrying = merge;
+        }
+
+        // The new leaf must have been placed, i.e., the tree is not full.
+        carrying.conditional_enforce_equal(&Boolean::constant(false), should_enforce)?;
+
+        let new_peaks_g = MerkleMountainRangePeaksVar::<P, CRHVar, F> {
+            size_g: UInt64::from_bits_le(&new_size_bits),
+            next_location_g: UInt64::addmany(&[location_g.clone(), UInt64::constant(1)])?,
+            peaks_g: new_peaks_g,
+        };
+
+        new_peaks_g
+            .root(parameters)?
+            .conditional_enforce_equal(&self.new_digest_g, should_enforce)
+    }
+}
+
+/// lookup proof gadgets for the Merkle mountain range
+pub struct MountainRangeLookupProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<MerkleMountainRangePathVar<P, CRHVar, F>>);
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<MerkleMountainRangePath<P>>, F> for MountainRangeLookupProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<MerkleMountainRangePath<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(MerkleMountainRangePathVar::<P, CRHVar, F>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(MountainRangeLookupProofVar { 0: vec })
+    }
+}
+
+/// append proof gadgets for the Merkle mountain range
+pub struct MountainRangeModifyProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<MerkleMountainRangeAppendProofVar<P, CRHVar, F>>);
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<MerkleMountainRangeAppendProof<P>>, F>
+    for MountainRangeModifyProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<MerkleMountainRangeAppendProof<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(
+                MerkleMountainRangeAppendProofVar::<P, CRHVar, F>::new_variable(
+                    ark_relations::ns!(cs, "value"),
+                    || Ok(value),
+                    mode,
+                )?,
+            );
+        }
+        Ok(MountainRangeModifyProofVar { 0: vec })
+    }
+}
+
+/// absence proof gadgets for the Merkle mountain range
+pub struct MountainRangeAbsenceProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<MerkleMountainRangePeaksVar<P, CRHVar, F>>);
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<MerkleMountainRangePeaks<P>>, F> for MountainRangeAbsenceProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<MerkleMountainRangePeaks<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(MerkleMountainRangePeaksVar::<P, CRHVar, F>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(MountainRangeAbsenceProofVar { 0: vec })
+    }
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    MT<F, u64, UInt64<F>> for MountainRangeMT<F, P, CRHVar>
+{
+    type PublicParameters = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Parameters;
+    type Digest = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output;
+    type T = MerkleMountainRange<P>;
+    type LookupProof = Vec<MerkleMountainRangePath<P>>;
+    type ModifyProof = Vec<MerkleMountainRangeAppendProof<P>>;
+    type AbsenceProof = Vec<MerkleMountainRangePeaks<P>>;
+    /// the paths do not share any siblings, so this is the same as a lookup proof
+    type MultiLookupProof = Vec<MerkleMountainRangePath<P>>;
+
+    type DigestVar = CRHVar::OutputVar;
+    type LookupProofVar = MountainRangeLookupProofVar<F, P, CRHVar>;
+    type ModifyProofVar = MountainRangeModifyProofVar<F, P, CRHVar>;
+    type AbsenceProofVar = MountainRangeAbsenceProofVar<F, P, CRHVar>;
+    type MultiLookupProofVar = MountainRangeLookupProofVar<F, P, CRHVar>;
+
+    fn setup<R: Rng + CryptoRng>(rng: &mut R) -> Result<Self::PublicParameters, Error> {
+        <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::setup(rng)
+    }
+
+    fn new<L: Default + MerkleLeaf<F>>(pp: &Self::PublicParameters) -> Result<Self::T, Error> {
+        Ok(MerkleMountainRange::blank(pp.clone()))
+    }
+
+    fn root(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<Self::Digest, Error> {
+        tree.root()
+    }
+
+    fn validate(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<bool, Error> {
+        tree.validate()
+    }
+
+    fn lookup(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::LookupProof, Error> {
+        let mut lookup_proof: Self::LookupProof = Vec::with_capacity(addr.len());
+
+        for i in addr {
+            lookup_proof.push(tree.generate_membership_proof(*i)?);
+        }
+
+        Ok(lookup_proof)
+    }
+
+    fn verify_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        lookup_proof: &Self::LookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != lookup_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        for ((addr_item, data_item), lookup_proof_item) in
+            addr.iter().zip(data.iter()).zip(lookup_proof.iter())
+        {
+            if !lookup_proof_item.verify_with_location(pp, rh, data_item, *addr_item)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn multi_lookup(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::MultiLookupProof, Error> {
+        Self::lookup(pp, tree, addr)
+    }
+
+    fn verify_multi_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        multi_lookup_proof: &Self::MultiLookupProof,
+    ) -> Result<bool, Error> {
+        Self::verify_lookup(pp, rh, addr, data, multi_lookup_proof)
+    }
+
+    fn prove_absence(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::AbsenceProof, Error> {
+        let mut absence_proof: Self::AbsenceProof = Vec::with_capacity(addr.len());
+
+        for i in addr {
+            absence_proof.push(tree.generate_absence_proof(*i)?);
+        }
+
+        Ok(absence_proof)
+    }
+
+    fn verify_absence<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        absence_proof: &Self::AbsenceProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != absence_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        for (addr_item, absence_proof_item) in addr.iter().zip(absence_proof.iter()) {
+            if *addr_item < absence_proof_item.next_location
+                || !absence_proof_item.verify(pp, rh)?
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn verify_modify<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh_old: &Self::Digest,
+        rh_new: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        modify_proof: &Self::ModifyProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != modify_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+        let data = encode_leaves(data)?;
+
+        let mut last_hash: Self::Digest = rh_old.clone();
+
+        for ((addr_item, data_item), modify_proof_item) in
+            addr.iter().zip(data.iter()).zip(modify_proof.iter())
+        {
+            if !modify_proof_item.verify(pp, &last_hash, data_item, *addr_item)? {
+                return Ok(false);
+            }
+
+            last_hash = modify_proof_item.new_digest.clone();
+        }
+
+        Ok(last_hash == *rh_new)
+    }
+
+    fn _new_with_map<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        map: &BTreeMap<u64, Data>,
+    ) -> Result<Self::T, Error> {
+        let mut tree = MerkleMountainRange::blank(pp.clone());
+
+        // The map is ordered, so the locations are appended in order.
+        for (location, data) in map.iter() {
+            tree.append_and_prove(*location, &encode_leaf(data)?)?;
+        }
+
+        Ok(tree)
+    }
+
+    fn _modify_and_apply<Data: MerkleLeaf<F> + Clone + Default>(
+        _pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        addr: &[u64],
+        data: &[Data],
+    ) -> Result<(Self::Digest, Self::ModifyProof), Error> {
+        if addr.len() != data.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        // Check the order first, so that a rejected batch leaves the tree untouched.
+        let mut next_location = tree.next_location();
+        for addr_item in addr {
+            if *addr_item < next_location {
+                return Err(MountainRangeError::NotAppend(*addr_item).into());
+            }
+            next_location = addr_item.checked_add(1).ok_or(MountainRangeError::Full)?;
+        }
+        if (tree.size() + addr.len() as u64) >> P::HEIGHT != 0 {
+            return Err(MountainRangeError::Full.into());
+        }
+        let data = encode_leaves(data)?;
+
+        let mut modify_proof: Self::ModifyProof = Vec::with_capacity(addr.len());
+        for (addr_item, data_item) in addr.iter().zip(data.iter()) {
+            let old_peaks = tree.append_and_prove(*addr_item, data_item)?;
+            modify_proof.push(MerkleMountainRangeAppendProof {
+                new_digest: tree.root()?,
+                old_peaks,
+            });
+        }
+
+        Ok((tree.root()?, modify_proof))
+    }
+
+    fn clear(_pp: &Self::PublicParameters, tree: &mut Self::T) -> Result<(), Error> {
+        tree.clear();
+
+        Ok(())
+    }
+
+    fn serialize_tree<W: Write>(tree: &Self::T, writer: W) -> Result<(), Error> {
+        tree.serialize_nodes(writer)?;
+
+        Ok(())
+    }
+
+    fn deserialize_tree<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &Self::PublicParameters,
+        reader: R,
+    ) -> Result<Self::T, Error> {
+        Ok(MerkleMountainRange::<P>::deserialize_nodes(
+            pp.clone(),
+            reader,
+        )?)
+    }
+
+    fn verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            lookup_proof_g_wrapped,
+            &Boolean::constant(true),
+        )
+    }
+
+    fn conditionally_verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            lookup_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        let lookup_proof_g = &lookup_proof_g_wrapped.0;
+
+        assert_eq!(
+            addr_g.len(),
+            data_g.len(),
+            "the address len {} does not equal the data len {}",
+            addr_g.len(),
+            data_g.len()
+        );
+        assert_eq!(
+            data_g.len(),
+            active_g.len(),
+            "the data len {} does not equal the flag len {}",
+            data_g.len(),
+            active_g.len()
+        );
+        assert_eq!(
+            data_g.len(),
+            lookup_proof_g.len(),
+            "the data len {} does not equal the proof len {}",
+            data_g.len(),
+            lookup_proof_g.len()
+        );
+        let data_g = encode_leaves_gadget(data_g)?;
+
+        for (((addr_g_item, data_g_item), active_g_item), lookup_proof_g_item) in addr_g
+            .iter()
+            .zip(data_g.iter())
+            .zip(active_g.iter())
+            .zip(lookup_proof_g.iter())
+        {
+            let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+            lookup_proof_g_item.conditionally_check_membership_with_location(
+                pp_g,
+                rh_g,
+                data_g_item,
+                addr_g_item,
+                &should_enforce_item,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_multi_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr: &[u64],
+        data_g: &[DataVar],
+        multi_lookup_proof_g: &Self::MultiLookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        let addr_g: Vec<UInt64<F>> = addr.iter().map(|i| UInt64::constant(*i)).collect();
+
+        Self::verify_lookup_gadget(cs, pp_g, rh_g, &addr_g, data_g, multi_lookup_proof_g)
+    }
+
+    fn verify_absence_gadget<Data: MerkleLeaf<F> + Clone + Default>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        absence_proof_g_wrapped: &Self::AbsenceProofVar,
+    ) -> Result<(), SynthesisError> {
+        let absence_proof_g = &absence_proof_g_wrapped.0;
+
+        assert_eq!(
+            addr_g.len(),
+            absence_proof_g.len(),
+            "the address len {} does not equal the proof len {}",
+            addr_g.len(),
+            absence_proof_g.len()
+        );
+
+        for (addr_g_item, absence_proof_g_item) in addr_g.iter().zip(absence_proof_g.iter()) {
+            absence_proof_g_item.root(pp_g)?.enforce_equal(rh_g)?;
+            conditionally_enforce_not_before(
+                addr_g_item,
+                &absence_proof_g_item.next_location_g,
+                &Boolean::constant(true),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn conditionally_verify_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_modify_gadget(
+            cs,
+            pp_g,
+            rh_old_g,
+            rh_new_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            modify_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        let modify_proof_g = &modify_proof_g_wrapped.0;
+
+        assert_eq!(addr_g.len(), data_g.len());
+        assert_eq!(data_g.len(), active_g.len());
+        assert_eq!(data_g.len(), modify_proof_g.len());
+        let data_g = encode_leaves_gadget(data_g)?;
+
+        let mut last_hash_g: Self::DigestVar = rh_old_g.clone();
+
+        for (((addr_g_item, data_g_item), active_g_item), modify_proof_g_item) in addr_g
+            .iter()
+            .zip(data_g.iter())
+            .zip(active_g.iter())
+            .zip(modify_proof_g.iter())
+        {
+            let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+            modify_proof_g_item.conditionally_check_append(
+                pp_g,
+                &last_hash_g,
+                data_g_item,
+                addr_g_item,
+                &should_enforce_item,
+            )?;
+
+            // An inactive entry leaves the digest unchanged.
+            last_hash_g = Self::DigestVar::conditionally_select(
+                &active_g_item,
+                &modify_proof_g_item.new_digest_g,
+                &last_hash_g,
+            )?;
+        }
+
+        last_hash_g.conditional_enforce_equal(rh_new_g, &should_enforce)?;
+
+        Ok(())
+    }
+
+    fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(MerkleMountainRangePath::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(MerkleMountainRangeAppendProof::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn pad_lookup_proof(proof: &mut Self::LookupProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(MerkleMountainRangePath::<P>::default());
+        }
+        Ok(())
+    }
+
+    fn pad_modify_proof(proof: &mut Self::ModifyProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(MerkleMountainRangeAppendProof::<P>::default());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// error type for the Merkle mountain range
+pub enum MountainRangeError {
+    /// the location is not after the last written one
+    NotAppend(u64),
+    /// the location has not been written
+    LocationNotFound(u64),
+    /// the tree cannot hold more leaves
+    Full,
+    /// the proof does not belong to this tree
+    IncorrectProof,
+}
+
+impl core::fmt::Display for MountainRangeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            MountainRangeError::NotAppend(location) => {
+                format!("location {} is not after the last written one", location)
+            }
+            MountainRangeError::LocationNotFound(location) => {
+                format!("location {} has not been written", location)
+            }
+            MountainRangeError::Full => "the tree is full".to_string(),
+            MountainRangeError::IncorrectProof => {
+                "the proof does not belong to this tree".to_string()
+            }
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl ark_std::error::Error for MountainRangeError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn ark_std::error::Error + 'static)> {
+        None
+    }
+}
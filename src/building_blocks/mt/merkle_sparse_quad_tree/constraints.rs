@@ -0,0 +1,660 @@
+use ark_ff::PrimeField;
+use ark_r1cs_std::{prelude::*, uint64::UInt64};
+use ark_relations::r1cs::{ConstraintSystemRef, Namespace, SynthesisError};
+
+use crate::building_blocks::crh::CRHforMerkleTreeGadget;
+use crate::building_blocks::mt::merkle_sparse_quad_tree::*;
+use ark_std::{borrow::Borrow, vec};
+
+/// Gadgets for one 4-ary Merkle tree path, storing only the siblings
+#[derive(Debug)]
+pub struct MerkleSparseQuadTreePathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    path: Vec<HVar::OutputVar>,
+}
+
+/// Gadgets for two 4-ary Merkle tree paths, which share the siblings
+#[derive(Debug)]
+pub struct MerkleSparseQuadTreeTwoPathsVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    old_leaf_hash: HVar::OutputVar,
+    path: MerkleSparseQuadTreePathVar<P, HVar, ConstraintF>,
+}
+
+/// Gadgets for a multi-opening of several leaves of a 4-ary Merkle tree
+#[derive(Debug)]
+pub struct MerkleSparseQuadTreeMultiPathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    auxiliary: Vec<HVar::OutputVar>,
+}
+
+impl<P, CRHVar, ConstraintF> MerkleSparseQuadTreePathVar<P, CRHVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    ConstraintF: PrimeField,
+    CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    /// check a lookup proof (with index)
+    pub fn check_membership_with_index(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf: impl ToBytesGadget<ConstraintF>,
+        index: &UInt64<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_membership_with_index(
+            parameters,
+            root,
+            leaf,
+            index,
+            &Boolean::Constant(true),
+        )
+    }
+
+    /// conditionally check a lookup proof (with index)
+    pub fn conditionally_check_membership_with_index(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf: impl ToBytesGadget<ConstraintF>,
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        let leaf_bits = leaf.to_bytes()?;
+        let leaf_hash = hash_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, &leaf_bits)?;
+
+        self.conditionally_check_leaf_hash_with_index(
+            parameters,
+            root,
+            leaf_hash,
+            index,
+            should_enforce,
+        )
+    }
+
+    /// check an absence proof (with index)
+    pub fn check_absence_with_index<L: Default + ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        index: &UInt64<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_absence_with_index::<L>(
+            parameters,
+            root,
+            index,
+            &Boolean::Constant(true),
+        )
+    }
+
+    /// conditionally check an absence proof (with index)
+    pub fn conditionally_check_absence_with_index<L: Default + ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        // The empty leaf hash is fixed by the parameters, so it is a constant.
+        let empty_leaf_hash = hash_empty::<P, L>(parameters, L::default())
+            .map_err(|_| SynthesisError::Unsatisfiable)?;
+        let empty_leaf_hash_g =
+            CRHVar::OutputVar::new_constant(ConstraintSystemRef::None, empty_leaf_hash)?;
+
+        self.conditionally_check_leaf_hash_with_index(
+            parameters,
+            root,
+            empty_leaf_hash_g,
+            index,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_check_leaf_hash_with_index(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf_hash: CRHVar::OutputVar,
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        if self.path.len() != 3 * (P::HEIGHT - 1) as usize {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // Check levels between leaf level and root.
+        let mut previous_hash = leaf_hash;
+        let index_bits = index.to_bits_le();
+        for (i, siblings) in self.path.chunks(3).enumerate() {
+            // Two index bits give the position of the previous_hash among the four children.
+            let children = place_child_gadget::<P, CRHVar, ConstraintF>(
+                siblings,
+                &previous_hash,
+                &index_bits[2 * i],
+                &index_bits[2 * i + 1],
+            )?;
+
+            previous_hash =
+                hash_inner_node_gadget::<P, CRHVar, ConstraintF>(parameters, &children)?;
+        }
+
+        root.conditional_enforce_equal(&previous_hash, should_enforce)
+    }
+}
+
+impl<P, CRHVar, ConstraintF> MerkleSparseQuadTreeTwoPathsVar<P, CRHVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    ConstraintF: PrimeField,
+    CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    /// check a modifying proof
+    pub fn check_update(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root: &CRHVar::OutputVar,
+        new_root: &CRHVar::OutputVar,
+        new_leaf: impl ToBytesGadget<ConstraintF>,
+        index: &UInt64<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_update(
+            parameters,
+            old_root,
+            new_root,
+            new_leaf,
+            index,
+            &Boolean::Constant(true),
+        )
+    }
+
+    /// conditionally check a modifying proof
+    pub fn conditionally_check_update(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root: &CRHVar::OutputVar,
+        new_root: &CRHVar::OutputVar,
+        new_leaf: impl ToBytesGadget<ConstraintF>,
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        // Check that the hash of the given leaf matches the new root.
+        let new_leaf_bits = new_leaf.to_bytes()?;
+        let new_leaf_hash = hash_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, &new_leaf_bits)?;
+
+        self.path.conditionally_check_leaf_hash_with_index(
+            parameters,
+            new_root,
+            new_leaf_hash,
+            index,
+            should_enforce,
+        )?;
+
+        // The siblings are unchanged, so the old leaf hash must lead to the old root.
+        self.path.conditionally_check_leaf_hash_with_index(
+            parameters,
+            old_root,
+            self.old_leaf_hash.clone(),
+            index,
+            should_enforce,
+        )
+    }
+}
+
+impl<P, CRHVar, ConstraintF> MerkleSparseQuadTreeMultiPathVar<P, CRHVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    ConstraintF: PrimeField,
+    CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    /// check a multi-opening, where the locations are fixed in the circuit
+    pub fn check_membership_with_indices<L: ToBytesGadget<ConstraintF>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaves: &[L],
+        indices: &[u64],
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_membership_with_indices(
+            parameters,
+            root,
+            leaves,
+            indices,
+            &Boolean::Constant(true),
+        )
+    }
+
+    /// conditionally check a multi-opening, where the locations are fixed in the circuit
+    pub fn conditionally_check_membership_with_indices<L: ToBytesGadget<ConstraintF>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaves: &[L],
+        indices: &[u64],
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        if leaves.is_empty() || leaves.len() != indices.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // Hash the leaves, the same location must be opened to the same leaf.
+        let mut current_level: BTreeMap<u64, CRHVar::OutputVar> = BTreeMap::new();
+        for (leaf, index) in leaves.iter().zip(indices.iter()) {
+            if *index >= num_leaves(P::HEIGHT) {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+
+            let leaf_bits = leaf.to_bytes()?;
+            let leaf_hash = hash_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, &leaf_bits)?;
+
+            let tree_index = convert_index_to_last_level(*index, P::HEIGHT);
+            if let Some(prev) = current_level.get(&tree_index) {
+                prev.conditional_enforce_equal(&leaf_hash, should_enforce)?;
+            } else {
+                current_level.insert(tree_index, leaf_hash);
+            }
+        }
+
+        // Compute the nodes level by level, in the same order as the native verifier.
+        let mut auxiliary_iter = self.auxiliary.iter();
+        for _ in 1..P::HEIGHT {
+            let mut next_level: BTreeMap<u64, CRHVar::OutputVar> = BTreeMap::new();
+            for current_node in current_level.keys() {
+                let parent_node = parent(*current_node).ok_or(SynthesisError::Unsatisfiable)?;
+                if next_level.contains_key(&parent_node) {
+                    continue;
+                }
+
+                // A multi-opening that does not match the locations is rejected.
+                let mut children = Vec::with_capacity(4);
+                for position in 0..4 {
+                    match current_level.get(&child(parent_node, position)) {
+                        Some(x) => children.push(x.clone()),
+                        None => children.push(
+                            auxiliary_iter
+                                .next()
+                                .ok_or(SynthesisError::Unsatisfiable)?
+                                .clone(),
+                        ),
+                    }
+                }
+
+                next_level.insert(
+                    parent_node,
+                    hash_inner_node_gadget::<P, CRHVar, ConstraintF>(parameters, &children)?,
+                );
+            }
+            current_level = next_level;
+        }
+
+        if auxiliary_iter.next().is_some() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let root_hash = current_level.get(&0).ok_or(SynthesisError::Unsatisfiable)?;
+        root.conditional_enforce_equal(root_hash, should_enforce)
+    }
+}
+
+/// Returns the four children, placing the child at the position given by the two bits.
+fn place_child_gadget<P, CRHVar, ConstraintF>(
+    siblings: &[CRHVar::OutputVar],
+    child: &CRHVar::OutputVar,
+    bit_0: &Boolean<ConstraintF>,
+    bit_1: &Boolean<ConstraintF>,
+) -> Result<Vec<CRHVar::OutputVar>, SynthesisError>
+where
+    P: MerkleSparseQuadTreeConfig,
+    ConstraintF: PrimeField,
+    CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    let is_0 = bit_0.not().and(&bit_1.not())?;
+    let is_1 = bit_0.and(&bit_1.not())?;
+    let is_2 = bit_0.not().and(bit_1)?;
+    let is_3 = bit_0.and(bit_1)?;
+
+    // The siblings before the position keep their place, the ones after it shift by one.
+    let child_0 = CRHVar::OutputVar::conditionally_select(&is_0, child, &siblings[0])?;
+    let child_1 = CRHVar::OutputVar::conditionally_select(
+        &is_0,
+        &siblings[0],
+        &CRHVar::OutputVar::conditionally_select(&is_1, child, &siblings[1])?,
+    )?;
+    let child_2 = CRHVar::OutputVar::conditionally_select(
+        &bit_1.not(),
+        &siblings[1],
+        &CRHVar::OutputVar::conditionally_select(&is_2, child, &siblings[2])?,
+    )?;
+    let child_3 = CRHVar::OutputVar::conditionally_select(&is_3, child, &siblings[2])?;
+
+    Ok(vec![child_0, child_1, child_2, child_3])
+}
+
+/// Returns the hash of the bytes of a leaf, prefixed by its tag unless the legacy encoding is used.
+pub(crate) fn hash_leaf_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf_bytes: &[UInt8<ConstraintF>],
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseQuadTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    match domain_tag::<P>(LEAF_TAG) {
+        Some(tag) => {
+            let mut bytes = vec![UInt8::constant(tag)];
+            bytes.extend_from_slice(leaf_bytes);
+            HG::hash_bytes(parameters, &bytes)
+        }
+        None => HG::hash_bytes(parameters, leaf_bytes),
+    }
+}
+
+pub(crate) fn hash_inner_node_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    children: &[HG::OutputVar],
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseQuadTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    match domain_tag::<P>(INNER_NODE_TAG) {
+        Some(tag) => HG::four_to_one_compress_with_tag(parameters, tag, children),
+        None => HG::four_to_one_compress(parameters, children),
+    }
+}
+
+impl<P, HVar, ConstraintF> AllocVar<MerkleSparseQuadTreePath<P>, ConstraintF>
+    for MerkleSparseQuadTreePathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    fn new_variable<T: Borrow<MerkleSparseQuadTreePath<P>>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut path = Vec::new();
+        for sibling in f()?.borrow().path.iter() {
+            let sibling_hash = HVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "sibling"),
+                || Ok(sibling.clone()),
+                mode,
+            )?;
+            path.push(sibling_hash);
+        }
+        Ok(MerkleSparseQuadTreePathVar { path })
+    }
+}
+
+impl<P, HVar, ConstraintF> AllocVar<MerkleSparseQuadTreeTwoPaths<P>, ConstraintF>
+    for MerkleSparseQuadTreeTwoPathsVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    fn new_variable<T: Borrow<MerkleSparseQuadTreeTwoPaths<P>>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let t = f()?;
+        let paths = t.borrow();
+
+        let old_leaf_hash = HVar::OutputVar::new_variable(
+            ark_relations::ns!(cs, "old_leaf_hash"),
+            || Ok(paths.old_leaf_hash.clone()),
+            mode,
+        )?;
+        let path = MerkleSparseQuadTreePathVar::new_variable(
+            ark_relations::ns!(cs, "shared_path"),
+            || Ok(&paths.path),
+            mode,
+        )?;
+        Ok(MerkleSparseQuadTreeTwoPathsVar {
+            old_leaf_hash,
+            path,
+        })
+    }
+}
+
+impl<P, HVar, ConstraintF> AllocVar<MerkleSparseQuadTreeMultiPath<P>, ConstraintF>
+    for MerkleSparseQuadTreeMultiPathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseQuadTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    fn new_variable<T: Borrow<MerkleSparseQuadTreeMultiPath<P>>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut auxiliary = Vec::new();
+        for hash in f()?.borrow().auxiliary.iter() {
+            auxiliary.push(HVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "auxiliary"),
+                || Ok(hash.clone()),
+                mode,
+            )?);
+        }
+        Ok(MerkleSparseQuadTreeMultiPathVar { auxiliary })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::building_blocks::crh::*;
+    use crate::building_blocks::mt::merkle_sparse_quad_tree::*;
+    use ark_ed_on_bls12_381::Fq;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand_chacha::ChaChaRng;
+
+    use super::*;
+    use crate::building_blocks::crh::poseidon::{
+        PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+    };
+
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fq>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fq>;
+
+    #[derive(Debug)]
+    struct JubJubMerkleQuadTreeParams;
+
+    impl MerkleSparseQuadTreeConfig for JubJubMerkleQuadTreeParams {
+        const HEIGHT: u64 = 16;
+        type H = H;
+    }
+
+    type JubJubMerkleQuadTree = MerkleSparseQuadTree<JubJubMerkleQuadTreeParams>;
+
+    fn generate_merkle_tree(leaves: &BTreeMap<u64, [u8; 30]>, use_bad_root: bool) {
+        let mut rng = ark_std::test_rng();
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), leaves).unwrap();
+        let root = tree.root();
+        let mut satisfied = true;
+        for (i, leaf) in leaves.iter() {
+            let cs_sys = ConstraintSystem::<Fq>::new();
+            let cs = ConstraintSystemRef::new(cs_sys);
+            let proof = tree.generate_proof(*i, &leaf).unwrap();
+            assert!(proof
+                .verify_with_index(&crh_parameters, &root, &leaf, *i)
+                .unwrap());
+
+            // Allocate Merkle Tree Root
+            let root = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+                ark_relations::ns!(cs, "new_digest"),
+                || {
+                    if use_bad_root {
+                        Ok(<H as CRHforMerkleTree>::Output::default())
+                    } else {
+                        Ok(root)
+                    }
+                },
+            )
+            .unwrap();
+
+            // Allocate Leaf
+            let leaf_g = UInt8::constant_vec(leaf);
+            let index_g = UInt64::new_witness(ark_relations::ns!(cs, "index"), || Ok(*i)).unwrap();
+
+            // Allocate Merkle Tree Path
+            let cw = MerkleSparseQuadTreePathVar::<_, HG, _>::new_witness(
+                ark_relations::ns!(cs, "new_witness"),
+                || Ok(proof),
+            )
+            .unwrap();
+
+            let constraints_before = cs.num_constraints();
+            let leaf_g: &[UInt8<Fq>] = leaf_g.as_slice();
+            cw.check_membership_with_index(&crh_parameters, &root, &leaf_g, &index_g)
+                .unwrap();
+            if !cs.is_satisfied().unwrap() {
+                satisfied = false;
+                println!(
+                    "Unsatisfied constraint: {}",
+                    cs.which_is_unsatisfied().unwrap().unwrap()
+                );
+            }
+            println!(
+                "number of constraints: {}",
+                cs.num_constraints() - constraints_before
+            );
+        }
+
+        assert!(satisfied);
+    }
+
+    #[test]
+    fn good_root_membership_test() {
+        let mut leaves: BTreeMap<u64, [u8; 30]> = BTreeMap::new();
+        for i in 0..10u8 {
+            let input = [i; 30];
+            leaves.insert(i as u64, input);
+        }
+        generate_merkle_tree(&leaves, false);
+    }
+
+    #[should_panic]
+    #[test]
+    fn bad_root_membership_test() {
+        let mut leaves: BTreeMap<u64, [u8; 30]> = BTreeMap::new();
+        for i in 0..10u8 {
+            let input = [i; 30];
+            leaves.insert(i as u64, input);
+        }
+        generate_merkle_tree(&leaves, true);
+    }
+
+    #[test]
+    fn good_root_update_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut old_leaves: BTreeMap<u64, [u8; 2]> = BTreeMap::new();
+        for i in 0..4u8 {
+            old_leaves.insert(i as u64, [i; 2]);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let mut tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), &old_leaves).unwrap();
+        for i in 0..8u64 {
+            let cs_sys = ConstraintSystem::<Fq>::new();
+            let cs = ConstraintSystemRef::new(cs_sys);
+
+            let new_leaf = [i as u8 + 1; 2];
+            let old_root = tree.root();
+            let update_proof = tree.update_and_prove(i, &new_leaf).unwrap();
+            let new_root = tree.root();
+
+            let old_root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+                ark_relations::ns!(cs, "old_digest"),
+                || Ok(old_root),
+            )
+            .unwrap();
+            let new_root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+                ark_relations::ns!(cs, "new_digest"),
+                || Ok(new_root),
+            )
+            .unwrap();
+            let leaf_g = UInt8::new_witness_vec(ark_relations::ns!(cs, "leaf"), &new_leaf).unwrap();
+            let index_g = UInt64::new_witness(ark_relations::ns!(cs, "index"), || Ok(i)).unwrap();
+            let update_proof_g = MerkleSparseQuadTreeTwoPathsVar::<_, HG, _>::new_witness(
+                ark_relations::ns!(cs, "update_proof"),
+                || Ok(update_proof),
+            )
+            .unwrap();
+
+            update_proof_g
+                .check_update(&crh_parameters, &old_root_g, &new_root_g, &leaf_g, &index_g)
+                .unwrap();
+
+            assert!(cs.is_satisfied().unwrap());
+        }
+    }
+
+    #[test]
+    fn absence_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, [u8; 2]> = BTreeMap::new();
+        for i in 0..4u8 {
+            leaves.insert(i as u64, [i + 1; 2]);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        for (i, expected) in [(4u64, true), (7u64, true), (2u64, false)].iter() {
+            let cs_sys = ConstraintSystem::<Fq>::new();
+            let cs = ConstraintSystemRef::new(cs_sys);
+
+            /* an occupied leaf cannot be proven absent, so use its plain path */
+            let proof = tree.generate_membership_proof(*i).unwrap();
+
+            let root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+                ark_relations::ns!(cs, "root"),
+                || Ok(root),
+            )
+            .unwrap();
+            let index_g = UInt64::new_witness(ark_relations::ns!(cs, "index"), || Ok(*i)).unwrap();
+            let proof_g = MerkleSparseQuadTreePathVar::<_, HG, _>::new_witness(
+                ark_relations::ns!(cs, "absence_proof"),
+                || Ok(proof),
+            )
+            .unwrap();
+
+            proof_g
+                .check_absence_with_index::<[u8; 2]>(&crh_parameters, &root_g, &index_g)
+                .unwrap();
+
+            assert_eq!(cs.is_satisfied().unwrap(), *expected);
+        }
+    }
+}
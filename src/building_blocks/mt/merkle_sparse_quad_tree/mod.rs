@@ -0,0 +1,950 @@
+use crate::building_blocks::crh::CRHforMerkleTree;
+use crate::building_blocks::mt::merkle_sparse_tree::{
+    hash_with_tag, MerkleSparseTreeError, INNER_NODE_TAG, LEAF_TAG,
+};
+use crate::Error;
+use ark_ff::ToBytes;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::collections::{BTreeMap, BTreeSet};
+use ark_std::fmt::Debug;
+use ark_std::{
+    io::{Read, Result as IoResult, Write},
+    vec::Vec,
+};
+
+/// constraints for the 4-ary Merkle sparse tree
+pub mod constraints;
+
+/// configuration of a 4-ary Merkle tree
+pub trait MerkleSparseQuadTreeConfig: Debug {
+    /// Tree height, the last level has `4^(HEIGHT - 1)` leaves, so it is at most 32
+    const HEIGHT: u64;
+    /// The CRH
+    type H: CRHforMerkleTree;
+    /// Hash leaves, inner nodes, and empty leaves without the domain-separation tags, as in the legacy encoding
+    const LEGACY_ENCODING: bool = false;
+}
+
+/// Returns the tag of this domain, or `None` under the legacy encoding.
+pub(crate) fn domain_tag<P: MerkleSparseQuadTreeConfig>(tag: u8) -> Option<u8> {
+    if P::LEGACY_ENCODING {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+/// Stores the sibling hashes of a particular path (in order) from leaf to root, three per level.
+/// The position at each level is given by the corresponding two bits of the leaf index.
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseQuadTreeConfig"),
+    Debug(bound = "P: MerkleSparseQuadTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct MerkleSparseQuadTreePath<P: MerkleSparseQuadTreeConfig> {
+    pub(crate) path: Vec<<P::H as CRHforMerkleTree>::Output>,
+}
+
+/// A modifying proof, consisting of the old leaf hash and the siblings shared by both paths
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MerkleSparseQuadTreeTwoPaths<P: MerkleSparseQuadTreeConfig> {
+    pub(crate) old_leaf_hash: <P::H as CRHforMerkleTree>::Output,
+    pub(crate) path: MerkleSparseQuadTreePath<P>,
+}
+
+/// A multi-opening of several leaves, storing only the sibling hashes that cannot be
+/// recomputed from the opened leaves, level by level from the leaves, in the order of the index
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseQuadTreeConfig"),
+    Debug(bound = "P: MerkleSparseQuadTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct MerkleSparseQuadTreeMultiPath<P: MerkleSparseQuadTreeConfig> {
+    pub(crate) auxiliary: Vec<<P::H as CRHforMerkleTree>::Output>,
+}
+
+impl<P: MerkleSparseQuadTreeConfig> Default for MerkleSparseQuadTreePath<P> {
+    fn default() -> Self {
+        let mut path = Vec::with_capacity(3 * P::HEIGHT as usize);
+        for _i in 3..3 * P::HEIGHT as usize {
+            path.push(<P::H as CRHforMerkleTree>::Output::default());
+        }
+        Self { path }
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> ToBytes for MerkleSparseQuadTreePath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        for p in &self.path {
+            p.write(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> Default for MerkleSparseQuadTreeTwoPaths<P> {
+    fn default() -> Self {
+        let old_leaf_hash = <P::H as CRHforMerkleTree>::Output::default();
+        let path: MerkleSparseQuadTreePath<P> = MerkleSparseQuadTreePath::default();
+        Self {
+            old_leaf_hash,
+            path,
+        }
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> ToBytes for MerkleSparseQuadTreeTwoPaths<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.old_leaf_hash.write(&mut writer)?;
+        self.path.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> Clone for MerkleSparseQuadTreeTwoPaths<P> {
+    fn clone(&self) -> Self {
+        MerkleSparseQuadTreeTwoPaths {
+            old_leaf_hash: self.old_leaf_hash.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> Default for MerkleSparseQuadTreeMultiPath<P> {
+    fn default() -> Self {
+        Self {
+            auxiliary: Vec::new(),
+        }
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> ToBytes for MerkleSparseQuadTreeMultiPath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        for hash in &self.auxiliary {
+            hash.write(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> MerkleSparseQuadTreePath<P> {
+    /// verify the lookup proof, given the location
+    pub fn verify_with_index<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        index: u64,
+    ) -> Result<bool, Error> {
+        let claimed_leaf_hash = hash_leaf::<P, L>(parameters, leaf)?;
+        self.verify_leaf_hash_with_index(parameters, root_hash, claimed_leaf_hash, index)
+    }
+
+    /// verify the absence proof, i.e., the location still holds the empty leaf
+    pub fn verify_absence_with_index<L: Default + ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        index: u64,
+    ) -> Result<bool, Error> {
+        let empty_leaf_hash = hash_empty::<P, L>(parameters, L::default())?;
+        self.verify_leaf_hash_with_index(parameters, root_hash, empty_leaf_hash, index)
+    }
+
+    fn verify_leaf_hash_with_index(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        claimed_leaf_hash: <P::H as CRHforMerkleTree>::Output,
+        index: u64,
+    ) -> Result<bool, Error> {
+        if self.path.len() != 3 * (P::HEIGHT - 1) as usize || self.path.is_empty() {
+            return Ok(false);
+        }
+        // Check that the index is a location in the last level.
+        if index >= num_leaves(P::HEIGHT) {
+            return Ok(false);
+        }
+
+        let mut prev = claimed_leaf_hash;
+        let mut prev_index = index;
+        // Check levels between leaf level and root, two index bits give the position.
+        for siblings in self.path.chunks(3) {
+            let children = place_child(siblings, &prev, (prev_index % 4) as usize);
+            prev = hash_inner_node::<P>(parameters, &children)?;
+            prev_index >>= 2;
+        }
+
+        Ok(root_hash == &prev)
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> MerkleSparseQuadTreeTwoPaths<P> {
+    /// verify the modifying proof
+    pub fn verify<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        new_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        index: u64,
+    ) -> Result<bool, Error> {
+        // Check the new path first
+        let claimed_leaf_hash = hash_leaf::<P, L>(parameters, leaf)?;
+        if !self.path.verify_leaf_hash_with_index(
+            parameters,
+            new_root_hash,
+            claimed_leaf_hash,
+            index,
+        )? {
+            return Ok(false);
+        }
+
+        // The siblings are unchanged, so the old path only differs in the leaf hash
+        self.path.verify_leaf_hash_with_index(
+            parameters,
+            old_root_hash,
+            self.old_leaf_hash.clone(),
+            index,
+        )
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> MerkleSparseQuadTreeMultiPath<P> {
+    /// verify the multi-opening, given the leaves and their locations
+    pub fn verify_with_indices<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaves: &[L],
+        indices: &[u64],
+    ) -> Result<bool, Error> {
+        if leaves.is_empty() || leaves.len() != indices.len() {
+            return Ok(false);
+        }
+
+        // Hash the leaves, rejecting an index opened to two different leaves.
+        let mut current_level: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
+        for (leaf, index) in leaves.iter().zip(indices.iter()) {
+            if *index >= num_leaves(P::HEIGHT) {
+                return Ok(false);
+            }
+
+            let leaf_hash = hash_leaf::<P, L>(parameters, leaf)?;
+            let tree_index = convert_index_to_last_level(*index, P::HEIGHT);
+            if let Some(prev) = current_level.insert(tree_index, leaf_hash.clone()) {
+                if prev != leaf_hash {
+                    return Ok(false);
+                }
+            }
+        }
+
+        // Recompute the nodes level by level, taking the missing children from the proof.
+        let mut auxiliary_iter = self.auxiliary.iter();
+        for _ in 1..P::HEIGHT {
+            let mut next_level: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
+            for current_node in current_level.keys() {
+                let parent_node = parent(*current_node).unwrap();
+                if next_level.contains_key(&parent_node) {
+                    continue;
+                }
+
+                let mut children = Vec::with_capacity(4);
+                for position in 0..4 {
+                    match current_level.get(&child(parent_node, position)) {
+                        Some(x) => children.push(x.clone()),
+                        None => match auxiliary_iter.next() {
+                            Some(x) => children.push(x.clone()),
+                            None => return Ok(false),
+                        },
+                    }
+                }
+
+                next_level.insert(parent_node, hash_inner_node::<P>(parameters, &children)?);
+            }
+            current_level = next_level;
+        }
+
+        if auxiliary_iter.next().is_some() {
+            return Ok(false);
+        }
+
+        match current_level.get(&0) {
+            Some(x) => Ok(x == root_hash),
+            None => Ok(false),
+        }
+    }
+}
+
+/// 4-ary Merkle sparse tree
+pub struct MerkleSparseQuadTree<P: MerkleSparseQuadTreeConfig> {
+    /// data of the tree
+    pub tree: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+    parameters: <P::H as CRHforMerkleTree>::Parameters,
+    root: Option<<P::H as CRHforMerkleTree>::Output>,
+    empty_hashes: Vec<<P::H as CRHforMerkleTree>::Output>,
+}
+
+impl<P: MerkleSparseQuadTreeConfig> Clone for MerkleSparseQuadTree<P> {
+    fn clone(&self) -> Self {
+        MerkleSparseQuadTree {
+            tree: self.tree.clone(),
+            parameters: self.parameters.clone(),
+            root: self.root.clone(),
+            empty_hashes: self.empty_hashes.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseQuadTreeConfig> MerkleSparseQuadTree<P> {
+    /// tree height
+    pub const HEIGHT: u64 = P::HEIGHT;
+
+    /// obtain an empty tree
+    pub fn blank<L: Default + ToBytes>(parameters: <P::H as CRHforMerkleTree>::Parameters) -> Self {
+        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default()).unwrap();
+
+        MerkleSparseQuadTree {
+            tree: BTreeMap::new(),
+            parameters,
+            root: Some(empty_hashes[(P::HEIGHT - 1) as usize].clone()),
+            empty_hashes,
+        }
+    }
+
+    /// initialize a tree (with optional data)
+    pub fn new<L: Default + ToBytes>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        leaves: &BTreeMap<u64, L>,
+    ) -> Result<Self, Error> {
+        if leaves.is_empty() {
+            return Ok(Self::blank::<L>(parameters));
+        }
+
+        // Initialize the merkle tree.
+        let mut tree: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
+        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default())?;
+
+        // Compute and store the hash values for each leaf.
+        let mut middle_nodes: BTreeSet<u64> = BTreeSet::new();
+        for (i, leaf) in leaves.iter() {
+            if *i >= num_leaves(Self::HEIGHT) {
+                return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+            }
+
+            let tree_index = convert_index_to_last_level(*i, Self::HEIGHT);
+            tree.insert(tree_index, hash_leaf::<P, _>(&parameters, leaf)?);
+            middle_nodes.insert(parent(tree_index).unwrap());
+        }
+
+        // Compute the hash values for every node in parts of the tree.
+        for level in 0..(Self::HEIGHT - 1) as usize {
+            // Iterate over the current level.
+            for current_index in &middle_nodes {
+                let hash =
+                    hash_children::<P>(&parameters, &tree, &empty_hashes[level], *current_index)?;
+                tree.insert(*current_index, hash);
+            }
+
+            let tmp_middle_nodes = middle_nodes.clone();
+            middle_nodes.clear();
+            for i in tmp_middle_nodes {
+                if !is_root(i) {
+                    middle_nodes.insert(parent(i).unwrap());
+                }
+            }
+        }
+
+        let root_hash = match tree.get(&0) {
+            Some(x) => (*x).clone(),
+            _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
+        };
+
+        Ok(MerkleSparseQuadTree {
+            tree,
+            parameters,
+            root: Some(root_hash),
+            empty_hashes,
+        })
+    }
+
+    #[inline]
+    /// obtain the root hash
+    pub fn root(&self) -> <P::H as CRHforMerkleTree>::Output {
+        self.root.clone().unwrap()
+    }
+
+    /// write the nodes of the tree, without the CRH parameters
+    pub fn serialize_nodes<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        (self.tree.len() as u64).serialize(&mut writer)?;
+        for (index, hash) in self.tree.iter() {
+            index.serialize(&mut writer)?;
+            hash.serialize(&mut writer)?;
+        }
+        self.root.serialize(&mut writer)?;
+
+        Ok(())
+    }
+
+    /// restore a tree from the nodes written by `serialize_nodes` and the CRH parameters, where
+    /// the empty hashes are recomputed from the parameters and the leaf type `L`
+    pub fn deserialize_nodes<L: Default + ToBytes, R: Read>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        mut reader: R,
+    ) -> Result<Self, Error> {
+        let len = u64::deserialize(&mut reader)?;
+        let mut tree = BTreeMap::new();
+        for _ in 0..len {
+            let index = u64::deserialize(&mut reader)?;
+            let hash = <P::H as CRHforMerkleTree>::Output::deserialize(&mut reader)?;
+            tree.insert(index, hash);
+        }
+        let root = Option::<<P::H as CRHforMerkleTree>::Output>::deserialize(&mut reader)?;
+        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default())?;
+
+        Ok(MerkleSparseQuadTree {
+            tree,
+            parameters,
+            root,
+            empty_hashes,
+        })
+    }
+
+    /// generate a membership proof (does not check the data point)
+    pub fn generate_membership_proof(
+        &self,
+        index: u64,
+    ) -> Result<MerkleSparseQuadTreePath<P>, Error> {
+        let mut path = Vec::with_capacity(3 * (Self::HEIGHT - 1) as usize);
+
+        let tree_index = convert_index_to_last_level(index, Self::HEIGHT);
+
+        // Iterate from the leaf up to the root, storing the three siblings at each level.
+        let mut current_node = tree_index;
+        let mut level = 0;
+        while !is_root(current_node) {
+            let parent_node = parent(current_node).unwrap();
+
+            for position in 0..4 {
+                let sibling_node = child(parent_node, position);
+                if sibling_node != current_node {
+                    path.push(self.node_hash(sibling_node, level));
+                }
+            }
+
+            current_node = parent_node;
+            level += 1;
+        }
+
+        if path.len() != 3 * (Self::HEIGHT - 1) as usize {
+            Err(MerkleSparseTreeError::IncorrectPathLength(path.len()).into())
+        } else {
+            Ok(MerkleSparseQuadTreePath { path })
+        }
+    }
+
+    /// generate a lookup proof
+    pub fn generate_proof<L: ToBytes>(
+        &self,
+        index: u64,
+        leaf: &L,
+    ) -> Result<MerkleSparseQuadTreePath<P>, Error> {
+        let leaf_hash = hash_leaf::<P, _>(&self.parameters, leaf)?;
+        let tree_index = convert_index_to_last_level(index, Self::HEIGHT);
+
+        // Check that the given index corresponds to the correct leaf.
+        if let Some(x) = self.tree.get(&tree_index) {
+            if leaf_hash != *x {
+                return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+            }
+        }
+
+        self.generate_membership_proof(index)
+    }
+
+    /// generate an absence proof, showing that the location has never been written
+    pub fn generate_absence_proof(&self, index: u64) -> Result<MerkleSparseQuadTreePath<P>, Error> {
+        let tree_index = convert_index_to_last_level(index, Self::HEIGHT);
+
+        // Check that the given index still holds the empty leaf.
+        if self.tree.contains_key(&tree_index) {
+            return Err(MerkleSparseTreeError::NonEmptyLeaf(index).into());
+        }
+
+        self.generate_membership_proof(index)
+    }
+
+    /// generate a multi-opening for several locations (does not check the data points)
+    pub fn generate_multi_membership_proof(
+        &self,
+        indices: &[u64],
+    ) -> Result<MerkleSparseQuadTreeMultiPath<P>, Error> {
+        let mut current_level: BTreeSet<u64> = BTreeSet::new();
+        for index in indices {
+            current_level.insert(convert_index_to_last_level(*index, Self::HEIGHT));
+        }
+
+        // Iterate from the leaves up to the root, storing the children outside the opened set.
+        let mut auxiliary = Vec::new();
+        for level in 0..(Self::HEIGHT - 1) as usize {
+            let mut next_level: BTreeSet<u64> = BTreeSet::new();
+            for current_node in &current_level {
+                let parent_node = parent(*current_node).unwrap();
+                if !next_level.insert(parent_node) {
+                    continue;
+                }
+
+                for position in 0..4 {
+                    let child_node = child(parent_node, position);
+                    if !current_level.contains(&child_node) {
+                        auxiliary.push(self.node_hash(child_node, level));
+                    }
+                }
+            }
+            current_level = next_level;
+        }
+
+        Ok(MerkleSparseQuadTreeMultiPath { auxiliary })
+    }
+
+    /// update the tree and provide a modifying proof
+    pub fn update_and_prove<L: ToBytes>(
+        &mut self,
+        index: u64,
+        new_leaf: &L,
+    ) -> Result<MerkleSparseQuadTreeTwoPaths<P>, Error> {
+        let path = self.generate_membership_proof(index)?;
+
+        let tree_index = convert_index_to_last_level(index, Self::HEIGHT);
+        let old_leaf_hash = self.node_hash(tree_index, 0);
+        let new_leaf_hash = hash_leaf::<P, _>(&self.parameters, new_leaf)?;
+
+        // Update the leaf and update the parents
+        self.tree.insert(tree_index, new_leaf_hash);
+
+        let mut current_node = tree_index;
+        let mut level = 0;
+        while !is_root(current_node) {
+            current_node = parent(current_node).unwrap();
+
+            let hash = hash_children::<P>(
+                &self.parameters,
+                &self.tree,
+                &self.empty_hashes[level],
+                current_node,
+            )?;
+            self.tree.insert(current_node, hash);
+
+            level += 1;
+        }
+
+        match self.tree.get(&0) {
+            Some(x) => self.root = Some((*x).clone()),
+            None => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
+        }
+
+        // Updating the leaf does not change the siblings, so the path is shared.
+        Ok(MerkleSparseQuadTreeTwoPaths {
+            old_leaf_hash,
+            path,
+        })
+    }
+
+    /// check if the tree is structurally valid
+    pub fn validate(&self) -> Result<bool, Error> {
+        /* Finding the leaf nodes */
+        let last_level_index = convert_index_to_last_level(0, Self::HEIGHT);
+        let mut middle_nodes: BTreeSet<u64> = BTreeSet::new();
+
+        for key in self.tree.keys() {
+            if *key >= last_level_index && !is_root(*key) {
+                middle_nodes.insert(parent(*key).unwrap());
+            }
+        }
+
+        for level in 0..(Self::HEIGHT - 1) as usize {
+            for current_index in &middle_nodes {
+                let hash = hash_children::<P>(
+                    &self.parameters,
+                    &self.tree,
+                    &self.empty_hashes[level],
+                    *current_index,
+                )?;
+
+                match self.tree.get(current_index) {
+                    Some(x) => {
+                        if *x != hash {
+                            return Ok(false);
+                        }
+                    }
+                    _ => {
+                        return Ok(false);
+                    }
+                }
+            }
+
+            let tmp_middle_nodes = middle_nodes.clone();
+            middle_nodes.clear();
+            for i in tmp_middle_nodes {
+                if !is_root(i) {
+                    middle_nodes.insert(parent(i).unwrap());
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the hash of a node, which is the empty hash of its level if never written.
+    fn node_hash(&self, index: u64, level: usize) -> <P::H as CRHforMerkleTree>::Output {
+        match self.tree.get(&index) {
+            Some(x) => x.clone(),
+            None => self.empty_hashes[level].clone(),
+        }
+    }
+}
+
+/// Returns the number of leaves, given the height of the tree.
+#[inline]
+fn num_leaves(tree_height: u64) -> u64 {
+    1u64 << (2 * (tree_height - 1))
+}
+
+/// Returns true iff the index represents the root.
+#[inline]
+fn is_root(index: u64) -> bool {
+    index == 0
+}
+
+/// Returns the index of the child at the position (from 0 to 3), given an index.
+#[inline]
+fn child(index: u64, position: u64) -> u64 {
+    4 * index + 1 + position
+}
+
+/// Returns the index of the parent, given an index.
+#[inline]
+fn parent(index: u64) -> Option<u64> {
+    if index > 0 {
+        Some((index - 1) >> 2)
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn convert_index_to_last_level(index: u64, tree_height: u64) -> u64 {
+    index + (num_leaves(tree_height) - 1) / 3
+}
+
+/// Returns the four children, placing the child at the position among the three siblings.
+fn place_child<T: Clone>(siblings: &[T], child: &T, position: usize) -> Vec<T> {
+    let mut children = Vec::with_capacity(4);
+    children.extend_from_slice(&siblings[..position]);
+    children.push(child.clone());
+    children.extend_from_slice(&siblings[position..]);
+    children
+}
+
+/// Returns the hash of a leaf.
+pub(crate) fn hash_leaf<P: MerkleSparseQuadTreeConfig, L: ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &L,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_with_tag::<P::H, L>(parameters, domain_tag::<P>(LEAF_TAG), leaf)
+}
+
+/// Returns the hash of an empty leaf, which is the hash of the default leaf.
+pub(crate) fn hash_empty<P: MerkleSparseQuadTreeConfig, L: ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    empty_leaf: L,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_leaf::<P, L>(parameters, &empty_leaf)
+}
+
+/// Returns the output hash, given the four children.
+pub(crate) fn hash_inner_node<P: MerkleSparseQuadTreeConfig>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    children: &[<P::H as CRHforMerkleTree>::Output],
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    match domain_tag::<P>(INNER_NODE_TAG) {
+        Some(tag) => P::H::four_to_one_compress_with_tag(parameters, tag, children),
+        None => P::H::four_to_one_compress(parameters, children),
+    }
+}
+
+/// Returns the hash of a node from its children in the tree, using the empty hash for the others.
+fn hash_children<P: MerkleSparseQuadTreeConfig>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    tree: &BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+    empty_hash: &<P::H as CRHforMerkleTree>::Output,
+    index: u64,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    let mut children = Vec::with_capacity(4);
+    for position in 0..4 {
+        match tree.get(&child(index, position)) {
+            Some(x) => children.push(x.clone()),
+            None => children.push(empty_hash.clone()),
+        }
+    }
+
+    hash_inner_node::<P>(parameters, &children)
+}
+
+fn gen_empty_hashes<P: MerkleSparseQuadTreeConfig, L: ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    empty_leaf: L,
+) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+    let mut empty_hashes = Vec::with_capacity(P::HEIGHT as usize);
+
+    let mut empty_hash = hash_empty::<P, L>(&parameters, empty_leaf)?;
+    empty_hashes.push(empty_hash.clone());
+
+    for _ in 1..P::HEIGHT {
+        let children = [
+            empty_hash.clone(),
+            empty_hash.clone(),
+            empty_hash.clone(),
+            empty_hash.clone(),
+        ];
+        empty_hash = hash_inner_node::<P>(&parameters, &children)?;
+        empty_hashes.push(empty_hash.clone());
+    }
+
+    Ok(empty_hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::building_blocks::mt::merkle_sparse_quad_tree::*;
+
+    use ark_ed_on_bls12_381::Fr;
+    use ark_ff::{ToBytes, Zero};
+
+    use crate::building_blocks::crh::poseidon::PoseidonCRHforMerkleTree;
+    use ark_std::collections::BTreeMap;
+    use rand_chacha::ChaChaRng;
+
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+
+    #[derive(Debug)]
+    struct JubJubMerkleQuadTreeParams;
+
+    impl MerkleSparseQuadTreeConfig for JubJubMerkleQuadTreeParams {
+        const HEIGHT: u64 = 16;
+        type H = H;
+    }
+    type JubJubMerkleQuadTree = MerkleSparseQuadTree<JubJubMerkleQuadTreeParams>;
+
+    fn generate_merkle_tree_and_test_membership<L: Default + ToBytes + Clone + Eq>(
+        leaves: &BTreeMap<u64, L>,
+        use_bad_root: bool,
+    ) {
+        let mut rng = ark_std::test_rng();
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), leaves).unwrap();
+        let root = if use_bad_root {
+            Fr::zero()
+        } else {
+            tree.root()
+        };
+        for (i, leaf) in leaves.iter() {
+            let proof = tree.generate_proof(*i, &leaf).unwrap();
+            assert!(proof
+                .verify_with_index(&crh_parameters, &root, &leaf, *i)
+                .unwrap());
+        }
+
+        assert!(tree.validate().unwrap());
+    }
+
+    #[test]
+    fn good_root_membership_test() {
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..4u8 {
+            leaves.insert(i as u64, i);
+        }
+        generate_merkle_tree_and_test_membership(&leaves, false);
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..100u8 {
+            leaves.insert(i as u64, i);
+        }
+        generate_merkle_tree_and_test_membership(&leaves, false);
+    }
+
+    #[should_panic]
+    #[test]
+    fn bad_root_membership_test() {
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..100u8 {
+            leaves.insert(i as u64, i);
+        }
+        generate_merkle_tree_and_test_membership(&leaves, true);
+    }
+
+    #[test]
+    fn absence_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..10u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        for i in 10..20u64 {
+            let proof = tree.generate_absence_proof(i).unwrap();
+            assert!(proof
+                .verify_absence_with_index::<u8>(&crh_parameters, &root, i)
+                .unwrap());
+            assert!(!proof
+                .verify_absence_with_index::<u8>(&crh_parameters, &root, i + 1)
+                .unwrap());
+        }
+
+        for i in 0..10u64 {
+            assert!(tree.generate_absence_proof(i).is_err());
+
+            let proof = tree.generate_membership_proof(i).unwrap();
+            assert!(!proof
+                .verify_absence_with_index::<u8>(&crh_parameters, &root, i)
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn multi_membership_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..20u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        let indices = [0u64, 1, 7, 19, 1000];
+        let data = [1u8, 2, 8, 20, 0];
+        let proof = tree.generate_multi_membership_proof(&indices).unwrap();
+        assert!(proof
+            .verify_with_indices(&crh_parameters, &root, &data, &indices)
+            .unwrap());
+        assert!(!proof
+            .verify_with_indices(&crh_parameters, &root, &[1u8, 2, 8, 21, 0], &indices)
+            .unwrap());
+        assert!(!proof
+            .verify_with_indices(&crh_parameters, &root, &data, &[0u64, 1, 7, 18, 1000])
+            .unwrap());
+    }
+
+    #[test]
+    fn good_root_update_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut old_leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..10u8 {
+            old_leaves.insert(i as u64, i);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let mut tree = JubJubMerkleQuadTree::new(crh_parameters.clone(), &old_leaves).unwrap();
+        for i in 0..20u64 {
+            let new_leaf = i as u8 + 1;
+
+            let old_root = tree.root();
+            let update_proof = tree.update_and_prove(i, &new_leaf).unwrap();
+            let new_root = tree.root();
+
+            assert!(update_proof
+                .verify(&crh_parameters, &old_root, &new_root, &new_leaf, i)
+                .unwrap());
+            assert!(!update_proof
+                .verify(&crh_parameters, &new_root, &new_root, &new_leaf, i)
+                .unwrap());
+
+            let new_leaf_membership_proof = tree.generate_proof(i, &new_leaf).unwrap();
+            assert!(new_leaf_membership_proof
+                .verify_with_index(&crh_parameters, &new_root, &new_leaf, i)
+                .unwrap());
+        }
+
+        assert!(tree.validate().unwrap());
+    }
+
+    #[test]
+    fn domain_separation_test() {
+        use crate::building_blocks::crh::blake2s::Blake2sCRHforMerkleTree;
+
+        #[derive(Debug)]
+        struct TaggedParams;
+
+        impl MerkleSparseQuadTreeConfig for TaggedParams {
+            const HEIGHT: u64 = 3;
+            type H = Blake2sCRHforMerkleTree;
+        }
+
+        #[derive(Debug)]
+        struct LegacyParams;
+
+        impl MerkleSparseQuadTreeConfig for LegacyParams {
+            const HEIGHT: u64 = 3;
+            type H = Blake2sCRHforMerkleTree;
+            const LEGACY_ENCODING: bool = true;
+        }
+
+        let mut children = Vec::new();
+        let mut children_bytes = Vec::new();
+        for i in 1..5u8 {
+            let hash = hash_leaf::<TaggedParams, u8>(&(), &i).unwrap();
+            children_bytes.extend_from_slice(&hash.0);
+            children.push(hash);
+        }
+
+        /* under the legacy encoding, a leaf holding four hashes collides with their parent */
+        assert!(
+            hash_leaf::<LegacyParams, Vec<u8>>(&(), &children_bytes).unwrap()
+                == hash_inner_node::<LegacyParams>(&(), &children).unwrap()
+        );
+        assert!(
+            hash_leaf::<TaggedParams, Vec<u8>>(&(), &children_bytes).unwrap()
+                != hash_inner_node::<TaggedParams>(&(), &children).unwrap()
+        );
+
+        /* the empty leaf still hashes as the default leaf */
+        assert!(
+            hash_leaf::<TaggedParams, u8>(&(), &0u8).unwrap()
+                == hash_empty::<TaggedParams, u8>(&(), 0u8).unwrap()
+        );
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..4u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+        let tagged_tree = MerkleSparseQuadTree::<TaggedParams>::new((), &leaves).unwrap();
+        let legacy_tree = MerkleSparseQuadTree::<LegacyParams>::new((), &leaves).unwrap();
+        assert!(tagged_tree.root() != legacy_tree.root());
+
+        for (i, leaf) in leaves.iter() {
+            let proof = tagged_tree.generate_proof(*i, leaf).unwrap();
+            assert!(proof
+                .verify_with_index(&(), &tagged_tree.root(), leaf, *i)
+                .unwrap());
+            let proof = legacy_tree.generate_proof(*i, leaf).unwrap();
+            assert!(proof
+                .verify_with_index(&(), &legacy_tree.root(), leaf, *i)
+                .unwrap());
+        }
+
+        /* an unwritten leaf opens to the default leaf */
+        let proof = tagged_tree.generate_proof(7, &0u8).unwrap();
+        assert!(proof
+            .verify_with_index(&(), &tagged_tree.root(), &0u8, 7)
+            .unwrap());
+    }
+}
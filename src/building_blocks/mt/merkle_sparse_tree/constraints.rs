@@ -1,12 +1,14 @@
 use ark_ff::PrimeField;
-use ark_r1cs_std::{boolean::AllocatedBit, prelude::*, uint64::UInt64};
+use ark_r1cs_std::{prelude::*, uint64::UInt64};
 use ark_relations::r1cs::{ConstraintSystemRef, Namespace, SynthesisError};
 
 use crate::building_blocks::crh::CRHforMerkleTreeGadget;
 use crate::building_blocks::mt::merkle_sparse_tree::*;
+use crate::building_blocks::mt::LeafEncodingVar;
+use crate::gadgets::FpVar;
 use ark_std::borrow::Borrow;
 
-/// Gadgets for one Merkle tree path
+/// Gadgets for one Merkle tree path, storing only the siblings
 #[derive(Debug)]
 pub struct MerkleSparseTreePathVar<P, HVar, ConstraintF>
 where
@@ -14,10 +16,10 @@ where
     HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
     ConstraintF: PrimeField,
 {
-    path: Vec<(HVar::OutputVar, HVar::OutputVar)>,
+    path: Vec<HVar::OutputVar>,
 }
 
-/// Gadgets for two Merkle tree paths
+/// Gadgets for two Merkle tree paths, which share the siblings
 #[derive(Debug)]
 pub struct MerkleSparseTreeTwoPathsVar<P, HVar, ConstraintF>
 where
@@ -25,8 +27,32 @@ where
     HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
     ConstraintF: PrimeField,
 {
-    old_path: Vec<(HVar::OutputVar, HVar::OutputVar)>,
-    new_path: Vec<(HVar::OutputVar, HVar::OutputVar)>,
+    old_leaf_hash: HVar::OutputVar,
+    path: MerkleSparseTreePathVar<P, HVar, ConstraintF>,
+}
+
+/// Gadgets for a multi-opening of several leaves
+#[derive(Debug)]
+pub struct MerkleSparseTreeMultiPathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    auxiliary: Vec<HVar::OutputVar>,
+}
+
+/// Gadgets for a batched opening of several leaves, whose locations are witnesses
+#[derive(Debug)]
+pub struct MerkleSparseTreeBatchPathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    pub(crate) empty: Vec<Boolean<ConstraintF>>,
+    frontier: Vec<HVar::OutputVar>,
+    paths: Vec<MerkleSparseTreePathVar<P, HVar, ConstraintF>>,
 }
 
 impl<P, CRHVar, ConstraintF> MerkleSparseTreePathVar<P, CRHVar, ConstraintF>
@@ -35,119 +61,210 @@ where
     ConstraintF: PrimeField,
     CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
 {
-    /// check a lookup proof (does not enforce index consistency)
+    /// check a lookup proof, which always fails since the path only keeps the siblings and no
+    /// longer records its location
+    #[deprecated(
+        note = "the path no longer records its location, so it cannot be checked without it; use `check_membership_with_index`"
+    )]
     pub fn check_membership(
         &self,
-        cs: ConstraintSystemRef<ConstraintF>,
+        _cs: ConstraintSystemRef<ConstraintF>,
+        _parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        _root: &CRHVar::OutputVar,
+        _leaf: impl ToBytesGadget<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        Err(SynthesisError::Unsatisfiable)
+    }
+
+    /// conditionally check a lookup proof, which always fails like `check_membership`
+    #[deprecated(
+        note = "the path no longer records its location, so it cannot be checked without it; use `conditionally_check_membership_with_index`"
+    )]
+    pub fn conditionally_check_membership(
+        &self,
+        _cs: ConstraintSystemRef<ConstraintF>,
+        _parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        _root: &CRHVar::OutputVar,
+        _leaf: impl ToBytesGadget<ConstraintF>,
+        _should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        Err(SynthesisError::Unsatisfiable)
+    }
+
+    /// check a lookup proof (with index)
+    pub fn check_membership_with_index(
+        &self,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
         root: &CRHVar::OutputVar,
         leaf: impl ToBytesGadget<ConstraintF>,
+        index: &UInt64<ConstraintF>,
     ) -> Result<(), SynthesisError> {
-        self.conditionally_check_membership(cs, parameters, root, leaf, &Boolean::Constant(true))
+        self.conditionally_check_membership_with_index(
+            parameters,
+            root,
+            leaf,
+            index,
+            &Boolean::Constant(true),
+        )
     }
 
-    /// conditionally check a lookup proof (does not enforce index consistency)
-    pub fn conditionally_check_membership(
+    /// conditionally check a lookup proof (with index)
+    pub fn conditionally_check_membership_with_index(
         &self,
-        cs: ConstraintSystemRef<ConstraintF>,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
         root: &CRHVar::OutputVar,
         leaf: impl ToBytesGadget<ConstraintF>,
+        index: &UInt64<ConstraintF>,
         should_enforce: &Boolean<ConstraintF>,
     ) -> Result<(), SynthesisError> {
-        assert_eq!(self.path.len(), (P::HEIGHT - 1) as usize);
         // Check that the hash of the given leaf matches the leaf hash in the membership
         // proof.
         let leaf_bits = leaf.to_bytes()?;
-        let leaf_hash = CRHVar::hash_bytes(parameters, &leaf_bits)?;
-
-        // Check if leaf is one of the bottom-most siblings.
-        let leaf_is_left = Boolean::Is(AllocatedBit::new_witness(
-            ark_relations::ns!(cs, "leaf_is_left"),
-            || Ok(leaf_hash.value()? == self.path[0].0.value()?),
-        )?);
-
-        leaf_hash.conditional_enforce_equal(
-            &CRHVar::OutputVar::conditionally_select(
-                &leaf_is_left,
-                &self.path[0].0,
-                &self.path[0].1,
-            )?,
-            should_enforce,
-        )?;
+        let leaf_hash = hash_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, &leaf_bits)?;
 
-        // Check levels between leaf level and root.
-        let mut previous_hash = leaf_hash;
-        for &(ref left_hash, ref right_hash) in self.path.iter() {
-            // Check if the previous_hash matches the correct current hash.
-            let previous_is_left = Boolean::Is(AllocatedBit::new_witness(
-                ark_relations::ns!(cs, "previous_is_left"),
-                || Ok(previous_hash.value()? == left_hash.value()?),
-            )?);
+        self.conditionally_check_leaf_hash_with_index(
+            parameters,
+            root,
+            leaf_hash,
+            index,
+            should_enforce,
+        )
+    }
 
-            previous_hash.conditional_enforce_equal(
-                &CRHVar::OutputVar::conditionally_select(&previous_is_left, left_hash, right_hash)?,
-                should_enforce,
-            )?;
+    /// check a lookup proof (with index) of a leaf given as field elements
+    pub fn check_membership_with_field_elements(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf: &[FpVar<ConstraintF>],
+        index: &UInt64<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_membership_with_field_elements(
+            parameters,
+            root,
+            leaf,
+            index,
+            &Boolean::Constant(true),
+        )
+    }
 
-            previous_hash = hash_inner_node_gadget::<P::H, CRHVar, ConstraintF>(
-                parameters, left_hash, right_hash,
-            )?;
-        }
+    /// conditionally check a lookup proof (with index) of a leaf given as field elements
+    pub fn conditionally_check_membership_with_field_elements(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf: &[FpVar<ConstraintF>],
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        // Hash the field elements directly, without decomposing them into bytes.
+        let leaf_hash = hash_field_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, leaf)?;
 
-        root.conditional_enforce_equal(&previous_hash, should_enforce)
+        self.conditionally_check_leaf_hash_with_index(
+            parameters,
+            root,
+            leaf_hash,
+            index,
+            should_enforce,
+        )
     }
 
-    /// check a lookup proof (with index)
-    pub fn check_membership_with_index(
+    /// check an absence proof (with index), i.e., the location has never been written
+    pub fn check_absence_with_index<L: Default + ToBytes>(
         &self,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
         root: &CRHVar::OutputVar,
-        leaf: impl ToBytesGadget<ConstraintF>,
         index: &UInt64<ConstraintF>,
     ) -> Result<(), SynthesisError> {
-        self.conditionally_check_membership_with_index(
+        self.conditionally_check_absence_with_index::<L>(
             parameters,
             root,
-            leaf,
             index,
             &Boolean::Constant(true),
         )
     }
 
-    /// conditionally check a lookup proof (with index)
-    pub fn conditionally_check_membership_with_index(
+    /// conditionally check an absence proof (with index)
+    pub fn conditionally_check_absence_with_index<L: Default + ToBytes>(
         &self,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
         root: &CRHVar::OutputVar,
-        leaf: impl ToBytesGadget<ConstraintF>,
         index: &UInt64<ConstraintF>,
         should_enforce: &Boolean<ConstraintF>,
     ) -> Result<(), SynthesisError> {
-        assert_eq!(self.path.len(), (P::HEIGHT - 1) as usize);
-        // Check that the hash of the given leaf matches the leaf hash in the membership
-        // proof.
-        let leaf_bits = leaf.to_bytes()?;
-        let leaf_hash = CRHVar::hash_bytes(parameters, &leaf_bits)?;
+        // The empty leaf hash is fixed by the parameters, so it is a constant.
+        let empty_leaf_hash = hash_empty::<P, L>(parameters, L::default())
+            .map_err(|_| SynthesisError::Unsatisfiable)?;
+        let empty_leaf_hash_g =
+            CRHVar::OutputVar::new_constant(ConstraintSystemRef::None, empty_leaf_hash)?;
+
+        self.conditionally_check_leaf_hash_with_index(
+            parameters,
+            root,
+            empty_leaf_hash_g,
+            index,
+            should_enforce,
+        )
+    }
+
+    pub(crate) fn conditionally_check_leaf_hash_with_index(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf_hash: CRHVar::OutputVar,
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        if self.path.len() != (P::HEIGHT - 1) as usize {
+            return Err(SynthesisError::Unsatisfiable);
+        }
 
         // Check levels between leaf level and root.
-        let mut previous_hash = leaf_hash;
         let index_bits = index.to_bits_le();
-        for (i, &(ref left_hash, ref right_hash)) in self.path.iter().enumerate() {
-            // Check if the previous_hash matches the correct current hash.
+        let root_hash = self.node_above(
+            parameters,
+            leaf_hash,
+            &index_bits[..(P::HEIGHT - 1) as usize],
+        )?;
+
+        root.conditional_enforce_equal(&root_hash, should_enforce)
+    }
+
+    /// Returns the node reached from the leaf after one level per bit of the index.
+    fn node_above(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        leaf_hash: CRHVar::OutputVar,
+        index_bits: &[Boolean<ConstraintF>],
+    ) -> Result<CRHVar::OutputVar, SynthesisError> {
+        if self.path.len() != index_bits.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let mut previous_hash = leaf_hash;
+        for (i, sibling_hash) in self.path.iter().enumerate() {
+            // The index bit tells whether the previous_hash is the left or the right child.
             let previous_is_left = index_bits[i].not();
 
-            previous_hash.conditional_enforce_equal(
-                &CRHVar::OutputVar::conditionally_select(&previous_is_left, left_hash, right_hash)?,
-                should_enforce,
+            let left_hash = CRHVar::OutputVar::conditionally_select(
+                &previous_is_left,
+                &previous_hash,
+                sibling_hash,
+            )?;
+            let right_hash = CRHVar::OutputVar::conditionally_select(
+                &previous_is_left,
+                sibling_hash,
+                &previous_hash,
             )?;
 
-            previous_hash = hash_inner_node_gadget::<P::H, CRHVar, ConstraintF>(
-                parameters, left_hash, right_hash,
+            previous_hash = hash_inner_node_gadget::<P, CRHVar, ConstraintF>(
+                parameters,
+                &left_hash,
+                &right_hash,
             )?;
         }
 
-        root.conditional_enforce_equal(&previous_hash, should_enforce)
+        Ok(previous_hash)
     }
 }
 
@@ -157,6 +274,11 @@ where
     ConstraintF: PrimeField,
     CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
 {
+    /// Returns the hash of the old leaf, which the path binds to the old root.
+    pub(crate) fn old_leaf_hash(&self) -> &CRHVar::OutputVar {
+        &self.old_leaf_hash
+    }
+
     /// check a modifying proof
     pub fn check_update(
         &self,
@@ -186,98 +308,479 @@ where
         index: &UInt64<ConstraintF>,
         should_enforce: &Boolean<ConstraintF>,
     ) -> Result<(), SynthesisError> {
-        assert_eq!(self.old_path.len(), (P::HEIGHT - 1) as usize);
-        assert_eq!(self.new_path.len(), (P::HEIGHT - 1) as usize);
-        // Check that the hash of the given leaf matches the leaf hash in the membership
-        // proof.
+        // Check that the hash of the given leaf matches the new root.
         let new_leaf_bits = new_leaf.to_bytes()?;
-        let new_leaf_hash = CRHVar::hash_bytes(parameters, &new_leaf_bits)?;
-
-        // Check levels between leaf level and root of the new tree.
-        let mut previous_hash = new_leaf_hash;
-        let index_bits = index.to_bits_le();
-        for (i, &(ref left_hash, ref right_hash)) in self.new_path.iter().enumerate() {
-            // Check if the previous_hash matches the correct current hash.
-            let previous_is_left = index_bits[i].not();
+        let new_leaf_hash = hash_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, &new_leaf_bits)?;
 
-            previous_hash.conditional_enforce_equal(
-                &CRHVar::OutputVar::conditionally_select(&previous_is_left, left_hash, right_hash)?,
-                should_enforce,
-            )?;
+        self.conditionally_check_leaf_hash(
+            parameters,
+            old_root,
+            new_root,
+            new_leaf_hash,
+            index,
+            should_enforce,
+        )
+    }
 
-            previous_hash = hash_inner_node_gadget::<P::H, CRHVar, ConstraintF>(
-                parameters, left_hash, right_hash,
-            )?;
-        }
+    /// check a modifying proof of a leaf given as field elements
+    pub fn check_update_with_field_elements(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root: &CRHVar::OutputVar,
+        new_root: &CRHVar::OutputVar,
+        new_leaf: &[FpVar<ConstraintF>],
+        index: &UInt64<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_update_with_field_elements(
+            parameters,
+            old_root,
+            new_root,
+            new_leaf,
+            index,
+            &Boolean::Constant(true),
+        )
+    }
 
-        new_root.conditional_enforce_equal(&previous_hash, should_enforce)?;
+    /// conditionally check a modifying proof of a leaf given as field elements
+    pub fn conditionally_check_update_with_field_elements(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root: &CRHVar::OutputVar,
+        new_root: &CRHVar::OutputVar,
+        new_leaf: &[FpVar<ConstraintF>],
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        let new_leaf_hash = hash_field_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, new_leaf)?;
 
-        let mut old_path_iter = self.old_path.iter();
-        let old_path_first_entry = old_path_iter.next().unwrap();
+        self.conditionally_check_leaf_hash(
+            parameters,
+            old_root,
+            new_root,
+            new_leaf_hash,
+            index,
+            should_enforce,
+        )
+    }
 
-        previous_hash = hash_inner_node_gadget::<P::H, CRHVar, ConstraintF>(
+    pub(crate) fn conditionally_check_leaf_hash(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root: &CRHVar::OutputVar,
+        new_root: &CRHVar::OutputVar,
+        new_leaf_hash: CRHVar::OutputVar,
+        index: &UInt64<ConstraintF>,
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.path.conditionally_check_leaf_hash_with_index(
             parameters,
-            &old_path_first_entry.0,
-            &old_path_first_entry.1,
+            new_root,
+            new_leaf_hash,
+            index,
+            should_enforce,
         )?;
 
-        let mut current_loc = 1;
-        loop {
-            let pair = old_path_iter.next();
-
-            match pair {
-                Some((left_hash, right_hash)) => {
-                    // Check if the previous_hash matches the correct current hash.
-                    let previous_is_left = index_bits[current_loc].not();
-
-                    previous_hash.conditional_enforce_equal(
-                        &CRHVar::OutputVar::conditionally_select(
-                            &previous_is_left,
-                            left_hash,
-                            right_hash,
-                        )?,
-                        should_enforce,
-                    )?;
-
-                    previous_hash = hash_inner_node_gadget::<P::H, CRHVar, ConstraintF>(
-                        parameters, left_hash, right_hash,
-                    )?;
+        // The siblings are unchanged, so the old leaf hash must lead to the old root.
+        self.path.conditionally_check_leaf_hash_with_index(
+            parameters,
+            old_root,
+            self.old_leaf_hash.clone(),
+            index,
+            should_enforce,
+        )
+    }
+}
+
+impl<P, CRHVar, ConstraintF> MerkleSparseTreeMultiPathVar<P, CRHVar, ConstraintF>
+where
+    P: MerkleSparseTreeConfig,
+    ConstraintF: PrimeField,
+    CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    /// check a multi-opening, where the locations are fixed in the circuit
+    pub fn check_membership_with_indices<L: ToBytesGadget<ConstraintF>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaves: &[L],
+        indices: &[u64],
+    ) -> Result<(), SynthesisError> {
+        self.conditionally_check_membership_with_indices(
+            parameters,
+            root,
+            leaves,
+            indices,
+            &Boolean::Constant(true),
+        )
+    }
+
+    /// conditionally check a multi-opening, where the locations are fixed in the circuit
+    ///
+    /// the shape of the multi-opening depends on the locations, so they cannot be witnesses (the
+    /// batched opening takes them as witnesses); each inner node shared by several locations is
+    /// hashed only once
+    pub fn conditionally_check_membership_with_indices<L: ToBytesGadget<ConstraintF>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaves: &[L],
+        indices: &[u64],
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        let leaf_hashes = leaves
+            .iter()
+            .map(|leaf| hash_leaf_gadget::<P, CRHVar, ConstraintF>(parameters, &leaf.to_bytes()?))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.conditionally_check_leaf_hashes_with_indices(
+            parameters,
+            root,
+            &leaf_hashes,
+            indices,
+            should_enforce,
+        )
+    }
+
+    pub(crate) fn conditionally_check_leaf_hashes_with_indices(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf_hashes: &[CRHVar::OutputVar],
+        indices: &[u64],
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        if leaf_hashes.is_empty() || leaf_hashes.len() != indices.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // Place the leaf hashes, the same location must be opened to the same leaf.
+        let mut current_level: BTreeMap<u64, CRHVar::OutputVar> = BTreeMap::new();
+        for (leaf_hash, index) in leaf_hashes.iter().zip(indices.iter()) {
+            if *index >= 1u64 << (P::HEIGHT - 1) {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+
+            let leaf_hash = leaf_hash.clone();
+            let tree_index = convert_index_to_last_level(*index, P::HEIGHT);
+            if let Some(prev) = current_level.get(&tree_index) {
+                prev.conditional_enforce_equal(&leaf_hash, should_enforce)?;
+            } else {
+                current_level.insert(tree_index, leaf_hash);
+            }
+        }
+
+        // Compute the nodes level by level, in the same order as the native verifier.
+        let mut auxiliary_iter = self.auxiliary.iter();
+        for _ in 1..P::HEIGHT {
+            let mut next_level: BTreeMap<u64, CRHVar::OutputVar> = BTreeMap::new();
+            for (current_node, current_hash) in current_level.iter() {
+                let parent_node = parent(*current_node).ok_or(SynthesisError::Unsatisfiable)?;
+                if next_level.contains_key(&parent_node) {
+                    continue;
                 }
-                None => break,
+
+                // A multi-opening that does not match the locations is rejected.
+                let sibling_node = sibling(*current_node).ok_or(SynthesisError::Unsatisfiable)?;
+                let sibling_hash = match current_level.get(&sibling_node) {
+                    Some(x) => x,
+                    None => auxiliary_iter.next().ok_or(SynthesisError::Unsatisfiable)?,
+                };
+
+                let parent_hash = if is_left_child(*current_node) {
+                    hash_inner_node_gadget::<P, CRHVar, ConstraintF>(
+                        parameters,
+                        current_hash,
+                        sibling_hash,
+                    )?
+                } else {
+                    hash_inner_node_gadget::<P, CRHVar, ConstraintF>(
+                        parameters,
+                        sibling_hash,
+                        current_hash,
+                    )?
+                };
+                next_level.insert(parent_node, parent_hash);
             }
-            current_loc += 1;
+            current_level = next_level;
         }
 
-        old_path_iter = self.old_path.iter();
-        for (i, &(ref left_hash, ref right_hash)) in self.new_path.iter().enumerate() {
-            // Check if the previous_hash matches the correct current hash.
-            let previous_is_left = index_bits[i].not();
-            let previous_is_right = previous_is_left.not();
+        if auxiliary_iter.next().is_some() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
 
-            let old_path_corresponding_entry = old_path_iter.next().unwrap();
+        let root_hash = current_level.get(&0).ok_or(SynthesisError::Unsatisfiable)?;
+        root.conditional_enforce_equal(root_hash, should_enforce)
+    }
+}
 
-            right_hash
-                .conditional_enforce_equal(&old_path_corresponding_entry.1, &previous_is_left)?;
+impl<P, CRHVar, ConstraintF> MerkleSparseTreeBatchPathVar<P, CRHVar, ConstraintF>
+where
+    P: MerkleSparseTreeConfig,
+    ConstraintF: PrimeField,
+    CRHVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    /// conditionally check a batched opening, where the locations are witnesses and each entry is
+    /// only checked if it is active
+    ///
+    /// each leaf is hashed up to the frontier and compared with the frontier node selected by the
+    /// top bits of its location, and the frontier is hashed up to the root only once; a location
+    /// that has never been written opens to the default leaf
+    pub fn conditionally_check_membership_with_indices<L: ToBytesGadget<ConstraintF>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaves: &[L],
+        indices: &[UInt64<ConstraintF>],
+        active: &[Boolean<ConstraintF>],
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        let leaf_hashes = leaves
+            .iter()
+            .zip(self.empty.iter())
+            .map(|(leaf, empty)| {
+                hash_leaf_or_empty_gadget::<P, CRHVar, ConstraintF>(
+                    parameters,
+                    &leaf.to_bytes()?,
+                    empty,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.conditionally_check_leaf_hashes_with_indices(
+            parameters,
+            root,
+            &leaf_hashes,
+            indices,
+            active,
+            should_enforce,
+        )
+    }
+
+    /// like `conditionally_check_membership_with_indices`, for leaves given as field elements
+    pub fn conditionally_check_membership_with_field_elements_and_indices(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaves: &[Vec<FpVar<ConstraintF>>],
+        indices: &[UInt64<ConstraintF>],
+        active: &[Boolean<ConstraintF>],
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        let leaf_hashes = leaves
+            .iter()
+            .zip(self.empty.iter())
+            .map(|(leaf, empty)| {
+                hash_field_leaf_or_empty_gadget::<P, CRHVar, ConstraintF>(parameters, leaf, empty)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.conditionally_check_leaf_hashes_with_indices(
+            parameters,
+            root,
+            &leaf_hashes,
+            indices,
+            active,
+            should_enforce,
+        )
+    }
 
-            left_hash
-                .conditional_enforce_equal(&old_path_corresponding_entry.0, &previous_is_right)?;
+    pub(crate) fn conditionally_check_leaf_hashes_with_indices(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root: &CRHVar::OutputVar,
+        leaf_hashes: &[CRHVar::OutputVar],
+        indices: &[UInt64<ConstraintF>],
+        active: &[Boolean<ConstraintF>],
+        should_enforce: &Boolean<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        if leaf_hashes.len() != indices.len()
+            || leaf_hashes.len() != active.len()
+            || leaf_hashes.len() != self.paths.len()
+            || leaf_hashes.len() != self.empty.len()
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        if leaf_hashes.is_empty() {
+            return Ok(());
+        }
+        let depth =
+            frontier_depth::<P>(self.frontier.len()).ok_or(SynthesisError::Unsatisfiable)?;
+        let path_len = (P::HEIGHT - 1 - depth) as usize;
+
+        for (((leaf_hash, index), active_item), path) in leaf_hashes
+            .iter()
+            .zip(indices.iter())
+            .zip(active.iter())
+            .zip(self.paths.iter())
+        {
+            let index_bits = index.to_bits_le();
+            let node = path.node_above(parameters, leaf_hash.clone(), &index_bits[..path_len])?;
+
+            // Select the frontier node above the location, one bit of the location at a time.
+            let mut candidates = self.frontier.clone();
+            for bit in &index_bits[path_len..(P::HEIGHT - 1) as usize] {
+                candidates = candidates
+                    .chunks(2)
+                    .map(|pair| CRHVar::OutputVar::conditionally_select(bit, &pair[1], &pair[0]))
+                    .collect::<Result<Vec<_>, _>>()?;
+            }
+
+            let should_enforce_item = should_enforce.and(active_item)?;
+            node.conditional_enforce_equal(&candidates[0], &should_enforce_item)?;
+        }
+
+        // Hash the frontier up to the root once.
+        let mut current_level = self.frontier.clone();
+        while current_level.len() > 1 {
+            current_level = current_level
+                .chunks(2)
+                .map(|pair| {
+                    hash_inner_node_gadget::<P, CRHVar, ConstraintF>(parameters, &pair[0], &pair[1])
+                })
+                .collect::<Result<Vec<_>, _>>()?;
         }
 
-        old_root.conditional_enforce_equal(&previous_hash, should_enforce)
+        root.conditional_enforce_equal(&current_level[0], should_enforce)
     }
 }
 
-pub(crate) fn hash_inner_node_gadget<H, HG, ConstraintF>(
-    parameters: &H::Parameters,
+pub(crate) fn hash_inner_node_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
     left_child: &HG::OutputVar,
     right_child: &HG::OutputVar,
 ) -> Result<HG::OutputVar, SynthesisError>
 where
     ConstraintF: PrimeField,
-    H: CRHforMerkleTree,
-    HG: CRHforMerkleTreeGadget<H, ConstraintF>,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    match domain_tag::<P>(INNER_NODE_TAG) {
+        Some(tag) => HG::two_to_one_compress_with_tag(parameters, tag, left_child, right_child),
+        None => HG::two_to_one_compress(parameters, left_child, right_child),
+    }
+}
+
+/// Returns the hash of the bytes of a leaf, prefixed by its tag unless the legacy encoding is used.
+pub(crate) fn hash_leaf_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf_bytes: &[UInt8<ConstraintF>],
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    hash_leaf_or_empty_gadget::<P, HG, ConstraintF>(
+        parameters,
+        leaf_bytes,
+        &Boolean::constant(false),
+    )
+}
+
+/// Returns the hash of the bytes of a leaf, prefixed by the tag of the empty leaves if `empty`
+/// holds and by the tag of the leaves otherwise, unless the legacy encoding is used.
+pub(crate) fn hash_leaf_or_empty_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf_bytes: &[UInt8<ConstraintF>],
+    empty: &Boolean<ConstraintF>,
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    match (domain_tag::<P>(LEAF_TAG), domain_tag::<P>(EMPTY_TAG)) {
+        (Some(leaf_tag), Some(empty_tag)) => {
+            let tag = UInt8::conditionally_select(
+                empty,
+                &UInt8::constant(empty_tag),
+                &UInt8::constant(leaf_tag),
+            )?;
+            let mut bytes = vec![tag];
+            bytes.extend_from_slice(leaf_bytes);
+            HG::hash_bytes(parameters, &bytes)
+        }
+        _ => HG::hash_bytes(parameters, leaf_bytes),
+    }
+}
+
+/// Returns the hash of a leaf given as field elements, prefixed by its tag unless the legacy encoding is used.
+pub(crate) fn hash_field_leaf_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &[FpVar<ConstraintF>],
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    hash_field_leaf_or_empty_gadget::<P, HG, ConstraintF>(
+        parameters,
+        leaf,
+        &Boolean::constant(false),
+    )
+}
+
+/// Returns the hash of a leaf given as field elements, prefixed by the tag of the empty leaves
+/// if `empty` holds and by the tag of the leaves otherwise, unless the legacy encoding is used.
+pub(crate) fn hash_field_leaf_or_empty_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &[FpVar<ConstraintF>],
+    empty: &Boolean<ConstraintF>,
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    match (domain_tag::<P>(LEAF_TAG), domain_tag::<P>(EMPTY_TAG)) {
+        (Some(leaf_tag), Some(empty_tag)) => {
+            let tag = FpVar::conditionally_select(
+                empty,
+                &FpVar::constant(ConstraintF::from(u64::from(empty_tag))),
+                &FpVar::constant(ConstraintF::from(u64::from(leaf_tag))),
+            )?;
+            let mut elements = vec![tag];
+            elements.extend_from_slice(leaf);
+            HG::hash_field_elements(parameters, &elements)
+        }
+        _ => HG::hash_field_elements(parameters, leaf),
+    }
+}
+
+/// Returns the hash of a leaf in its encoding, as bytes or as field elements.
+pub(crate) fn hash_encoded_leaf_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &LeafEncodingVar<ConstraintF>,
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
 {
-    HG::two_to_one_compress(parameters, left_child, right_child)
+    hash_encoded_leaf_or_empty_gadget::<P, HG, ConstraintF>(
+        parameters,
+        leaf,
+        &Boolean::constant(false),
+    )
+}
+
+/// Returns the hash of a leaf in its encoding, in the domain of the empty leaves if `empty` holds.
+pub(crate) fn hash_encoded_leaf_or_empty_gadget<P, HG, ConstraintF>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &LeafEncodingVar<ConstraintF>,
+    empty: &Boolean<ConstraintF>,
+) -> Result<HG::OutputVar, SynthesisError>
+where
+    ConstraintF: PrimeField,
+    P: MerkleSparseTreeConfig,
+    HG: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+{
+    match leaf {
+        LeafEncodingVar::Bytes(bytes) => {
+            hash_leaf_or_empty_gadget::<P, HG, ConstraintF>(parameters, bytes, empty)
+        }
+        LeafEncodingVar::FieldElements(elements) => {
+            hash_field_leaf_or_empty_gadget::<P, HG, ConstraintF>(parameters, elements, empty)
+        }
+    }
 }
 
 impl<P, HVar, ConstraintF> AllocVar<MerkleSparseTreePath<P>, ConstraintF>
@@ -296,18 +799,13 @@ where
         let cs = ns.cs();
 
         let mut path = Vec::new();
-        for &(ref l, ref r) in f()?.borrow().path.iter() {
-            let l_hash = HVar::OutputVar::new_variable(
-                ark_relations::ns!(cs, "l_child"),
-                || Ok(l.clone()),
-                mode,
-            )?;
-            let r_hash = HVar::OutputVar::new_variable(
-                ark_relations::ns!(cs, "r_child"),
-                || Ok(r.clone()),
+        for sibling in f()?.borrow().path.iter() {
+            let sibling_hash = HVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "sibling"),
+                || Ok(sibling.clone()),
                 mode,
             )?;
-            path.push((l_hash, r_hash));
+            path.push(sibling_hash);
         }
         Ok(MerkleSparseTreePathVar { path })
     }
@@ -328,38 +826,111 @@ where
         let ns = cs.into();
         let cs = ns.cs();
 
-        let mut old_path = Vec::new();
-
         let t = f()?;
         let paths = t.borrow();
-        for &(ref l, ref r) in paths.old_path.path.iter() {
-            let l_hash = HVar::OutputVar::new_variable(
-                ark_relations::ns!(cs, "old_path_l_child"),
-                || Ok(l.clone()),
+
+        let old_leaf_hash = HVar::OutputVar::new_variable(
+            ark_relations::ns!(cs, "old_leaf_hash"),
+            || Ok(paths.old_leaf_hash.clone()),
+            mode,
+        )?;
+        let path = MerkleSparseTreePathVar::new_variable(
+            ark_relations::ns!(cs, "shared_path"),
+            || Ok(&paths.path),
+            mode,
+        )?;
+        Ok(MerkleSparseTreeTwoPathsVar {
+            old_leaf_hash,
+            path,
+        })
+    }
+}
+
+impl<P, HVar, ConstraintF> AllocVar<MerkleSparseTreeMultiPath<P>, ConstraintF>
+    for MerkleSparseTreeMultiPathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    fn new_variable<T: Borrow<MerkleSparseTreeMultiPath<P>>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut auxiliary = Vec::new();
+        for hash in f()?.borrow().auxiliary.iter() {
+            auxiliary.push(HVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "auxiliary"),
+                || Ok(hash.clone()),
                 mode,
-            )?;
-            let r_hash = HVar::OutputVar::new_variable(
-                ark_relations::ns!(cs, "old_path_r_child"),
-                || Ok(r.clone()),
+            )?);
+        }
+        Ok(MerkleSparseTreeMultiPathVar { auxiliary })
+    }
+}
+
+impl<P, HVar, ConstraintF> AllocVar<MerkleSparseTreeBatchPath<P>, ConstraintF>
+    for MerkleSparseTreeBatchPathVar<P, HVar, ConstraintF>
+where
+    P: MerkleSparseTreeConfig,
+    HVar: CRHforMerkleTreeGadget<P::H, ConstraintF>,
+    ConstraintF: PrimeField,
+{
+    fn new_variable<T: Borrow<MerkleSparseTreeBatchPath<P>>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let t = f()?;
+        let batch_path = t.borrow();
+
+        let mut empty = Vec::new();
+        for value in batch_path.empty.iter() {
+            empty.push(Boolean::new_variable(
+                ark_relations::ns!(cs, "empty"),
+                || Ok(*value),
                 mode,
-            )?;
-            old_path.push((l_hash, r_hash));
+            )?);
         }
-        let mut new_path = Vec::new();
-        for &(ref l, ref r) in paths.new_path.path.iter() {
-            let l_hash = HVar::OutputVar::new_variable(
-                ark_relations::ns!(cs, "new_path_l_child"),
-                || Ok(l.clone()),
+
+        let mut frontier = Vec::new();
+        for hash in batch_path.frontier.iter() {
+            frontier.push(HVar::OutputVar::new_variable(
+                ark_relations::ns!(cs, "frontier"),
+                || Ok(hash.clone()),
                 mode,
-            )?;
-            let r_hash = HVar::OutputVar::new_variable(
-                ark_relations::ns!(cs, "new_path_r_child"),
-                || Ok(r.clone()),
+            )?);
+        }
+
+        // The constraints cannot depend on which nodes the entries share, so each entry gets its
+        // own path, rebuilt from the shared siblings.
+        let depth =
+            frontier_depth::<P>(batch_path.frontier.len()).ok_or(SynthesisError::Unsatisfiable)?;
+        let path_len = (P::HEIGHT - 1 - depth) as usize;
+
+        let mut paths = Vec::new();
+        for entry in 0..batch_path.positions.len() {
+            let path = batch_path
+                .path(entry, path_len)
+                .ok_or(SynthesisError::Unsatisfiable)?;
+            paths.push(MerkleSparseTreePathVar::new_variable(
+                ark_relations::ns!(cs, "path"),
+                || Ok(path),
                 mode,
-            )?;
-            new_path.push((l_hash, r_hash));
+            )?);
         }
-        Ok(MerkleSparseTreeTwoPathsVar { old_path, new_path })
+        Ok(MerkleSparseTreeBatchPathVar {
+            empty,
+            frontier,
+            paths,
+        })
     }
 }
 
@@ -400,7 +971,6 @@ mod test {
             let cs_sys = ConstraintSystem::<Fq>::new();
             let cs = ConstraintSystemRef::new(cs_sys);
             let proof = tree.generate_proof(*i, &leaf).unwrap();
-            assert!(proof.verify(&crh_parameters, &root, &leaf).unwrap());
 
             // Allocate Merkle Tree Root
             let root = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
@@ -446,13 +1016,6 @@ mod test {
                 - constraints_from_leaf;
             println!("constraints from path: {}", constraints_from_path);
             let leaf_g: &[UInt8<Fq>] = leaf_g.as_slice();
-            cw.check_membership(
-                ark_relations::ns!(cs, "check_membership").cs(),
-                &crh_parameters,
-                &root,
-                &leaf_g,
-            )
-            .unwrap();
             cw.check_membership_with_index(&crh_parameters, &root, &leaf_g, &index_g)
                 .unwrap();
             if !cs.is_satisfied().unwrap() {
@@ -470,6 +1033,11 @@ mod test {
                 "number of constraints: {}",
                 cs.num_constraints() - setup_constraints
             );
+
+            // The path does not record its location, so the deprecated check without it fails.
+            #[allow(deprecated)]
+            let result = cw.check_membership(cs.clone(), &crh_parameters, &root, &leaf_g);
+            assert!(result.is_err());
         }
 
         assert!(satisfied);
@@ -611,6 +1179,185 @@ mod test {
         assert!(satisfied);
     }
 
+    #[test]
+    fn absence_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, [u8; 2]> = BTreeMap::new();
+        for i in 0..4u8 {
+            leaves.insert(i as u64, [i + 1; 2]);
+        }
+        /* a location holding the default leaf has been written, so it is not absent */
+        leaves.insert(5, [0u8; 2]);
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        for (i, expected) in [(4u64, true), (7u64, true), (2u64, false), (5u64, false)].iter() {
+            let cs_sys = ConstraintSystem::<Fq>::new();
+            let cs = ConstraintSystemRef::new(cs_sys);
+
+            /* an occupied leaf cannot be proven absent, so use its plain path */
+            let proof = tree.generate_membership_proof(*i).unwrap();
+
+            let root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+                ark_relations::ns!(cs, "root"),
+                || Ok(root),
+            )
+            .unwrap();
+            let index_g = UInt64::new_witness(ark_relations::ns!(cs, "index"), || Ok(*i)).unwrap();
+            let proof_g = MerkleSparseTreePathVar::<_, HG, _>::new_witness(
+                ark_relations::ns!(cs, "absence_proof"),
+                || Ok(proof),
+            )
+            .unwrap();
+
+            proof_g
+                .check_absence_with_index::<[u8; 2]>(&crh_parameters, &root_g, &index_g)
+                .unwrap();
+
+            assert_eq!(cs.is_satisfied().unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn field_elements_test() {
+        let mut rng = ark_std::test_rng();
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let mut tree = JubJubMerkleTree::blank::<[u8; 2]>(crh_parameters.clone());
+        let old_root = tree.root();
+
+        let leaf = vec![Fq::from(3u64), Fq::from(5u64)];
+        let proof = tree.update_field_elements_and_prove(7, &leaf).unwrap();
+        let new_root = tree.root();
+        assert!(proof
+            .verify_field_elements(&crh_parameters, &old_root, &new_root, &leaf, 7)
+            .unwrap());
+
+        let path = tree.generate_membership_proof(7).unwrap();
+        assert!(path
+            .verify_field_elements_with_index(&crh_parameters, &new_root, &leaf, 7)
+            .unwrap());
+
+        let cs_sys = ConstraintSystem::<Fq>::new();
+        let cs = ConstraintSystemRef::new(cs_sys);
+
+        let old_root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+            ark_relations::ns!(cs, "old_root"),
+            || Ok(old_root),
+        )
+        .unwrap();
+        let new_root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+            ark_relations::ns!(cs, "new_root"),
+            || Ok(new_root),
+        )
+        .unwrap();
+        let leaf_g =
+            Vec::<FpVar<Fq>>::new_witness(ark_relations::ns!(cs, "leaf"), || Ok(leaf.clone()))
+                .unwrap();
+        let index_g = UInt64::new_witness(ark_relations::ns!(cs, "index"), || Ok(7)).unwrap();
+        let path_g = MerkleSparseTreePathVar::<_, HG, _>::new_witness(
+            ark_relations::ns!(cs, "path"),
+            || Ok(path),
+        )
+        .unwrap();
+        let proof_g = MerkleSparseTreeTwoPathsVar::<_, HG, _>::new_witness(
+            ark_relations::ns!(cs, "proof"),
+            || Ok(proof),
+        )
+        .unwrap();
+
+        let before = cs.num_constraints();
+        path_g
+            .check_membership_with_field_elements(&crh_parameters, &new_root_g, &leaf_g, &index_g)
+            .unwrap();
+        let field_constraints = cs.num_constraints() - before;
+        proof_g
+            .check_update_with_field_elements(
+                &crh_parameters,
+                &old_root_g,
+                &new_root_g,
+                &leaf_g,
+                &index_g,
+            )
+            .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        /* the same leaf hashed through its bytes costs more constraints */
+        let before = cs.num_constraints();
+        let mut leaf_bytes = Vec::new();
+        for elem in leaf_g.iter() {
+            leaf_bytes.extend_from_slice(&elem.to_bytes().unwrap());
+        }
+        let leaf_bytes: &[UInt8<Fq>] = leaf_bytes.as_slice();
+        path_g
+            .conditionally_check_membership_with_index(
+                &crh_parameters,
+                &new_root_g,
+                &leaf_bytes,
+                &index_g,
+                &Boolean::Constant(false),
+            )
+            .unwrap();
+        assert!(field_constraints < cs.num_constraints() - before);
+    }
+
+    #[test]
+    fn multi_membership_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, [u8; 2]> = BTreeMap::new();
+        for i in 0..8u8 {
+            leaves.insert(i as u64, [i + 1; 2]);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        let indices = [2u64, 3, 4, 5];
+        let proof = tree.generate_multi_membership_proof(&indices).unwrap();
+
+        for (use_bad_leaf, expected) in [(false, true), (true, false)].iter() {
+            let cs_sys = ConstraintSystem::<Fq>::new();
+            let cs = ConstraintSystemRef::new(cs_sys);
+
+            let root_g = <HG as CRHforMerkleTreeGadget<H, _>>::OutputVar::new_witness(
+                ark_relations::ns!(cs, "root"),
+                || Ok(root),
+            )
+            .unwrap();
+            let mut leaves_g = Vec::new();
+            for index in indices.iter() {
+                let mut leaf = leaves[index];
+                if *use_bad_leaf && *index == 4 {
+                    leaf[0] += 1;
+                }
+                leaves_g
+                    .push(UInt8::new_witness_vec(ark_relations::ns!(cs, "leaf"), &leaf).unwrap());
+            }
+            let proof_g = MerkleSparseTreeMultiPathVar::<_, HG, _>::new_witness(
+                ark_relations::ns!(cs, "multi_path"),
+                || Ok(proof.clone()),
+            )
+            .unwrap();
+
+            let constraints_before = cs.num_constraints();
+            proof_g
+                .check_membership_with_indices(&crh_parameters, &root_g, &leaves_g, &indices)
+                .unwrap();
+            println!(
+                "constraints for {} leaves: {}",
+                indices.len(),
+                cs.num_constraints() - constraints_before
+            );
+
+            assert_eq!(cs.is_satisfied().unwrap(), *expected);
+        }
+    }
+
     #[test]
     fn good_root_update_test() {
         let mut old_leaves: BTreeMap<u64, [u8; 2]> = BTreeMap::new();
@@ -1,44 +1,112 @@
 use crate::building_blocks::crh::CRHforMerkleTree;
+use crate::building_blocks::mt::merkle_sparse_tree::storage::NodeStorage;
+use crate::building_blocks::mt::LeafEncoding;
 use crate::Error;
-use ark_ff::ToBytes;
+use ark_ff::{PrimeField, ToBytes, ToConstraintField};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
 use ark_std::collections::{BTreeMap, BTreeSet};
 use ark_std::io::Cursor;
 use ark_std::{fmt::Debug, format};
 use ark_std::{
-    io::{Result as IoResult, Write},
+    io::{Read, Result as IoResult, Write},
     string::ToString,
+    vec,
     vec::Vec,
 };
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 /// constraints for the Merkle sparse tree
 pub mod constraints;
 
+/// storage backends for the nodes of the Merkle sparse tree
+pub mod storage;
+
 /// configuration of a Merkle tree
 pub trait MerkleSparseTreeConfig: Debug {
     /// Tree height
     const HEIGHT: u64;
     /// The CRH
     type H: CRHforMerkleTree;
+    /// Hash leaves, inner nodes, and empty leaves without the domain-separation tags, as in the legacy encoding
+    const LEGACY_ENCODING: bool = false;
+}
+
+/// Domain-separation tag of the leaves
+pub const LEAF_TAG: u8 = 0;
+
+/// Domain-separation tag of the inner nodes
+pub const INNER_NODE_TAG: u8 = 1;
+
+/// Domain-separation tag of the empty leaves, so that a location that has never been written
+/// differs from one that holds the default leaf
+pub const EMPTY_TAG: u8 = 2;
+
+/// Returns the tag of this domain, or `None` under the legacy encoding.
+pub(crate) fn domain_tag<P: MerkleSparseTreeConfig>(tag: u8) -> Option<u8> {
+    if P::LEGACY_ENCODING {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+/// Returns the tag of a leaf, which is the tag of the empty leaves if the leaf is empty.
+pub(crate) fn leaf_tag(empty: bool) -> u8 {
+    if empty {
+        EMPTY_TAG
+    } else {
+        LEAF_TAG
+    }
 }
 
-/// Stores the hashes of a particular path (in order) from leaf to root.
-/// Our path `is_left_child()` if the boolean in `path` is true.
-#[derive(Derivative)]
+/// Stores the sibling hashes of a particular path (in order) from leaf to root.
+/// The direction at each level is given by the corresponding bit of the leaf index.
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
 #[derivative(
     Clone(bound = "P: MerkleSparseTreeConfig"),
     Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
 )]
 pub struct MerkleSparseTreePath<P: MerkleSparseTreeConfig> {
-    pub(crate) path: Vec<(
-        <P::H as CRHforMerkleTree>::Output,
-        <P::H as CRHforMerkleTree>::Output,
-    )>,
+    pub(crate) path: Vec<<P::H as CRHforMerkleTree>::Output>,
 }
 
-/// A modifying proof, consisting of two Merkle tree paths
+/// A modifying proof, consisting of the old leaf hash and the siblings shared by both paths
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
 pub struct MerkleSparseTreeTwoPaths<P: MerkleSparseTreeConfig> {
-    pub(crate) old_path: MerkleSparseTreePath<P>,
-    pub(crate) new_path: MerkleSparseTreePath<P>,
+    pub(crate) old_leaf_hash: <P::H as CRHforMerkleTree>::Output,
+    pub(crate) path: MerkleSparseTreePath<P>,
+}
+
+/// A multi-opening of several leaves, storing only the sibling hashes that cannot be
+/// recomputed from the opened leaves, level by level from the leaves, in the order of the index
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseTreeConfig"),
+    Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct MerkleSparseTreeMultiPath<P: MerkleSparseTreeConfig> {
+    pub(crate) auxiliary: Vec<<P::H as CRHforMerkleTree>::Output>,
+}
+
+/// A batched opening of several leaves, whose locations can be witnesses in the circuit: each
+/// path stops below the top levels, and all the nodes at the bottom of the top levels are given
+/// once, in the order of the index, as the frontier; the siblings below the frontier are also
+/// given once, and shared by the paths that go through the same nodes
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(
+    Clone(bound = "P: MerkleSparseTreeConfig"),
+    Debug(bound = "P: MerkleSparseTreeConfig, <P::H as CRHforMerkleTree>::Output: Debug")
+)]
+pub struct MerkleSparseTreeBatchPath<P: MerkleSparseTreeConfig> {
+    /// whether each entry has never been written, in which case its leaf must be the default one,
+    /// hashed as the empty leaf
+    pub(crate) empty: Vec<bool>,
+    pub(crate) frontier: Vec<<P::H as CRHforMerkleTree>::Output>,
+    pub(crate) siblings: Vec<<P::H as CRHforMerkleTree>::Output>,
+    /// for each entry, the position in `siblings` of its sibling at each level below the frontier
+    pub(crate) positions: Vec<Vec<u64>>,
 }
 
 /// public parameters of the Merkle sparse tree
@@ -51,10 +119,7 @@ impl<P: MerkleSparseTreeConfig> Default for MerkleSparseTreePath<P> {
     fn default() -> Self {
         let mut path = Vec::with_capacity(P::HEIGHT as usize);
         for _i in 1..P::HEIGHT as usize {
-            path.push((
-                <P::H as CRHforMerkleTree>::Output::default(),
-                <P::H as CRHforMerkleTree>::Output::default(),
-            ));
+            path.push(<P::H as CRHforMerkleTree>::Output::default());
         }
         Self { path }
     }
@@ -63,8 +128,50 @@ impl<P: MerkleSparseTreeConfig> Default for MerkleSparseTreePath<P> {
 impl<P: MerkleSparseTreeConfig> ToBytes for MerkleSparseTreePath<P> {
     fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
         for p in &self.path {
-            p.0.write(&mut writer)?;
-            p.1.write(&mut writer)?;
+            p.write(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for MerkleSparseTreeMultiPath<P> {
+    fn default() -> Self {
+        Self {
+            auxiliary: Vec::new(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for MerkleSparseTreeMultiPath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        for hash in &self.auxiliary {
+            hash.write(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for MerkleSparseTreeBatchPath<P> {
+    fn default() -> Self {
+        Self::dummy(0)
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for MerkleSparseTreeBatchPath<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        for empty in &self.empty {
+            empty.write(&mut writer)?;
+        }
+        for hash in &self.frontier {
+            hash.write(&mut writer)?;
+        }
+        for hash in &self.siblings {
+            hash.write(&mut writer)?;
+        }
+        for positions in &self.positions {
+            for position in positions {
+                position.write(&mut writer)?;
+            }
         }
         Ok(())
     }
@@ -72,16 +179,19 @@ impl<P: MerkleSparseTreeConfig> ToBytes for MerkleSparseTreePath<P> {
 
 impl<P: MerkleSparseTreeConfig> Default for MerkleSparseTreeTwoPaths<P> {
     fn default() -> Self {
-        let old_path: MerkleSparseTreePath<P> = MerkleSparseTreePath::default();
-        let new_path: MerkleSparseTreePath<P> = MerkleSparseTreePath::default();
-        Self { old_path, new_path }
+        let old_leaf_hash = <P::H as CRHforMerkleTree>::Output::default();
+        let path: MerkleSparseTreePath<P> = MerkleSparseTreePath::default();
+        Self {
+            old_leaf_hash,
+            path,
+        }
     }
 }
 
 impl<P: MerkleSparseTreeConfig> ToBytes for MerkleSparseTreeTwoPaths<P> {
     fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
-        self.old_path.write(&mut writer)?;
-        self.new_path.write(&mut writer)?;
+        self.old_leaf_hash.write(&mut writer)?;
+        self.path.write(&mut writer)?;
         Ok(())
     }
 }
@@ -89,255 +199,639 @@ impl<P: MerkleSparseTreeConfig> ToBytes for MerkleSparseTreeTwoPaths<P> {
 impl<P: MerkleSparseTreeConfig> Clone for MerkleSparseTreeTwoPaths<P> {
     fn clone(&self) -> Self {
         MerkleSparseTreeTwoPaths {
-            old_path: self.old_path.clone(),
-            new_path: self.new_path.clone(),
+            old_leaf_hash: self.old_leaf_hash.clone(),
+            path: self.path.clone(),
         }
     }
 }
 
 impl<P: MerkleSparseTreeConfig> MerkleSparseTreePath<P> {
-    /// verify the lookup proof, just checking the membership
+    /// verify the lookup proof, which always fails since the path only keeps the siblings and
+    /// no longer records its location
+    #[deprecated(
+        note = "the path no longer records its location, so it cannot be verified without it; use `verify_with_index`"
+    )]
     pub fn verify<L: ToBytes>(
+        &self,
+        _parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        _root_hash: &<P::H as CRHforMerkleTree>::Output,
+        _leaf: &L,
+    ) -> Result<bool, Error> {
+        Err(MerkleSparseTreeError::MissingIndex.into())
+    }
+
+    /// verify the lookup proof, given the location, which must have been written
+    pub fn verify_with_index<L: ToBytes>(
         &self,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
         root_hash: &<P::H as CRHforMerkleTree>::Output,
         leaf: &L,
+        index: u64,
     ) -> Result<bool, Error> {
-        if self.path.len() != (P::HEIGHT - 1) as usize {
-            return Ok(false);
-        }
-        // Check that the given leaf matches the leaf in the membership proof.
-        if !self.path.is_empty() {
-            let claimed_leaf_hash = hash_leaf::<P::H, L>(parameters, leaf)?;
-
-            if claimed_leaf_hash != self.path[0].0 && claimed_leaf_hash != self.path[0].1 {
-                return Ok(false);
-            }
+        let claimed_leaf_hash = hash_leaf::<P, L>(parameters, leaf)?;
+        self.verify_leaf_hash_with_index(parameters, root_hash, claimed_leaf_hash, index)
+    }
 
-            let mut prev = claimed_leaf_hash;
-            // Check levels between leaf level and root.
-            for &(ref left_hash, ref right_hash) in &self.path {
-                // Check if the previous hash matches the correct current hash.
-                if &prev != left_hash && &prev != right_hash {
-                    return Ok(false);
-                }
-                prev = hash_inner_node::<P::H>(parameters, left_hash, right_hash)?;
-            }
+    /// verify the lookup proof of a leaf hashed as field elements, given the location
+    pub fn verify_field_elements_with_index<F: PrimeField, L: ToConstraintField<F>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        index: u64,
+    ) -> Result<bool, Error> {
+        let claimed_leaf_hash = hash_field_leaf::<P, F, L>(parameters, leaf)?;
+        self.verify_leaf_hash_with_index(parameters, root_hash, claimed_leaf_hash, index)
+    }
 
-            if root_hash != &prev {
-                return Ok(false);
-            }
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+    /// verify the absence proof, i.e., the location has never been written, which differs from
+    /// holding the default leaf unless the legacy encoding is used
+    pub fn verify_absence_with_index<L: Default + ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        index: u64,
+    ) -> Result<bool, Error> {
+        let empty_leaf_hash = hash_empty::<P, L>(parameters, L::default())?;
+        self.verify_leaf_hash_with_index(parameters, root_hash, empty_leaf_hash, index)
     }
 
-    /// verify the lookup proof, given the location
-    pub fn verify_with_index<L: ToBytes>(
+    pub(crate) fn verify_leaf_hash_with_index(
         &self,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
         root_hash: &<P::H as CRHforMerkleTree>::Output,
-        leaf: &L,
+        claimed_leaf_hash: <P::H as CRHforMerkleTree>::Output,
         index: u64,
     ) -> Result<bool, Error> {
-        if self.path.len() != (P::HEIGHT - 1) as usize {
+        if self.path.len() != (P::HEIGHT - 1) as usize || self.path.is_empty() {
+            return Ok(false);
+        }
+        // Check that the index is a location in the last level.
+        if index >= 1u64 << (P::HEIGHT - 1) {
             return Ok(false);
         }
-        // Check that the given leaf matches the leaf in the membership proof.
-        let last_level_index: u64 = (1u64 << (P::HEIGHT - 1)) - 1;
-        let tree_index: u64 = last_level_index + index;
-
-        let mut index_from_path: u64 = last_level_index;
-        let mut index_offset: u64 = 1;
 
-        if !self.path.is_empty() {
-            let claimed_leaf_hash = hash_leaf::<P::H, L>(parameters, leaf)?;
+        let root = self.node_above(parameters, claimed_leaf_hash, index)?;
 
-            if tree_index % 2 == 1 {
-                if claimed_leaf_hash != self.path[0].0 {
-                    return Ok(false);
-                }
-            } else if claimed_leaf_hash != self.path[0].1 {
-                return Ok(false);
-            }
+        Ok(root_hash == &root)
+    }
 
-            let mut prev = claimed_leaf_hash;
-            let mut prev_index = tree_index;
-            // Check levels between leaf level and root.
-            for &(ref left_hash, ref right_hash) in &self.path {
-                // Check if the previous hash matches the correct current hash.
-                if prev_index % 2 == 1 {
-                    if &prev != left_hash {
-                        return Ok(false);
-                    }
-                } else {
-                    if &prev != right_hash {
-                        return Ok(false);
-                    }
-                    index_from_path += index_offset;
-                }
-                index_offset *= 2;
-                prev_index = (prev_index - 1) / 2;
-                prev = hash_inner_node::<P::H>(parameters, left_hash, right_hash)?;
+    /// Returns the node reached from the leaf after as many levels as there are siblings.
+    fn node_above(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        leaf_hash: <P::H as CRHforMerkleTree>::Output,
+        index: u64,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        let mut prev = leaf_hash;
+        let mut prev_index = index;
+        // The index bit tells which side we are on.
+        for sibling_hash in &self.path {
+            if prev_index % 2 == 0 {
+                prev = hash_inner_node::<P>(parameters, &prev, sibling_hash)?;
+            } else {
+                prev = hash_inner_node::<P>(parameters, sibling_hash, &prev)?;
             }
+            prev_index >>= 1;
+        }
 
-            if root_hash != &prev {
-                return Ok(false);
-            }
+        Ok(prev)
+    }
+}
 
-            if index_from_path != tree_index {
-                return Ok(false);
-            }
+impl<P: MerkleSparseTreeConfig> MerkleSparseTreeBatchPath<P> {
+    /// output a dummy batched opening with `num` entries, whose frontier is sized for them
+    pub fn dummy(num: usize) -> Self {
+        let depth = batch_frontier_depth::<P>(num);
+        let path_len = (P::HEIGHT - 1 - depth) as usize;
 
-            Ok(true)
+        // All the dummy entries share a single default sibling.
+        let siblings = if num > 0 && path_len > 0 {
+            vec![<P::H as CRHforMerkleTree>::Output::default()]
         } else {
-            Ok(false)
+            Vec::new()
+        };
+
+        Self {
+            empty: vec![false; num],
+            frontier: vec![<P::H as CRHforMerkleTree>::Output::default(); 1 << depth],
+            siblings,
+            positions: vec![vec![0; path_len]; num],
         }
     }
-}
 
-impl<P: MerkleSparseTreeConfig> MerkleSparseTreeTwoPaths<P> {
-    /// verify the modifying proof
-    pub fn verify<L: ToBytes>(
+    /// pad with dummy entries up to `num` entries, keeping the frontier
+    pub fn pad(&mut self, num: usize) -> Result<(), Error> {
+        let depth = frontier_depth::<P>(self.frontier.len()).ok_or(
+            MerkleSparseTreeError::IncorrectPathLength(self.frontier.len()),
+        )?;
+        let path_len = (P::HEIGHT - 1 - depth) as usize;
+
+        if self.positions.len() >= num {
+            return Ok(());
+        }
+
+        // The dummy entries share a single default sibling, added after the existing ones.
+        let position = self.siblings.len() as u64;
+        if path_len > 0 {
+            self.siblings
+                .push(<P::H as CRHforMerkleTree>::Output::default());
+        }
+        self.positions.resize(num, vec![position; path_len]);
+        self.empty.resize(num, false);
+        Ok(())
+    }
+
+    /// Returns the path of the entry below the frontier, from the shared siblings, or `None` if
+    /// the positions of the entry do not match the frontier.
+    pub(crate) fn path(&self, entry: usize, path_len: usize) -> Option<MerkleSparseTreePath<P>> {
+        let positions = self.positions.get(entry)?;
+        if positions.len() != path_len {
+            return None;
+        }
+
+        let mut path = Vec::with_capacity(path_len);
+        for position in positions {
+            path.push(self.siblings.get(*position as usize)?.clone());
+        }
+        Some(MerkleSparseTreePath { path })
+    }
+
+    /// verify the batched opening, given the leaves and their locations, where a location that
+    /// has never been written opens to the default leaf
+    pub fn verify_with_indices<L: ToBytes>(
         &self,
         parameters: &<P::H as CRHforMerkleTree>::Parameters,
-        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
-        new_root_hash: &<P::H as CRHforMerkleTree>::Output,
-        leaf: &L,
-        index: u64,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaves: &[L],
+        indices: &[u64],
     ) -> Result<bool, Error> {
-        if self.old_path.path.len() != (P::HEIGHT - 1) as usize
-            || self.new_path.path.len() != (P::HEIGHT - 1) as usize
-        {
+        if leaves.len() != self.empty.len() {
             return Ok(false);
         }
-        // Check that the given leaf matches the leaf in the membership proof.
-        let last_level_index: u64 = (1u64 << (P::HEIGHT - 1)) - 1;
-        let tree_index: u64 = last_level_index + index;
+        let leaf_hashes = hash_leaves_or_empty::<P, L>(
+            parameters,
+            leaves.iter().zip(self.empty.iter().cloned()),
+        )?;
+        self.verify_leaf_hashes_with_indices(parameters, root_hash, &leaf_hashes, indices)
+    }
 
-        let mut index_from_path: u64 = last_level_index;
-        let mut index_offset: u64 = 1;
+    /// verify the batched opening of leaves hashed as field elements, given their locations
+    pub fn verify_field_elements_with_indices<F: PrimeField, L: ToConstraintField<F>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaves: &[L],
+        indices: &[u64],
+    ) -> Result<bool, Error> {
+        if leaves.len() != self.empty.len() {
+            return Ok(false);
+        }
+        let leaf_hashes = leaves
+            .iter()
+            .zip(self.empty.iter())
+            .map(|(leaf, empty)| hash_field_leaf_or_empty::<P, F, L>(parameters, leaf, *empty))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.verify_leaf_hashes_with_indices(parameters, root_hash, &leaf_hashes, indices)
+    }
 
-        if !self.old_path.path.is_empty() && !self.new_path.path.is_empty() {
-            // Check the new path first
-            let claimed_leaf_hash = hash_leaf::<P::H, L>(parameters, leaf)?;
+    pub(crate) fn verify_leaf_hashes_with_indices(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf_hashes: &[<P::H as CRHforMerkleTree>::Output],
+        indices: &[u64],
+    ) -> Result<bool, Error> {
+        if leaf_hashes.len() != indices.len()
+            || leaf_hashes.len() != self.positions.len()
+            || leaf_hashes.len() != self.empty.len()
+        {
+            return Ok(false);
+        }
+        if leaf_hashes.is_empty() {
+            return Ok(true);
+        }
+        let depth = match frontier_depth::<P>(self.frontier.len()) {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let path_len = P::HEIGHT - 1 - depth;
+
+        // Hash each leaf up to the frontier node above its location.
+        for (entry, (leaf_hash, index)) in leaf_hashes.iter().zip(indices.iter()).enumerate() {
+            let path = match self.path(entry, path_len as usize) {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+            if *index >= 1u64 << (P::HEIGHT - 1) {
+                return Ok(false);
+            }
 
-            if tree_index % 2 == 1 {
-                if claimed_leaf_hash != self.new_path.path[0].0 {
-                    return Ok(false);
-                }
-            } else if claimed_leaf_hash != self.new_path.path[0].1 {
+            let node = path.node_above(parameters, leaf_hash.clone(), *index)?;
+            if node != self.frontier[(*index >> path_len) as usize] {
                 return Ok(false);
             }
+        }
+
+        // Hash the frontier up to the root once.
+        let mut current_level = self.frontier.clone();
+        while current_level.len() > 1 {
+            let children: Vec<_> = current_level
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            current_level = hash_inner_nodes::<P>(parameters, &children)?;
+        }
 
-            let mut prev = claimed_leaf_hash;
-            let mut prev_index = tree_index;
+        Ok(current_level[0] == *root_hash)
+    }
+}
 
-            // Check levels between leaf level and root.
-            for &(ref left_hash, ref right_hash) in &self.new_path.path {
-                // Check if the previous hash matches the correct current hash.
-                if prev_index % 2 == 1 {
-                    if &prev != left_hash {
-                        return Ok(false);
-                    }
-                } else {
-                    if &prev != right_hash {
-                        return Ok(false);
-                    }
-                    index_from_path += index_offset;
-                }
-                index_offset *= 2;
-                prev_index = (prev_index - 1) / 2;
-                prev = hash_inner_node::<P::H>(parameters, left_hash, right_hash)?;
-            }
+/// Checks that the index is a location in the last level.
+fn check_index<P: MerkleSparseTreeConfig>(index: u64) -> Result<(), Error> {
+    if index >= 1u64 << (P::HEIGHT - 1) {
+        return Err(MerkleSparseTreeError::IncorrectIndex(index).into());
+    }
+    Ok(())
+}
 
-            if new_root_hash != &prev {
-                return Ok(false);
-            }
+/// Returns the depth of the frontier of a batched opening with `num` entries, the smallest depth
+/// with at least as many nodes as entries.
+pub(crate) fn batch_frontier_depth<P: MerkleSparseTreeConfig>(num: usize) -> u64 {
+    let mut depth = 0;
+    while depth < P::HEIGHT - 1 && (1u64 << depth) < num as u64 {
+        depth += 1;
+    }
+    depth
+}
 
-            if index_from_path != tree_index {
-                return Ok(false);
-            }
+/// Returns the depth of a frontier with `len` nodes, or `None` if no level has that many nodes.
+pub(crate) fn frontier_depth<P: MerkleSparseTreeConfig>(len: usize) -> Option<u64> {
+    if !len.is_power_of_two() {
+        return None;
+    }
+    let depth = u64::from(len.trailing_zeros());
+    if depth > P::HEIGHT - 1 {
+        return None;
+    }
+    Some(depth)
+}
 
-            if tree_index % 2 == 1 {
-                prev = self.old_path.path[0].0.clone();
-            } else {
-                prev = self.old_path.path[0].1.clone();
+impl<P: MerkleSparseTreeConfig> MerkleSparseTreeMultiPath<P> {
+    /// verify the multi-opening, given the leaves and their locations
+    pub fn verify_with_indices<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaves: &[L],
+        indices: &[u64],
+    ) -> Result<bool, Error> {
+        let leaf_hashes = leaves
+            .iter()
+            .map(|leaf| hash_leaf::<P, L>(parameters, leaf))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.verify_leaf_hashes_with_indices(parameters, root_hash, &leaf_hashes, indices)
+    }
+
+    pub(crate) fn verify_leaf_hashes_with_indices(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf_hashes: &[<P::H as CRHforMerkleTree>::Output],
+        indices: &[u64],
+    ) -> Result<bool, Error> {
+        if leaf_hashes.is_empty() || leaf_hashes.len() != indices.len() {
+            return Ok(false);
+        }
+
+        // Place the leaf hashes, rejecting an index opened to two different leaves.
+        let mut current_level: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
+        for (leaf_hash, index) in leaf_hashes.iter().zip(indices.iter()) {
+            if *index >= 1u64 << (P::HEIGHT - 1) {
+                return Ok(false);
             }
 
-            prev_index = tree_index;
-            let mut new_path_iter = self.new_path.path.iter();
-            for &(ref left_hash, ref right_hash) in &self.old_path.path {
-                // Check if the previous hash matches the correct current hash.
-                if prev_index % 2 == 1 {
-                    if &prev != left_hash {
-                        return Ok(false);
-                    }
-                } else if &prev != right_hash {
+            let leaf_hash = leaf_hash.clone();
+            let tree_index = convert_index_to_last_level(*index, P::HEIGHT);
+            if let Some(prev) = current_level.insert(tree_index, leaf_hash.clone()) {
+                if prev != leaf_hash {
                     return Ok(false);
                 }
+            }
+        }
 
-                let new_path_corresponding_entry = new_path_iter.next();
-
-                // Check the co-path is unchanged
-                match new_path_corresponding_entry {
-                    Some(x) => {
-                        if prev_index % 2 == 1 {
-                            if *right_hash != x.1 {
-                                return Ok(false);
-                            }
-                        } else if *left_hash != x.0 {
-                            return Ok(false);
-                        }
-                    }
-                    None => return Ok(false),
+        // Recompute the nodes level by level, taking the missing siblings from the proof.
+        let mut auxiliary_iter = self.auxiliary.iter();
+        for _ in 1..P::HEIGHT {
+            let mut next_level: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
+            for (current_node, current_hash) in current_level.iter() {
+                let parent_node = parent(*current_node).unwrap();
+                if next_level.contains_key(&parent_node) {
+                    continue;
                 }
 
-                prev_index = (prev_index - 1) / 2;
-                prev = hash_inner_node::<P::H>(parameters, left_hash, right_hash)?;
+                let sibling_node = sibling(*current_node).unwrap();
+                let sibling_hash = match current_level.get(&sibling_node) {
+                    Some(x) => x,
+                    None => match auxiliary_iter.next() {
+                        Some(x) => x,
+                        None => return Ok(false),
+                    },
+                };
+
+                let parent_hash = if is_left_child(*current_node) {
+                    hash_inner_node::<P>(parameters, current_hash, sibling_hash)?
+                } else {
+                    hash_inner_node::<P>(parameters, sibling_hash, current_hash)?
+                };
+                next_level.insert(parent_node, parent_hash);
             }
+            current_level = next_level;
+        }
 
-            if old_root_hash != &prev {
-                return Ok(false);
-            }
+        if auxiliary_iter.next().is_some() {
+            return Ok(false);
+        }
 
-            Ok(true)
-        } else {
-            Ok(false)
+        match current_level.get(&0) {
+            Some(x) => Ok(x == root_hash),
+            None => Ok(false),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleSparseTreeTwoPaths<P> {
+    /// verify the modifying proof
+    pub fn verify<L: ToBytes>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        new_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        index: u64,
+    ) -> Result<bool, Error> {
+        let claimed_leaf_hash = hash_leaf::<P, L>(parameters, leaf)?;
+        self.verify_leaf_hash(
+            parameters,
+            old_root_hash,
+            new_root_hash,
+            claimed_leaf_hash,
+            index,
+        )
+    }
+
+    /// verify the modifying proof of a leaf hashed as field elements
+    pub fn verify_field_elements<F: PrimeField, L: ToConstraintField<F>>(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        new_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        leaf: &L,
+        index: u64,
+    ) -> Result<bool, Error> {
+        let claimed_leaf_hash = hash_field_leaf::<P, F, L>(parameters, leaf)?;
+        self.verify_leaf_hash(
+            parameters,
+            old_root_hash,
+            new_root_hash,
+            claimed_leaf_hash,
+            index,
+        )
+    }
+
+    pub(crate) fn verify_leaf_hash(
+        &self,
+        parameters: &<P::H as CRHforMerkleTree>::Parameters,
+        old_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        new_root_hash: &<P::H as CRHforMerkleTree>::Output,
+        claimed_leaf_hash: <P::H as CRHforMerkleTree>::Output,
+        index: u64,
+    ) -> Result<bool, Error> {
+        // Check the new path first
+        if !self.path.verify_leaf_hash_with_index(
+            parameters,
+            new_root_hash,
+            claimed_leaf_hash,
+            index,
+        )? {
+            return Ok(false);
         }
+
+        // The siblings are unchanged, so the old path only differs in the leaf hash
+        self.path.verify_leaf_hash_with_index(
+            parameters,
+            old_root_hash,
+            self.old_leaf_hash.clone(),
+            index,
+        )
     }
 }
 
+/// in-memory storage of the nodes of a Merkle sparse tree
+pub type MemoryNodeStorage<P> =
+    BTreeMap<u64, <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output>;
+
 /// Merkle sparse tree
-pub struct MerkleSparseTree<P: MerkleSparseTreeConfig> {
+pub struct MerkleSparseTree<P: MerkleSparseTreeConfig, S = MemoryNodeStorage<P>>
+where
+    S: NodeStorage<<P::H as CRHforMerkleTree>::Output>,
+{
     /// data of the tree
-    pub tree: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+    pub tree: S,
     parameters: <P::H as CRHforMerkleTree>::Parameters,
     root: Option<<P::H as CRHforMerkleTree>::Output>,
     empty_hashes: Vec<<P::H as CRHforMerkleTree>::Output>,
+    versions: Option<NodeVersions<P>>,
 }
 
-impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
-    /// tree height
-    pub const HEIGHT: u64 = P::HEIGHT;
-
-    /// obtain an empty tree
-    pub fn blank<L: Default + ToBytes>(parameters: <P::H as CRHforMerkleTree>::Parameters) -> Self {
-        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default()).unwrap();
-
+impl<P: MerkleSparseTreeConfig, S> Clone for MerkleSparseTree<P, S>
+where
+    S: NodeStorage<<P::H as CRHforMerkleTree>::Output> + Clone,
+{
+    fn clone(&self) -> Self {
         MerkleSparseTree {
-            tree: BTreeMap::new(),
-            parameters,
-            root: Some(empty_hashes[(P::HEIGHT - 1) as usize].clone()),
-            empty_hashes,
+            tree: self.tree.clone(),
+            parameters: self.parameters.clone(),
+            root: self.root.clone(),
+            empty_hashes: self.empty_hashes.clone(),
+            versions: self.versions.clone(),
+        }
+    }
+}
+
+/// The past versions of a tree, keeping the replaced value of each node instead of overwriting it.
+struct NodeVersions<P: MerkleSparseTreeConfig> {
+    /// The root of each version, keyed by the label given to it.
+    roots: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+    /// For each replaced node, its value at each version it was replaced after, keyed by that
+    /// version plus one, so that the value at a version is in the first entry above it.
+    replaced_nodes: BTreeMap<u64, Vec<(u64, Option<<P::H as CRHforMerkleTree>::Output>)>>,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for NodeVersions<P> {
+    fn clone(&self) -> Self {
+        NodeVersions {
+            roots: self.roots.clone(),
+            replaced_nodes: self.replaced_nodes.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> NodeVersions<P> {
+    /// Returns the versions of a tree whose only version is the current one.
+    fn new(version: u64, root: <P::H as CRHforMerkleTree>::Output) -> Self {
+        let mut roots = BTreeMap::new();
+        roots.insert(version, root);
+        NodeVersions {
+            roots,
+            replaced_nodes: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the latest version.
+    fn latest(&self) -> u64 {
+        *self.roots.keys().next_back().unwrap()
+    }
+
+    /// Writes the versions, as the roots and then the replaced nodes.
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        (self.roots.len() as u64).serialize(&mut writer)?;
+        for (version, root) in self.roots.iter() {
+            version.serialize(&mut writer)?;
+            root.serialize(&mut writer)?;
         }
+        (self.replaced_nodes.len() as u64).serialize(&mut writer)?;
+        for (index, replaced) in self.replaced_nodes.iter() {
+            index.serialize(&mut writer)?;
+            (replaced.len() as u64).serialize(&mut writer)?;
+            for (key, old_hash) in replaced.iter() {
+                key.serialize(&mut writer)?;
+                old_hash.serialize(&mut writer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the versions written by `serialize`, checking that the keys are increasing.
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut roots = BTreeMap::new();
+        for _ in 0..u64::deserialize(&mut reader)? {
+            let version = u64::deserialize(&mut reader)?;
+            let root = <P::H as CRHforMerkleTree>::Output::deserialize(&mut reader)?;
+            if roots.insert(version, root).is_some() {
+                return Err(SerializationError::InvalidData.into());
+            }
+        }
+        if roots.is_empty() {
+            return Err(SerializationError::InvalidData.into());
+        }
+
+        let mut replaced_nodes = BTreeMap::new();
+        for _ in 0..u64::deserialize(&mut reader)? {
+            let index = u64::deserialize(&mut reader)?;
+            let mut replaced: Vec<(u64, Option<<P::H as CRHforMerkleTree>::Output>)> = Vec::new();
+            for _ in 0..u64::deserialize(&mut reader)? {
+                let key = u64::deserialize(&mut reader)?;
+                let old_hash =
+                    Option::<<P::H as CRHforMerkleTree>::Output>::deserialize(&mut reader)?;
+                if replaced
+                    .last()
+                    .map_or(false, |(last_key, _)| *last_key >= key)
+                {
+                    return Err(SerializationError::InvalidData.into());
+                }
+                replaced.push((key, old_hash));
+            }
+            replaced_nodes.insert(index, replaced);
+        }
+
+        Ok(NodeVersions {
+            roots,
+            replaced_nodes,
+        })
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
+    /// obtain an empty tree
+    pub fn blank<L: Default + ToBytes>(parameters: <P::H as CRHforMerkleTree>::Parameters) -> Self {
+        Self::from_storage::<L>(parameters, BTreeMap::new()).unwrap()
     }
 
     /// initialize a tree (with optional data)
     pub fn new<L: Default + ToBytes>(
         parameters: <P::H as CRHforMerkleTree>::Parameters,
         leaves: &BTreeMap<u64, L>,
+    ) -> Result<Self, Error> {
+        Self::new_with_storage(parameters, BTreeMap::new(), leaves)
+    }
+
+    /// restore a tree from the nodes written by `serialize_nodes` and the CRH parameters, where
+    /// `L` is the type of the leaves the tree was initialized with
+    pub fn deserialize_nodes<L: Default + ToBytes, R: Read>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        reader: R,
+    ) -> Result<Self, Error> {
+        Self::deserialize_nodes_with_storage::<L, R>(parameters, BTreeMap::new(), reader)
+    }
+}
+
+impl<P: MerkleSparseTreeConfig, S: NodeStorage<<P::H as CRHforMerkleTree>::Output>>
+    MerkleSparseTree<P, S>
+{
+    /// tree height
+    pub const HEIGHT: u64 = P::HEIGHT;
+
+    /// open a tree over the nodes already in the storage, which is empty for a new tree
+    pub fn from_storage<L: Default + ToBytes>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+    ) -> Result<Self, Error> {
+        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default())?;
+        Self::from_storage_with_empty_hashes(parameters, storage, empty_hashes)
+    }
+
+    /// like `from_storage`, for a tree whose leaves are hashed as field elements
+    pub fn from_storage_with_field_leaves<F: PrimeField, L: Default + ToConstraintField<F>>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+    ) -> Result<Self, Error> {
+        let empty_leaf_hash =
+            hash_field_leaf_or_empty::<P, F, L>(&parameters, &L::default(), true)?;
+        let empty_hashes = gen_empty_hashes_from_leaf_hash::<P>(&parameters, empty_leaf_hash)?;
+        Self::from_storage_with_empty_hashes(parameters, storage, empty_hashes)
+    }
+
+    pub(crate) fn from_storage_with_empty_hashes(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+        empty_hashes: Vec<<P::H as CRHforMerkleTree>::Output>,
+    ) -> Result<Self, Error> {
+        let root = match storage.get(0)? {
+            Some(x) => x,
+            None => empty_hashes[(P::HEIGHT - 1) as usize].clone(),
+        };
+        // A storage that keeps metadata also keeps the past versions of the tree.
+        let versions = match storage.metadata()? {
+            Some(metadata) => Some(NodeVersions::deserialize(&metadata[..])?),
+            None => None,
+        };
+
+        Ok(MerkleSparseTree {
+            tree: storage,
+            parameters,
+            root: Some(root),
+            empty_hashes,
+            versions,
+        })
+    }
+
+    /// initialize a tree (with optional data) in an empty storage
+    pub fn new_with_storage<L: Default + ToBytes>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+        leaves: &BTreeMap<u64, L>,
     ) -> Result<Self, Error> {
         let last_level_size = leaves.len().next_power_of_two();
         let tree_size = 2 * last_level_size - 1;
@@ -345,16 +839,70 @@ impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
         assert!(tree_height <= Self::HEIGHT);
 
         // Initialize the merkle tree.
-        let mut tree: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
-        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default())?;
+        let mut tree = Self::from_storage::<L>(parameters, storage)?;
+        if leaves.is_empty() || !tree.tree.is_empty() {
+            return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+        }
+
+        // Compute the hash values for the leaves and every node above them.
+        tree.batch_update(leaves)?;
+
+        Ok(tree)
+    }
+
+    /// like `new_with_storage`, for a tree whose leaves are hashed as field elements
+    pub fn new_field_elements_with_storage<F: PrimeField, L: Default + ToConstraintField<F>>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+        leaves: &BTreeMap<u64, L>,
+    ) -> Result<Self, Error> {
+        let mut tree = Self::from_storage_with_field_leaves::<F, L>(parameters, storage)?;
+        if leaves.is_empty() || !tree.tree.is_empty() {
+            return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+        }
+
+        tree.batch_update_field_elements(leaves)?;
+
+        Ok(tree)
+    }
+
+    /// write several leaves at once, hashing each changed inner node only once
+    pub fn batch_update<L: ToBytes>(&mut self, leaves: &BTreeMap<u64, L>) -> Result<(), Error> {
+        let leaf_hashes = hash_leaves::<P, L>(&self.parameters, leaves.values())?;
+        self.batch_update_leaf_hashes(leaves.keys().cloned().zip(leaf_hashes).collect())
+    }
+
+    /// like `batch_update`, with the leaves hashed as field elements
+    pub fn batch_update_field_elements<F: PrimeField, L: ToConstraintField<F>>(
+        &mut self,
+        leaves: &BTreeMap<u64, L>,
+    ) -> Result<(), Error> {
+        let mut leaf_hashes = BTreeMap::new();
+        for (index, leaf) in leaves.iter() {
+            leaf_hashes.insert(*index, hash_field_leaf::<P, F, L>(&self.parameters, leaf)?);
+        }
+        self.batch_update_leaf_hashes(leaf_hashes)
+    }
+
+    pub(crate) fn batch_update_leaf_hashes(
+        &mut self,
+        leaves: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+    ) -> Result<(), Error> {
+        if leaves.is_empty() {
+            let root_hash = self.root();
+            return self.commit_changes(BTreeMap::new(), root_hash);
+        }
+
+        for index in leaves.keys() {
+            check_index::<P>(*index)?;
+        }
+
+        // The nodes changed by this batch, which shadow the storage until the commit.
+        let mut changed_nodes: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output> = BTreeMap::new();
 
-        // Compute and store the hash values for each leaf.
         let last_level_index: u64 = (1u64 << (Self::HEIGHT - 1)) - 1;
-        for (i, leaf) in leaves.iter() {
-            tree.insert(
-                last_level_index + *i,
-                hash_leaf::<P::H, _>(&parameters, leaf)?,
-            );
+        for (i, leaf_hash) in leaves.iter() {
+            changed_nodes.insert(last_level_index + *i, leaf_hash.clone());
         }
 
         let mut middle_nodes: BTreeSet<u64> = BTreeSet::new();
@@ -365,32 +913,26 @@ impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
         // Compute the hash values for every node in parts of the tree.
         for level in 0..Self::HEIGHT {
             // Iterate over the current level.
+            let mut children = Vec::with_capacity(middle_nodes.len());
             for current_index in &middle_nodes {
-                let left_index = left_child(*current_index);
-                let right_index = right_child(*current_index);
-
-                let mut left_hash = empty_hashes[level as usize].clone();
-                let mut right_hash = empty_hashes[level as usize].clone();
-
-                if tree.contains_key(&left_index) {
-                    match tree.get(&left_index) {
-                        Some(x) => left_hash = x.clone(),
-                        _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
-                    }
-                }
-
-                if tree.contains_key(&right_index) {
-                    match tree.get(&right_index) {
-                        Some(x) => right_hash = x.clone(),
-                        _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
-                    }
-                }
+                let left_hash = self.changed_node_hash(
+                    &changed_nodes,
+                    left_child(*current_index),
+                    level as usize,
+                )?;
+                let right_hash = self.changed_node_hash(
+                    &changed_nodes,
+                    right_child(*current_index),
+                    level as usize,
+                )?;
+
+                children.push((left_hash, right_hash));
+            }
 
-                // Compute Hash(left || right).
-                tree.insert(
-                    *current_index,
-                    hash_inner_node::<P::H>(&parameters, &left_hash, &right_hash)?,
-                );
+            // Compute Hash(left || right) for the whole level.
+            let hashes = hash_inner_nodes::<P>(&self.parameters, &children)?;
+            for (current_index, hash) in middle_nodes.iter().zip(hashes) {
+                changed_nodes.insert(*current_index, hash);
             }
 
             let tmp_middle_nodes = middle_nodes.clone();
@@ -402,18 +944,13 @@ impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
             }
         }
 
-        let root_hash;
-        match tree.get(&0) {
-            Some(x) => root_hash = (*x).clone(),
+        let root_hash = match changed_nodes.get(&0) {
+            Some(x) => (*x).clone(),
             _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
-        }
+        };
 
-        Ok(MerkleSparseTree {
-            tree,
-            parameters,
-            root: Some(root_hash),
-            empty_hashes,
-        })
+        // Write each changed node once.
+        self.commit_changes(changed_nodes, root_hash)
     }
 
     #[inline]
@@ -422,133 +959,707 @@ impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
         self.root.clone().unwrap()
     }
 
+    /// write the nodes of the tree and its past versions, without the CRH parameters
+    pub fn serialize_nodes<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        (self.tree.len() as u64).serialize(&mut writer)?;
+        for index in self.tree.indices()? {
+            index.serialize(&mut writer)?;
+            match self.tree.get(index)? {
+                Some(x) => x.serialize(&mut writer)?,
+                None => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
+            }
+        }
+        self.root.serialize(&mut writer)?;
+
+        match &self.versions {
+            Some(versions) => {
+                true.serialize(&mut writer)?;
+                versions.serialize(&mut writer)?;
+            }
+            None => false.serialize(&mut writer)?,
+        }
+
+        Ok(())
+    }
+
+    /// restore a tree from the nodes written by `serialize_nodes` and the CRH parameters, into
+    /// the storage, where the empty hashes are recomputed from the parameters and the leaf type `L`
+    pub fn deserialize_nodes_with_storage<L: Default + ToBytes, R: Read>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+        reader: R,
+    ) -> Result<Self, Error> {
+        let empty_hashes = gen_empty_hashes::<P, L>(&parameters, L::default())?;
+        Self::deserialize_nodes_with_empty_hashes(parameters, storage, empty_hashes, reader)
+    }
+
+    /// like `deserialize_nodes_with_storage`, for a tree whose leaves are hashed as field elements
+    pub fn deserialize_nodes_with_storage_and_field_leaves<
+        F: PrimeField,
+        L: Default + ToConstraintField<F>,
+        R: Read,
+    >(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+        reader: R,
+    ) -> Result<Self, Error> {
+        let empty_leaf_hash =
+            hash_field_leaf_or_empty::<P, F, L>(&parameters, &L::default(), true)?;
+        let empty_hashes = gen_empty_hashes_from_leaf_hash::<P>(&parameters, empty_leaf_hash)?;
+        Self::deserialize_nodes_with_empty_hashes(parameters, storage, empty_hashes, reader)
+    }
+
+    pub(crate) fn deserialize_nodes_with_empty_hashes<R: Read>(
+        parameters: <P::H as CRHforMerkleTree>::Parameters,
+        mut storage: S,
+        empty_hashes: Vec<<P::H as CRHforMerkleTree>::Output>,
+        mut reader: R,
+    ) -> Result<Self, Error> {
+        let len = u64::deserialize(&mut reader)?;
+        let mut nodes = Vec::new();
+        for _ in 0..len {
+            let index = u64::deserialize(&mut reader)?;
+            let hash = <P::H as CRHforMerkleTree>::Output::deserialize(&mut reader)?;
+            nodes.push((index, Some(hash)));
+        }
+        // Every tree has a root, which `serialize_nodes` writes.
+        let root = Option::<<P::H as CRHforMerkleTree>::Output>::deserialize(&mut reader)?;
+        if root.is_none() {
+            return Err(SerializationError::InvalidData.into());
+        }
+
+        let versions = if bool::deserialize(&mut reader)? {
+            Some(NodeVersions::deserialize(&mut reader)?)
+        } else {
+            None
+        };
+
+        storage.clear()?;
+        Self::commit_with_versions(&mut storage, nodes, versions.as_ref())?;
+
+        Ok(MerkleSparseTree {
+            tree: storage,
+            parameters,
+            root,
+            empty_hashes,
+            versions,
+        })
+    }
+
     /// generate a membership proof (does not check the data point)
     pub fn generate_membership_proof(&self, index: u64) -> Result<MerkleSparseTreePath<P>, Error> {
+        self.generate_membership_proof_with_version(index, None)
+    }
+
+    /// start keeping the past versions of the tree, labelling the current tree as `version`
+    /// (e.g., the IVLS step count), unless versioning is already enabled
+    ///
+    /// the past versions are kept in memory and written by `serialize_nodes`, and a storage that
+    /// keeps metadata (e.g., `FileNodeStorage`) also writes them with every change, so that they
+    /// are restored when the storage is reopened; `prune_versions` keeps their size bounded
+    pub fn enable_versioning(&mut self, version: u64) -> Result<(), Error> {
+        if self.versions.is_none() {
+            self.versions = Some(NodeVersions::new(version, self.root()));
+            self.write_versions()?;
+        }
+
+        Ok(())
+    }
+
+    /// label the current tree as `version`, which must be above the latest version, so that the
+    /// updates since the latest version are kept apart from it
+    pub fn set_version(&mut self, version: u64) -> Result<(), Error> {
+        let root = self.root();
+        match &mut self.versions {
+            Some(versions) if version > versions.latest() => {
+                versions.roots.insert(version, root);
+                self.write_versions()
+            }
+            _ => Err(MerkleSparseTreeError::InvalidVersion(version).into()),
+        }
+    }
+
+    /// drop the past versions below `oldest` and the replaced nodes that only they read, where
+    /// the latest version is always kept, which does nothing if versioning is not enabled
+    pub fn prune_versions(&mut self, oldest: u64) -> Result<(), Error> {
+        if let Some(versions) = &mut self.versions {
+            let oldest = oldest.min(versions.latest());
+            versions.roots = versions.roots.split_off(&oldest);
+
+            // The value at a kept version is in the first entry above it, so the entries up to
+            // the oldest kept version are no longer read.
+            for replaced in versions.replaced_nodes.values_mut() {
+                replaced.retain(|(key, _)| *key > oldest);
+            }
+            versions
+                .replaced_nodes
+                .retain(|_, replaced| !replaced.is_empty());
+            self.write_versions()?;
+        }
+
+        Ok(())
+    }
+
+    /// obtain the latest version, or `None` if versioning is not enabled
+    pub fn version(&self) -> Option<u64> {
+        self.versions.as_ref().map(|versions| versions.latest())
+    }
+
+    /// obtain the root hash of a past version
+    pub fn root_at(&self, version: u64) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        self.versions
+            .as_ref()
+            .and_then(|versions| versions.roots.get(&version))
+            .cloned()
+            .ok_or_else(|| MerkleSparseTreeError::UnknownVersion(version).into())
+    }
+
+    /// remove every leaf, where the past versions are dropped and, if versioning is enabled, the
+    /// empty tree becomes version 0
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.tree.clear()?;
+        self.root = Some(self.empty_hashes[(P::HEIGHT - 1) as usize].clone());
+        if self.versions.is_some() {
+            self.versions = Some(NodeVersions::new(0, self.root()));
+            self.write_versions()?;
+        }
+
+        Ok(())
+    }
+
+    /// generate a membership proof against the root of a past version (does not check the data
+    /// point)
+    pub fn generate_membership_proof_at(
+        &self,
+        version: u64,
+        index: u64,
+    ) -> Result<MerkleSparseTreePath<P>, Error> {
+        // Check that the version exists.
+        self.root_at(version)?;
+
+        self.generate_membership_proof_with_version(index, Some(version))
+    }
+
+    /// Generates a membership proof against the given version, or the latest one for `None`.
+    fn generate_membership_proof_with_version(
+        &self,
+        index: u64,
+        version: Option<u64>,
+    ) -> Result<MerkleSparseTreePath<P>, Error> {
         let mut path = Vec::new();
 
         let tree_height = Self::HEIGHT;
         let tree_index = convert_index_to_last_level(index, tree_height);
 
-        // Iterate from the leaf up to the root, storing all intermediate hash values.
+        // Iterate from the leaf up to the root, storing the sibling hash values.
         let mut current_node = tree_index;
-        let mut empty_hashes_iter = self.empty_hashes.iter();
+        let mut level = 0;
         while !is_root(current_node) {
             let sibling_node = sibling(current_node).unwrap();
+            path.push(self.node_hash_with_version(sibling_node, level, version)?);
+
+            current_node = parent(current_node).unwrap();
+            level += 1;
+        }
+
+        if path.len() != (Self::HEIGHT - 1) as usize {
+            Err(MerkleSparseTreeError::IncorrectPathLength(path.len()).into())
+        } else {
+            Ok(MerkleSparseTreePath { path })
+        }
+    }
+
+    /// generate a multi-opening for several locations (does not check the data points)
+    pub fn generate_multi_membership_proof(
+        &self,
+        indices: &[u64],
+    ) -> Result<MerkleSparseTreeMultiPath<P>, Error> {
+        let tree_height = Self::HEIGHT;
+
+        let mut current_level: BTreeSet<u64> = BTreeSet::new();
+        for index in indices {
+            current_level.insert(convert_index_to_last_level(*index, tree_height));
+        }
 
-            let mut current_hash = empty_hashes_iter.next().unwrap().clone();
-            let mut sibling_hash = current_hash.clone();
+        // Iterate from the leaves up to the root, storing the siblings outside the opened set.
+        let mut auxiliary = Vec::new();
+        for level in 0..(tree_height - 1) as usize {
+            let mut next_level: BTreeSet<u64> = BTreeSet::new();
+            for current_node in &current_level {
+                let sibling_node = sibling(*current_node).unwrap();
 
-            if self.tree.contains_key(&current_node) {
-                match self.tree.get(&current_node) {
-                    Some(x) => current_hash = x.clone(),
-                    _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
+                if !current_level.contains(&sibling_node) {
+                    auxiliary.push(self.stored_node_hash(sibling_node, level)?);
                 }
+
+                next_level.insert(parent(*current_node).unwrap());
             }
+            current_level = next_level;
+        }
 
-            if self.tree.contains_key(&sibling_node) {
-                match self.tree.get(&sibling_node) {
-                    Some(x) => sibling_hash = x.clone(),
-                    _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
-                }
+        Ok(MerkleSparseTreeMultiPath { auxiliary })
+    }
+
+    /// generate a batched opening for several locations, with a frontier sized for `num`
+    /// entries so that it can be padded up to `num` entries (does not check the data points)
+    pub fn generate_batch_membership_proof(
+        &self,
+        indices: &[u64],
+        num: usize,
+    ) -> Result<MerkleSparseTreeBatchPath<P>, Error> {
+        self.generate_batch_membership_proof_with_version(indices, num, None)
+    }
+
+    /// like `generate_batch_membership_proof`, against the root of a past version
+    pub fn generate_batch_membership_proof_at(
+        &self,
+        version: u64,
+        indices: &[u64],
+        num: usize,
+    ) -> Result<MerkleSparseTreeBatchPath<P>, Error> {
+        // Check that the version exists.
+        self.root_at(version)?;
+
+        self.generate_batch_membership_proof_with_version(indices, num, Some(version))
+    }
+
+    /// Generates a batched opening against the given version, or the latest one for `None`.
+    fn generate_batch_membership_proof_with_version(
+        &self,
+        indices: &[u64],
+        num: usize,
+        version: Option<u64>,
+    ) -> Result<MerkleSparseTreeBatchPath<P>, Error> {
+        let depth = batch_frontier_depth::<P>(num.max(indices.len()));
+        let path_len = (Self::HEIGHT - 1 - depth) as usize;
+
+        // The frontier is the whole level at that depth, read from the first node of the level.
+        let first_node = (1u64 << depth) - 1;
+        let mut frontier = Vec::with_capacity(1 << depth);
+        for i in 0..(1u64 << depth) {
+            frontier.push(self.node_hash_with_version(first_node + i, path_len, version)?);
+        }
+
+        // Each sibling below the frontier is identified by its level and its position in the
+        // level, and stored once.
+        let mut sibling_positions: BTreeMap<(usize, u64), u64> = BTreeMap::new();
+        let mut siblings = Vec::new();
+        let mut positions = Vec::with_capacity(num.max(indices.len()));
+        let mut empty = Vec::with_capacity(num.max(indices.len()));
+        for index in indices {
+            let path = self.generate_membership_proof_with_version(*index, version)?;
+            empty.push(self.is_empty_leaf_with_version(*index, version)?);
+
+            let mut entry_positions = Vec::with_capacity(path_len);
+            for (level, sibling) in path.path.into_iter().take(path_len).enumerate() {
+                let position = *sibling_positions
+                    .entry((level, (*index >> level) ^ 1))
+                    .or_insert_with(|| {
+                        siblings.push(sibling);
+                        (siblings.len() - 1) as u64
+                    });
+                entry_positions.push(position);
             }
+            positions.push(entry_positions);
+        }
 
-            if is_left_child(current_node) {
-                path.push((current_hash, sibling_hash));
-            } else {
-                path.push((sibling_hash, current_hash));
+        Ok(MerkleSparseTreeBatchPath {
+            empty,
+            frontier,
+            siblings,
+            positions,
+        })
+    }
+
+    /// generate a lookup proof
+    pub fn generate_proof<L: ToBytes>(
+        &self,
+        index: u64,
+        leaf: &L,
+    ) -> Result<MerkleSparseTreePath<P>, Error> {
+        let leaf_hash = hash_leaf::<P, _>(&self.parameters, leaf)?;
+        let tree_height = Self::HEIGHT;
+        let tree_index = convert_index_to_last_level(index, tree_height);
+
+        // Check that the given index corresponds to the correct leaf.
+        if let Some(x) = self.tree.get(tree_index)? {
+            if leaf_hash != x {
+                return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
             }
+        }
+
+        self.generate_membership_proof(index)
+    }
+
+    /// generate an absence proof, showing that the location has never been written
+    pub fn generate_absence_proof(&self, index: u64) -> Result<MerkleSparseTreePath<P>, Error> {
+        let tree_height = Self::HEIGHT;
+        let tree_index = convert_index_to_last_level(index, tree_height);
+
+        // Check that the given index still holds the empty leaf.
+        if self.tree.get(tree_index)?.is_some() {
+            return Err(MerkleSparseTreeError::NonEmptyLeaf(index).into());
+        }
+
+        self.generate_membership_proof(index)
+    }
+
+    /// update the tree and provide a modifying proof
+    pub fn update_and_prove<L: ToBytes>(
+        &mut self,
+        index: u64,
+        new_leaf: &L,
+    ) -> Result<MerkleSparseTreeTwoPaths<P>, Error> {
+        let new_leaf_hash = hash_leaf::<P, _>(&self.parameters, new_leaf)?;
+        self.update_leaf_hash_and_prove(index, new_leaf_hash)
+    }
+
+    /// update the tree with a leaf hashed as field elements and provide a modifying proof
+    pub fn update_field_elements_and_prove<F: PrimeField, L: ToConstraintField<F>>(
+        &mut self,
+        index: u64,
+        new_leaf: &L,
+    ) -> Result<MerkleSparseTreeTwoPaths<P>, Error> {
+        let new_leaf_hash = hash_field_leaf::<P, F, L>(&self.parameters, new_leaf)?;
+        self.update_leaf_hash_and_prove(index, new_leaf_hash)
+    }
+
+    fn update_leaf_hash_and_prove(
+        &mut self,
+        index: u64,
+        new_leaf_hash: <P::H as CRHforMerkleTree>::Output,
+    ) -> Result<MerkleSparseTreeTwoPaths<P>, Error> {
+        let path = self.generate_membership_proof(index)?;
+
+        let tree_height = Self::HEIGHT;
+        let tree_index = convert_index_to_last_level(index, tree_height);
+
+        let old_leaf_hash = self.stored_node_hash(tree_index, 0)?;
+
+        // Update the leaf and update the parents
+        let mut changed_nodes = BTreeMap::new();
+        changed_nodes.insert(tree_index, new_leaf_hash.clone());
+
+        // Iterate from the leaf up to the root, storing all intermediate hash values.
+        let mut current_node = tree_index;
+        let mut current_hash = new_leaf_hash;
+        for sibling_hash in path.path.iter() {
+            current_hash = if is_left_child(current_node) {
+                hash_inner_node::<P>(&self.parameters, &current_hash, sibling_hash)?
+            } else {
+                hash_inner_node::<P>(&self.parameters, sibling_hash, &current_hash)?
+            };
+
             current_node = parent(current_node).unwrap();
+            changed_nodes.insert(current_node, current_hash.clone());
+        }
+
+        if !is_root(current_node) {
+            return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+        }
+
+        self.commit_changes(changed_nodes, current_hash)?;
+
+        // Updating the leaf does not change the siblings, so the path is shared.
+        Ok(MerkleSparseTreeTwoPaths {
+            old_leaf_hash,
+            path,
+        })
+    }
+
+    /// write several leaves in order, and provide the new root and the modifying proof of each
+    /// write
+    ///
+    /// the chain of modifying proofs needs the root after every write, so each changed node is
+    /// still hashed once for each write below it, i.e., `HEIGHT - 1` hashes per write as with
+    /// `update_and_prove`; only the lookups of the siblings and the writes to the storage are
+    /// shared, as the changed nodes are hashed level by level and written once at the end. Use
+    /// `batch_update` to hash each changed node only once, without the proofs
+    pub fn batch_update_and_prove<L: ToBytes>(
+        &mut self,
+        indices: &[u64],
+        new_leaves: &[L],
+    ) -> Result<
+        Vec<(
+            <P::H as CRHforMerkleTree>::Output,
+            MerkleSparseTreeTwoPaths<P>,
+        )>,
+        Error,
+    > {
+        if indices.len() != new_leaves.len() {
+            return Err(
+                MerkleSparseTreeError::IncorrectBatchSize(indices.len(), new_leaves.len()).into(),
+            );
+        }
+
+        let new_leaf_hashes = hash_leaves::<P, L>(&self.parameters, new_leaves.iter())?;
+        self.batch_update_leaf_hashes_and_prove(indices, new_leaf_hashes)
+    }
+
+    /// like `batch_update_and_prove`, with the leaves hashed as field elements
+    pub fn batch_update_field_elements_and_prove<F: PrimeField, L: ToConstraintField<F>>(
+        &mut self,
+        indices: &[u64],
+        new_leaves: &[L],
+    ) -> Result<
+        Vec<(
+            <P::H as CRHforMerkleTree>::Output,
+            MerkleSparseTreeTwoPaths<P>,
+        )>,
+        Error,
+    > {
+        if indices.len() != new_leaves.len() {
+            return Err(
+                MerkleSparseTreeError::IncorrectBatchSize(indices.len(), new_leaves.len()).into(),
+            );
+        }
+
+        let new_leaf_hashes = new_leaves
+            .iter()
+            .map(|leaf| hash_field_leaf::<P, F, L>(&self.parameters, leaf))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.batch_update_leaf_hashes_and_prove(indices, new_leaf_hashes)
+    }
+
+    pub(crate) fn batch_update_leaf_hashes_and_prove(
+        &mut self,
+        indices: &[u64],
+        new_leaf_hashes: Vec<<P::H as CRHforMerkleTree>::Output>,
+    ) -> Result<
+        Vec<(
+            <P::H as CRHforMerkleTree>::Output,
+            MerkleSparseTreeTwoPaths<P>,
+        )>,
+        Error,
+    > {
+        for index in indices {
+            check_index::<P>(*index)?;
+        }
+
+        // The values of each node changed by the batch, with the write that gave each value.
+        let mut changed_nodes: BTreeMap<u64, Vec<(usize, <P::H as CRHforMerkleTree>::Output)>> =
+            BTreeMap::new();
+
+        // The node of each write at the current level, and its value after the write.
+        let mut nodes: Vec<u64> = indices
+            .iter()
+            .map(|index| convert_index_to_last_level(*index, Self::HEIGHT))
+            .collect();
+        let mut current_hashes = new_leaf_hashes;
+
+        // The old leaf of a write may have been written by an earlier write in the batch.
+        let mut old_leaf_hashes = Vec::with_capacity(indices.len());
+        for (i, (node, new_leaf_hash)) in nodes.iter().zip(current_hashes.iter()).enumerate() {
+            old_leaf_hashes.push(self.node_hash_before_write(&changed_nodes, *node, 0, i)?);
+            changed_nodes
+                .entry(*node)
+                .or_insert_with(Vec::new)
+                .push((i, new_leaf_hash.clone()));
+        }
+
+        let mut paths = vec![Vec::with_capacity((Self::HEIGHT - 1) as usize); indices.len()];
+        for level in 0..(Self::HEIGHT - 1) as usize {
+            // A write does not change its sibling, so the sibling is the same before and after it.
+            let mut children = Vec::with_capacity(nodes.len());
+            for (i, (node, current_hash)) in nodes.iter().zip(current_hashes.iter()).enumerate() {
+                let sibling_node = sibling(*node).unwrap();
+                let sibling_hash =
+                    self.node_hash_before_write(&changed_nodes, sibling_node, level, i)?;
+
+                if is_left_child(*node) {
+                    children.push((current_hash.clone(), sibling_hash.clone()));
+                } else {
+                    children.push((sibling_hash.clone(), current_hash.clone()));
+                }
+                paths[i].push(sibling_hash);
+            }
+
+            // Compute Hash(left || right) for the whole level.
+            current_hashes = hash_inner_nodes::<P>(&self.parameters, &children)?;
+            for (i, (node, current_hash)) in nodes.iter_mut().zip(current_hashes.iter()).enumerate()
+            {
+                *node = parent(*node).unwrap();
+                changed_nodes
+                    .entry(*node)
+                    .or_insert_with(Vec::new)
+                    .push((i, current_hash.clone()));
+            }
+        }
+
+        // Write the last value of each changed node once.
+        let root_hash = match current_hashes.last() {
+            Some(x) => x.clone(),
+            None => self.root(),
+        };
+        let mut last_changed_nodes = BTreeMap::new();
+        for (index, values) in changed_nodes {
+            if let Some((_, hash)) = values.into_iter().last() {
+                last_changed_nodes.insert(index, hash);
+            }
+        }
+        self.commit_changes(last_changed_nodes, root_hash)?;
+
+        Ok(current_hashes
+            .into_iter()
+            .zip(old_leaf_hashes.into_iter().zip(paths))
+            .map(|(new_root, (old_leaf_hash, path))| {
+                (
+                    new_root,
+                    MerkleSparseTreeTwoPaths {
+                        old_leaf_hash,
+                        path: MerkleSparseTreePath { path },
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Returns the hash of a node before a write of the batch, given the values that the earlier
+    /// writes gave to the nodes.
+    fn node_hash_before_write(
+        &self,
+        changed_nodes: &BTreeMap<u64, Vec<(usize, <P::H as CRHforMerkleTree>::Output)>>,
+        index: u64,
+        level: usize,
+        write: usize,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        let earlier_value = changed_nodes
+            .get(&index)
+            .and_then(|values| values.iter().rev().find(|(i, _)| *i < write));
+        match earlier_value {
+            Some((_, x)) => Ok(x.clone()),
+            None => self.stored_node_hash(index, level),
         }
+    }
 
-        if path.len() != (Self::HEIGHT - 1) as usize {
-            Err(MerkleSparseTreeError::IncorrectPathLength(path.len()).into())
-        } else {
-            Ok(MerkleSparseTreePath { path })
+    /// Returns the hash of a node, or the empty hash of its level if it has never been written.
+    fn stored_node_hash(
+        &self,
+        index: u64,
+        level: usize,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        match self.tree.get(index)? {
+            Some(x) => Ok(x),
+            None => Ok(self.empty_hashes[level].clone()),
         }
     }
 
-    /// generate a lookup proof
-    pub fn generate_proof<L: ToBytes>(
+    /// Returns the hash of a node in the given version, or in the latest one for `None`, or the
+    /// empty hash of its level if it had not been written by then.
+    fn node_hash_with_version(
         &self,
         index: u64,
-        leaf: &L,
-    ) -> Result<MerkleSparseTreePath<P>, Error> {
-        let leaf_hash = hash_leaf::<P::H, _>(&self.parameters, leaf)?;
-        let tree_height = Self::HEIGHT;
-        let tree_index = convert_index_to_last_level(index, tree_height);
+        level: usize,
+        version: Option<u64>,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        match self.written_node_hash_with_version(index, version)? {
+            Some(x) => Ok(x),
+            None => Ok(self.empty_hashes[level].clone()),
+        }
+    }
 
-        // Check that the given index corresponds to the correct leaf.
-        if let Some(x) = self.tree.get(&tree_index) {
-            if leaf_hash != *x {
-                return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+    /// Returns the hash of a node in the given version, or in the latest one for `None`, or
+    /// `None` if it had not been written by then.
+    fn written_node_hash_with_version(
+        &self,
+        index: u64,
+        version: Option<u64>,
+    ) -> Result<Option<<P::H as CRHforMerkleTree>::Output>, Error> {
+        // The first entry above the version holds the value at the version.
+        if let (Some(version), Some(versions)) = (version, &self.versions) {
+            if let Some(replaced) = versions.replaced_nodes.get(&index) {
+                if let Some((_, old_hash)) = replaced.iter().find(|(v, _)| *v > version) {
+                    return Ok(old_hash.clone());
+                }
             }
         }
 
-        self.generate_membership_proof(index)
+        self.tree.get(index)
     }
 
-    /// update the tree and provide a modifying proof
-    pub fn update_and_prove<L: ToBytes>(
-        &mut self,
+    /// Returns whether the location had never been written in the given version, or in the
+    /// latest one for `None`.
+    pub(crate) fn is_empty_leaf_with_version(
+        &self,
         index: u64,
-        new_leaf: &L,
-    ) -> Result<MerkleSparseTreeTwoPaths<P>, Error> {
-        let old_path = self.generate_membership_proof(index)?;
-
-        let new_leaf_hash = hash_leaf::<P::H, _>(&self.parameters, new_leaf)?;
-
-        let tree_height = Self::HEIGHT;
-        let tree_index = convert_index_to_last_level(index, tree_height);
-
-        // Update the leaf and update the parents
-        self.tree.insert(tree_index, new_leaf_hash);
-
-        // Iterate from the leaf up to the root, storing all intermediate hash values.
-        let mut current_node = tree_index;
-        current_node = parent(current_node).unwrap();
-
-        let mut empty_hashes_iter = self.empty_hashes.iter();
-        loop {
-            let left_node = left_child(current_node);
-            let right_node = right_child(current_node);
-
-            let mut left_hash = empty_hashes_iter.next().unwrap().clone();
-            let mut right_hash = left_hash.clone();
+        version: Option<u64>,
+    ) -> Result<bool, Error> {
+        let tree_index = convert_index_to_last_level(index, Self::HEIGHT);
+        Ok(self
+            .written_node_hash_with_version(tree_index, version)?
+            .is_none())
+    }
 
-            if self.tree.contains_key(&left_node) {
-                match self.tree.get(&left_node) {
-                    Some(x) => left_hash = x.clone(),
-                    _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
+    /// Writes the changed nodes and the new root, keeping the replaced nodes if versioning is
+    /// enabled.
+    fn commit_changes(
+        &mut self,
+        changed_nodes: BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+        root_hash: <P::H as CRHforMerkleTree>::Output,
+    ) -> Result<(), Error> {
+        // Keep the value of each node at the latest version, the first time it is replaced.
+        if let Some(versions) = &mut self.versions {
+            let key = versions.latest() + 1;
+            for index in changed_nodes.keys() {
+                let replaced = versions
+                    .replaced_nodes
+                    .entry(*index)
+                    .or_insert_with(Vec::new);
+                if replaced
+                    .last()
+                    .map_or(true, |(last_key, _)| *last_key != key)
+                {
+                    replaced.push((key, self.tree.get(*index)?));
                 }
             }
+        }
 
-            if self.tree.contains_key(&right_node) {
-                match self.tree.get(&right_node) {
-                    Some(x) => right_hash = x.clone(),
-                    _ => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
-                }
-            }
+        Self::commit_with_versions(
+            &mut self.tree,
+            changed_nodes
+                .into_iter()
+                .map(|(i, x)| (i, Some(x)))
+                .collect(),
+            self.versions.as_ref(),
+        )?;
+        self.root = Some(root_hash);
 
-            self.tree.insert(
-                current_node,
-                hash_inner_node::<P::H>(&self.parameters, &left_hash, &right_hash)?,
-            );
+        Ok(())
+    }
 
-            if is_root(current_node) {
-                break;
-            }
+    /// Writes the past versions to a storage that keeps metadata.
+    fn write_versions(&mut self) -> Result<(), Error> {
+        Self::commit_with_versions(&mut self.tree, Vec::new(), self.versions.as_ref())
+    }
 
-            current_node = parent(current_node).unwrap();
+    /// Writes the changes to the storage, together with the past versions if the storage keeps
+    /// them.
+    fn commit_with_versions(
+        storage: &mut S,
+        batch: Vec<(u64, Option<<P::H as CRHforMerkleTree>::Output>)>,
+        versions: Option<&NodeVersions<P>>,
+    ) -> Result<(), Error> {
+        match versions {
+            Some(versions) if storage.keeps_metadata() => {
+                let mut metadata = Vec::new();
+                versions.serialize(&mut metadata)?;
+                storage.commit_with_metadata(batch, metadata)
+            }
+            _ => storage.commit(batch),
         }
+    }
 
-        match self.tree.get(&0) {
-            Some(x) => self.root = Some((*x).clone()),
-            None => return Err(MerkleSparseTreeError::IncorrectTreeStructure.into()),
+    /// Returns the hash of a node, looking at the changed nodes first.
+    fn changed_node_hash(
+        &self,
+        changed_nodes: &BTreeMap<u64, <P::H as CRHforMerkleTree>::Output>,
+        index: u64,
+        level: usize,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        match changed_nodes.get(&index) {
+            Some(x) => Ok(x.clone()),
+            None => self.stored_node_hash(index, level),
         }
-
-        let new_path = self.generate_proof(index, new_leaf)?;
-
-        Ok(MerkleSparseTreeTwoPaths { old_path, new_path })
     }
 
     /// check if the tree is structurally valid
@@ -557,43 +1668,28 @@ impl<P: MerkleSparseTreeConfig> MerkleSparseTree<P> {
         let last_level_index: u64 = (1u64 << (Self::HEIGHT - 1)) - 1;
         let mut middle_nodes: BTreeSet<u64> = BTreeSet::new();
 
-        for key in self.tree.keys() {
-            if *key >= last_level_index && !is_root(*key) {
-                middle_nodes.insert(parent(*key).unwrap());
+        for key in self.tree.indices()? {
+            if key >= last_level_index && !is_root(key) {
+                middle_nodes.insert(parent(key).unwrap());
             }
         }
 
         for level in 0..Self::HEIGHT {
+            let mut children = Vec::with_capacity(middle_nodes.len());
             for current_index in &middle_nodes {
-                let left_index = left_child(*current_index);
-                let right_index = right_child(*current_index);
-
-                let mut left_hash = self.empty_hashes[level as usize].clone();
-                let mut right_hash = self.empty_hashes[level as usize].clone();
-
-                if self.tree.contains_key(&left_index) {
-                    match self.tree.get(&left_index) {
-                        Some(x) => left_hash = x.clone(),
-                        _ => {
-                            return Ok(false);
-                        }
-                    }
-                }
-
-                if self.tree.contains_key(&right_index) {
-                    match self.tree.get(&right_index) {
-                        Some(x) => right_hash = x.clone(),
-                        _ => {
-                            return Ok(false);
-                        }
-                    }
-                }
+                let left_hash =
+                    self.stored_node_hash(left_child(*current_index), level as usize)?;
+                let right_hash =
+                    self.stored_node_hash(right_child(*current_index), level as usize)?;
 
-                let hash = hash_inner_node::<P::H>(&self.parameters, &left_hash, &right_hash)?;
+                children.push((left_hash, right_hash));
+            }
 
-                match self.tree.get(current_index) {
+            let hashes = hash_inner_nodes::<P>(&self.parameters, &children)?;
+            for (current_index, hash) in middle_nodes.iter().zip(hashes) {
+                match self.tree.get(*current_index)? {
                     Some(x) => {
-                        if *x != hash {
+                        if x != hash {
                             return Ok(false);
                         }
                     }
@@ -623,6 +1719,20 @@ pub enum MerkleSparseTreeError {
     IncorrectPathLength(usize),
     /// tree structure is incorrect, some nodes are missing
     IncorrectTreeStructure,
+    /// the leaf at this index has been written
+    NonEmptyLeaf(u64),
+    /// the numbers of indices and leaves in a batch differ
+    IncorrectBatchSize(usize, usize),
+    /// the version is not kept by this tree
+    UnknownVersion(u64),
+    /// the version is not above the latest version, or versioning is not enabled
+    InvalidVersion(u64),
+    /// the leaf cannot be converted into field elements
+    IncorrectLeaf,
+    /// the index is not a location in the last level
+    IncorrectIndex(u64),
+    /// the path does not record its location, which must be given
+    MissingIndex,
 }
 
 impl core::fmt::Display for MerkleSparseTreeError {
@@ -632,6 +1742,28 @@ impl core::fmt::Display for MerkleSparseTreeError {
                 format!("incorrect path length: {}", len)
             }
             MerkleSparseTreeError::IncorrectTreeStructure => "incorrect tree structure".to_string(),
+            MerkleSparseTreeError::NonEmptyLeaf(index) => {
+                format!("leaf at index {} is not empty", index)
+            }
+            MerkleSparseTreeError::IncorrectBatchSize(indices_len, leaves_len) => format!(
+                "the number of indices {} does not equal the number of leaves {}",
+                indices_len, leaves_len
+            ),
+            MerkleSparseTreeError::UnknownVersion(version) => {
+                format!("version {} is not kept by this tree", version)
+            }
+            MerkleSparseTreeError::InvalidVersion(version) => {
+                format!("version {} cannot be added to this tree", version)
+            }
+            MerkleSparseTreeError::IncorrectLeaf => {
+                "the leaf cannot be converted into field elements".to_string()
+            }
+            MerkleSparseTreeError::IncorrectIndex(index) => {
+                format!("index {} is not a location of the tree", index)
+            }
+            MerkleSparseTreeError::MissingIndex => {
+                "the path does not record its location, which must be given".to_string()
+            }
         };
         write!(f, "{}", msg)
     }
@@ -703,46 +1835,217 @@ fn convert_index_to_last_level(index: u64, tree_height: u64) -> u64 {
 }
 
 /// Returns the output hash, given a left and right hash value.
-pub(crate) fn hash_inner_node<H: CRHforMerkleTree>(
-    parameters: &H::Parameters,
-    left: &H::Output,
-    right: &H::Output,
-) -> Result<H::Output, Error> {
-    H::two_to_one_compress(parameters, left, right)
+pub(crate) fn hash_inner_node<P: MerkleSparseTreeConfig>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    left: &<P::H as CRHforMerkleTree>::Output,
+    right: &<P::H as CRHforMerkleTree>::Output,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    match domain_tag::<P>(INNER_NODE_TAG) {
+        Some(tag) => P::H::two_to_one_compress_with_tag(parameters, tag, left, right),
+        None => P::H::two_to_one_compress(parameters, left, right),
+    }
 }
 
 /// Returns the hash of a leaf.
-fn hash_leaf<H: CRHforMerkleTree, L: ToBytes>(
-    parameters: &H::Parameters,
+pub(crate) fn hash_leaf<P: MerkleSparseTreeConfig, L: ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
     leaf: &L,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_with_tag::<P::H, L>(parameters, domain_tag::<P>(LEAF_TAG), leaf)
+}
+
+/// Returns the hash of the bytes of a value, prefixed by the tag if there is one.
+pub(crate) fn hash_with_tag<H: CRHforMerkleTree, L: ToBytes>(
+    parameters: &H::Parameters,
+    tag: Option<u8>,
+    value: &L,
 ) -> Result<H::Output, Error> {
+    H::hash_bytes(parameters, &bytes_with_tag(tag, value)?)
+}
+
+/// Returns the bytes of a value, prefixed by the tag if there is one.
+fn bytes_with_tag<L: ToBytes>(tag: Option<u8>, value: &L) -> Result<Vec<u8>, Error> {
     let mut writer = Cursor::new(Vec::<u8>::new());
-    leaf.write(&mut writer)?;
+    if let Some(tag) = tag {
+        tag.write(&mut writer)?;
+    }
+    value.write(&mut writer)?;
+
+    Ok(writer.into_inner())
+}
+
+/// Returns the hash of a leaf, in the domain of the empty leaves if `empty`.
+pub(crate) fn hash_leaf_or_empty<P: MerkleSparseTreeConfig, L: ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &L,
+    empty: bool,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_with_tag::<P::H, L>(parameters, domain_tag::<P>(leaf_tag(empty)), leaf)
+}
+
+/// Returns the hash of a leaf given as field elements, which skips the conversion to bytes.
+/// The tag, if there is one, is prefixed as a field element.
+pub(crate) fn hash_field_leaf<P: MerkleSparseTreeConfig, F: PrimeField, L: ToConstraintField<F>>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &L,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_field_leaf_or_empty::<P, F, L>(parameters, leaf, false)
+}
 
-    H::hash_bytes(parameters, &writer.into_inner())
+/// Returns the hash of a leaf given as field elements, or of the empty leaf if `empty`.
+fn hash_field_leaf_or_empty<P: MerkleSparseTreeConfig, F: PrimeField, L: ToConstraintField<F>>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &L,
+    empty: bool,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    let elements = leaf
+        .to_field_elements()
+        .ok_or(MerkleSparseTreeError::IncorrectLeaf)?;
+    hash_elements_with_tag::<P::H, F>(parameters, domain_tag::<P>(leaf_tag(empty)), &elements)
 }
 
-fn hash_empty<H: CRHforMerkleTree, L: ToBytes>(
+/// Returns the hash of the field elements, prefixed by the tag as a field element if there is one.
+fn hash_elements_with_tag<H: CRHforMerkleTree, F: PrimeField>(
     parameters: &H::Parameters,
-    empty_leaf: L,
+    tag: Option<u8>,
+    elements: &[F],
 ) -> Result<H::Output, Error> {
-    let mut writer = Cursor::new(Vec::<u8>::new());
-    empty_leaf.write(&mut writer)?;
+    let mut tagged = Vec::with_capacity(elements.len() + 1);
+    if let Some(tag) = tag {
+        tagged.push(F::from(u64::from(tag)));
+    }
+    tagged.extend_from_slice(elements);
+
+    H::hash_field_elements(parameters, &tagged)
+}
+
+/// Returns the hash of a leaf in its encoding, as bytes or as field elements.
+pub(crate) fn hash_encoded_leaf<P: MerkleSparseTreeConfig, F: PrimeField>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &LeafEncoding<F>,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_encoded_leaf_or_empty::<P, F>(parameters, leaf, false)
+}
+
+/// Returns the hash of a leaf in its encoding, or of the empty leaf if `empty`.
+pub(crate) fn hash_encoded_leaf_or_empty<P: MerkleSparseTreeConfig, F: PrimeField>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaf: &LeafEncoding<F>,
+    empty: bool,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    let tag = domain_tag::<P>(leaf_tag(empty));
+    match leaf {
+        LeafEncoding::Bytes(bytes) => hash_with_tag::<P::H, Vec<u8>>(parameters, tag, bytes),
+        LeafEncoding::FieldElements(elements) => {
+            hash_elements_with_tag::<P::H, F>(parameters, tag, elements)
+        }
+    }
+}
+
+/// Returns the hashes of the leaves, in parallel if the `parallel` feature is enabled.
+fn hash_leaves<'a, P: MerkleSparseTreeConfig, L: 'a + ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaves: impl Iterator<Item = &'a L>,
+) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+    hash_leaves_or_empty::<P, L>(parameters, leaves.map(|leaf| (leaf, false)))
+}
+
+/// Returns the hashes of the leaves, each of which is hashed as the empty leaf if its flag is
+/// set, in parallel if the `parallel` feature is enabled.
+fn hash_leaves_or_empty<'a, P: MerkleSparseTreeConfig, L: 'a + ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    leaves: impl Iterator<Item = (&'a L, bool)>,
+) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+    // Only the bytes of the leaves are handed to the threads.
+    let mut leaves_bytes = Vec::new();
+    for (leaf, empty) in leaves {
+        leaves_bytes.push(bytes_with_tag(domain_tag::<P>(leaf_tag(empty)), leaf)?);
+    }
+
+    #[cfg(feature = "parallel")]
+    {
+        leaves_bytes
+            .par_iter()
+            .map(|leaf_bytes| P::H::hash_bytes(parameters, leaf_bytes).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.into())
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        leaves_bytes
+            .iter()
+            .map(|leaf_bytes| P::H::hash_bytes(parameters, leaf_bytes))
+            .collect()
+    }
+}
+
+/// Returns the hashes of pairs of children, in parallel if the `parallel` feature is enabled.
+fn hash_inner_nodes<P: MerkleSparseTreeConfig>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    children: &[(
+        <P::H as CRHforMerkleTree>::Output,
+        <P::H as CRHforMerkleTree>::Output,
+    )],
+) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+    #[cfg(feature = "parallel")]
+    {
+        children
+            .par_iter()
+            .map(|(left, right)| {
+                hash_inner_node::<P>(parameters, left, right).map_err(|e| e.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.into())
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        children
+            .iter()
+            .map(|(left, right)| hash_inner_node::<P>(parameters, left, right))
+            .collect()
+    }
+}
+
+/// Returns the hash of an empty leaf, which is in its own domain, so that a location that has
+/// never been written differs from one that holds the default leaf.
+pub(crate) fn hash_empty<P: MerkleSparseTreeConfig, L: ToBytes>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    empty_leaf: L,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_leaf_or_empty::<P, L>(parameters, &empty_leaf, true)
+}
 
-    H::hash_bytes(parameters, &writer.into_inner())
+/// Returns the hash of an empty leaf in its encoding, as `hash_empty` does for the bytes.
+pub(crate) fn hash_encoded_empty<P: MerkleSparseTreeConfig, F: PrimeField>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    empty_leaf: &LeafEncoding<F>,
+) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+    hash_encoded_leaf_or_empty::<P, F>(parameters, empty_leaf, true)
 }
 
-fn gen_empty_hashes<P: MerkleSparseTreeConfig, L: ToBytes>(
+/// Returns the empty hash of each level for this empty leaf, from the leaves up.
+pub(crate) fn gen_empty_hashes<P: MerkleSparseTreeConfig, L: ToBytes>(
     parameters: &<P::H as CRHforMerkleTree>::Parameters,
     empty_leaf: L,
 ) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
-    let mut empty_hashes = Vec::with_capacity(P::HEIGHT as usize);
+    let empty_leaf_hash = hash_empty::<P, L>(&parameters, empty_leaf)?;
+    gen_empty_hashes_from_leaf_hash::<P>(parameters, empty_leaf_hash)
+}
+
+/// Returns the empty hash of each level, from the hash of the empty leaf up.
+pub(crate) fn gen_empty_hashes_from_leaf_hash<P: MerkleSparseTreeConfig>(
+    parameters: &<P::H as CRHforMerkleTree>::Parameters,
+    empty_leaf_hash: <P::H as CRHforMerkleTree>::Output,
+) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+    let mut empty_hashes = Vec::with_capacity(P::HEIGHT as usize + 1);
 
-    let mut empty_hash = hash_empty::<P::H, L>(&parameters, empty_leaf)?;
+    let mut empty_hash = empty_leaf_hash;
     empty_hashes.push(empty_hash.clone());
 
     for _ in 1..=P::HEIGHT {
-        empty_hash = hash_inner_node::<P::H>(&parameters, &empty_hash, &empty_hash)?;
+        empty_hash = hash_inner_node::<P>(&parameters, &empty_hash, &empty_hash)?;
         empty_hashes.push(empty_hash.clone());
     }
 
@@ -781,10 +2084,14 @@ mod test {
         let root = tree.root();
         for (i, leaf) in leaves.iter() {
             let proof = tree.generate_proof(*i, &leaf).unwrap();
-            assert!(proof.verify(&crh_parameters, &root, &leaf).unwrap());
             assert!(proof
                 .verify_with_index(&crh_parameters, &root, &leaf, *i)
                 .unwrap());
+
+            /* the path does not record its location, so the deprecated check without it fails */
+            #[allow(deprecated)]
+            let result = proof.verify(&crh_parameters, &root, &leaf);
+            assert!(result.is_err());
         }
 
         assert!(tree.validate().unwrap());
@@ -814,7 +2121,6 @@ mod test {
         let root = Fr::zero();
         for (i, leaf) in leaves.iter() {
             let proof = tree.generate_proof(*i, &leaf).unwrap();
-            assert!(proof.verify(&crh_parameters, &root, &leaf).unwrap());
             assert!(proof
                 .verify_with_index(&crh_parameters, &root, &leaf, *i)
                 .unwrap());
@@ -870,6 +2176,10 @@ mod test {
                     assert!(update_proof
                         .verify(&crh_parameters, &old_root, &new_root, &new_leaf, *i)
                         .unwrap());
+                    /* the shared siblings do not lead from the old leaf to the new root */
+                    assert!(!update_proof
+                        .verify(&crh_parameters, &new_root, &new_root, &new_leaf, *i)
+                        .unwrap());
                 }
                 None => {
                     let update_proof = tree.update_and_prove(*i, &new_leaf).unwrap();
@@ -887,6 +2197,155 @@ mod test {
         }
     }
 
+    #[test]
+    fn absence_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..10u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let mut tree = JubJubMerkleTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        for i in 10..20u64 {
+            let proof = tree.generate_absence_proof(i).unwrap();
+            assert!(proof
+                .verify_absence_with_index::<u8>(&crh_parameters, &root, i)
+                .unwrap());
+            assert!(!proof
+                .verify_absence_with_index::<u8>(&crh_parameters, &root, i + 1)
+                .unwrap());
+        }
+
+        for i in 0..10u64 {
+            assert!(tree.generate_absence_proof(i).is_err());
+
+            let proof = tree.generate_membership_proof(i).unwrap();
+            assert!(!proof
+                .verify_absence_with_index::<u8>(&crh_parameters, &root, i)
+                .unwrap());
+        }
+
+        /* a location holding the default leaf has been written, so it is not absent */
+        tree.update_and_prove(15, &0u8).unwrap();
+        let root = tree.root();
+        assert!(tree.generate_absence_proof(15).is_err());
+        let proof = tree.generate_membership_proof(15).unwrap();
+        assert!(!proof
+            .verify_absence_with_index::<u8>(&crh_parameters, &root, 15)
+            .unwrap());
+        assert!(proof
+            .verify_with_index(&crh_parameters, &root, &0u8, 15)
+            .unwrap());
+    }
+
+    #[test]
+    fn multi_membership_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..20u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        /* a range of leaves shares most of the inner nodes */
+        let indices: Vec<u64> = (3..11u64).collect();
+        let data: Vec<u8> = indices.iter().map(|i| leaves[i]).collect();
+        let proof = tree.generate_multi_membership_proof(&indices).unwrap();
+        assert!(proof
+            .verify_with_indices(&crh_parameters, &root, &data, &indices)
+            .unwrap());
+        assert!(proof.auxiliary.len() < indices.len() * (JubJubMerkleTree::HEIGHT - 1) as usize);
+
+        /* the leaves can be given in any order */
+        let mut rev_indices = indices.clone();
+        rev_indices.reverse();
+        let mut rev_data = data.clone();
+        rev_data.reverse();
+        assert!(proof
+            .verify_with_indices(&crh_parameters, &root, &rev_data, &rev_indices)
+            .unwrap());
+
+        /* scattered locations, including a never-written one */
+        let indices = [0u64, 7, 19, 1000];
+        let data = [1u8, 8, 20, 0];
+        let proof = tree.generate_multi_membership_proof(&indices).unwrap();
+        assert!(proof
+            .verify_with_indices(&crh_parameters, &root, &data, &indices)
+            .unwrap());
+
+        /* wrong data, a wrong location, or a missing location is rejected */
+        assert!(!proof
+            .verify_with_indices(&crh_parameters, &root, &[1u8, 8, 21, 0], &indices)
+            .unwrap());
+        assert!(!proof
+            .verify_with_indices(&crh_parameters, &root, &data, &[0u64, 7, 18, 1000])
+            .unwrap());
+        assert!(!proof
+            .verify_with_indices(&crh_parameters, &root, &data[..3], &indices[..3])
+            .unwrap());
+    }
+
+    #[test]
+    fn batch_membership_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..20u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+        leaves.insert(1u64 << 30, 100);
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let tree = JubJubMerkleTree::new(crh_parameters.clone(), &leaves).unwrap();
+        let root = tree.root();
+
+        /* overlapping addresses share the siblings below the frontier */
+        let indices = vec![4u64, 5, 6];
+        let data: Vec<u8> = indices.iter().map(|i| leaves[i]).collect();
+        let proof = tree
+            .generate_batch_membership_proof(&indices, indices.len())
+            .unwrap();
+        assert!(proof
+            .verify_with_indices(&crh_parameters, &root, &data, &indices)
+            .unwrap());
+        assert!(proof.siblings.len() < indices.len() * proof.positions[0].len());
+
+        /* addresses in different halves of the tree share no sibling */
+        let far_indices = vec![4u64, 1u64 << 30];
+        let far_data: Vec<u8> = far_indices.iter().map(|i| leaves[i]).collect();
+        let far_proof = tree
+            .generate_batch_membership_proof(&far_indices, far_indices.len())
+            .unwrap();
+        assert!(far_proof
+            .verify_with_indices(&crh_parameters, &root, &far_data, &far_indices)
+            .unwrap());
+        assert_eq!(
+            far_proof.siblings.len(),
+            far_indices.len() * far_proof.positions[0].len()
+        );
+
+        /* the wrong data, or a position out of the siblings, fails */
+        let mut wrong_data = data.clone();
+        wrong_data[1] += 1;
+        assert!(!proof
+            .verify_with_indices(&crh_parameters, &root, &wrong_data, &indices)
+            .unwrap());
+
+        let mut wrong_proof = proof.clone();
+        wrong_proof.positions[0][0] = wrong_proof.siblings.len() as u64;
+        assert!(!wrong_proof
+            .verify_with_indices(&crh_parameters, &root, &data, &indices)
+            .unwrap());
+    }
+
     #[test]
     fn good_root_update_test() {
         let mut old_leaves: BTreeMap<u64, u8> = BTreeMap::new();
@@ -899,4 +2358,311 @@ mod test {
         }
         generate_merkle_tree_and_test_update(&old_leaves, &new_leaves);
     }
+
+    #[test]
+    fn batch_update_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut old_leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..10u8 {
+            old_leaves.insert(i as u64, i);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let mut tree = JubJubMerkleTree::new(crh_parameters.clone(), &old_leaves).unwrap();
+        let mut expected_tree = JubJubMerkleTree::new(crh_parameters.clone(), &old_leaves).unwrap();
+
+        /* neighbouring, repeated and never-written locations */
+        let indices = [3u64, 4, 3, 25, 1000, 4];
+        let data = [30u8, 40, 31, 250, 100, 41];
+
+        let mut old_root = tree.root();
+        let proofs = tree.batch_update_and_prove(&indices, &data).unwrap();
+        assert_eq!(proofs.len(), indices.len());
+        for ((i, leaf), (new_root, proof)) in indices.iter().zip(data.iter()).zip(proofs.iter()) {
+            let expected_proof = expected_tree.update_and_prove(*i, leaf).unwrap();
+            assert!(expected_tree.root() == *new_root);
+            assert!(expected_proof.old_leaf_hash == proof.old_leaf_hash);
+            assert!(expected_proof.path.path == proof.path.path);
+
+            assert!(proof
+                .verify(&crh_parameters, &old_root, new_root, leaf, *i)
+                .unwrap());
+            old_root = new_root.clone();
+        }
+        assert!(tree.root() == expected_tree.root());
+        assert!(tree.validate().unwrap());
+
+        /* writing a map at once matches building the tree from it */
+        let mut new_leaves = old_leaves.clone();
+        for i in 5..20u8 {
+            new_leaves.insert(i as u64, i + 1);
+        }
+        let mut tree = JubJubMerkleTree::new(crh_parameters.clone(), &old_leaves).unwrap();
+        tree.batch_update(&new_leaves).unwrap();
+        let expected_tree = JubJubMerkleTree::new(crh_parameters.clone(), &new_leaves).unwrap();
+        assert!(tree.root() == expected_tree.root());
+        assert!(tree.tree == expected_tree.tree);
+
+        assert!(tree.batch_update_and_prove(&indices, &data[..3]).is_err());
+
+        /* a location outside the last level is rejected, leaving the tree untouched */
+        let outside_index = 1u64 << (JubJubMerkleTree::HEIGHT - 1);
+        let mut outside_leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        outside_leaves.insert(outside_index, 1);
+        assert!(tree
+            .batch_update_and_prove(&[1u64, outside_index], &[1u8, 1])
+            .is_err());
+        assert!(tree.batch_update(&outside_leaves).is_err());
+        assert!(tree.root() == expected_tree.root());
+        assert!(tree.tree == expected_tree.tree);
+    }
+
+    #[test]
+    fn versioning_test() {
+        let mut rng = ark_std::test_rng();
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..4u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+
+        let crh_parameters = H::setup(&mut rng).unwrap();
+        let mut tree = JubJubMerkleTree::new(crh_parameters.clone(), &leaves).unwrap();
+        assert!(tree.version().is_none());
+        assert!(tree.generate_membership_proof_at(0, 0).is_err());
+        assert!(tree.set_version(1).is_err());
+
+        tree.enable_versioning(3).unwrap();
+        assert_eq!(tree.version(), Some(3));
+
+        /* the leaves at each version, where the versions need not be consecutive */
+        let mut history = vec![(3u64, leaves.clone())];
+
+        /* several updates between two versions make a single version */
+        tree.update_and_prove(1, &10u8).unwrap();
+        tree.update_and_prove(1, &12u8).unwrap();
+        leaves.insert(1, 12);
+        tree.set_version(4).unwrap();
+        history.push((4, leaves.clone()));
+
+        tree.batch_update_and_prove(&[1u64, 5, 2], &[11u8, 50, 20])
+            .unwrap();
+        leaves.insert(1, 11);
+        leaves.insert(5, 50);
+        leaves.insert(2, 20);
+        tree.set_version(6).unwrap();
+        history.push((6, leaves.clone()));
+
+        /* a version without updates has the same root */
+        tree.set_version(7).unwrap();
+        history.push((7, leaves.clone()));
+
+        let mut new_leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        new_leaves.insert(0, 100);
+        new_leaves.insert(6, 60);
+        tree.batch_update(&new_leaves).unwrap();
+        leaves.extend(new_leaves);
+        tree.set_version(8).unwrap();
+        history.push((8, leaves.clone()));
+
+        /* the versions only increase */
+        assert!(tree.set_version(8).is_err());
+        assert!(tree.set_version(5).is_err());
+
+        assert_eq!(tree.version(), Some(8));
+        assert!(tree.root_at(8).unwrap() == tree.root());
+        assert!(tree.root_at(7).unwrap() == tree.root_at(6).unwrap());
+        assert!(tree.root_at(3).unwrap() != tree.root_at(4).unwrap());
+        assert!(tree.root_at(5).is_err());
+
+        /* the versions are kept by a serialization round trip */
+        let mut bytes = Vec::new();
+        tree.serialize_nodes(&mut bytes).unwrap();
+        let restored =
+            JubJubMerkleTree::deserialize_nodes::<u8, _>(crh_parameters.clone(), &bytes[..])
+                .unwrap();
+        assert_eq!(restored.version(), Some(8));
+
+        /* a stream without a root is rejected */
+        let mut bytes = Vec::new();
+        0u64.serialize(&mut bytes).unwrap();
+        Option::<<H as CRHforMerkleTree>::Output>::None
+            .serialize(&mut bytes)
+            .unwrap();
+        false.serialize(&mut bytes).unwrap();
+        assert!(
+            JubJubMerkleTree::deserialize_nodes::<u8, _>(crh_parameters.clone(), &bytes[..])
+                .is_err()
+        );
+
+        for tree in [&tree, &restored].iter() {
+            for (version, leaves) in history.iter() {
+                let root = tree.root_at(*version).unwrap();
+                for i in 0..8u64 {
+                    let proof = tree.generate_membership_proof_at(*version, i).unwrap();
+                    match leaves.get(&i) {
+                        Some(leaf) => assert!(proof
+                            .verify_with_index(&crh_parameters, &root, leaf, i)
+                            .unwrap()),
+                        None => assert!(proof
+                            .verify_absence_with_index::<u8>(&crh_parameters, &root, i)
+                            .unwrap()),
+                    }
+                }
+
+                let indices: Vec<u64> = leaves.keys().cloned().collect();
+                let values: Vec<u8> = leaves.values().cloned().collect();
+                let proof = tree
+                    .generate_batch_membership_proof_at(*version, &indices, indices.len())
+                    .unwrap();
+                assert!(proof
+                    .verify_with_indices(&crh_parameters, &root, &values, &indices)
+                    .unwrap());
+            }
+        }
+
+        /* a past proof does not verify against the latest root */
+        let proof = tree.generate_membership_proof_at(3, 1).unwrap();
+        assert!(!proof
+            .verify_with_index(&crh_parameters, &tree.root(), &2u8, 1)
+            .unwrap());
+
+        assert!(tree.root_at(9).is_err());
+        assert!(tree.generate_membership_proof_at(9, 0).is_err());
+
+        /* pruning drops the versions below the oldest kept one and the nodes only they read */
+        let num_replaced = |tree: &JubJubMerkleTree| -> usize {
+            let versions = tree.versions.as_ref().unwrap();
+            versions.replaced_nodes.values().map(|r| r.len()).sum()
+        };
+        let num_replaced_before = num_replaced(&tree);
+        tree.prune_versions(6).unwrap();
+        assert!(num_replaced(&tree) < num_replaced_before);
+        assert_eq!(tree.version(), Some(8));
+        assert!(tree.root_at(4).is_err());
+        assert!(tree.generate_membership_proof_at(3, 1).is_err());
+        for (version, leaves) in history.iter().filter(|(version, _)| *version >= 6) {
+            let root = tree.root_at(*version).unwrap();
+            for i in 0..8u64 {
+                let proof = tree.generate_membership_proof_at(*version, i).unwrap();
+                match leaves.get(&i) {
+                    Some(leaf) => assert!(proof
+                        .verify_with_index(&crh_parameters, &root, leaf, i)
+                        .unwrap()),
+                    None => assert!(proof
+                        .verify_absence_with_index::<u8>(&crh_parameters, &root, i)
+                        .unwrap()),
+                }
+            }
+        }
+
+        /* the latest version is always kept */
+        tree.prune_versions(100).unwrap();
+        assert_eq!(tree.version(), Some(8));
+        assert!(tree.root_at(8).unwrap() == tree.root());
+        assert!(tree.root_at(7).is_err());
+
+        /* clearing the tree drops the past versions, and the empty tree becomes version 0 */
+        tree.clear().unwrap();
+        let empty_tree = JubJubMerkleTree::blank::<u8>(crh_parameters.clone());
+        assert!(tree.root() == empty_tree.root());
+        assert_eq!(tree.version(), Some(0));
+        assert!(tree.root_at(0).unwrap() == empty_tree.root());
+        assert!(tree.root_at(8).is_err());
+        let proof = tree.generate_membership_proof_at(0, 1).unwrap();
+        assert!(proof
+            .verify_absence_with_index::<u8>(&crh_parameters, &tree.root(), 1)
+            .unwrap());
+    }
+
+    #[test]
+    fn domain_separation_test() {
+        use crate::building_blocks::crh::blake2s::Blake2sCRHforMerkleTree;
+
+        #[derive(Debug)]
+        struct TaggedParams;
+
+        impl MerkleSparseTreeConfig for TaggedParams {
+            const HEIGHT: u64 = 4;
+            type H = Blake2sCRHforMerkleTree;
+        }
+
+        #[derive(Debug)]
+        struct LegacyParams;
+
+        impl MerkleSparseTreeConfig for LegacyParams {
+            const HEIGHT: u64 = 4;
+            type H = Blake2sCRHforMerkleTree;
+            const LEGACY_ENCODING: bool = true;
+        }
+
+        let left = hash_leaf::<TaggedParams, u8>(&(), &1u8).unwrap();
+        let right = hash_leaf::<TaggedParams, u8>(&(), &2u8).unwrap();
+        let mut children = left.0.to_vec();
+        children.extend_from_slice(&right.0);
+
+        /* under the legacy encoding, a leaf holding two hashes collides with their parent */
+        assert!(
+            hash_leaf::<LegacyParams, Vec<u8>>(&(), &children).unwrap()
+                == hash_inner_node::<LegacyParams>(&(), &left, &right).unwrap()
+        );
+        assert!(
+            hash_leaf::<TaggedParams, Vec<u8>>(&(), &children).unwrap()
+                != hash_inner_node::<TaggedParams>(&(), &left, &right).unwrap()
+        );
+
+        /* the empty leaf hashes as the default leaf only under the legacy encoding */
+        assert!(
+            hash_leaf::<LegacyParams, u8>(&(), &0u8).unwrap()
+                == hash_empty::<LegacyParams, u8>(&(), 0u8).unwrap()
+        );
+        assert!(
+            hash_leaf::<TaggedParams, u8>(&(), &0u8).unwrap()
+                != hash_empty::<TaggedParams, u8>(&(), 0u8).unwrap()
+        );
+
+        let mut leaves: BTreeMap<u64, u8> = BTreeMap::new();
+        for i in 0..4u8 {
+            leaves.insert(i as u64, i + 1);
+        }
+        let tagged_tree = MerkleSparseTree::<TaggedParams>::new((), &leaves).unwrap();
+        let legacy_tree = MerkleSparseTree::<LegacyParams>::new((), &leaves).unwrap();
+        assert!(tagged_tree.root() != legacy_tree.root());
+
+        for (i, leaf) in leaves.iter() {
+            let proof = tagged_tree.generate_proof(*i, leaf).unwrap();
+            assert!(proof
+                .verify_with_index(&(), &tagged_tree.root(), leaf, *i)
+                .unwrap());
+            let proof = legacy_tree.generate_proof(*i, leaf).unwrap();
+            assert!(proof
+                .verify_with_index(&(), &legacy_tree.root(), leaf, *i)
+                .unwrap());
+        }
+
+        /* a location that has never been written does not open to the default leaf, but a batch
+        proof records that it is empty and still reads the default leaf */
+        let proof = tagged_tree.generate_membership_proof(6).unwrap();
+        assert!(!proof
+            .verify_with_index(&(), &tagged_tree.root(), &0u8, 6)
+            .unwrap());
+        assert!(proof
+            .verify_absence_with_index::<u8>(&(), &tagged_tree.root(), 6)
+            .unwrap());
+        let proof = legacy_tree.generate_membership_proof(6).unwrap();
+        assert!(proof
+            .verify_with_index(&(), &legacy_tree.root(), &0u8, 6)
+            .unwrap());
+
+        let proof = tagged_tree
+            .generate_batch_membership_proof(&[1u64, 6], 2)
+            .unwrap();
+        assert!(proof
+            .verify_with_indices(&(), &tagged_tree.root(), &[2u8, 0], &[1u64, 6])
+            .unwrap());
+        assert!(!proof
+            .verify_with_indices(&(), &tagged_tree.root(), &[2u8, 1], &[1u64, 6])
+            .unwrap());
+    }
 }
@@ -0,0 +1,820 @@
+use crate::Error;
+use ark_std::collections::BTreeMap;
+use ark_std::vec::Vec;
+
+#[cfg(feature = "std")]
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+#[cfg(feature = "std")]
+use ark_std::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// storage for the nodes of a Merkle sparse tree, keyed by the node index
+pub trait NodeStorage<V> {
+    /// obtain the node at this index, or `None` if it has never been written
+    fn get(&self, index: u64) -> Result<Option<V>, Error>;
+
+    /// write the node at this index
+    fn put(&mut self, index: u64, value: V) -> Result<(), Error>;
+
+    /// remove the node at this index
+    fn delete(&mut self, index: u64) -> Result<(), Error>;
+
+    /// write a batch of changes at once, where `None` removes the node
+    fn commit(&mut self, batch: Vec<(u64, Option<V>)>) -> Result<(), Error> {
+        for (index, value) in batch {
+            match value {
+                Some(value) => self.put(index, value)?,
+                None => self.delete(index)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// like `commit`, also replacing the metadata of the tree (e.g., its past versions) in the same
+    /// batch, which a storage that does not keep metadata ignores
+    fn commit_with_metadata(
+        &mut self,
+        batch: Vec<(u64, Option<V>)>,
+        _metadata: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.commit(batch)
+    }
+
+    /// whether the storage keeps the metadata given to `commit_with_metadata`
+    fn keeps_metadata(&self) -> bool {
+        false
+    }
+
+    /// the metadata of the latest `commit_with_metadata`, or `None` if there is none or the
+    /// storage does not keep metadata
+    fn metadata(&self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(None)
+    }
+
+    /// the indices of the stored nodes, in increasing order
+    fn indices(&self) -> Result<Vec<u64>, Error>;
+
+    /// the number of stored nodes
+    fn len(&self) -> usize;
+
+    /// check if no node is stored
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// remove all the nodes
+    fn clear(&mut self) -> Result<(), Error>;
+}
+
+/// in-memory storage
+impl<V: Clone> NodeStorage<V> for BTreeMap<u64, V> {
+    fn get(&self, index: u64) -> Result<Option<V>, Error> {
+        Ok(BTreeMap::get(self, &index).cloned())
+    }
+
+    fn put(&mut self, index: u64, value: V) -> Result<(), Error> {
+        self.insert(index, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, index: u64) -> Result<(), Error> {
+        self.remove(&index);
+        Ok(())
+    }
+
+    fn indices(&self) -> Result<Vec<u64>, Error> {
+        Ok(self.keys().cloned().collect())
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        BTreeMap::clear(self);
+        Ok(())
+    }
+}
+
+/// file-backed storage, which appends every change to a log and keeps the offset of the latest
+/// value of each node in an index file next to it, so that neither is held in memory
+///
+/// each batch of changes is appended as records of the node index and an optional value, where
+/// `None` marks a removal, and possibly a record of the metadata of the tree, followed by a commit
+/// record; reopening the file replays the batches that the index does not cover yet and drops a
+/// batch cut short by a crash, and the index is rebuilt from the log if a crash left it
+/// half-updated
+///
+/// the index file also points to the latest metadata, so that a tree reopened from the file
+/// restores its past versions
+#[cfg(feature = "std")]
+pub struct FileNodeStorage<V: CanonicalSerialize + CanonicalDeserialize> {
+    log: File,
+    index: File,
+    path: PathBuf,
+    index_path: PathBuf,
+    header: IndexHeader,
+    /// length of the log
+    end: u64,
+    /// whether the files are removed when the storage is dropped
+    temporary: bool,
+    value_phantom: PhantomData<V>,
+}
+
+/// The header of the index file.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+struct IndexHeader {
+    /// The number of slots, a power of two.
+    capacity: u64,
+    /// The number of stored nodes.
+    len: u64,
+    /// The number of slots holding a node or a removed node.
+    used: u64,
+    /// The length of the log covered by the index.
+    synced_end: u64,
+    /// Whether the index is being updated.
+    dirty: bool,
+    /// The offset of the latest metadata in the log, or `NO_METADATA`.
+    metadata_offset: u64,
+    /// The length of the latest metadata.
+    metadata_len: u64,
+}
+
+/// The length of the header of the index file.
+#[cfg(feature = "std")]
+const INDEX_HEADER_LEN: u64 = 56;
+
+/// The length of a slot of the index file, the node index followed by the offset of its value.
+#[cfg(feature = "std")]
+const INDEX_SLOT_LEN: u64 = 16;
+
+/// The smallest number of slots of the index file.
+#[cfg(feature = "std")]
+const MIN_INDEX_CAPACITY: u64 = 64;
+
+/// The offset marking a slot that has never held a node.
+#[cfg(feature = "std")]
+const EMPTY_SLOT: u64 = u64::MAX;
+
+/// The offset marking a slot whose node has been removed.
+#[cfg(feature = "std")]
+const REMOVED_SLOT: u64 = u64::MAX - 1;
+
+/// The offset marking that the log has no metadata.
+#[cfg(feature = "std")]
+const NO_METADATA: u64 = u64::MAX;
+
+/// The number of nodes in each batch of a compacted log.
+#[cfg(feature = "std")]
+const COMPACT_BATCH_SIZE: u64 = 1024;
+
+/// The kinds of records in the log.
+#[cfg(feature = "std")]
+const PUT_RECORD: u8 = 0;
+#[cfg(feature = "std")]
+const REMOVE_RECORD: u8 = 1;
+#[cfg(feature = "std")]
+const COMMIT_RECORD: u8 = 2;
+#[cfg(feature = "std")]
+const METADATA_RECORD: u8 = 3;
+
+/// A record of the log.
+#[cfg(feature = "std")]
+enum Record {
+    /// A node and the offset of its value.
+    Put(u64, u64),
+    /// A removed node.
+    Remove(u64),
+    /// The end of a batch, with the number of its changes.
+    Commit(u64),
+    /// The offset and the length of the metadata.
+    Metadata(u64, u64),
+}
+
+#[cfg(feature = "std")]
+static TEMPORARY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "std")]
+impl<V: CanonicalSerialize + CanonicalDeserialize> FileNodeStorage<V> {
+    /// open the log at this path, creating it if it does not exist, with its index at the same
+    /// path followed by `.index`
+    pub fn open<T: AsRef<Path>>(path: T) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut index_path = path.clone().into_os_string();
+        index_path.push(".index");
+        let index_path = PathBuf::from(index_path);
+
+        let log = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)?;
+        let index = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&index_path)?;
+
+        let mut storage = FileNodeStorage {
+            log,
+            index,
+            path,
+            index_path,
+            header: IndexHeader {
+                capacity: MIN_INDEX_CAPACITY,
+                len: 0,
+                used: 0,
+                synced_end: 0,
+                dirty: true,
+                metadata_offset: NO_METADATA,
+                metadata_len: 0,
+            },
+            end: 0,
+            temporary: false,
+            value_phantom: PhantomData,
+        };
+
+        // Replay the batches after the ones covered by the index, or rebuild an index that a
+        // crash left half-updated.
+        let log_len = storage.log.metadata()?.len();
+        let index_len = storage.index.metadata()?.len();
+        let header = if index_len >= INDEX_HEADER_LEN {
+            Some(Self::read_header(&storage.index)?)
+        } else {
+            None
+        };
+        match header {
+            Some(header)
+                if !header.dirty
+                    && header.synced_end <= log_len
+                    && header.capacity.is_power_of_two()
+                    && header.capacity.checked_mul(INDEX_SLOT_LEN)
+                        == Some(index_len - INDEX_HEADER_LEN)
+                    && (header.metadata_offset == NO_METADATA
+                        || header.metadata_offset.checked_add(header.metadata_len)
+                            <= Some(header.synced_end)) =>
+            {
+                storage.header = header;
+                storage.begin_index_update()?;
+                storage.replay(header.synced_end)?;
+                storage.end_index_update()?;
+            }
+            _ => storage.rebuild_index()?,
+        }
+
+        Ok(storage)
+    }
+
+    /// the path of the log
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// rewrite the log with only the latest value of each node and the latest metadata
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let mut compact_path = self.path.clone().into_os_string();
+        compact_path.push(".compact");
+        let compact_path = PathBuf::from(compact_path);
+
+        // Copy the latest values slot by slot, committing them in batches.
+        let compact_file = File::create(&compact_path)?;
+        let mut writer = BufWriter::new(&compact_file);
+        let mut slots = BufReader::new(self.index.try_clone()?);
+        slots.seek(SeekFrom::Start(INDEX_HEADER_LEN))?;
+        let mut buffer = Vec::new();
+        let mut batch_len = 0u64;
+        for _ in 0..self.header.capacity {
+            let (index, offset) = (read_u64(&mut slots)?, read_u64(&mut slots)?);
+            if offset >= REMOVED_SLOT {
+                continue;
+            }
+
+            let value = self.read_value(offset)?;
+            Self::write_record(&mut buffer, index, Some(&value))?;
+            batch_len += 1;
+            if batch_len == COMPACT_BATCH_SIZE {
+                Self::write_commit_record(&mut buffer, batch_len)?;
+                writer.write_all(&buffer)?;
+                buffer.clear();
+                batch_len = 0;
+            }
+        }
+        if batch_len > 0 {
+            Self::write_commit_record(&mut buffer, batch_len)?;
+            writer.write_all(&buffer)?;
+        }
+        if let Some(metadata) = self.metadata()? {
+            buffer.clear();
+            Self::write_metadata_record(&mut buffer, &metadata)?;
+            Self::write_commit_record(&mut buffer, 0)?;
+            writer.write_all(&buffer)?;
+        }
+
+        writer.flush()?;
+        drop(writer);
+        compact_file.sync_all()?;
+
+        // The index no longer matches the log once it is replaced, so it is rebuilt, also after
+        // a crash.
+        self.begin_index_update()?;
+        std::fs::rename(&compact_path, &self.path)?;
+        self.log = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.rebuild_index()
+    }
+
+    /// Reads the record starting at `start`, returning it and the end of the record.
+    fn read_record<R: Read>(
+        reader: &mut R,
+        start: u64,
+    ) -> Result<(Record, u64), SerializationError> {
+        let kind = u8::deserialize(&mut *reader)?;
+        let index = u64::deserialize(&mut *reader)?;
+        let value_offset = start + kind.serialized_size() as u64 + index.serialized_size() as u64;
+        match kind {
+            PUT_RECORD => {
+                let value = V::deserialize(&mut *reader)?;
+                Ok((
+                    Record::Put(index, value_offset),
+                    value_offset + value.serialized_size() as u64,
+                ))
+            }
+            REMOVE_RECORD => Ok((Record::Remove(index), value_offset)),
+            COMMIT_RECORD => Ok((Record::Commit(index), value_offset)),
+            METADATA_RECORD => {
+                // The metadata is skipped, and read from its offset when needed.
+                let len = std::io::copy(&mut (&mut *reader).take(index), &mut std::io::sink())?;
+                if len != index {
+                    return Err(SerializationError::InvalidData);
+                }
+                Ok((Record::Metadata(value_offset, len), value_offset + len))
+            }
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+
+    /// Appends a record to the buffer, returning the offset of the value within the buffer.
+    fn write_record(
+        buffer: &mut Vec<u8>,
+        index: u64,
+        value: Option<&V>,
+    ) -> Result<u64, SerializationError> {
+        match value {
+            Some(_) => PUT_RECORD.serialize(&mut *buffer)?,
+            None => REMOVE_RECORD.serialize(&mut *buffer)?,
+        }
+        index.serialize(&mut *buffer)?;
+        let offset = buffer.len() as u64;
+        if let Some(value) = value {
+            value.serialize(&mut *buffer)?;
+        }
+
+        Ok(offset)
+    }
+
+    /// Appends a record of the metadata to the buffer, returning the offset of the metadata within
+    /// the buffer.
+    fn write_metadata_record(
+        buffer: &mut Vec<u8>,
+        metadata: &[u8],
+    ) -> Result<u64, SerializationError> {
+        METADATA_RECORD.serialize(&mut *buffer)?;
+        (metadata.len() as u64).serialize(&mut *buffer)?;
+        let offset = buffer.len() as u64;
+        buffer.extend_from_slice(metadata);
+
+        Ok(offset)
+    }
+
+    /// Appends the commit record of a batch of `len` changes to the buffer.
+    fn write_commit_record(buffer: &mut Vec<u8>, len: u64) -> Result<(), SerializationError> {
+        COMMIT_RECORD.serialize(&mut *buffer)?;
+        len.serialize(&mut *buffer)
+    }
+
+    /// Appends a batch of changes, and the metadata if any, to the log and applies them to the
+    /// index.
+    fn append_batch(
+        &mut self,
+        batch: Vec<(u64, Option<V>)>,
+        metadata: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        // Write the whole batch and its commit record with a single append.
+        let mut buffer = Vec::new();
+        let mut changes = Vec::with_capacity(batch.len());
+        for (index, value) in batch.iter() {
+            let offset = Self::write_record(&mut buffer, *index, value.as_ref())?;
+            changes.push((*index, value.as_ref().map(|_| self.end + offset)));
+        }
+        let metadata_offset = match metadata {
+            Some(metadata) => Some(self.end + Self::write_metadata_record(&mut buffer, metadata)?),
+            None => None,
+        };
+        Self::write_commit_record(&mut buffer, batch.len() as u64)?;
+
+        let mut log = &self.log;
+        log.seek(SeekFrom::Start(self.end))?;
+        log.write_all(&buffer)?;
+        log.sync_data()?;
+        self.end += buffer.len() as u64;
+
+        // The batch is in the log, so a crash from here on only loses the index update.
+        self.begin_index_update()?;
+        self.apply_changes(changes.into_iter())?;
+        if let (Some(offset), Some(metadata)) = (metadata_offset, metadata) {
+            self.header.metadata_offset = offset;
+            self.header.metadata_len = metadata.len() as u64;
+        }
+        self.end_index_update()
+    }
+
+    /// Reads the value at this offset of the log.
+    fn read_value(&self, offset: u64) -> Result<V, Error> {
+        let mut log = &self.log;
+        log.seek(SeekFrom::Start(offset))?;
+        Ok(V::deserialize(BufReader::new(log))?)
+    }
+
+    /// Applies the committed batches of the log from `start` to the index, and drops a batch
+    /// without its commit record at the end of the log.
+    fn replay(&mut self, start: u64) -> Result<(), Error> {
+        let len = self.log.metadata()?.len();
+        let mut reader = BufReader::new(self.log.try_clone()?);
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut pending = Vec::new();
+        let mut pending_metadata = None;
+        let mut end = start;
+        let mut committed_end = start;
+        while end < len {
+            match Self::read_record(&mut reader, end) {
+                Ok((Record::Put(index, offset), record_end)) => {
+                    pending.push((index, Some(offset)));
+                    end = record_end;
+                }
+                Ok((Record::Remove(index), record_end)) => {
+                    pending.push((index, None));
+                    end = record_end;
+                }
+                Ok((Record::Metadata(offset, len), record_end)) => {
+                    pending_metadata = Some((offset, len));
+                    end = record_end;
+                }
+                Ok((Record::Commit(batch_len), record_end))
+                    if batch_len == pending.len() as u64 =>
+                {
+                    self.apply_changes(pending.drain(..))?;
+                    if let Some((offset, len)) = pending_metadata.take() {
+                        self.header.metadata_offset = offset;
+                        self.header.metadata_len = len;
+                    }
+                    end = record_end;
+                    committed_end = end;
+                }
+                _ => break,
+            }
+        }
+
+        if committed_end != len {
+            self.log.set_len(committed_end)?;
+            self.log.sync_data()?;
+        }
+        self.end = committed_end;
+
+        Ok(())
+    }
+
+    /// Empties the index and applies the whole log to it.
+    fn rebuild_index(&mut self) -> Result<(), Error> {
+        self.header = IndexHeader {
+            capacity: MIN_INDEX_CAPACITY,
+            len: 0,
+            used: 0,
+            synced_end: 0,
+            dirty: true,
+            metadata_offset: NO_METADATA,
+            metadata_len: 0,
+        };
+        self.index.set_len(0)?;
+        Self::init_index(&self.index, &self.header)?;
+        self.index.sync_data()?;
+
+        self.replay(0)?;
+        self.end_index_update()
+    }
+
+    /// Marks the index as being updated, so that a crash before the end of the update makes the
+    /// next opening rebuild it.
+    fn begin_index_update(&mut self) -> Result<(), Error> {
+        self.header.dirty = true;
+        Self::write_header(&self.index, &self.header)?;
+        self.index.sync_data()?;
+        Ok(())
+    }
+
+    /// Marks the index as covering the whole log.
+    fn end_index_update(&mut self) -> Result<(), Error> {
+        self.header.dirty = false;
+        self.header.synced_end = self.end;
+        Self::write_header(&self.index, &self.header)?;
+        self.index.sync_data()?;
+        Ok(())
+    }
+
+    /// Applies the changes, given by the offsets of the new values, to the index.
+    fn apply_changes(
+        &mut self,
+        changes: impl Iterator<Item = (u64, Option<u64>)>,
+    ) -> Result<(), Error> {
+        for (index, offset) in changes {
+            match offset {
+                Some(offset) => self.index_insert(index, offset)?,
+                None => self.index_remove(index)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the slot holding this node and the offset of its value, if any.
+    fn index_find(&self, index: u64) -> Result<Option<(u64, u64)>, Error> {
+        let mut slot = slot_of(index, self.header.capacity);
+        loop {
+            let (slot_index, slot_offset) = Self::read_slot(&self.index, slot)?;
+            if slot_offset == EMPTY_SLOT {
+                return Ok(None);
+            }
+            if slot_offset != REMOVED_SLOT && slot_index == index {
+                return Ok(Some((slot, slot_offset)));
+            }
+            slot = (slot + 1) & (self.header.capacity - 1);
+        }
+    }
+
+    /// Points the node to a new value, reusing the slot of a removed node if possible.
+    fn index_insert(&mut self, index: u64, offset: u64) -> Result<(), Error> {
+        if let Some((slot, _)) = self.index_find(index)? {
+            return Self::write_slot(&self.index, slot, index, offset);
+        }
+
+        // Keep at least half of the slots empty, so that the probes stay short.
+        if 2 * (self.header.used + 1) > self.header.capacity {
+            self.grow()?;
+        }
+
+        let mut slot = slot_of(index, self.header.capacity);
+        loop {
+            let (_, slot_offset) = Self::read_slot(&self.index, slot)?;
+            if slot_offset == EMPTY_SLOT || slot_offset == REMOVED_SLOT {
+                if slot_offset == EMPTY_SLOT {
+                    self.header.used += 1;
+                }
+                self.header.len += 1;
+                return Self::write_slot(&self.index, slot, index, offset);
+            }
+            slot = (slot + 1) & (self.header.capacity - 1);
+        }
+    }
+
+    /// Removes the node from the index.
+    fn index_remove(&mut self, index: u64) -> Result<(), Error> {
+        if let Some((slot, _)) = self.index_find(index)? {
+            self.header.len -= 1;
+            Self::write_slot(&self.index, slot, index, REMOVED_SLOT)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the stored nodes to a new index with room for them, dropping the removed nodes.
+    fn grow(&mut self) -> Result<(), Error> {
+        let mut new_path = self.index_path.clone().into_os_string();
+        new_path.push(".new");
+        let new_path = PathBuf::from(new_path);
+
+        let header = IndexHeader {
+            capacity: MIN_INDEX_CAPACITY.max(4 * (self.header.len + 1).next_power_of_two()),
+            used: self.header.len,
+            ..self.header
+        };
+        let new_index = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&new_path)?;
+        Self::init_index(&new_index, &header)?;
+
+        let mut slots = BufReader::new(self.index.try_clone()?);
+        slots.seek(SeekFrom::Start(INDEX_HEADER_LEN))?;
+        for _ in 0..self.header.capacity {
+            let (index, offset) = (read_u64(&mut slots)?, read_u64(&mut slots)?);
+            if offset >= REMOVED_SLOT {
+                continue;
+            }
+
+            let mut slot = slot_of(index, header.capacity);
+            while Self::read_slot(&new_index, slot)?.1 != EMPTY_SLOT {
+                slot = (slot + 1) & (header.capacity - 1);
+            }
+            Self::write_slot(&new_index, slot, index, offset)?;
+        }
+        new_index.sync_data()?;
+
+        // The new index is also marked as being updated, until the end of the update.
+        std::fs::rename(&new_path, &self.index_path)?;
+        self.index = new_index;
+        self.header = header;
+
+        Ok(())
+    }
+
+    /// Writes the header and empty slots to an empty index file.
+    fn init_index(file: &File, header: &IndexHeader) -> Result<(), Error> {
+        Self::write_header(file, header)?;
+
+        let mut writer = BufWriter::new(file);
+        let empty_slot = [0xffu8; INDEX_SLOT_LEN as usize];
+        for _ in 0..header.capacity {
+            writer.write_all(&empty_slot)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads the header of an index file.
+    fn read_header(mut file: &File) -> Result<IndexHeader, Error> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(file);
+        Ok(IndexHeader {
+            capacity: read_u64(&mut reader)?,
+            len: read_u64(&mut reader)?,
+            used: read_u64(&mut reader)?,
+            synced_end: read_u64(&mut reader)?,
+            dirty: read_u64(&mut reader)? != 0,
+            metadata_offset: read_u64(&mut reader)?,
+            metadata_len: read_u64(&mut reader)?,
+        })
+    }
+
+    /// Writes the header of an index file.
+    fn write_header(mut file: &File, header: &IndexHeader) -> Result<(), Error> {
+        let mut bytes = Vec::with_capacity(INDEX_HEADER_LEN as usize);
+        bytes.extend_from_slice(&header.capacity.to_le_bytes());
+        bytes.extend_from_slice(&header.len.to_le_bytes());
+        bytes.extend_from_slice(&header.used.to_le_bytes());
+        bytes.extend_from_slice(&header.synced_end.to_le_bytes());
+        bytes.extend_from_slice(&u64::from(header.dirty).to_le_bytes());
+        bytes.extend_from_slice(&header.metadata_offset.to_le_bytes());
+        bytes.extend_from_slice(&header.metadata_len.to_le_bytes());
+
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Reads the node index and the offset in a slot of an index file.
+    fn read_slot(mut file: &File, slot: u64) -> Result<(u64, u64), Error> {
+        file.seek(SeekFrom::Start(INDEX_HEADER_LEN + slot * INDEX_SLOT_LEN))?;
+        let mut bytes = [0u8; INDEX_SLOT_LEN as usize];
+        file.read_exact(&mut bytes)?;
+        let mut reader = &bytes[..];
+        Ok((read_u64(&mut reader)?, read_u64(&mut reader)?))
+    }
+
+    /// Writes the node index and the offset in a slot of an index file.
+    fn write_slot(mut file: &File, slot: u64, index: u64, offset: u64) -> Result<(), Error> {
+        let mut bytes = Vec::with_capacity(INDEX_SLOT_LEN as usize);
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes.extend_from_slice(&offset.to_le_bytes());
+
+        file.seek(SeekFrom::Start(INDEX_HEADER_LEN + slot * INDEX_SLOT_LEN))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// Reads a little-endian u64.
+#[cfg(feature = "std")]
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Returns the first slot to probe for this node, mixing the bits of the node index (as the
+/// finalizer of SplitMix64 does) since the nodes of a path have close indices.
+#[cfg(feature = "std")]
+fn slot_of(index: u64, capacity: u64) -> u64 {
+    let mut x = index;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    x & (capacity - 1)
+}
+
+/// a storage in a new file under the temporary directory, which is removed when dropped
+#[cfg(feature = "std")]
+impl<V: CanonicalSerialize + CanonicalDeserialize> Default for FileNodeStorage<V> {
+    fn default() -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "ivls-nodes-{}-{}.log",
+            std::process::id(),
+            TEMPORARY_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut storage = Self::open(&path).unwrap();
+        storage.clear().unwrap();
+        storage.temporary = true;
+        storage
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: CanonicalSerialize + CanonicalDeserialize> Drop for FileNodeStorage<V> {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+            let _ = std::fs::remove_file(&self.index_path);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: CanonicalSerialize + CanonicalDeserialize> NodeStorage<V> for FileNodeStorage<V> {
+    fn get(&self, index: u64) -> Result<Option<V>, Error> {
+        match self.index_find(index)? {
+            Some((_, offset)) => Ok(Some(self.read_value(offset)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, index: u64, value: V) -> Result<(), Error> {
+        self.commit(vec![(index, Some(value))])
+    }
+
+    fn delete(&mut self, index: u64) -> Result<(), Error> {
+        self.commit(vec![(index, None)])
+    }
+
+    fn commit(&mut self, batch: Vec<(u64, Option<V>)>) -> Result<(), Error> {
+        self.append_batch(batch, None)
+    }
+
+    fn commit_with_metadata(
+        &mut self,
+        batch: Vec<(u64, Option<V>)>,
+        metadata: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.append_batch(batch, Some(&metadata))
+    }
+
+    fn keeps_metadata(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self) -> Result<Option<Vec<u8>>, Error> {
+        if self.header.metadata_offset == NO_METADATA {
+            return Ok(None);
+        }
+
+        let mut log = &self.log;
+        log.seek(SeekFrom::Start(self.header.metadata_offset))?;
+        let mut metadata = vec![0u8; self.header.metadata_len as usize];
+        log.read_exact(&mut metadata)?;
+        Ok(Some(metadata))
+    }
+
+    fn indices(&self) -> Result<Vec<u64>, Error> {
+        let mut slots = BufReader::new(self.index.try_clone()?);
+        slots.seek(SeekFrom::Start(INDEX_HEADER_LEN))?;
+
+        let mut indices = Vec::with_capacity(self.header.len as usize);
+        for _ in 0..self.header.capacity {
+            let (index, offset) = (read_u64(&mut slots)?, read_u64(&mut slots)?);
+            if offset < REMOVED_SLOT {
+                indices.push(index);
+            }
+        }
+        indices.sort_unstable();
+
+        Ok(indices)
+    }
+
+    fn len(&self) -> usize {
+        self.header.len as usize
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        self.log.set_len(0)?;
+        self.log.sync_data()?;
+        self.end = 0;
+
+        self.rebuild_index()
+    }
+}
@@ -1,49 +1,95 @@
 use crate::building_blocks::crh::{CRHforMerkleTree, CRHforMerkleTreeGadget};
 use crate::{
+    building_blocks::mt::merkle_sparse_quad_tree::{
+        constraints::{
+            MerkleSparseQuadTreeMultiPathVar, MerkleSparseQuadTreePathVar,
+            MerkleSparseQuadTreeTwoPathsVar,
+        },
+        MerkleSparseQuadTree, MerkleSparseQuadTreeConfig, MerkleSparseQuadTreeMultiPath,
+        MerkleSparseQuadTreePath, MerkleSparseQuadTreeTwoPaths,
+    },
     building_blocks::mt::merkle_sparse_tree::{
-        constraints::{MerkleSparseTreePathVar, MerkleSparseTreeTwoPathsVar},
-        MerkleSparseTree, MerkleSparseTreeConfig, MerkleSparseTreePath, MerkleSparseTreeTwoPaths,
+        constraints::{
+            hash_encoded_leaf_gadget, hash_encoded_leaf_or_empty_gadget,
+            MerkleSparseTreeBatchPathVar, MerkleSparseTreeMultiPathVar, MerkleSparseTreePathVar,
+            MerkleSparseTreeTwoPathsVar,
+        },
+        gen_empty_hashes_from_leaf_hash, hash_encoded_empty, hash_encoded_leaf,
+        hash_encoded_leaf_or_empty,
+        storage::NodeStorage,
+        MemoryNodeStorage, MerkleSparseTree, MerkleSparseTreeBatchPath, MerkleSparseTreeConfig,
+        MerkleSparseTreeError, MerkleSparseTreeMultiPath, MerkleSparseTreePath,
+        MerkleSparseTreeTwoPaths,
+    },
+    gadgets::{
+        AllocVar, Boolean, CondSelectGadget, EqGadget, FpVar, ToBytesGadget, ToConstraintField,
+        ToConstraintFieldGadget, UInt64, UInt8,
     },
-    gadgets::{AllocVar, Boolean, CondSelectGadget, EqGadget, ToBytesGadget, UInt64},
     Borrow, Error, PhantomData, PrimeField, SynthesisError, ToBytes, Vec,
 };
 use ark_r1cs_std::alloc::AllocationMode;
 use ark_relations::r1cs::{ConstraintSystemRef, Namespace};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::collections::BTreeMap;
 use ark_std::rand::{CryptoRng, Rng};
 use ark_std::{
-    io::{Result as IoResult, Write},
+    io::{Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write},
     string::ToString,
+    vec,
 };
 
 /// implementation of sparse Merkle tree
 pub mod merkle_sparse_tree;
 
+/// implementation of 4-ary sparse Merkle tree
+pub mod merkle_sparse_quad_tree;
+
+/// implementation of sparse Merkle tree keyed by hashed addresses
+pub mod hashed_addr_tree;
+
+/// implementation of append-only incremental Merkle tree
+pub mod merkle_incremental_tree;
+
+/// implementation of Merkle mountain range
+pub mod merkle_mountain_range;
+
 /// trait for a Merkle tree primitive
 pub trait MT<F: PrimeField, Addr: ToBytes + Default + Eq + Clone + Ord, AddrVar: AllocVar<Addr, F>>
 {
     /// public parameters
     type PublicParameters: Clone;
     /// digest
-    type Digest: Default + Eq + Clone + ToBytes;
+    type Digest: Default + Eq + Clone + ToBytes + CanonicalSerialize + CanonicalDeserialize;
     /// tree
     type T;
     /// lookup proof
-    type LookupProof: Default + ToBytes + Clone;
+    type LookupProof: Default + ToBytes + Clone + CanonicalSerialize + CanonicalDeserialize;
     /// modifying proof
-    type ModifyProof: Default + ToBytes + Clone;
+    type ModifyProof: Default + ToBytes + Clone + CanonicalSerialize + CanonicalDeserialize;
+    /// absence proof
+    type AbsenceProof: Default + ToBytes + Clone + CanonicalSerialize + CanonicalDeserialize;
+    /// multi-opening of several addresses, sharing the common inner nodes
+    type MultiLookupProof: Default + ToBytes + Clone + CanonicalSerialize + CanonicalDeserialize;
 
     /// gadgets for digest
-    type DigestVar: AllocVar<Self::Digest, F> + Clone + ToBytesGadget<F> + CondSelectGadget<F>;
+    type DigestVar: AllocVar<Self::Digest, F>
+        + Clone
+        + ToBytesGadget<F>
+        + CondSelectGadget<F>
+        + EqGadget<F>;
     /// gadgets for lookup proof
     type LookupProofVar: AllocVar<Self::LookupProof, F>;
     /// gadgets for modifying proof
     type ModifyProofVar: AllocVar<Self::ModifyProof, F>;
+    /// gadgets for absence proof
+    type AbsenceProofVar: AllocVar<Self::AbsenceProof, F>;
+    /// gadgets for multi-opening
+    type MultiLookupProofVar: AllocVar<Self::MultiLookupProof, F>;
 
     /// sample the Merkle tree public parameters
     fn setup<R: Rng + CryptoRng>(rng: &mut R) -> Result<Self::PublicParameters, Error>;
     /// initialize an empty tree using the public parameters
-    fn new<L: Default + ToBytes>(pp: &Self::PublicParameters) -> Result<Self::T, Error>;
+    fn new<L: Default + MerkleLeaf<F>>(pp: &Self::PublicParameters) -> Result<Self::T, Error>;
     /// obtain the root hash
     fn root(pp: &Self::PublicParameters, tree: &Self::T) -> Result<Self::Digest, Error>;
     /// check if a tree if structurally valid
@@ -56,8 +102,20 @@ pub trait MT<F: PrimeField, Addr: ToBytes + Default + Eq + Clone + Ord, AddrVar:
         addr: &[Addr],
     ) -> Result<Self::LookupProof, Error>;
 
+    /// lookup data, with the proof padded with dummy entries up to `num` entries
+    fn lookup_padded(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[Addr],
+        num: usize,
+    ) -> Result<Self::LookupProof, Error> {
+        let mut lookup_proof = Self::lookup(pp, tree, addr)?;
+        Self::pad_lookup_proof(&mut lookup_proof, num)?;
+        Ok(lookup_proof)
+    }
+
     /// verify a lookup proof
-    fn verify_lookup<Data: ToBytes + Clone + Default>(
+    fn verify_lookup<Data: MerkleLeaf<F> + Clone + Default>(
         pp: &Self::PublicParameters,
         rh: &Self::Digest,
         addr: &[Addr],
@@ -65,8 +123,39 @@ pub trait MT<F: PrimeField, Addr: ToBytes + Default + Eq + Clone + Ord, AddrVar:
         lookup_proof: &Self::LookupProof,
     ) -> Result<bool, Error>;
 
+    /// lookup data at several addresses with a single multi-opening
+    fn multi_lookup(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[Addr],
+    ) -> Result<Self::MultiLookupProof, Error>;
+
+    /// verify a multi-opening
+    fn verify_multi_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[Addr],
+        data: &[Data],
+        multi_lookup_proof: &Self::MultiLookupProof,
+    ) -> Result<bool, Error>;
+
+    /// prove that the addresses have never been written
+    fn prove_absence(
+        pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[Addr],
+    ) -> Result<Self::AbsenceProof, Error>;
+
+    /// verify an absence proof, where `Data` is the leaf type the tree was created with
+    fn verify_absence<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[Addr],
+        absence_proof: &Self::AbsenceProof,
+    ) -> Result<bool, Error>;
+
     /// verify a modifying proof
-    fn verify_modify<Data: ToBytes + Clone + Default>(
+    fn verify_modify<Data: MerkleLeaf<F> + Clone + Default>(
         pp: &Self::PublicParameters,
         rh_old: &Self::Digest,
         rh_new: &Self::Digest,
@@ -75,55 +164,1200 @@ pub trait MT<F: PrimeField, Addr: ToBytes + Default + Eq + Clone + Ord, AddrVar:
         modify_proof: &Self::ModifyProof,
     ) -> Result<bool, Error>;
 
-    /// create a new tree with an existing data map
-    fn _new_with_map<Data: ToBytes + Clone + Default>(
-        pp: &Self::PublicParameters,
-        map: &BTreeMap<Addr, Data>,
-    ) -> Result<Self::T, Error>;
+    /// create a new tree with an existing data map
+    fn _new_with_map<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        map: &BTreeMap<Addr, Data>,
+    ) -> Result<Self::T, Error>;
+
+    /// modify the tree and apply the change
+    fn _modify_and_apply<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        addr: &[Addr],
+        data: &[Data],
+    ) -> Result<(Self::Digest, Self::ModifyProof), Error>;
+
+    /// clear the data in the tree
+    fn clear(pp: &Self::PublicParameters, tree: &mut Self::T) -> Result<(), Error>;
+
+    /// start keeping the past versions of the tree, labelling the current tree as `version`,
+    /// which trees without past versions ignore
+    fn enable_versioning(
+        _pp: &Self::PublicParameters,
+        _tree: &mut Self::T,
+        _version: u64,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// label the current tree as `version`, above the latest version, which trees without past
+    /// versions ignore
+    fn set_version(
+        _pp: &Self::PublicParameters,
+        _tree: &mut Self::T,
+        _version: u64,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// drop the past versions below `oldest`, which trees without past versions ignore
+    fn prune_versions(
+        _pp: &Self::PublicParameters,
+        _tree: &mut Self::T,
+        _oldest: u64,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// lookup data in a past version, against the root of that version
+    fn lookup_at(
+        _pp: &Self::PublicParameters,
+        _tree: &Self::T,
+        _version: u64,
+        _addr: &[Addr],
+    ) -> Result<Self::LookupProof, Error> {
+        Err(SparseMTError::UnsupportedVersioning.into())
+    }
+
+    /// write the tree, which can be restored with the same public parameters
+    fn serialize_tree<W: Write>(tree: &Self::T, writer: W) -> Result<(), Error>;
+
+    /// restore a tree written by `serialize_tree`, where `L` is the type of the leaves the tree
+    /// was initialized with
+    fn deserialize_tree<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &Self::PublicParameters,
+        reader: R,
+    ) -> Result<Self::T, Error>;
+
+    /// check a lookup proof
+    fn verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        lookup_proof_g: &Self::LookupProofVar,
+    ) -> Result<(), SynthesisError>;
+
+    /// conditionally check a lookup proof
+    fn conditionally_verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        lookup_proof_g: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError>;
+
+    /// conditionally check a lookup proof, skipping the entries whose active flag is false
+    fn conditionally_verify_partial_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        lookup_proof_g: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError>;
+
+    /// check a multi-opening, where the addresses are fixed in the circuit
+    fn verify_multi_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr: &[Addr],
+        data_g: &[DataVar],
+        multi_lookup_proof_g: &Self::MultiLookupProofVar,
+    ) -> Result<(), SynthesisError>;
+
+    /// check an absence proof
+    fn verify_absence_gadget<Data: MerkleLeaf<F> + Clone + Default>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        absence_proof_g: &Self::AbsenceProofVar,
+    ) -> Result<(), SynthesisError>;
+
+    /// check a modifying proof
+    fn conditionally_verify_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        modify_proof_g: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError>;
+
+    /// conditionally check a modifying proof, skipping the entries whose active flag is false
+    fn conditionally_verify_partial_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[AddrVar],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        modify_proof_g: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError>;
+
+    /// output a dummy lookup proof
+    fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error>;
+    /// output a dummy modifying proof
+    fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error>;
+    /// pad a lookup proof with dummy entries up to `num` entries
+    fn pad_lookup_proof(proof: &mut Self::LookupProof, num: usize) -> Result<(), Error>;
+    /// pad a modifying proof with dummy entries up to `num` entries
+    fn pad_modify_proof(proof: &mut Self::ModifyProof, num: usize) -> Result<(), Error>;
+}
+
+/// encoding of a leaf, in which the trees hash it
+#[derive(Clone, Debug)]
+pub enum LeafEncoding<F: PrimeField> {
+    /// the bytes of the leaf
+    Bytes(Vec<u8>),
+    /// the field elements of the leaf, which `SparseMT` hashes without the conversion to bytes,
+    /// and the other trees hash as their bytes
+    FieldElements(Vec<F>),
+}
+
+impl<F: PrimeField> ToBytes for LeafEncoding<F> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        match self {
+            LeafEncoding::Bytes(bytes) => bytes.write(&mut writer),
+            LeafEncoding::FieldElements(elements) => elements.write(&mut writer),
+        }
+    }
+}
+
+/// leaf of a Merkle tree
+///
+/// every `ToBytes` type is a leaf encoded as its bytes, and `FieldLeaf` opts a leaf into the
+/// encoding as field elements
+pub trait MerkleLeaf<F: PrimeField> {
+    /// the encoding of the leaf
+    fn encode_leaf(&self) -> Result<LeafEncoding<F>, Error>;
+}
+
+impl<F: PrimeField, T: ToBytes> MerkleLeaf<F> for T {
+    fn encode_leaf(&self) -> Result<LeafEncoding<F>, Error> {
+        let mut writer = Cursor::new(Vec::<u8>::new());
+        self.write(&mut writer)?;
+        Ok(LeafEncoding::Bytes(writer.into_inner()))
+    }
+}
+
+/// a leaf encoded as its field elements
+#[derive(Clone, Debug, Default)]
+pub struct FieldLeaf<T>(pub T);
+
+impl<F: PrimeField, T: ToConstraintField<F>> MerkleLeaf<F> for FieldLeaf<T> {
+    fn encode_leaf(&self) -> Result<LeafEncoding<F>, Error> {
+        let elements = self
+            .0
+            .to_field_elements()
+            .ok_or(SparseMTError::IncorrectInput)?;
+        Ok(LeafEncoding::FieldElements(elements))
+    }
+}
+
+/// gadgets for the encoding of a leaf
+#[derive(Clone, Debug)]
+pub enum LeafEncodingVar<F: PrimeField> {
+    /// the bytes of the leaf
+    Bytes(Vec<UInt8<F>>),
+    /// the field elements of the leaf
+    FieldElements(Vec<FpVar<F>>),
+}
+
+impl<F: PrimeField> ToBytesGadget<F> for LeafEncodingVar<F> {
+    fn to_bytes(&self) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        match self {
+            LeafEncodingVar::Bytes(bytes) => Ok(bytes.clone()),
+            LeafEncodingVar::FieldElements(elements) => {
+                let mut bytes = Vec::new();
+                for element in elements.iter() {
+                    bytes.extend_from_slice(&element.to_bytes()?);
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// gadgets for a leaf of a Merkle tree, which must be encoded as the leaf is natively
+pub trait MerkleLeafGadget<F: PrimeField> {
+    /// the encoding of the leaf
+    fn encode_leaf(&self) -> Result<LeafEncodingVar<F>, SynthesisError>;
+}
+
+impl<F: PrimeField, T: ToBytesGadget<F>> MerkleLeafGadget<F> for T {
+    fn encode_leaf(&self) -> Result<LeafEncodingVar<F>, SynthesisError> {
+        Ok(LeafEncodingVar::Bytes(self.to_bytes()?))
+    }
+}
+
+/// gadgets for a leaf encoded as its field elements
+pub struct FieldLeafVar<T>(pub T);
+
+impl<F: PrimeField, T: ToConstraintFieldGadget<F>> MerkleLeafGadget<F> for FieldLeafVar<T> {
+    fn encode_leaf(&self) -> Result<LeafEncodingVar<F>, SynthesisError> {
+        Ok(LeafEncodingVar::FieldElements(
+            self.0.to_constraint_field()?,
+        ))
+    }
+}
+
+/// A leaf in its encoding, or the default `L` if there is none, for the trees that hash the
+/// leaves as bytes.
+pub(crate) struct EncodedLeaf<F: PrimeField, L> {
+    encoding: Option<LeafEncoding<F>>,
+    leaf_phantom: PhantomData<L>,
+}
+
+impl<F: PrimeField, L> Clone for EncodedLeaf<F, L> {
+    fn clone(&self) -> Self {
+        EncodedLeaf {
+            encoding: self.encoding.clone(),
+            leaf_phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: PrimeField, L> Default for EncodedLeaf<F, L> {
+    fn default() -> Self {
+        EncodedLeaf {
+            encoding: None,
+            leaf_phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: PrimeField, L: Default + MerkleLeaf<F>> ToBytes for EncodedLeaf<F, L> {
+    fn write<W: Write>(&self, writer: W) -> IoResult<()> {
+        match &self.encoding {
+            Some(encoding) => encoding.write(writer),
+            None => L::default()
+                .encode_leaf()
+                .map_err(|_| IoError::from(ErrorKind::InvalidData))?
+                .write(writer),
+        }
+    }
+}
+
+/// Returns the leaves in their encodings.
+pub(crate) fn encode_leaves<F: PrimeField, L: MerkleLeaf<F>>(
+    leaves: &[L],
+) -> Result<Vec<EncodedLeaf<F, L>>, Error> {
+    let mut encoded_leaves = Vec::with_capacity(leaves.len());
+    for leaf in leaves.iter() {
+        encoded_leaves.push(encode_leaf(leaf)?);
+    }
+    Ok(encoded_leaves)
+}
+
+/// Returns the leaf in its encoding.
+pub(crate) fn encode_leaf<F: PrimeField, L: MerkleLeaf<F>>(
+    leaf: &L,
+) -> Result<EncodedLeaf<F, L>, Error> {
+    Ok(EncodedLeaf {
+        encoding: Some(leaf.encode_leaf()?),
+        leaf_phantom: PhantomData,
+    })
+}
+
+/// Returns the leaf gadgets in their encodings.
+pub(crate) fn encode_leaves_gadget<F: PrimeField, L: MerkleLeafGadget<F>>(
+    leaves: &[L],
+) -> Result<Vec<LeafEncodingVar<F>>, SynthesisError> {
+    leaves.iter().map(|leaf| leaf.encode_leaf()).collect()
+}
+
+/// Sparse Merkle tree, with the nodes kept in the storage `S`
+pub struct SparseMT<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    S = MemoryNodeStorage<P>,
+> {
+    f_phantom: PhantomData<F>,
+    tree_config_phantom: PhantomData<P>,
+    crh_gadget_phantom: PhantomData<CRHVar>,
+    storage_phantom: PhantomData<S>,
+}
+
+/// A single Merkle tree modifying proof
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct ModifyProofType<P: MerkleSparseTreeConfig> {
+    /// the new digest
+    new_digest: <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output,
+    /// the modifying proof
+    modify_proof: MerkleSparseTreeTwoPaths<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for ModifyProofType<P> {
+    fn clone(&self) -> Self {
+        ModifyProofType {
+            new_digest: self.new_digest.clone(),
+            modify_proof: self.modify_proof.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for ModifyProofType<P> {
+    fn default() -> Self {
+        ModifyProofType {
+            new_digest: <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output::default(),
+            modify_proof: MerkleSparseTreeTwoPaths::<P>::default(),
+        }
+    }
+}
+
+struct ModifyProofTypeVar<
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+    F: PrimeField,
+> {
+    new_digest_g: CRHVar::OutputVar,
+    modify_proof_g: MerkleSparseTreeTwoPathsVar<P, CRHVar, F>,
+}
+
+impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AllocVar<ModifyProofType<P>, F> for ModifyProofTypeVar<P, CRHVar, F>
+{
+    fn new_variable<T: Borrow<ModifyProofType<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let val = f()?;
+        let modify_proof_type = val.borrow().clone();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let new_digest_g = CRHVar::OutputVar::new_variable(
+            ark_relations::ns!(cs, "modify_proof_type_gadget_new_digest"),
+            || Ok(&modify_proof_type.new_digest),
+            mode,
+        )?;
+        let modify_proof_g = MerkleSparseTreeTwoPathsVar::new_variable(
+            ark_relations::ns!(cs, "modify_proof_type_gadget_modify_proof"),
+            || Ok(&modify_proof_type.modify_proof),
+            mode,
+        )?;
+
+        Ok(ModifyProofTypeVar {
+            new_digest_g,
+            modify_proof_g,
+        })
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for ModifyProofType<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.new_digest.write(&mut writer)?;
+        self.modify_proof.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+/// lookup proof gadgets
+pub struct LookupProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<MerkleSparseTreePathVar<P, CRHVar, F>>);
+
+/// modifying proof gadgets
+pub struct ModifyProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+>(Vec<ModifyProofTypeVar<P, CRHVar, F>>);
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<MerkleSparseTreePath<P>>, F> for LookupProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<MerkleSparseTreePath<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(MerkleSparseTreePathVar::<P, CRHVar, F>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(LookupProofVar { 0: vec })
+    }
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<ModifyProofType<P>>, F> for ModifyProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<Vec<ModifyProofType<P>>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut vec = Vec::new();
+        for value in f()?.borrow().iter() {
+            vec.push(ModifyProofTypeVar::<P, CRHVar, F>::new_variable(
+                ark_relations::ns!(cs, "value"),
+                || Ok(value),
+                mode,
+            )?);
+        }
+        Ok(ModifyProofVar { 0: vec })
+    }
+}
+
+/// A Merkle tree multi-lookup proof, which also records the locations that have never been written
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MultiLookupProofType<P: MerkleSparseTreeConfig> {
+    /// whether each location has never been written, in which case its data must be the default one
+    empty: Vec<bool>,
+    /// the multi-opening
+    multi_path: MerkleSparseTreeMultiPath<P>,
+}
+
+impl<P: MerkleSparseTreeConfig> Clone for MultiLookupProofType<P> {
+    fn clone(&self) -> Self {
+        MultiLookupProofType {
+            empty: self.empty.clone(),
+            multi_path: self.multi_path.clone(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> Default for MultiLookupProofType<P> {
+    fn default() -> Self {
+        MultiLookupProofType {
+            empty: Vec::new(),
+            multi_path: MerkleSparseTreeMultiPath::<P>::default(),
+        }
+    }
+}
+
+impl<P: MerkleSparseTreeConfig> ToBytes for MultiLookupProofType<P> {
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        for empty in &self.empty {
+            empty.write(&mut writer)?;
+        }
+        self.multi_path.write(&mut writer)?;
+        Ok(())
+    }
+}
+
+/// multi-lookup proof gadgets
+pub struct MultiLookupProofVar<
+    F: PrimeField,
+    P: MerkleSparseTreeConfig,
+    CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+> {
+    empty_g: Vec<Boolean<F>>,
+    multi_path_g: MerkleSparseTreeMultiPathVar<P, CRHVar, F>,
+}
+
+impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<MultiLookupProofType<P>, F> for MultiLookupProofVar<F, P, CRHVar>
+{
+    fn new_variable<T: Borrow<MultiLookupProofType<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let val = f()?;
+        let multi_lookup_proof = val.borrow();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let mut empty_g = Vec::new();
+        for value in multi_lookup_proof.empty.iter() {
+            empty_g.push(Boolean::new_variable(
+                ark_relations::ns!(cs, "multi_lookup_proof_empty"),
+                || Ok(*value),
+                mode,
+            )?);
+        }
+        let multi_path_g = MerkleSparseTreeMultiPathVar::new_variable(
+            ark_relations::ns!(cs, "multi_lookup_proof_multi_path"),
+            || Ok(&multi_lookup_proof.multi_path),
+            mode,
+        )?;
+
+        Ok(MultiLookupProofVar {
+            empty_g,
+            multi_path_g,
+        })
+    }
+}
+
+impl<
+        F: PrimeField,
+        P: MerkleSparseTreeConfig,
+        CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+        S: NodeStorage<<P::H as CRHforMerkleTree>::Output>,
+    > SparseMT<F, P, CRHVar, S>
+{
+    /// like `new`, but over the nodes already in a configured storage (e.g., a reopened
+    /// `FileNodeStorage`) instead of `S::default()`
+    pub fn new_with_storage<L: Default + MerkleLeaf<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+    ) -> Result<MerkleSparseTree<P, S>, Error> {
+        MerkleSparseTree::from_storage_with_empty_hashes(
+            pp.clone(),
+            storage,
+            Self::empty_hashes::<L>(pp)?,
+        )
+    }
+
+    /// like `deserialize_tree`, but writing the nodes into a configured storage
+    pub fn deserialize_tree_with_storage<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+        storage: S,
+        reader: R,
+    ) -> Result<MerkleSparseTree<P, S>, Error> {
+        MerkleSparseTree::deserialize_nodes_with_empty_hashes(
+            pp.clone(),
+            storage,
+            Self::empty_hashes::<L>(pp)?,
+            reader,
+        )
+    }
+
+    /// Returns the hash of the empty leaf, which is the default `L` in its encoding.
+    fn empty_leaf_hash<L: Default + MerkleLeaf<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+    ) -> Result<<P::H as CRHforMerkleTree>::Output, Error> {
+        hash_encoded_empty::<P, F>(pp, &L::default().encode_leaf()?)
+    }
+
+    /// Returns the empty hash of each level, for a tree whose empty leaf is the default `L`.
+    fn empty_hashes<L: Default + MerkleLeaf<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+    ) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+        gen_empty_hashes_from_leaf_hash::<P>(pp, Self::empty_leaf_hash::<L>(pp)?)
+    }
+
+    /// Returns the hash of each leaf, in its encoding.
+    fn leaf_hashes<L: MerkleLeaf<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+        leaves: &[L],
+    ) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+        leaves
+            .iter()
+            .map(|leaf| hash_encoded_leaf::<P, F>(pp, &leaf.encode_leaf()?))
+            .collect()
+    }
+
+    /// Returns the hash of each leaf gadget, in its encoding.
+    fn leaf_hashes_gadget<L: MerkleLeafGadget<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+        leaves: &[L],
+    ) -> Result<Vec<CRHVar::OutputVar>, SynthesisError> {
+        leaves
+            .iter()
+            .map(|leaf| hash_encoded_leaf_gadget::<P, CRHVar, F>(pp, &leaf.encode_leaf()?))
+            .collect()
+    }
+
+    /// Returns the hash of each leaf read by a lookup, in its encoding, where the leaf of a
+    /// location that has never been written is hashed as the empty leaf.
+    fn read_leaf_hashes<L: MerkleLeaf<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+        leaves: &[L],
+        empty: &[bool],
+    ) -> Result<Vec<<P::H as CRHforMerkleTree>::Output>, Error> {
+        leaves
+            .iter()
+            .zip(empty.iter())
+            .map(|(leaf, empty)| {
+                hash_encoded_leaf_or_empty::<P, F>(pp, &leaf.encode_leaf()?, *empty)
+            })
+            .collect()
+    }
+
+    /// Returns the hash of each leaf gadget read by a lookup, as `read_leaf_hashes` does.
+    fn read_leaf_hashes_gadget<L: MerkleLeafGadget<F>>(
+        pp: &<P::H as CRHforMerkleTree>::Parameters,
+        leaves: &[L],
+        empty: &[Boolean<F>],
+    ) -> Result<Vec<CRHVar::OutputVar>, SynthesisError> {
+        leaves
+            .iter()
+            .zip(empty.iter())
+            .map(|(leaf, empty)| {
+                hash_encoded_leaf_or_empty_gadget::<P, CRHVar, F>(pp, &leaf.encode_leaf()?, empty)
+            })
+            .collect()
+    }
+}
+
+impl<
+        F: PrimeField,
+        P: MerkleSparseTreeConfig,
+        CRHVar: CRHforMerkleTreeGadget<P::H, F>,
+        S: NodeStorage<<P::H as CRHforMerkleTree>::Output> + Default,
+    > MT<F, u64, UInt64<F>> for SparseMT<F, P, CRHVar, S>
+{
+    type PublicParameters = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Parameters;
+    type Digest = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output;
+    type T = MerkleSparseTree<P, S>;
+    type LookupProof = MerkleSparseTreeBatchPath<P>;
+    type ModifyProof = Vec<ModifyProofType<P>>;
+    type AbsenceProof = Vec<MerkleSparseTreePath<P>>;
+    type MultiLookupProof = MultiLookupProofType<P>;
+
+    type DigestVar = CRHVar::OutputVar;
+    type LookupProofVar = MerkleSparseTreeBatchPathVar<P, CRHVar, F>;
+    type ModifyProofVar = ModifyProofVar<F, P, CRHVar>;
+    type AbsenceProofVar = LookupProofVar<F, P, CRHVar>;
+    type MultiLookupProofVar = MultiLookupProofVar<F, P, CRHVar>;
+
+    fn setup<R: Rng + CryptoRng>(rng: &mut R) -> Result<Self::PublicParameters, Error> {
+        <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::setup(rng)
+    }
+
+    fn new<L: Default + MerkleLeaf<F>>(pp: &Self::PublicParameters) -> Result<Self::T, Error> {
+        Self::new_with_storage::<L>(pp, S::default())
+    }
+
+    fn root(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<Self::Digest, Error> {
+        Ok(tree.root())
+    }
+
+    fn validate(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<bool, Error> {
+        tree.validate()
+    }
+
+    fn lookup(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::LookupProof, Error> {
+        tree.generate_batch_membership_proof(addr, addr.len())
+    }
+
+    fn lookup_padded(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+        num: usize,
+    ) -> Result<Self::LookupProof, Error> {
+        // The frontier is sized for the padded proof, as in the dummy proof of `num` entries.
+        let mut lookup_proof = tree.generate_batch_membership_proof(addr, num)?;
+        lookup_proof.pad(num)?;
+        Ok(lookup_proof)
+    }
+
+    fn verify_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        lookup_proof: &Self::LookupProof,
+    ) -> Result<bool, Error> {
+        let addr_len = addr.len();
+        let data_len = data.len();
+        let lookup_proof_len = lookup_proof.positions.len();
+
+        if addr_len != data_len || data_len != lookup_proof_len {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        lookup_proof.verify_leaf_hashes_with_indices(
+            pp,
+            rh,
+            &Self::read_leaf_hashes(pp, data, &lookup_proof.empty)?,
+            addr,
+        )
+    }
+
+    fn multi_lookup(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::MultiLookupProof, Error> {
+        let multi_path = tree.generate_multi_membership_proof(addr)?;
+
+        let mut empty = Vec::with_capacity(addr.len());
+        for addr_item in addr {
+            empty.push(tree.is_empty_leaf_with_version(*addr_item, None)?);
+        }
+
+        Ok(MultiLookupProofType { empty, multi_path })
+    }
+
+    fn verify_multi_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        multi_lookup_proof: &Self::MultiLookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() || data.len() != multi_lookup_proof.empty.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        multi_lookup_proof
+            .multi_path
+            .verify_leaf_hashes_with_indices(
+                pp,
+                rh,
+                &Self::read_leaf_hashes(pp, data, &multi_lookup_proof.empty)?,
+                addr,
+            )
+    }
+
+    fn prove_absence(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::AbsenceProof, Error> {
+        let mut absence_proof: Self::AbsenceProof = Vec::with_capacity(addr.len());
+
+        for i in addr {
+            absence_proof.push(tree.generate_absence_proof(*i)?);
+        }
+
+        Ok(absence_proof)
+    }
+
+    fn verify_absence<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        absence_proof: &Self::AbsenceProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != absence_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        let empty_leaf_hash = Self::empty_leaf_hash::<Data>(pp)?;
+        for (addr_item, absence_proof_item) in addr.iter().zip(absence_proof.iter()) {
+            if !absence_proof_item.verify_leaf_hash_with_index(
+                pp,
+                rh,
+                empty_leaf_hash.clone(),
+                *addr_item,
+            )? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn verify_modify<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh_old: &Self::Digest,
+        rh_new: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        modify_proof: &Self::ModifyProof,
+    ) -> Result<bool, Error> {
+        let addr_len = addr.len();
+        let data_len = data.len();
+        let modify_proof_len = modify_proof.len();
+
+        if addr_len != data_len || data_len != modify_proof_len {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        let leaf_hashes = Self::leaf_hashes(pp, data)?;
+
+        let mut addr_iter = addr.iter();
+        let mut leaf_hash_iter = leaf_hashes.into_iter();
+        let mut modify_proof_iter = modify_proof.iter();
+
+        let mut last_hash: Self::Digest = rh_old.clone();
+        let mut cur_hash: Self::Digest;
+
+        loop {
+            let addr_iter_next_item = addr_iter.next();
+            match addr_iter_next_item {
+                Some(addr_item) => {
+                    let leaf_hash_item = leaf_hash_iter.next().unwrap();
+                    let modify_proof_item = modify_proof_iter.next().unwrap();
+
+                    cur_hash = modify_proof_item.new_digest.clone();
+
+                    if !modify_proof_item.modify_proof.verify_leaf_hash(
+                        pp,
+                        &last_hash,
+                        &cur_hash,
+                        leaf_hash_item,
+                        *addr_item,
+                    )? {
+                        return Ok(false);
+                    }
+
+                    last_hash = cur_hash;
+                }
+                _ => break,
+            }
+        }
+
+        if last_hash != *rh_new {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn _new_with_map<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        map: &BTreeMap<u64, Data>,
+    ) -> Result<Self::T, Error> {
+        let mut tree = Self::new::<Data>(pp)?;
+        if map.is_empty() {
+            return Err(MerkleSparseTreeError::IncorrectTreeStructure.into());
+        }
+
+        let mut leaf_hashes = BTreeMap::new();
+        for (index, leaf) in map.iter() {
+            leaf_hashes.insert(*index, hash_encoded_leaf::<P, F>(pp, &leaf.encode_leaf()?)?);
+        }
+        tree.batch_update_leaf_hashes(leaf_hashes)?;
+
+        Ok(tree)
+    }
+
+    fn _modify_and_apply<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        addr: &[u64],
+        data: &[Data],
+    ) -> Result<(Self::Digest, Self::ModifyProof), Error> {
+        let addr_len = addr.len();
+        let data_len = data.len();
+
+        if addr_len != data_len {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        let modify_proof: Self::ModifyProof = tree
+            .batch_update_leaf_hashes_and_prove(addr, Self::leaf_hashes(pp, data)?)?
+            .into_iter()
+            .map(|(new_digest, modify_proof)| ModifyProofType {
+                new_digest,
+                modify_proof,
+            })
+            .collect();
+
+        let last_rh = tree.root();
+
+        Ok((last_rh, modify_proof))
+    }
+
+    fn clear(_pp: &Self::PublicParameters, tree: &mut Self::T) -> Result<(), Error> {
+        tree.clear()
+    }
+
+    fn enable_versioning(
+        _pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        version: u64,
+    ) -> Result<(), Error> {
+        tree.enable_versioning(version)
+    }
+
+    fn set_version(
+        _pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        version: u64,
+    ) -> Result<(), Error> {
+        tree.set_version(version)
+    }
+
+    fn prune_versions(
+        _pp: &Self::PublicParameters,
+        tree: &mut Self::T,
+        oldest: u64,
+    ) -> Result<(), Error> {
+        tree.prune_versions(oldest)
+    }
+
+    fn lookup_at(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        version: u64,
+        addr: &[u64],
+    ) -> Result<Self::LookupProof, Error> {
+        tree.generate_batch_membership_proof_at(version, addr, addr.len())
+    }
+
+    fn serialize_tree<W: Write>(tree: &Self::T, writer: W) -> Result<(), Error> {
+        tree.serialize_nodes(writer)?;
+
+        Ok(())
+    }
+
+    fn deserialize_tree<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &Self::PublicParameters,
+        reader: R,
+    ) -> Result<Self::T, Error> {
+        let tree = Self::deserialize_tree_with_storage::<L, R>(pp, S::default(), reader)?;
+
+        Ok(tree)
+    }
+
+    fn verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            lookup_proof_g_wrapped,
+            &Boolean::constant(true),
+        )
+    }
+
+    fn conditionally_verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            lookup_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        active_g: &[Boolean<F>],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        // The batched opening rejects inputs whose lengths do not match.
+        lookup_proof_g_wrapped.conditionally_check_leaf_hashes_with_indices(
+            pp_g,
+            rh_g,
+            &Self::read_leaf_hashes_gadget(pp_g, data_g, &lookup_proof_g_wrapped.empty)?,
+            addr_g,
+            active_g,
+            should_enforce,
+        )
+    }
+
+    fn verify_multi_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr: &[u64],
+        data_g: &[DataVar],
+        multi_lookup_proof_g: &Self::MultiLookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        if addr.len() != data_g.len() || data_g.len() != multi_lookup_proof_g.empty_g.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        multi_lookup_proof_g
+            .multi_path_g
+            .conditionally_check_leaf_hashes_with_indices(
+                pp_g,
+                rh_g,
+                &Self::read_leaf_hashes_gadget(pp_g, data_g, &multi_lookup_proof_g.empty_g)?,
+                addr,
+                &Boolean::constant(true),
+            )
+    }
+
+    fn verify_absence_gadget<Data: MerkleLeaf<F> + Clone + Default>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        absence_proof_g_wrapped: &Self::AbsenceProofVar,
+    ) -> Result<(), SynthesisError> {
+        let absence_proof_g = &absence_proof_g_wrapped.0;
+
+        if addr_g.len() != absence_proof_g.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
 
-    /// modify the tree and apply the change
-    fn _modify_and_apply<Data: ToBytes + Clone + Default>(
-        pp: &Self::PublicParameters,
-        tree: &mut Self::T,
-        addr: &[Addr],
-        data: &[Data],
-    ) -> Result<(Self::Digest, Self::ModifyProof), Error>;
+        // The empty leaf hash is fixed by the parameters, so it is a constant.
+        let empty_leaf_hash =
+            Self::empty_leaf_hash::<Data>(pp_g).map_err(|_| SynthesisError::Unsatisfiable)?;
+        let empty_leaf_hash_g =
+            CRHVar::OutputVar::new_constant(ConstraintSystemRef::None, empty_leaf_hash)?;
+
+        for (addr_g_item, absence_proof_g_item) in addr_g.iter().zip(absence_proof_g.iter()) {
+            absence_proof_g_item.conditionally_check_leaf_hash_with_index(
+                pp_g,
+                &rh_g,
+                empty_leaf_hash_g.clone(),
+                &addr_g_item,
+                &Boolean::constant(true),
+            )?;
+        }
 
-    /// clear the data in the tree
-    fn clear(pp: &Self::PublicParameters, tree: &mut Self::T) -> Result<(), Error>;
+        Ok(())
+    }
 
-    /// check a lookup proof
-    fn verify_lookup_gadget<DataVar: ToBytesGadget<F>>(
+    fn conditionally_verify_modify_gadget<DataVar: MerkleLeafGadget<F>>(
         cs: ConstraintSystemRef<F>,
         pp_g: &Self::PublicParameters,
-        rh_g: &Self::DigestVar,
-        addr_g: &[AddrVar],
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
         data_g: &[DataVar],
-        lookup_proof_g: &Self::LookupProofVar,
-    ) -> Result<(), SynthesisError>;
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_modify_gadget(
+            cs,
+            pp_g,
+            rh_old_g,
+            rh_new_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            modify_proof_g_wrapped,
+            should_enforce,
+        )
+    }
 
-    /// check a modifying proof
-    fn conditionally_verify_modify_gadget<DataVar: ToBytesGadget<F>>(
-        cs: ConstraintSystemRef<F>,
+    fn conditionally_verify_partial_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
         pp_g: &Self::PublicParameters,
         rh_old_g: &Self::DigestVar,
         rh_new_g: &Self::DigestVar,
-        addr_g: &[AddrVar],
+        addr_g: &[UInt64<F>],
         data_g: &[DataVar],
-        modify_proof_g: &Self::ModifyProofVar,
+        active_g: &[Boolean<F>],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
         should_enforce: &Boolean<F>,
-    ) -> Result<(), SynthesisError>;
+    ) -> Result<(), SynthesisError> {
+        let addr_g_len = addr_g.len();
+        let data_g_len = data_g.len();
+        let active_g_len = active_g.len();
+        let modify_proof_g = &modify_proof_g_wrapped.0;
+        let modify_proof_g_len = modify_proof_g.len();
 
-    /// output a dummy lookup proof
-    fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error>;
-    /// output a dummy modifying proof
-    fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error>;
+        if addr_g_len != data_g_len
+            || data_g_len != active_g_len
+            || data_g_len != modify_proof_g_len
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let leaf_hashes_g = Self::leaf_hashes_gadget(pp_g, data_g)?;
+
+        let mut addr_g_iter = addr_g.iter();
+        let mut leaf_hash_g_iter = leaf_hashes_g.into_iter();
+        let mut active_g_iter = active_g.iter();
+        let mut modify_proof_g_iter = modify_proof_g.iter();
+
+        let mut last_hash_g: Self::DigestVar = rh_old_g.clone();
+
+        loop {
+            let addr_g_iter_next_item = addr_g_iter.next();
+            match addr_g_iter_next_item {
+                Some(addr_g_item) => {
+                    let leaf_hash_g_item = leaf_hash_g_iter.next().unwrap();
+                    let active_g_item = active_g_iter.next().unwrap();
+                    let modify_proof_g_item = modify_proof_g_iter.next().unwrap();
+
+                    let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+                    modify_proof_g_item
+                        .modify_proof_g
+                        .conditionally_check_leaf_hash(
+                            pp_g,
+                            &last_hash_g,
+                            &modify_proof_g_item.new_digest_g,
+                            leaf_hash_g_item,
+                            &addr_g_item,
+                            &should_enforce_item,
+                        )?;
+
+                    /* an inactive entry leaves the digest unchanged */
+                    last_hash_g = Self::DigestVar::conditionally_select(
+                        &active_g_item,
+                        &modify_proof_g_item.new_digest_g,
+                        &last_hash_g,
+                    )?;
+                }
+                _ => break,
+            }
+        }
+
+        last_hash_g.conditional_enforce_equal(rh_new_g, &should_enforce)?;
+
+        Ok(())
+    }
+
+    fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error> {
+        Ok(MerkleSparseTreeBatchPath::dummy(num))
+    }
+
+    fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error> {
+        let mut res = Vec::with_capacity(num);
+        for _ in 0..num {
+            res.push(ModifyProofType::<P>::default());
+        }
+        Ok(res)
+    }
+
+    fn pad_lookup_proof(proof: &mut Self::LookupProof, num: usize) -> Result<(), Error> {
+        proof.pad(num)
+    }
+
+    fn pad_modify_proof(proof: &mut Self::ModifyProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(ModifyProofType::<P>::default());
+        }
+        Ok(())
+    }
 }
 
-/// Sparse Merkle tree
-pub struct SparseMT<
+/// 4-ary sparse Merkle tree
+pub struct SparseQuadMT<
     F: PrimeField,
-    P: MerkleSparseTreeConfig,
+    P: MerkleSparseQuadTreeConfig,
     CRHVar: CRHforMerkleTreeGadget<P::H, F>,
 > {
     f_phantom: PhantomData<F>,
@@ -131,45 +1365,47 @@ pub struct SparseMT<
     crh_gadget_phantom: PhantomData<CRHVar>,
 }
 
-/// A single Merkle tree modifying proof
-pub struct ModifyProofType<P: MerkleSparseTreeConfig> {
+/// A single 4-ary Merkle tree modifying proof
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct QuadModifyProofType<P: MerkleSparseQuadTreeConfig> {
     /// the new digest
-    new_digest: <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output,
+    new_digest: <<P as MerkleSparseQuadTreeConfig>::H as CRHforMerkleTree>::Output,
     /// the modifying proof
-    modify_proof: MerkleSparseTreeTwoPaths<P>,
+    modify_proof: MerkleSparseQuadTreeTwoPaths<P>,
 }
 
-impl<P: MerkleSparseTreeConfig> Clone for ModifyProofType<P> {
+impl<P: MerkleSparseQuadTreeConfig> Clone for QuadModifyProofType<P> {
     fn clone(&self) -> Self {
-        ModifyProofType {
+        QuadModifyProofType {
             new_digest: self.new_digest.clone(),
             modify_proof: self.modify_proof.clone(),
         }
     }
 }
 
-impl<P: MerkleSparseTreeConfig> Default for ModifyProofType<P> {
+impl<P: MerkleSparseQuadTreeConfig> Default for QuadModifyProofType<P> {
     fn default() -> Self {
-        ModifyProofType {
-            new_digest: <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output::default(),
-            modify_proof: MerkleSparseTreeTwoPaths::<P>::default(),
+        QuadModifyProofType {
+            new_digest: <<P as MerkleSparseQuadTreeConfig>::H as CRHforMerkleTree>::Output::default(
+            ),
+            modify_proof: MerkleSparseQuadTreeTwoPaths::<P>::default(),
         }
     }
 }
 
-struct ModifyProofTypeVar<
-    P: MerkleSparseTreeConfig,
+struct QuadModifyProofTypeVar<
+    P: MerkleSparseQuadTreeConfig,
     CRHVar: CRHforMerkleTreeGadget<P::H, F>,
     F: PrimeField,
 > {
     new_digest_g: CRHVar::OutputVar,
-    modify_proof_g: MerkleSparseTreeTwoPathsVar<P, CRHVar, F>,
+    modify_proof_g: MerkleSparseQuadTreeTwoPathsVar<P, CRHVar, F>,
 }
 
-impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
-    AllocVar<ModifyProofType<P>, F> for ModifyProofTypeVar<P, CRHVar, F>
+impl<P: MerkleSparseQuadTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: PrimeField>
+    AllocVar<QuadModifyProofType<P>, F> for QuadModifyProofTypeVar<P, CRHVar, F>
 {
-    fn new_variable<T: Borrow<ModifyProofType<P>>>(
+    fn new_variable<T: Borrow<QuadModifyProofType<P>>>(
         cs: impl Into<Namespace<F>>,
         f: impl FnOnce() -> Result<T, SynthesisError>,
         mode: AllocationMode,
@@ -185,20 +1421,20 @@ impl<P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>, F: Prim
             || Ok(&modify_proof_type.new_digest),
             mode,
         )?;
-        let modify_proof_g = MerkleSparseTreeTwoPathsVar::new_variable(
+        let modify_proof_g = MerkleSparseQuadTreeTwoPathsVar::new_variable(
             ark_relations::ns!(cs, "modify_proof_type_gadget_modify_proof"),
             || Ok(&modify_proof_type.modify_proof),
             mode,
         )?;
 
-        Ok(ModifyProofTypeVar {
+        Ok(QuadModifyProofTypeVar {
             new_digest_g,
             modify_proof_g,
         })
     }
 }
 
-impl<P: MerkleSparseTreeConfig> ToBytes for ModifyProofType<P> {
+impl<P: MerkleSparseQuadTreeConfig> ToBytes for QuadModifyProofType<P> {
     fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
         self.new_digest.write(&mut writer)?;
         self.modify_proof.write(&mut writer)?;
@@ -206,24 +1442,24 @@ impl<P: MerkleSparseTreeConfig> ToBytes for ModifyProofType<P> {
     }
 }
 
-/// lookup proof gadgets
-pub struct LookupProofVar<
+/// lookup proof gadgets for the 4-ary tree
+pub struct QuadLookupProofVar<
     F: PrimeField,
-    P: MerkleSparseTreeConfig,
+    P: MerkleSparseQuadTreeConfig,
     CRHVar: CRHforMerkleTreeGadget<P::H, F>,
->(Vec<MerkleSparseTreePathVar<P, CRHVar, F>>);
+>(Vec<MerkleSparseQuadTreePathVar<P, CRHVar, F>>);
 
-/// modifying proof gadgets
-pub struct ModifyProofVar<
+/// modifying proof gadgets for the 4-ary tree
+pub struct QuadModifyProofVar<
     F: PrimeField,
-    P: MerkleSparseTreeConfig,
+    P: MerkleSparseQuadTreeConfig,
     CRHVar: CRHforMerkleTreeGadget<P::H, F>,
->(Vec<ModifyProofTypeVar<P, CRHVar, F>>);
+>(Vec<QuadModifyProofTypeVar<P, CRHVar, F>>);
 
-impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
-    AllocVar<Vec<MerkleSparseTreePath<P>>, F> for LookupProofVar<F, P, CRHVar>
+impl<F: PrimeField, P: MerkleSparseQuadTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<MerkleSparseQuadTreePath<P>>, F> for QuadLookupProofVar<F, P, CRHVar>
 {
-    fn new_variable<T: Borrow<Vec<MerkleSparseTreePath<P>>>>(
+    fn new_variable<T: Borrow<Vec<MerkleSparseQuadTreePath<P>>>>(
         cs: impl Into<Namespace<F>>,
         f: impl FnOnce() -> Result<T, SynthesisError>,
         mode: AllocationMode,
@@ -233,20 +1469,20 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
 
         let mut vec = Vec::new();
         for value in f()?.borrow().iter() {
-            vec.push(MerkleSparseTreePathVar::<P, CRHVar, F>::new_variable(
+            vec.push(MerkleSparseQuadTreePathVar::<P, CRHVar, F>::new_variable(
                 ark_relations::ns!(cs, "value"),
                 || Ok(value),
                 mode,
             )?);
         }
-        Ok(LookupProofVar { 0: vec })
+        Ok(QuadLookupProofVar { 0: vec })
     }
 }
 
-impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
-    AllocVar<Vec<ModifyProofType<P>>, F> for ModifyProofVar<F, P, CRHVar>
+impl<F: PrimeField, P: MerkleSparseQuadTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    AllocVar<Vec<QuadModifyProofType<P>>, F> for QuadModifyProofVar<F, P, CRHVar>
 {
-    fn new_variable<T: Borrow<Vec<ModifyProofType<P>>>>(
+    fn new_variable<T: Borrow<Vec<QuadModifyProofType<P>>>>(
         cs: impl Into<Namespace<F>>,
         f: impl FnOnce() -> Result<T, SynthesisError>,
         mode: AllocationMode,
@@ -256,35 +1492,39 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
 
         let mut vec = Vec::new();
         for value in f()?.borrow().iter() {
-            vec.push(ModifyProofTypeVar::<P, CRHVar, F>::new_variable(
+            vec.push(QuadModifyProofTypeVar::<P, CRHVar, F>::new_variable(
                 ark_relations::ns!(cs, "value"),
                 || Ok(value),
                 mode,
             )?);
         }
-        Ok(ModifyProofVar { 0: vec })
+        Ok(QuadModifyProofVar { 0: vec })
     }
 }
 
-impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
-    MT<F, u64, UInt64<F>> for SparseMT<F, P, CRHVar>
+impl<F: PrimeField, P: MerkleSparseQuadTreeConfig, CRHVar: CRHforMerkleTreeGadget<P::H, F>>
+    MT<F, u64, UInt64<F>> for SparseQuadMT<F, P, CRHVar>
 {
-    type PublicParameters = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Parameters;
-    type Digest = <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::Output;
-    type T = MerkleSparseTree<P>;
-    type LookupProof = Vec<MerkleSparseTreePath<P>>;
-    type ModifyProof = Vec<ModifyProofType<P>>;
+    type PublicParameters = <<P as MerkleSparseQuadTreeConfig>::H as CRHforMerkleTree>::Parameters;
+    type Digest = <<P as MerkleSparseQuadTreeConfig>::H as CRHforMerkleTree>::Output;
+    type T = MerkleSparseQuadTree<P>;
+    type LookupProof = Vec<MerkleSparseQuadTreePath<P>>;
+    type ModifyProof = Vec<QuadModifyProofType<P>>;
+    type AbsenceProof = Vec<MerkleSparseQuadTreePath<P>>;
+    type MultiLookupProof = MerkleSparseQuadTreeMultiPath<P>;
 
     type DigestVar = CRHVar::OutputVar;
-    type LookupProofVar = LookupProofVar<F, P, CRHVar>;
-    type ModifyProofVar = ModifyProofVar<F, P, CRHVar>;
+    type LookupProofVar = QuadLookupProofVar<F, P, CRHVar>;
+    type ModifyProofVar = QuadModifyProofVar<F, P, CRHVar>;
+    type AbsenceProofVar = QuadLookupProofVar<F, P, CRHVar>;
+    type MultiLookupProofVar = MerkleSparseQuadTreeMultiPathVar<P, CRHVar, F>;
 
     fn setup<R: Rng + CryptoRng>(rng: &mut R) -> Result<Self::PublicParameters, Error> {
-        <<P as MerkleSparseTreeConfig>::H as CRHforMerkleTree>::setup(rng)
+        <<P as MerkleSparseQuadTreeConfig>::H as CRHforMerkleTree>::setup(rng)
     }
 
-    fn new<L: Default + ToBytes>(pp: &Self::PublicParameters) -> Result<Self::T, Error> {
-        Ok(MerkleSparseTree::blank::<L>(pp.clone()))
+    fn new<L: Default + MerkleLeaf<F>>(pp: &Self::PublicParameters) -> Result<Self::T, Error> {
+        Ok(MerkleSparseQuadTree::blank::<EncodedLeaf<F, L>>(pp.clone()))
     }
 
     fn root(_pp: &Self::PublicParameters, tree: &Self::T) -> Result<Self::Digest, Error> {
@@ -309,7 +1549,7 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         Ok(lookup_proof)
     }
 
-    fn verify_lookup<Data: ToBytes + Clone + Default>(
+    fn verify_lookup<Data: MerkleLeaf<F> + Clone + Default>(
         pp: &Self::PublicParameters,
         rh: &Self::Digest,
         addr: &[u64],
@@ -323,6 +1563,7 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         if addr_len != data_len || data_len != lookup_proof_len {
             return Err(SparseMTError::IncorrectInput.into());
         }
+        let data = encode_leaves(data)?;
 
         let mut addr_iter = addr.iter();
         let mut data_iter = data.iter();
@@ -346,7 +1587,64 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         Ok(true)
     }
 
-    fn verify_modify<Data: ToBytes + Clone + Default>(
+    fn multi_lookup(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::MultiLookupProof, Error> {
+        tree.generate_multi_membership_proof(addr)
+    }
+
+    fn verify_multi_lookup<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        data: &[Data],
+        multi_lookup_proof: &Self::MultiLookupProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != data.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        multi_lookup_proof.verify_with_indices(pp, rh, &encode_leaves(data)?, addr)
+    }
+
+    fn prove_absence(
+        _pp: &Self::PublicParameters,
+        tree: &Self::T,
+        addr: &[u64],
+    ) -> Result<Self::AbsenceProof, Error> {
+        let mut absence_proof: Self::AbsenceProof = Vec::with_capacity(addr.len());
+
+        for i in addr {
+            absence_proof.push(tree.generate_absence_proof(*i)?);
+        }
+
+        Ok(absence_proof)
+    }
+
+    fn verify_absence<Data: MerkleLeaf<F> + Clone + Default>(
+        pp: &Self::PublicParameters,
+        rh: &Self::Digest,
+        addr: &[u64],
+        absence_proof: &Self::AbsenceProof,
+    ) -> Result<bool, Error> {
+        if addr.len() != absence_proof.len() {
+            return Err(SparseMTError::IncorrectInput.into());
+        }
+
+        for (addr_item, absence_proof_item) in addr.iter().zip(absence_proof.iter()) {
+            if !absence_proof_item
+                .verify_absence_with_index::<EncodedLeaf<F, Data>>(pp, rh, *addr_item)?
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn verify_modify<Data: MerkleLeaf<F> + Clone + Default>(
         pp: &Self::PublicParameters,
         rh_old: &Self::Digest,
         rh_new: &Self::Digest,
@@ -361,6 +1659,7 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         if addr_len != data_len || data_len != modify_proof_len {
             return Err(SparseMTError::IncorrectInput.into());
         }
+        let data = encode_leaves(data)?;
 
         let mut addr_iter = addr.iter();
         let mut data_iter = data.iter();
@@ -398,14 +1697,19 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         Ok(true)
     }
 
-    fn _new_with_map<Data: ToBytes + Clone + Default>(
+    fn _new_with_map<Data: MerkleLeaf<F> + Clone + Default>(
         pp: &Self::PublicParameters,
         map: &BTreeMap<u64, Data>,
     ) -> Result<Self::T, Error> {
-        MerkleSparseTree::new(pp.clone(), &map)
+        let mut encoded_map = BTreeMap::new();
+        for (addr, data) in map.iter() {
+            encoded_map.insert(*addr, encode_leaf(data)?);
+        }
+
+        MerkleSparseQuadTree::new(pp.clone(), &encoded_map)
     }
 
-    fn _modify_and_apply<Data: ToBytes + Clone + Default>(
+    fn _modify_and_apply<Data: MerkleLeaf<F> + Clone + Default>(
         _pp: &Self::PublicParameters,
         tree: &mut Self::T,
         addr: &[u64],
@@ -417,6 +1721,7 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         if addr_len != data_len {
             return Err(SparseMTError::IncorrectInput.into());
         }
+        let data = encode_leaves(data)?;
 
         let mut modify_proof: Self::ModifyProof = Vec::with_capacity(addr_len);
 
@@ -431,7 +1736,7 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
 
                     let proof = tree.update_and_prove(*addr_item, data_item)?;
                     let rh = tree.root();
-                    modify_proof.push(ModifyProofType {
+                    modify_proof.push(QuadModifyProofType {
                         new_digest: rh,
                         modify_proof: proof,
                     });
@@ -451,33 +1756,92 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         Ok(())
     }
 
-    fn verify_lookup_gadget<DataVar: ToBytesGadget<F>>(
+    fn serialize_tree<W: Write>(tree: &Self::T, writer: W) -> Result<(), Error> {
+        tree.serialize_nodes(writer)?;
+
+        Ok(())
+    }
+
+    fn deserialize_tree<L: Default + MerkleLeaf<F>, R: Read>(
+        pp: &Self::PublicParameters,
+        reader: R,
+    ) -> Result<Self::T, Error> {
+        let tree = MerkleSparseQuadTree::<P>::deserialize_nodes::<EncodedLeaf<F, L>, R>(
+            pp.clone(),
+            reader,
+        )?;
+
+        Ok(tree)
+    }
+
+    fn verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            lookup_proof_g_wrapped,
+            &Boolean::constant(true),
+        )
+    }
+
+    fn conditionally_verify_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_lookup_gadget(
+            cs,
+            pp_g,
+            rh_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            lookup_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
         _cs: ConstraintSystemRef<F>,
         pp_g: &Self::PublicParameters,
         rh_g: &Self::DigestVar,
         addr_g: &[UInt64<F>],
         data_g: &[DataVar],
+        active_g: &[Boolean<F>],
         lookup_proof_g_wrapped: &Self::LookupProofVar,
+        should_enforce: &Boolean<F>,
     ) -> Result<(), SynthesisError> {
         let addr_g_len = addr_g.len();
         let data_g_len = data_g.len();
+        let active_g_len = active_g.len();
 
         let lookup_proof_g = &lookup_proof_g_wrapped.0;
         let lookup_proof_g_len = lookup_proof_g.len();
 
-        assert_eq!(
-            addr_g_len, data_g_len,
-            "the address len {} does not equal the data len {}",
-            addr_g_len, data_g_len
-        );
-        assert_eq!(
-            data_g_len, lookup_proof_g_len,
-            "the data len {} does not equal the proof len {}",
-            data_g_len, lookup_proof_g_len
-        );
+        if addr_g_len != data_g_len
+            || data_g_len != active_g_len
+            || data_g_len != lookup_proof_g_len
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let data_g = encode_leaves_gadget(data_g)?;
 
         let mut addr_g_iter = addr_g.iter();
         let mut data_g_iter = data_g.iter();
+        let mut active_g_iter = active_g.iter();
         let mut lookup_proof_g_iter = lookup_proof_g.iter();
 
         loop {
@@ -485,13 +1849,17 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
             match addr_g_iter_next_item {
                 Some(addr_g_item) => {
                     let data_g_item = data_g_iter.next().unwrap();
+                    let active_g_item = active_g_iter.next().unwrap();
                     let lookup_proof_g_item = lookup_proof_g_iter.next().unwrap();
 
-                    lookup_proof_g_item.check_membership_with_index(
+                    let should_enforce_item = should_enforce.and(&active_g_item)?;
+
+                    lookup_proof_g_item.conditionally_check_membership_with_index(
                         pp_g,
                         &rh_g,
                         &data_g_item,
                         &addr_g_item,
+                        &should_enforce_item,
                     )?;
                 }
                 _ => break,
@@ -501,52 +1869,132 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
         Ok(())
     }
 
-    fn conditionally_verify_modify_gadget<DataVar: ToBytesGadget<F>>(
+    fn verify_multi_lookup_gadget<DataVar: MerkleLeafGadget<F>>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr: &[u64],
+        data_g: &[DataVar],
+        multi_lookup_proof_g: &Self::MultiLookupProofVar,
+    ) -> Result<(), SynthesisError> {
+        if addr.len() != data_g.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        multi_lookup_proof_g.check_membership_with_indices(
+            pp_g,
+            rh_g,
+            &encode_leaves_gadget(data_g)?,
+            addr,
+        )
+    }
+
+    fn verify_absence_gadget<Data: MerkleLeaf<F> + Clone + Default>(
+        _cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        absence_proof_g_wrapped: &Self::AbsenceProofVar,
+    ) -> Result<(), SynthesisError> {
+        let absence_proof_g = &absence_proof_g_wrapped.0;
+
+        if addr_g.len() != absence_proof_g.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        for (addr_g_item, absence_proof_g_item) in addr_g.iter().zip(absence_proof_g.iter()) {
+            absence_proof_g_item.check_absence_with_index::<EncodedLeaf<F, Data>>(
+                pp_g,
+                &rh_g,
+                &addr_g_item,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn conditionally_verify_modify_gadget<DataVar: MerkleLeafGadget<F>>(
+        cs: ConstraintSystemRef<F>,
+        pp_g: &Self::PublicParameters,
+        rh_old_g: &Self::DigestVar,
+        rh_new_g: &Self::DigestVar,
+        addr_g: &[UInt64<F>],
+        data_g: &[DataVar],
+        modify_proof_g_wrapped: &Self::ModifyProofVar,
+        should_enforce: &Boolean<F>,
+    ) -> Result<(), SynthesisError> {
+        Self::conditionally_verify_partial_modify_gadget(
+            cs,
+            pp_g,
+            rh_old_g,
+            rh_new_g,
+            addr_g,
+            data_g,
+            &vec![Boolean::constant(true); addr_g.len()],
+            modify_proof_g_wrapped,
+            should_enforce,
+        )
+    }
+
+    fn conditionally_verify_partial_modify_gadget<DataVar: MerkleLeafGadget<F>>(
         _cs: ConstraintSystemRef<F>,
         pp_g: &Self::PublicParameters,
         rh_old_g: &Self::DigestVar,
         rh_new_g: &Self::DigestVar,
         addr_g: &[UInt64<F>],
         data_g: &[DataVar],
+        active_g: &[Boolean<F>],
         modify_proof_g_wrapped: &Self::ModifyProofVar,
         should_enforce: &Boolean<F>,
     ) -> Result<(), SynthesisError> {
         let addr_g_len = addr_g.len();
         let data_g_len = data_g.len();
+        let active_g_len = active_g.len();
         let modify_proof_g = &modify_proof_g_wrapped.0;
         let modify_proof_g_len = modify_proof_g.len();
 
-        assert_eq!(addr_g_len, data_g_len);
-        assert_eq!(data_g_len, modify_proof_g_len);
+        if addr_g_len != data_g_len
+            || data_g_len != active_g_len
+            || data_g_len != modify_proof_g_len
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let data_g = encode_leaves_gadget(data_g)?;
 
         let mut addr_g_iter = addr_g.iter();
         let mut data_g_iter = data_g.iter();
+        let mut active_g_iter = active_g.iter();
         let mut modify_proof_g_iter = modify_proof_g.iter();
 
         let mut last_hash_g: Self::DigestVar = rh_old_g.clone();
-        let mut cur_hash_g: Self::DigestVar;
 
         loop {
             let addr_g_iter_next_item = addr_g_iter.next();
             match addr_g_iter_next_item {
                 Some(addr_g_item) => {
                     let data_g_item = data_g_iter.next().unwrap();
+                    let active_g_item = active_g_iter.next().unwrap();
                     let modify_proof_g_item = modify_proof_g_iter.next().unwrap();
 
-                    cur_hash_g = modify_proof_g_item.new_digest_g.clone();
+                    let should_enforce_item = should_enforce.and(&active_g_item)?;
 
                     modify_proof_g_item
                         .modify_proof_g
                         .conditionally_check_update(
                             pp_g,
                             &last_hash_g,
-                            &cur_hash_g,
+                            &modify_proof_g_item.new_digest_g,
                             &data_g_item,
                             &addr_g_item,
-                            &should_enforce,
+                            &should_enforce_item,
                         )?;
 
-                    last_hash_g = cur_hash_g;
+                    /* an inactive entry leaves the digest unchanged */
+                    last_hash_g = Self::DigestVar::conditionally_select(
+                        &active_g_item,
+                        &modify_proof_g_item.new_digest_g,
+                        &last_hash_g,
+                    )?;
                 }
                 _ => break,
             }
@@ -560,7 +2008,7 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
     fn default_lookup_proof(num: usize) -> Result<Self::LookupProof, Error> {
         let mut res = Vec::with_capacity(num);
         for _ in 0..num {
-            res.push(MerkleSparseTreePath::<P>::default());
+            res.push(MerkleSparseQuadTreePath::<P>::default());
         }
         Ok(res)
     }
@@ -568,10 +2016,24 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
     fn default_modify_proof(num: usize) -> Result<Self::ModifyProof, Error> {
         let mut res = Vec::with_capacity(num);
         for _ in 0..num {
-            res.push(ModifyProofType::<P>::default());
+            res.push(QuadModifyProofType::<P>::default());
         }
         Ok(res)
     }
+
+    fn pad_lookup_proof(proof: &mut Self::LookupProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(MerkleSparseQuadTreePath::<P>::default());
+        }
+        Ok(())
+    }
+
+    fn pad_modify_proof(proof: &mut Self::ModifyProof, num: usize) -> Result<(), Error> {
+        while proof.len() < num {
+            proof.push(QuadModifyProofType::<P>::default());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -579,12 +2041,17 @@ impl<F: PrimeField, P: MerkleSparseTreeConfig, CRHVar: CRHforMerkleTreeGadget<P:
 pub enum SparseMTError {
     /// length of data/proof does not match each other
     IncorrectInput,
+    /// the tree does not keep its past versions
+    UnsupportedVersioning,
 }
 
 impl core::fmt::Display for SparseMTError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let msg = match self {
             SparseMTError::IncorrectInput => "incorrect input".to_string(),
+            SparseMTError::UnsupportedVersioning => {
+                "the tree does not keep its past versions".to_string()
+            }
         };
         write!(f, "{}", msg)
     }
@@ -1,15 +1,15 @@
-use crate::compiler::IVLS;
-use crate::ivls::data_structures::Commitment;
+use crate::compiler::{derive_pp_mt, IVLS};
 use crate::{
     building_blocks::mt::MT,
     gadgets::UInt64,
     ivls::{
+        error::IVLSError,
         history::VerifiableHistory,
         state::VerifiableState,
         transition_function::{VerifiableTransitionFunction, VerifiableTransitionFunctionConfig},
     },
     ledger_system::transition_function::TransitionFunction,
-    Error, PhantomData,
+    PhantomData,
 };
 use ark_pcd::PCD;
 use ark_std::rand::{CryptoRng, RngCore};
@@ -39,33 +39,24 @@ pub struct CircuitSpecificSetupIVLSPP<VC: VerifiableTransitionFunctionConfig> {
         >>::Digest,
         <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::Digest,
     ),
+    /// the seed used to sample Merkle tree parameters
+    pub pp_mt_seed: [u8; 32],
 }
 
 impl<VC: VerifiableTransitionFunctionConfig> CircuitSpecificSetupIVLSCompiler<VC> {
     /// IVLS.setup (circuit-specific)
     pub fn circuit_specific_setup<R: RngCore + CryptoRng>(
         rng: &mut R,
-    ) -> Result<CircuitSpecificSetupIVLSPP<VC>, Error> {
-        let pp_mt = (
-            <VC::MTState as MT<
-                VC::F,
-                <VC::TF as TransitionFunction<VC::F>>::Addr,
-                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-            >>::setup(rng)?,
-            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::setup(rng)?,
-        );
+    ) -> Result<CircuitSpecificSetupIVLSPP<VC>, IVLSError> {
+        let mut pp_mt_seed = [0u8; 32];
+        rng.fill_bytes(&mut pp_mt_seed);
 
-        let empty_tree_state =
-            VC::MTState::new::<<VC::TF as TransitionFunction<VC::F>>::Data>(&pp_mt.0)?;
-        let empty_tree_history = VC::MTHistory::new::<Commitment<VC>>(&pp_mt.1)?;
-        let empty_digest = (
-            VC::MTState::root(&pp_mt.0, &empty_tree_state)?,
-            VC::MTHistory::root(&pp_mt.1, &empty_tree_history)?,
-        );
+        let (pp_mt, empty_digest) = derive_pp_mt::<VC>(&pp_mt_seed)?;
 
         Ok(CircuitSpecificSetupIVLSPP {
             pp_mt,
             empty_digest,
+            pp_mt_seed,
         })
     }
 
@@ -73,7 +64,7 @@ impl<VC: VerifiableTransitionFunctionConfig> CircuitSpecificSetupIVLSCompiler<VC
     pub fn make_sfh<R: RngCore + CryptoRng>(
         pp: &CircuitSpecificSetupIVLSPP<VC>,
         rng: &mut R,
-    ) -> Result<IVLS<VC>, Error> {
+    ) -> Result<IVLS<VC>, IVLSError> {
         let p = VerifiableTransitionFunction::<VC> {
             pp_mt: pp.pp_mt.clone(),
             empty_digest: pp.empty_digest.clone(),
@@ -100,6 +91,7 @@ impl<VC: VerifiableTransitionFunctionConfig> CircuitSpecificSetupIVLSCompiler<VC
             vh: VerifiableHistory::<VC> {
                 pp_mt: pp.pp_mt.clone(),
             },
+            pp_mt_seed: pp.pp_mt_seed,
         })
     }
 }
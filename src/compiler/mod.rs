@@ -1,8 +1,20 @@
+use crate::ivls::data_structures::{Commitment, StateLeaf};
+use crate::ivls::error::IVLSError;
 use crate::ivls::history::VerifiableHistory;
 use crate::ivls::state::VerifiableState;
 use crate::ivls::transition_function::{
     VerifiableTransitionFunction, VerifiableTransitionFunctionConfig,
 };
+use crate::ivls::verifier::IVLSVerifier;
+use crate::{
+    building_blocks::mt::MT, gadgets::UInt64,
+    ledger_system::transition_function::TransitionFunction,
+};
+use ark_pcd::PCD;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::io::{Read, Write};
+use ark_std::rand::rngs::StdRng;
+use ark_std::rand::SeedableRng;
 
 /// compiler for circuit-specific setup IVLS
 pub mod circuit_specific_setup_compiler;
@@ -17,4 +29,250 @@ pub struct IVLS<VC: VerifiableTransitionFunctionConfig> {
     pub vs: VerifiableState<VC>,
     /// verifiable history
     pub vh: VerifiableHistory<VC>,
+    /// the seed used to sample Merkle tree parameters
+    pub pp_mt_seed: [u8; 32],
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> IVLS<VC> {
+    /// the bundle for the prover, which contains the PCD pk and vk
+    pub fn prover_bundle(&self) -> Result<IVLSProverBundle<VC>, IVLSError> {
+        Ok(IVLSProverBundle {
+            pp_mt_seed: self.pp_mt_seed,
+            ipk: self.vf.ipk.clone().ok_or(IVLSError::MissingKey)?,
+            ivk: self.vs.ivk.clone(),
+        })
+    }
+
+    /// the bundle for the verifier, which contains only the PCD vk
+    pub fn verifier_bundle(&self) -> IVLSVerifierBundle<VC> {
+        IVLSVerifierBundle {
+            pp_mt_seed: self.pp_mt_seed,
+            ivk: self.vs.ivk.clone(),
+        }
+    }
+
+    /// the verifier-only handle
+    pub fn verifier(&self) -> IVLSVerifier<VC> {
+        IVLSVerifier {
+            vs: self.vs.clone(),
+            vh: self.vh.clone(),
+            empty_digest: self.vf.empty_digest.clone(),
+        }
+    }
+}
+
+/// the prover's part of an IVLS
+pub struct IVLSProverBundle<VC: VerifiableTransitionFunctionConfig> {
+    /// the seed used to sample Merkle tree parameters
+    pub pp_mt_seed: [u8; 32],
+    /// the PCD pk
+    pub ipk: <VC::I as PCD<VC::F>>::ProvingKey,
+    /// the PCD vk
+    pub ivk: <VC::I as PCD<VC::F>>::VerifyingKey,
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> IVLSProverBundle<VC> {
+    /// rebuild the IVLS from the bundle
+    pub fn load(&self) -> Result<IVLS<VC>, IVLSError> {
+        let (pp_mt, empty_digest) = derive_pp_mt::<VC>(&self.pp_mt_seed)?;
+
+        Ok(IVLS::<VC> {
+            vf: VerifiableTransitionFunction::<VC> {
+                pp_mt: pp_mt.clone(),
+                empty_digest,
+                ipk: Some(self.ipk.clone()),
+                ivk: Some(self.ivk.clone()),
+            },
+            vs: VerifiableState::<VC> {
+                pp_mt: pp_mt.clone(),
+                ivk: self.ivk.clone(),
+            },
+            vh: VerifiableHistory::<VC> { pp_mt },
+            pp_mt_seed: self.pp_mt_seed,
+        })
+    }
+}
+
+/// the verifier's part of an IVLS
+pub struct IVLSVerifierBundle<VC: VerifiableTransitionFunctionConfig> {
+    /// the seed used to sample Merkle tree parameters
+    pub pp_mt_seed: [u8; 32],
+    /// the PCD vk
+    pub ivk: <VC::I as PCD<VC::F>>::VerifyingKey,
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> IVLSVerifierBundle<VC> {
+    /// rebuild vS and vH from the bundle, without the PCD pk or any randomness
+    pub fn load(&self) -> Result<(VerifiableState<VC>, VerifiableHistory<VC>), IVLSError> {
+        let (pp_mt, _) = derive_pp_mt::<VC>(&self.pp_mt_seed)?;
+
+        Ok((
+            VerifiableState::<VC> {
+                pp_mt: pp_mt.clone(),
+                ivk: self.ivk.clone(),
+            },
+            VerifiableHistory::<VC> { pp_mt },
+        ))
+    }
+
+    /// build the verifier-only handle from the bundle, without the PCD pk or any randomness
+    pub fn load_verifier(&self) -> Result<IVLSVerifier<VC>, IVLSError> {
+        let (pp_mt, empty_digest) = derive_pp_mt::<VC>(&self.pp_mt_seed)?;
+
+        Ok(IVLSVerifier::<VC> {
+            vs: VerifiableState::<VC> {
+                pp_mt: pp_mt.clone(),
+                ivk: self.ivk.clone(),
+            },
+            vh: VerifiableHistory::<VC> { pp_mt },
+            empty_digest,
+        })
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> CanonicalSerialize for IVLSProverBundle<VC>
+where
+    <VC::I as PCD<VC::F>>::ProvingKey: CanonicalSerialize,
+    <VC::I as PCD<VC::F>>::VerifyingKey: CanonicalSerialize,
+{
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        writer.write_all(&self.pp_mt_seed)?;
+        self.ipk.serialize(&mut writer)?;
+        self.ivk.serialize(&mut writer)
+    }
+
+    fn serialized_size(&self) -> usize {
+        32 + self.ipk.serialized_size() + self.ivk.serialized_size()
+    }
+
+    fn serialize_uncompressed<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        writer.write_all(&self.pp_mt_seed)?;
+        self.ipk.serialize_uncompressed(&mut writer)?;
+        self.ivk.serialize_uncompressed(&mut writer)
+    }
+
+    fn uncompressed_size(&self) -> usize {
+        32 + self.ipk.uncompressed_size() + self.ivk.uncompressed_size()
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> CanonicalDeserialize for IVLSProverBundle<VC>
+where
+    <VC::I as PCD<VC::F>>::ProvingKey: CanonicalDeserialize,
+    <VC::I as PCD<VC::F>>::VerifyingKey: CanonicalDeserialize,
+{
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut pp_mt_seed = [0u8; 32];
+        reader.read_exact(&mut pp_mt_seed)?;
+
+        Ok(IVLSProverBundle {
+            pp_mt_seed,
+            ipk: <VC::I as PCD<VC::F>>::ProvingKey::deserialize(&mut reader)?,
+            ivk: <VC::I as PCD<VC::F>>::VerifyingKey::deserialize(&mut reader)?,
+        })
+    }
+
+    fn deserialize_uncompressed<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut pp_mt_seed = [0u8; 32];
+        reader.read_exact(&mut pp_mt_seed)?;
+
+        Ok(IVLSProverBundle {
+            pp_mt_seed,
+            ipk: <VC::I as PCD<VC::F>>::ProvingKey::deserialize_uncompressed(&mut reader)?,
+            ivk: <VC::I as PCD<VC::F>>::VerifyingKey::deserialize_uncompressed(&mut reader)?,
+        })
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> CanonicalSerialize for IVLSVerifierBundle<VC>
+where
+    <VC::I as PCD<VC::F>>::VerifyingKey: CanonicalSerialize,
+{
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        writer.write_all(&self.pp_mt_seed)?;
+        self.ivk.serialize(&mut writer)
+    }
+
+    fn serialized_size(&self) -> usize {
+        32 + self.ivk.serialized_size()
+    }
+
+    fn serialize_uncompressed<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        writer.write_all(&self.pp_mt_seed)?;
+        self.ivk.serialize_uncompressed(&mut writer)
+    }
+
+    fn uncompressed_size(&self) -> usize {
+        32 + self.ivk.uncompressed_size()
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> CanonicalDeserialize for IVLSVerifierBundle<VC>
+where
+    <VC::I as PCD<VC::F>>::VerifyingKey: CanonicalDeserialize,
+{
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut pp_mt_seed = [0u8; 32];
+        reader.read_exact(&mut pp_mt_seed)?;
+
+        Ok(IVLSVerifierBundle {
+            pp_mt_seed,
+            ivk: <VC::I as PCD<VC::F>>::VerifyingKey::deserialize(&mut reader)?,
+        })
+    }
+
+    fn deserialize_uncompressed<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut pp_mt_seed = [0u8; 32];
+        reader.read_exact(&mut pp_mt_seed)?;
+
+        Ok(IVLSVerifierBundle {
+            pp_mt_seed,
+            ivk: <VC::I as PCD<VC::F>>::VerifyingKey::deserialize_uncompressed(&mut reader)?,
+        })
+    }
+}
+
+/// sample the Merkle tree public parameters from the seed, and compute the empty digests
+pub(crate) fn derive_pp_mt<VC: VerifiableTransitionFunctionConfig>(
+    pp_mt_seed: &[u8; 32],
+) -> Result<
+    (
+        (
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::PublicParameters,
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::PublicParameters,
+        ),
+        (
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::Digest,
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::Digest,
+        ),
+    ),
+    IVLSError,
+> {
+    let mut setup_rng = StdRng::from_seed(*pp_mt_seed);
+
+    let pp_mt = (
+        <VC::MTState as MT<
+            VC::F,
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+        >>::setup(&mut setup_rng)?,
+        <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::setup(&mut setup_rng)?,
+    );
+
+    let empty_tree_state = VC::MTState::new::<StateLeaf<VC>>(&pp_mt.0)?;
+    let empty_tree_history = VC::MTHistory::new::<Commitment<VC>>(&pp_mt.1)?;
+    let empty_digest = (
+        VC::MTState::root(&pp_mt.0, &empty_tree_state)?,
+        VC::MTHistory::root(&pp_mt.1, &empty_tree_history)?,
+    );
+
+    Ok((pp_mt, empty_digest))
 }
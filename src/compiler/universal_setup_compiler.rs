@@ -1,19 +1,15 @@
-use crate::compiler::IVLS;
-use crate::ivls::data_structures::Commitment;
+use crate::compiler::{derive_pp_mt, IVLS};
 use crate::{
-    building_blocks::mt::MT,
-    gadgets::UInt64,
     ivls::{
+        error::IVLSError,
         history::VerifiableHistory,
         state::VerifiableState,
         transition_function::{VerifiableTransitionFunction, VerifiableTransitionFunctionConfig},
     },
-    ledger_system::transition_function::TransitionFunction,
-    Error, PhantomData,
+    PhantomData,
 };
 use ark_pcd::UniversalSetupPCD;
-use ark_std::rand::rngs::StdRng;
-use ark_std::rand::{CryptoRng, RngCore, SeedableRng};
+use ark_std::rand::{CryptoRng, RngCore};
 
 /// compiler for universal setup IVLS
 pub struct UniversalSetupIVLSCompiler<VC: VerifiableTransitionFunctionConfig>
@@ -42,7 +38,7 @@ where
     pub fn universal_setup<R: RngCore + CryptoRng>(
         setup_bound: <VC::I as UniversalSetupPCD<VC::F>>::PredicateBound,
         rng: &mut R,
-    ) -> Result<UniversalSetupIVLSPP<VC>, Error> {
+    ) -> Result<UniversalSetupIVLSPP<VC>, IVLSError> {
         let pp_pcd = <VC::I as UniversalSetupPCD<VC::F>>::universal_setup::<R>(&setup_bound, rng)?;
 
         let mut pp_mt_seed = [0u8; 32];
@@ -55,25 +51,8 @@ where
     pub fn make_sfh<R: RngCore + CryptoRng>(
         pp: &UniversalSetupIVLSPP<VC>,
         rng: &mut R,
-    ) -> Result<IVLS<VC>, Error> {
-        let mut setup_rng = StdRng::from_seed(pp.pp_mt_seed);
-
-        let pp_mt = (
-            <VC::MTState as MT<
-                VC::F,
-                <VC::TF as TransitionFunction<VC::F>>::Addr,
-                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-            >>::setup(&mut setup_rng)?,
-            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::setup(&mut setup_rng)?,
-        );
-
-        let empty_tree_state =
-            VC::MTState::new::<<VC::TF as TransitionFunction<VC::F>>::Data>(&pp_mt.0)?;
-        let empty_tree_history = VC::MTHistory::new::<Commitment<VC>>(&pp_mt.1)?;
-        let empty_digest = (
-            VC::MTState::root(&pp_mt.0, &empty_tree_state)?,
-            VC::MTHistory::root(&pp_mt.1, &empty_tree_history)?,
-        );
+    ) -> Result<IVLS<VC>, IVLSError> {
+        let (pp_mt, empty_digest) = derive_pp_mt::<VC>(&pp.pp_mt_seed)?;
 
         let p = VerifiableTransitionFunction::<VC> {
             pp_mt: pp_mt.clone(),
@@ -98,6 +77,7 @@ where
                 ivk,
             },
             vh: VerifiableHistory::<VC> { pp_mt },
+            pp_mt_seed: pp.pp_mt_seed,
         })
     }
 }
@@ -11,7 +11,7 @@ pub use ark_r1cs_std::{
     eq::EqGadget,
     fields::{fp::FpVar, FieldVar},
     select::CondSelectGadget,
-    Assignment,
+    Assignment, ToConstraintFieldGadget,
 };
 use ark_relations::r1cs::Namespace;
 use ark_std::vec::Vec;
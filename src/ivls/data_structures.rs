@@ -1,19 +1,22 @@
 use crate::{
-    building_blocks::mt::MT,
-    gadgets::{AllocVar, ToBytesGadget, UInt64},
+    building_blocks::mt::{LeafEncoding, LeafEncodingVar, MerkleLeaf, MerkleLeafGadget, MT},
+    gadgets::{AllocVar, Boolean, ToBytesGadget, UInt64},
     ivls::transition_function::VerifiableTransitionFunctionConfig,
     ledger_system::transition_function::TransitionFunction,
-    Borrow, SynthesisError, ToBytes, Vec,
+    Borrow, Error, SynthesisError, ToBytes, Vec,
 };
 use ark_r1cs_std::alloc::AllocationMode;
 use ark_r1cs_std::bits::uint8::UInt8;
 use ark_relations::r1cs::Namespace;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::collections::BTreeMap;
 use ark_std::{
     io::{Result as IoResult, Write},
     vec,
 };
 
 /// the commitment in the IVLS systems
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
 pub struct Commitment<VC: VerifiableTransitionFunctionConfig> {
     /// root hash of the state tree
     pub state_rh: <VC::MTState as MT<
@@ -127,6 +130,7 @@ impl<VC: VerifiableTransitionFunctionConfig> Clone for CommitmentVar<VC> {
 }
 
 /// the PCD message for IVLS
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
 pub struct VerifiableTransitionFunctionMsg<VC: VerifiableTransitionFunctionConfig> {
     /// the step count
     pub t: u64,
@@ -219,6 +223,8 @@ pub struct VerifiableTransitionFunctionWitness<VC: VerifiableTransitionFunctionC
     pub raddr: Vec<<VC::TF as TransitionFunction<VC::F>>::Addr>,
     /// Accessed data
     pub rdata: Vec<<VC::TF as TransitionFunction<VC::F>>::Data>,
+    /// Whether each read is performed
+    pub ractive: Vec<bool>,
     /// Merkle state tree lookup proofs
     pub read_proof: <VC::MTState as MT<
         VC::F,
@@ -231,6 +237,8 @@ pub struct VerifiableTransitionFunctionWitness<VC: VerifiableTransitionFunctionC
     pub waddr: Vec<<VC::TF as TransitionFunction<VC::F>>::Addr>,
     /// Written data
     pub wdata: Vec<<VC::TF as TransitionFunction<VC::F>>::Data>,
+    /// Whether each write is performed
+    pub wactive: Vec<bool>,
     /// Merkle state tree modifying proofs
     pub write_proof: <VC::MTState as MT<
         VC::F,
@@ -239,6 +247,8 @@ pub struct VerifiableTransitionFunctionWitness<VC: VerifiableTransitionFunctionC
     >>::ModifyProof,
     /// Merkle history tree insertion proofs
     pub insert_proof: <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::ModifyProof,
+    /// Commitment after applying the transaction
+    pub cm: Commitment<VC>,
 }
 
 impl<VC: VerifiableTransitionFunctionConfig> Default for VerifiableTransitionFunctionWitness<VC> {
@@ -252,6 +262,7 @@ impl<VC: VerifiableTransitionFunctionConfig> Default for VerifiableTransitionFun
             <VC::TF as TransitionFunction<VC::F>>::Data::default();
             <VC::TF as TransitionFunction<VC::F>>::NUM_READS
         ];
+        let ractive = vec![false; <VC::TF as TransitionFunction<VC::F>>::NUM_READS];
         let read_proof =
             <VC::MTState as MT<
                 VC::F,
@@ -268,6 +279,7 @@ impl<VC: VerifiableTransitionFunctionConfig> Default for VerifiableTransitionFun
             <VC::TF as TransitionFunction<VC::F>>::Data::default();
             <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
         ];
+        let wactive = vec![false; <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES];
         let write_proof =
             <VC::MTState as MT<
                 VC::F,
@@ -277,17 +289,21 @@ impl<VC: VerifiableTransitionFunctionConfig> Default for VerifiableTransitionFun
             .unwrap();
         let insert_proof =
             <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::default_modify_proof(1).unwrap();
+        let cm = Commitment::<VC>::default();
 
         VerifiableTransitionFunctionWitness {
             trans,
             raddr,
             rdata,
+            ractive,
             read_proof,
             tx,
             waddr,
             wdata,
+            wactive,
             write_proof,
             insert_proof,
+            cm,
         }
     }
 }
@@ -298,12 +314,15 @@ impl<VC: VerifiableTransitionFunctionConfig> Clone for VerifiableTransitionFunct
             trans: self.trans.clone(),
             raddr: self.raddr.clone(),
             rdata: self.rdata.clone(),
+            ractive: self.ractive.clone(),
             read_proof: self.read_proof.clone(),
             tx: self.tx.clone(),
             waddr: self.waddr.clone(),
             wdata: self.wdata.clone(),
+            wactive: self.wactive.clone(),
             write_proof: self.write_proof.clone(),
             insert_proof: self.insert_proof.clone(),
+            cm: self.cm.clone(),
         }
     }
 }
@@ -316,6 +335,8 @@ pub struct VerifiableTransitionFunctionWitnessVar<VC: VerifiableTransitionFuncti
     pub raddr_g: Vec<<VC::TF as TransitionFunction<VC::F>>::AddrVar>,
     /// Accessed data
     pub rdata_g: Vec<<VC::TF as TransitionFunction<VC::F>>::DataVar>,
+    /// Whether each read is performed
+    pub ractive_g: Vec<Boolean<VC::F>>,
     /// Merkle state tree lookup proofs
     pub read_proof_g: <VC::MTState as MT<
         VC::F,
@@ -328,6 +349,8 @@ pub struct VerifiableTransitionFunctionWitnessVar<VC: VerifiableTransitionFuncti
     pub waddr_g: Vec<<VC::TF as TransitionFunction<VC::F>>::AddrVar>,
     /// Written data
     pub wdata_g: Vec<<VC::TF as TransitionFunction<VC::F>>::DataVar>,
+    /// Whether each write is performed
+    pub wactive_g: Vec<Boolean<VC::F>>,
     /// Merkle state tree modifying proofs
     pub write_proof_g: <VC::MTState as MT<
         VC::F,
@@ -336,6 +359,8 @@ pub struct VerifiableTransitionFunctionWitnessVar<VC: VerifiableTransitionFuncti
     >>::ModifyProofVar,
     /// Merkle history tree insert proofs
     pub insert_proof_g: <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::ModifyProofVar,
+    /// Commitment after applying the transaction
+    pub cm_g: CommitmentVar<VC>,
 }
 
 impl<VC: VerifiableTransitionFunctionConfig>
@@ -361,6 +386,10 @@ impl<VC: VerifiableTransitionFunctionConfig>
             witness.rdata.len(),
             <VC::TF as TransitionFunction<VC::F>>::NUM_READS
         );
+        assert_eq!(
+            witness.ractive.len(),
+            <VC::TF as TransitionFunction<VC::F>>::NUM_READS
+        );
 
         assert_eq!(
             witness.waddr.len(),
@@ -370,6 +399,10 @@ impl<VC: VerifiableTransitionFunctionConfig>
             witness.wdata.len(),
             <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
         );
+        assert_eq!(
+            witness.wactive.len(),
+            <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
+        );
 
         let trans_g = <VC::TF as TransitionFunction<VC::F>>::WitnessVar::new_variable(
             ark_relations::ns!(cs, "witness_gadget_trans"),
@@ -387,6 +420,11 @@ impl<VC: VerifiableTransitionFunctionConfig>
             || Ok(witness.rdata.clone()),
             mode,
         )?;
+        let ractive_g = Vec::<Boolean<VC::F>>::new_variable(
+            ark_relations::ns!(cs, "witness_gadget_ractive"),
+            || Ok(witness.ractive.clone()),
+            mode,
+        )?;
         let read_proof_g = <VC::MTState as MT<
             VC::F,
             <VC::TF as TransitionFunction<VC::F>>::Addr,
@@ -411,6 +449,11 @@ impl<VC: VerifiableTransitionFunctionConfig>
             || Ok(witness.wdata.clone()),
             mode,
         )?;
+        let wactive_g = Vec::<Boolean<VC::F>>::new_variable(
+            ark_relations::ns!(cs, "witness_gadget_wactive"),
+            || Ok(witness.wactive.clone()),
+            mode,
+        )?;
         let write_proof_g = <VC::MTState as MT<
             VC::F,
             <VC::TF as TransitionFunction<VC::F>>::Addr,
@@ -426,17 +469,186 @@ impl<VC: VerifiableTransitionFunctionConfig>
                 || Ok(&witness.insert_proof),
                 mode,
             )?;
+        let cm_g = CommitmentVar::<VC>::new_variable(
+            ark_relations::ns!(cs, "witness_gadget_cm"),
+            || Ok(&witness.cm),
+            mode,
+        )?;
 
         Ok(VerifiableTransitionFunctionWitnessVar {
             trans_g,
             raddr_g,
             rdata_g,
+            ractive_g,
             read_proof_g,
             tx_g,
             waddr_g,
             wdata_g,
+            wactive_g,
             write_proof_g,
             insert_proof_g,
+            cm_g,
         })
     }
 }
+
+/// Batch witness for IVLS's PCD, one slot per transaction
+pub struct VerifiableTransitionFunctionBatchWitness<VC: VerifiableTransitionFunctionConfig> {
+    /// Whether each slot carries a transaction (the active slots form a prefix)
+    pub active: Vec<bool>,
+    /// Witnesses of the slots
+    pub steps: Vec<VerifiableTransitionFunctionWitness<VC>>,
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> Default
+    for VerifiableTransitionFunctionBatchWitness<VC>
+{
+    fn default() -> Self {
+        let active = vec![false; VC::BATCH_SIZE];
+        let mut steps = Vec::with_capacity(VC::BATCH_SIZE);
+        for _ in 0..VC::BATCH_SIZE {
+            steps.push(VerifiableTransitionFunctionWitness::<VC>::default());
+        }
+
+        VerifiableTransitionFunctionBatchWitness { active, steps }
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> Clone
+    for VerifiableTransitionFunctionBatchWitness<VC>
+{
+    fn clone(&self) -> Self {
+        VerifiableTransitionFunctionBatchWitness {
+            active: self.active.clone(),
+            steps: self.steps.clone(),
+        }
+    }
+}
+
+/// Batch witness gadget for IVLS's PCD
+pub struct VerifiableTransitionFunctionBatchWitnessVar<VC: VerifiableTransitionFunctionConfig> {
+    /// Whether each slot carries a transaction
+    pub active_g: Vec<Boolean<VC::F>>,
+    /// Witnesses of the slots
+    pub steps_g: Vec<VerifiableTransitionFunctionWitnessVar<VC>>,
+}
+
+impl<VC: VerifiableTransitionFunctionConfig>
+    AllocVar<VerifiableTransitionFunctionBatchWitness<VC>, VC::F>
+    for VerifiableTransitionFunctionBatchWitnessVar<VC>
+{
+    fn new_variable<T: Borrow<VerifiableTransitionFunctionBatchWitness<VC>>>(
+        cs: impl Into<Namespace<<VC as VerifiableTransitionFunctionConfig>::F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let t = f()?;
+        let witness = t.borrow().clone();
+
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        assert_eq!(witness.active.len(), VC::BATCH_SIZE);
+        assert_eq!(witness.steps.len(), VC::BATCH_SIZE);
+
+        let active_g = Vec::<Boolean<VC::F>>::new_variable(
+            ark_relations::ns!(cs, "batch_witness_gadget_active"),
+            || Ok(witness.active.clone()),
+            mode,
+        )?;
+        let steps_g = Vec::<VerifiableTransitionFunctionWitnessVar<VC>>::new_variable(
+            ark_relations::ns!(cs, "batch_witness_gadget_steps"),
+            || Ok(witness.steps.clone()),
+            mode,
+        )?;
+
+        Ok(VerifiableTransitionFunctionBatchWitnessVar { active_g, steps_g })
+    }
+}
+
+/// the proof for an answer of the client function
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct QueryProof<VC: VerifiableTransitionFunctionConfig> {
+    /// Addresses read by the client function
+    pub raddr: Vec<<VC::TF as TransitionFunction<VC::F>>::Addr>,
+    /// Data read by the client function
+    pub rdata: Vec<<VC::TF as TransitionFunction<VC::F>>::Data>,
+    /// Merkle state tree lookup proofs
+    pub read_proof: <VC::MTState as MT<
+        VC::F,
+        <VC::TF as TransitionFunction<VC::F>>::Addr,
+        <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+    >>::LookupProof,
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> Clone for QueryProof<VC> {
+    fn clone(&self) -> Self {
+        QueryProof {
+            raddr: self.raddr.clone(),
+            rdata: self.rdata.clone(),
+            read_proof: self.read_proof.clone(),
+        }
+    }
+}
+
+/// A leaf of the state tree, which is encoded by the transition function.
+pub(crate) struct StateLeaf<VC: VerifiableTransitionFunctionConfig>(
+    pub(crate) <VC::TF as TransitionFunction<VC::F>>::Data,
+);
+
+impl<VC: VerifiableTransitionFunctionConfig> StateLeaf<VC> {
+    /// Returns the data as leaves of the state tree.
+    pub(crate) fn wrap(data: &[<VC::TF as TransitionFunction<VC::F>>::Data]) -> Vec<Self> {
+        data.iter()
+            .map(|data_item| StateLeaf(data_item.clone()))
+            .collect()
+    }
+
+    /// Returns the map of the state as leaves of the state tree.
+    pub(crate) fn wrap_map(
+        map: &BTreeMap<
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::Data,
+        >,
+    ) -> BTreeMap<<VC::TF as TransitionFunction<VC::F>>::Addr, Self> {
+        map.iter()
+            .map(|(addr, data)| (addr.clone(), StateLeaf(data.clone())))
+            .collect()
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> Clone for StateLeaf<VC> {
+    fn clone(&self) -> Self {
+        StateLeaf(self.0.clone())
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> Default for StateLeaf<VC> {
+    fn default() -> Self {
+        StateLeaf(<VC::TF as TransitionFunction<VC::F>>::Data::default())
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> MerkleLeaf<VC::F> for StateLeaf<VC> {
+    fn encode_leaf(&self) -> Result<LeafEncoding<VC::F>, Error> {
+        VC::TF::encode_data(&self.0)
+    }
+}
+
+/// The gadgets for a leaf of the state tree, which is encoded by the transition function.
+pub(crate) struct StateLeafVar<'a, VC: VerifiableTransitionFunctionConfig>(
+    pub(crate) &'a <VC::TF as TransitionFunction<VC::F>>::DataVar,
+);
+
+impl<'a, VC: VerifiableTransitionFunctionConfig> StateLeafVar<'a, VC> {
+    /// Returns the data gadgets as leaves of the state tree.
+    pub(crate) fn wrap(data_g: &'a [<VC::TF as TransitionFunction<VC::F>>::DataVar]) -> Vec<Self> {
+        data_g.iter().map(StateLeafVar).collect()
+    }
+}
+
+impl<'a, VC: VerifiableTransitionFunctionConfig> MerkleLeafGadget<VC::F> for StateLeafVar<'a, VC> {
+    fn encode_leaf(&self) -> Result<LeafEncodingVar<VC::F>, SynthesisError> {
+        VC::TF::encode_data_gadget(self.0)
+    }
+}
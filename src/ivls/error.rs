@@ -0,0 +1,89 @@
+use crate::Error;
+use ark_serialize::SerializationError;
+use ark_std::{format, string::ToString};
+
+#[derive(Debug)]
+/// error type for IVLS
+pub enum IVLSError {
+    /// the aux state has not been initialized, or misses a tree
+    UninitializedAuxState,
+    /// the proof is missing
+    MissingProof,
+    /// the commitment is missing
+    MissingCommitment,
+    /// a proof or a commitment is given for t = 0, where there is none
+    UnexpectedProof,
+    /// the PCD key is missing
+    MissingKey,
+    /// the length does not match the expected one
+    LengthMismatch(usize, usize),
+    /// the trees in the aux state do not match the commitment
+    TreeInconsistency,
+    /// the range of times is empty or includes t = 0
+    InvalidRange(u64, u64),
+    /// the commitment at this time is not in the history tree, as the time is in the middle of a
+    /// batch and only the start of each batch is recorded
+    UnrecordedTime(u64),
+    /// the state tree at this time is not kept, as it is older than the last `STATE_VERSIONS`
+    /// steps
+    StateNotKept(u64),
+    /// an error of a building block, i.e., the Merkle trees, the PCD or the transition function
+    BuildingBlock(Error),
+    /// an error while reading or writing a snapshot or a bundle
+    Serialization(SerializationError),
+}
+
+impl core::fmt::Display for IVLSError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            IVLSError::UninitializedAuxState => "uninitialized aux state".to_string(),
+            IVLSError::MissingProof => "missing proof".to_string(),
+            IVLSError::MissingCommitment => "missing commitment".to_string(),
+            IVLSError::UnexpectedProof => "unexpected proof at t = 0".to_string(),
+            IVLSError::MissingKey => "missing key".to_string(),
+            IVLSError::LengthMismatch(len, expected) => {
+                format!("length {} does not match the expected {}", len, expected)
+            }
+            IVLSError::TreeInconsistency => "the trees do not match the commitment".to_string(),
+            IVLSError::InvalidRange(t1, t2) => format!("invalid range from {} to {}", t1, t2),
+            IVLSError::UnrecordedTime(t) => {
+                format!(
+                    "the commitment at {} is in the middle of a batch and not recorded",
+                    t
+                )
+            }
+            IVLSError::StateNotKept(t) => format!("the state tree at {} is not kept", t),
+            IVLSError::BuildingBlock(err) => format!("{}", err),
+            IVLSError::Serialization(err) => format!("serialization error: {}", err),
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl ark_std::error::Error for IVLSError {
+    fn source(&self) -> Option<&(dyn ark_std::error::Error + 'static)> {
+        match self {
+            IVLSError::BuildingBlock(err) => Some(err.as_ref()),
+            IVLSError::Serialization(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for IVLSError {
+    fn from(err: Error) -> Self {
+        IVLSError::BuildingBlock(err)
+    }
+}
+
+impl From<SerializationError> for IVLSError {
+    fn from(err: SerializationError) -> Self {
+        IVLSError::Serialization(err)
+    }
+}
+
+impl From<ark_std::io::Error> for IVLSError {
+    fn from(err: ark_std::io::Error) -> Self {
+        IVLSError::Serialization(SerializationError::IoError(err))
+    }
+}
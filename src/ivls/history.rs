@@ -2,11 +2,13 @@ use crate::{
     building_blocks::mt::MT,
     gadgets::UInt64,
     ivls::{
-        data_structures::Commitment, state::AuxState,
+        data_structures::{Commitment, StateLeaf},
+        error::IVLSError,
+        state::AuxState,
         transition_function::VerifiableTransitionFunctionConfig,
     },
     ledger_system::{state::State, transition_function::TransitionFunction},
-    Error, Vec,
+    Vec,
 };
 
 /// vH
@@ -22,21 +24,37 @@ pub struct VerifiableHistory<VC: VerifiableTransitionFunctionConfig> {
     ),
 }
 
+impl<VC: VerifiableTransitionFunctionConfig> Clone for VerifiableHistory<VC> {
+    fn clone(&self) -> Self {
+        VerifiableHistory::<VC> {
+            pp_mt: self.pp_mt.clone(),
+        }
+    }
+}
+
 impl<VC: VerifiableTransitionFunctionConfig> VerifiableHistory<VC> {
-    /// History.prove
+    /// History.prove, where `t` must be the start of a batch if the history tree only records
+    /// those (i.e., without `HISTORY_PER_TX` and with a `BATCH_SIZE` above one), since a `t` in the
+    /// middle of a batch would open an empty leaf
     pub fn prove(
         &self,
         _state: &State<VC::F, VC::TF>,
         aux_state: &AuxState<VC>,
         t: u64,
-    ) -> Result<Option<<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof>, Error> {
+    ) -> Result<Option<<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof>, IVLSError>
+    {
         if t == 0 {
             Ok(None)
         } else {
+            Self::check_recorded(aux_state, t)?;
+
             let mut t_vec: Vec<u64> = Vec::with_capacity(1);
             t_vec.push(t);
 
-            let tree = aux_state.tree_history.as_ref().unwrap();
+            let tree = aux_state
+                .tree_history
+                .as_ref()
+                .ok_or(IVLSError::UninitializedAuxState)?;
 
             let proof = VC::MTHistory::lookup(&self.pp_mt.1, tree, &t_vec)?;
 
@@ -44,36 +62,190 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableHistory<VC> {
         }
     }
 
-    /// History.verify
+    /// History.verify, where missing or unexpected inputs are errors and a wrong claim is `Ok(false)`
     pub fn verify(
         &self,
         cm: &Commitment<VC>,
         t: u64,
         cm_t: &Option<Commitment<VC>>,
         proof: &Option<<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof>,
-    ) -> Result<bool, Error> {
+    ) -> Result<bool, IVLSError> {
         if t == 0 {
-            if cm_t.is_none() && proof.is_none() {
-                Ok(true)
-            } else {
-                Ok(false)
+            if cm_t.is_some() || proof.is_some() {
+                return Err(IVLSError::UnexpectedProof);
             }
+
+            Ok(true)
         } else {
             let mut t_vec: Vec<u64> = Vec::with_capacity(1);
             t_vec.push(t);
 
+            let cm_t = cm_t.as_ref().ok_or(IVLSError::MissingCommitment)?;
+
             let mut cm_vec: Vec<Commitment<VC>> = Vec::with_capacity(1);
-            cm_vec.push(cm_t.clone().unwrap());
+            cm_vec.push(cm_t.clone());
 
-            let lookup_proof = proof.as_ref().unwrap();
+            let lookup_proof = proof.as_ref().ok_or(IVLSError::MissingProof)?;
 
-            VC::MTHistory::verify_lookup(
+            Ok(VC::MTHistory::verify_lookup(
                 &self.pp_mt.1,
                 &cm.history_rh,
                 &t_vec,
                 &cm_vec,
                 lookup_proof,
-            )
+            )?)
+        }
+    }
+
+    /// History.prove for all the commitments from time `t1` to `t2` (inclusive), as a single
+    /// multi-opening of the history tree, where `t2` must be before the current step and, as in
+    /// `prove`, every time in the range must be recorded
+    pub fn prove_range(
+        &self,
+        _state: &State<VC::F, VC::TF>,
+        aux_state: &AuxState<VC>,
+        t1: u64,
+        t2: u64,
+    ) -> Result<<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::MultiLookupProof, IVLSError> {
+        // Only the steps already in the history are accepted, which also bounds the allocation.
+        if t1 == 0 || t1 > t2 || t2 >= aux_state.t {
+            return Err(IVLSError::InvalidRange(t1, t2));
+        }
+
+        let t_vec: Vec<u64> = (t1..=t2).collect();
+        for t in t_vec.iter() {
+            Self::check_recorded(aux_state, *t)?;
+        }
+
+        let tree = aux_state
+            .tree_history
+            .as_ref()
+            .ok_or(IVLSError::UninitializedAuxState)?;
+
+        Ok(VC::MTHistory::multi_lookup(&self.pp_mt.1, tree, &t_vec)?)
+    }
+
+    /// History.verify for all the commitments from time `t1` to `t2` (inclusive), where `cm_range`
+    /// lists the commitments in order
+    pub fn verify_range(
+        &self,
+        cm: &Commitment<VC>,
+        t1: u64,
+        t2: u64,
+        cm_range: &[Commitment<VC>],
+        proof: &<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::MultiLookupProof,
+    ) -> Result<bool, IVLSError> {
+        if t1 == 0 || t1 > t2 {
+            return Err(IVLSError::InvalidRange(t1, t2));
+        }
+
+        // Check the length before building the steps, so that an untrusted t2 allocates nothing.
+        if cm_range.len() as u64 != t2 - t1 + 1 {
+            return Err(IVLSError::LengthMismatch(
+                cm_range.len(),
+                (t2 - t1 + 1) as usize,
+            ));
+        }
+
+        let t_vec: Vec<u64> = (t1..=t2).collect();
+
+        Ok(VC::MTHistory::verify_multi_lookup(
+            &self.pp_mt.1,
+            &cm.history_rh,
+            &t_vec,
+            cm_range,
+            proof,
+        )?)
+    }
+
+    /// History.prove for the data at the addresses at time `t`, as the proof of the commitment
+    /// at time `t` and a lookup in the state tree of that time, where `t` must be before the
+    /// current step, as in `prove`, recorded, and among the last `STATE_VERSIONS` steps
+    pub fn prove_state_at(
+        &self,
+        state: &State<VC::F, VC::TF>,
+        aux_state: &AuxState<VC>,
+        t: u64,
+        addr: &[<VC::TF as TransitionFunction<VC::F>>::Addr],
+    ) -> Result<
+        (
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof,
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::LookupProof,
+        ),
+        IVLSError,
+    > {
+        if t == 0 || t >= aux_state.t {
+            return Err(IVLSError::InvalidRange(t, t));
+        }
+
+        let history_proof = self
+            .prove(state, aux_state, t)?
+            .ok_or(IVLSError::MissingProof)?;
+
+        if t.saturating_add(VC::STATE_VERSIONS) < aux_state.t {
+            return Err(IVLSError::StateNotKept(t));
+        }
+
+        let tree = aux_state
+            .tree_state
+            .as_ref()
+            .ok_or(IVLSError::UninitializedAuxState)?;
+
+        let read_proof = VC::MTState::lookup_at(&self.pp_mt.0, tree, t, addr)?;
+
+        Ok((history_proof, read_proof))
+    }
+
+    /// History.verify for the data at the addresses at time `t`, where `cm_t` is the commitment
+    /// at time `t`, proven by `history_proof` against `cm`, and `data` is checked against its
+    /// state tree
+    pub fn verify_state_at(
+        &self,
+        cm: &Commitment<VC>,
+        t: u64,
+        cm_t: &Commitment<VC>,
+        addr: &[<VC::TF as TransitionFunction<VC::F>>::Addr],
+        data: &[<VC::TF as TransitionFunction<VC::F>>::Data],
+        proof: &(
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof,
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::LookupProof,
+        ),
+    ) -> Result<bool, IVLSError> {
+        if t == 0 {
+            return Err(IVLSError::InvalidRange(t, t));
         }
+
+        let (history_proof, read_proof) = proof;
+        if !self.verify(cm, t, &Some(cm_t.clone()), &Some(history_proof.clone()))? {
+            return Ok(false);
+        }
+
+        Ok(VC::MTState::verify_lookup(
+            &self.pp_mt.0,
+            &cm_t.state_rh,
+            addr,
+            &StateLeaf::<VC>::wrap(data),
+            read_proof,
+        )?)
+    }
+
+    /// Returns an error if the commitment at time `t` is not in the history tree.
+    fn check_recorded(aux_state: &AuxState<VC>, t: u64) -> Result<(), IVLSError> {
+        if !VC::HISTORY_PER_TX
+            && VC::BATCH_SIZE > 1
+            && aux_state.batch_starts.binary_search(&t).is_err()
+        {
+            return Err(IVLSError::UnrecordedTime(t));
+        }
+
+        Ok(())
     }
 }
@@ -1,8 +1,12 @@
 /// other data structures
 pub mod data_structures;
+/// errors
+pub mod error;
 /// History
 pub mod history;
 /// vS
 pub mod state;
 /// vF
 pub mod transition_function;
+/// the verifier-only handle
+pub mod verifier;
@@ -3,13 +3,18 @@ use crate::{
     building_blocks::mt::MT,
     gadgets::UInt64,
     ivls::{
-        data_structures::{Commitment, VerifiableTransitionFunctionMsg},
+        data_structures::{Commitment, QueryProof, StateLeaf, VerifiableTransitionFunctionMsg},
+        error::IVLSError,
         transition_function::VerifiableTransitionFunctionConfig,
     },
-    ledger_system::{state::State, transition_function::TransitionFunction},
-    Error,
+    ledger_system::{
+        client_function::ClientFunction, state::State, transition_function::TransitionFunction,
+    },
+    Vec,
 };
 use ark_pcd::PCD;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::io::{Read, Write};
 
 /// A
 pub struct AuxState<VC: VerifiableTransitionFunctionConfig> {
@@ -19,6 +24,8 @@ pub struct AuxState<VC: VerifiableTransitionFunctionConfig> {
     pub cm: Option<Commitment<VC>>,
     /// the current proof
     pub proof: Option<<VC::I as PCD<VC::F>>::Proof>,
+    /// the step count at the start of each batch, in increasing order
+    pub batch_starts: Vec<u64>,
     /// Merkle tree for state
     pub tree_state: Option<
         <VC::MTState as MT<
@@ -37,6 +44,7 @@ impl<VC: VerifiableTransitionFunctionConfig> Default for AuxState<VC> {
             t: 0,
             cm: None,
             proof: None,
+            batch_starts: Vec::new(),
             tree_state: None,
             tree_history: None,
         }
@@ -53,14 +61,151 @@ impl<VC: VerifiableTransitionFunctionConfig> AuxState<VC> {
             <VC::TF as TransitionFunction<VC::F>>::AddrVar,
         >>::PublicParameters,
         pp_history: &<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::PublicParameters,
-    ) -> Result<(), Error> {
-        self.tree_state = Some(VC::MTState::new::<
-            <VC::TF as TransitionFunction<VC::F>>::Data,
-        >(pp_state)?);
-        self.tree_history = Some(VC::MTHistory::new::<Commitment<VC>>(pp_history)?);
+    ) -> Result<(), IVLSError> {
+        let (tree_state, tree_history) = Self::new_trees(pp_state, pp_history)?;
+        self.tree_state = Some(tree_state);
+        self.tree_history = Some(tree_history);
 
         Ok(())
     }
+
+    /// Returns the empty state and history trees of step 0.
+    pub(crate) fn new_trees(
+        pp_state: &<VC::MTState as MT<
+            VC::F,
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+        >>::PublicParameters,
+        pp_history: &<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::PublicParameters,
+    ) -> Result<
+        (
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::T,
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::T,
+        ),
+        IVLSError,
+    > {
+        let mut tree_state = VC::MTState::new::<StateLeaf<VC>>(pp_state)?;
+        /* if so configured, the state tree keeps the state of the last steps, from step 0 */
+        if VC::STATE_VERSIONS > 0 {
+            VC::MTState::enable_versioning(pp_state, &mut tree_state, 0)?;
+        }
+        let tree_history = VC::MTHistory::new::<Commitment<VC>>(pp_history)?;
+
+        Ok((tree_state, tree_history))
+    }
+
+    /// write a snapshot of the aux state
+    pub fn snapshot<W: Write>(&self, mut writer: W) -> Result<(), IVLSError>
+    where
+        <VC::I as PCD<VC::F>>::Proof: CanonicalSerialize,
+    {
+        self.t.serialize(&mut writer)?;
+        self.cm.serialize(&mut writer)?;
+        self.proof.serialize(&mut writer)?;
+        self.batch_starts.serialize(&mut writer)?;
+
+        match &self.tree_state {
+            Some(tree) => {
+                true.serialize(&mut writer)?;
+                VC::MTState::serialize_tree(tree, &mut writer)?;
+            }
+            None => false.serialize(&mut writer)?,
+        }
+
+        match &self.tree_history {
+            Some(tree) => {
+                true.serialize(&mut writer)?;
+                VC::MTHistory::serialize_tree(tree, &mut writer)?;
+            }
+            None => false.serialize(&mut writer)?,
+        }
+
+        Ok(())
+    }
+
+    /// restore the aux state from a snapshot, to be checked by `VerifiableState::verify_all`
+    pub fn restore<R: Read>(
+        pp_state: &<VC::MTState as MT<
+            VC::F,
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+        >>::PublicParameters,
+        pp_history: &<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::PublicParameters,
+        mut reader: R,
+    ) -> Result<Self, IVLSError>
+    where
+        <VC::I as PCD<VC::F>>::Proof: CanonicalDeserialize,
+    {
+        let t = u64::deserialize(&mut reader)?;
+        let cm = Option::<Commitment<VC>>::deserialize(&mut reader)?;
+        let proof = Option::<<VC::I as PCD<VC::F>>::Proof>::deserialize(&mut reader)?;
+        let batch_starts = Vec::<u64>::deserialize(&mut reader)?;
+        /* a batch has at least one transaction, so the starts increase and are before t */
+        if batch_starts.windows(2).any(|w| w[0] >= w[1])
+            || batch_starts.last().map_or(false, |last| *last >= t)
+        {
+            return Err(SerializationError::InvalidData.into());
+        }
+
+        let tree_state = if bool::deserialize(&mut reader)? {
+            Some(VC::MTState::deserialize_tree::<StateLeaf<VC>, _>(
+                pp_state,
+                &mut reader,
+            )?)
+        } else {
+            None
+        };
+
+        let tree_history = if bool::deserialize(&mut reader)? {
+            Some(VC::MTHistory::deserialize_tree::<Commitment<VC>, _>(
+                pp_history,
+                &mut reader,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(AuxState {
+            t,
+            cm,
+            proof,
+            batch_starts,
+            tree_state,
+            tree_history,
+        })
+    }
+
+    /// write a snapshot of the aux state to a file
+    #[cfg(feature = "std")]
+    pub fn snapshot_to_file<T: AsRef<std::path::Path>>(&self, path: T) -> Result<(), IVLSError>
+    where
+        <VC::I as PCD<VC::F>>::Proof: CanonicalSerialize,
+    {
+        let file = std::fs::File::create(path)?;
+        self.snapshot(std::io::BufWriter::new(file))
+    }
+
+    /// restore the aux state from a snapshot file
+    #[cfg(feature = "std")]
+    pub fn restore_from_file<T: AsRef<std::path::Path>>(
+        pp_state: &<VC::MTState as MT<
+            VC::F,
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+        >>::PublicParameters,
+        pp_history: &<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::PublicParameters,
+        path: T,
+    ) -> Result<Self, IVLSError>
+    where
+        <VC::I as PCD<VC::F>>::Proof: CanonicalDeserialize,
+    {
+        let file = std::fs::File::open(path)?;
+        Self::restore(pp_state, pp_history, std::io::BufReader::new(file))
+    }
 }
 
 /// vS
@@ -78,6 +223,15 @@ pub struct VerifiableState<VC: VerifiableTransitionFunctionConfig> {
     pub ivk: <VC::I as PCD<VC::F>>::VerifyingKey,
 }
 
+impl<VC: VerifiableTransitionFunctionConfig> Clone for VerifiableState<VC> {
+    fn clone(&self) -> Self {
+        VerifiableState::<VC> {
+            pp_mt: self.pp_mt.clone(),
+            ivk: self.ivk.clone(),
+        }
+    }
+}
+
 impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
     /// vs.info
     pub fn info(
@@ -90,7 +244,7 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
             Option<Commitment<VC>>,
             Option<<VC::I as PCD<VC::F>>::Proof>,
         ),
-        Error,
+        IVLSError,
     > {
         Ok((aux_state.t, aux_state.cm.clone(), aux_state.proof.clone()))
     }
@@ -100,16 +254,23 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
         &self,
         state: &State<VC::F, VC::TF>,
         cm: &Option<Commitment<VC>>,
-    ) -> Result<bool, Error> {
-        if cm.is_none() {
-            Ok(state.map.is_empty())
-        } else {
-            let cm_ok = cm.as_ref().unwrap();
-
-            let state_tree = VC::MTState::_new_with_map(&self.pp_mt.0, &state.map)?;
-            let state_tree_digest = VC::MTState::root(&self.pp_mt.0, &state_tree)?;
+    ) -> Result<bool, IVLSError> {
+        match cm {
+            None => Ok(state.map.is_empty()),
+            Some(cm_ok) => {
+                /* an empty state can only match the root of the empty tree */
+                let state_tree = if state.map.is_empty() {
+                    VC::MTState::new::<StateLeaf<VC>>(&self.pp_mt.0)?
+                } else {
+                    VC::MTState::_new_with_map(
+                        &self.pp_mt.0,
+                        &StateLeaf::<VC>::wrap_map(&state.map),
+                    )?
+                };
+                let state_tree_digest = VC::MTState::root(&self.pp_mt.0, &state_tree)?;
 
-            Ok(state_tree_digest == cm_ok.state_rh)
+                Ok(state_tree_digest == cm_ok.state_rh)
+            }
         }
     }
 
@@ -118,7 +279,7 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
         &self,
         state: &State<VC::F, VC::TF>,
         aux_state: &AuxState<VC>,
-    ) -> Result<bool, Error> {
+    ) -> Result<bool, IVLSError> {
         if aux_state.cm.is_none() {
             if state.map.is_empty()
                 && aux_state.proof.is_none()
@@ -130,7 +291,10 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
                 Ok(false)
             }
         } else {
-            let cm = aux_state.cm.as_ref().unwrap();
+            let cm = aux_state
+                .cm
+                .as_ref()
+                .ok_or(IVLSError::UninitializedAuxState)?;
             let cm_state_rh = cm.state_rh.clone();
             let cm_history_rh = cm.history_rh.clone();
 
@@ -145,7 +309,7 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
                 cm: cm.clone(),
             };
 
-            let proof = aux_state.proof.as_ref().unwrap();
+            let proof = aux_state.proof.as_ref().ok_or(IVLSError::MissingProof)?;
             let ivc_result =
                 VC::I::verify::<VerifiableTransitionFunction<VC>>(&self.ivk, &z, proof)?;
 
@@ -153,7 +317,10 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
                 return Ok(false);
             }
 
-            let tree_state = aux_state.tree_state.as_ref().unwrap();
+            let tree_state = aux_state
+                .tree_state
+                .as_ref()
+                .ok_or(IVLSError::UninitializedAuxState)?;
 
             let state_tree_well_formed = VC::MTState::validate(&self.pp_mt.0, tree_state)?;
 
@@ -167,7 +334,10 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
                 return Ok(false);
             }
 
-            let tree_history = aux_state.tree_history.as_ref().unwrap();
+            let tree_history = aux_state
+                .tree_history
+                .as_ref()
+                .ok_or(IVLSError::UninitializedAuxState)?;
 
             let history_tree_well_formed = VC::MTHistory::validate(&self.pp_mt.1, tree_history)?;
 
@@ -184,4 +354,54 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableState<VC> {
             Ok(true)
         }
     }
+
+    /// vS.query
+    pub fn prove_query<CF: ClientFunction<VC::F, VC::TF>>(
+        &self,
+        state: &State<VC::F, VC::TF>,
+        aux_state: &AuxState<VC>,
+        query: &CF::Query,
+    ) -> Result<(CF::Answer, QueryProof<VC>), IVLSError> {
+        let (answer, raddr, rdata) = CF::run(state, query)?;
+
+        let read_proof = match aux_state.tree_state.as_ref() {
+            Some(tree_state) => VC::MTState::lookup(&self.pp_mt.0, tree_state, &raddr)?,
+            None => {
+                let empty_tree_state = VC::MTState::new::<StateLeaf<VC>>(&self.pp_mt.0)?;
+                VC::MTState::lookup(&self.pp_mt.0, &empty_tree_state, &raddr)?
+            }
+        };
+
+        Ok((
+            answer,
+            QueryProof {
+                raddr,
+                rdata,
+                read_proof,
+            },
+        ))
+    }
+
+    /// vS.verify_query
+    pub fn verify_query<CF: ClientFunction<VC::F, VC::TF>>(
+        &self,
+        cm: &Commitment<VC>,
+        query: &CF::Query,
+        answer: &CF::Answer,
+        proof: &QueryProof<VC>,
+    ) -> Result<bool, IVLSError> {
+        let lookup_result = VC::MTState::verify_lookup(
+            &self.pp_mt.0,
+            &cm.state_rh,
+            &proof.raddr,
+            &StateLeaf::<VC>::wrap(&proof.rdata),
+            &proof.read_proof,
+        )?;
+
+        if !lookup_result {
+            return Ok(false);
+        }
+
+        Ok(CF::verify(query, answer, &proof.raddr, &proof.rdata)?)
+    }
 }
@@ -4,13 +4,16 @@ use crate::{
     gadgets::{Boolean, CondSelectGadget, EqGadget, UInt64},
     ivls::{
         data_structures::{
-            Commitment, VerifiableTransitionFunctionMsg, VerifiableTransitionFunctionMsgVar,
-            VerifiableTransitionFunctionWitness, VerifiableTransitionFunctionWitnessVar,
+            Commitment, CommitmentVar, StateLeaf, StateLeafVar,
+            VerifiableTransitionFunctionBatchWitness, VerifiableTransitionFunctionBatchWitnessVar,
+            VerifiableTransitionFunctionMsg, VerifiableTransitionFunctionMsgVar,
+            VerifiableTransitionFunctionWitness,
         },
+        error::IVLSError,
         state::AuxState,
     },
     ledger_system::{state::State, transition_function::TransitionFunction},
-    Error, PrimeField, RngCore, Sized, SynthesisError, Vec,
+    PrimeField, RngCore, Sized, SynthesisError, Vec,
 };
 use ark_pcd::{PCDPredicate, PCD};
 use ark_relations::r1cs::ConstraintSystemRef;
@@ -35,6 +38,21 @@ pub trait VerifiableTransitionFunctionConfig: Sized {
 
     /// The PCD engine
     type I: PCD<Self::F>;
+
+    /// the maximal number of transactions applied in one PCD step
+    const BATCH_SIZE: usize = 1;
+    /// whether the history tree records the commitment before every transaction in a batch,
+    /// instead of only the one before the batch
+    ///
+    /// if not, with a `BATCH_SIZE` above one, the history cannot prove the commitments in the
+    /// middle of a batch
+    const HISTORY_PER_TX: bool = false;
+    /// the number of past steps whose state tree is kept for `VerifiableHistory::prove_state_at`,
+    /// where 0 disables the versioning of the state tree and `u64::MAX` keeps every step
+    ///
+    /// the state tree keeps the replaced nodes of the kept steps, which grow with the number of
+    /// writes in them
+    const STATE_VERSIONS: u64 = 0;
 }
 
 /// vF
@@ -82,125 +100,310 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableTransitionFunction<VC> {
         aux_state: &mut AuxState<VC>,
         tx: &<VC::TF as TransitionFunction<VC::F>>::Tx,
         rng: &mut R,
-    ) -> Result<<VC::TF as TransitionFunction<VC::F>>::Output, Error> {
-        let (y, raddr, rdata, waddr, wdata, cs_witness) =
-            <VC::TF as TransitionFunction<VC::F>>::run(state, &tx)?;
-
-        let mut z_old: Option<VerifiableTransitionFunctionMsg<VC>> = None;
-        let mut ivc_proof_old: Option<<VC::I as PCD<VC::F>>::Proof> = None;
-        let read_proof: <VC::MTState as MT<
+    ) -> Result<<VC::TF as TransitionFunction<VC::F>>::Output, IVLSError>
+    where
+        <VC::MTState as MT<
             VC::F,
             <VC::TF as TransitionFunction<VC::F>>::Addr,
             <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-        >>::LookupProof;
-        let insert_proof: <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::ModifyProof;
-        let rh_history_new;
-        let t_new: u64;
-
-        if aux_state.t == 0 {
-            /* the base case */
-            t_new = 1;
+        >>::T: Clone,
+        <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::T: Clone,
+    {
+        let mut y = self.run_batch(state, aux_state, &[tx.clone()], rng)?;
+        Ok(y.remove(0))
+    }
 
-            aux_state.init(&self.pp_mt.0, &self.pp_mt.1)?;
+    /// vF.run over a batch of at most `VC::BATCH_SIZE` transactions in one PCD step
+    ///
+    /// the transactions are run on a copy of the state and the updates are applied to copies of
+    /// the trees, which replace the originals only once the batch is proven, so any error leaves
+    /// the state and the aux state untouched
+    pub fn run_batch<R: RngCore + CryptoRng>(
+        &mut self,
+        state: &mut State<VC::F, VC::TF>,
+        aux_state: &mut AuxState<VC>,
+        txs: &[<VC::TF as TransitionFunction<VC::F>>::Tx],
+        rng: &mut R,
+    ) -> Result<Vec<<VC::TF as TransitionFunction<VC::F>>::Output>, IVLSError>
+    where
+        <VC::MTState as MT<
+            VC::F,
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+        >>::T: Clone,
+        <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::T: Clone,
+    {
+        if txs.is_empty() || txs.len() > VC::BATCH_SIZE {
+            return Err(IVLSError::LengthMismatch(txs.len(), VC::BATCH_SIZE));
+        }
 
-            let tree_history = aux_state.tree_history.as_ref().unwrap();
-            rh_history_new = <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::root(
-                &self.pp_mt.1,
-                &tree_history,
+        let ipk = self.ipk.clone().ok_or(IVLSError::MissingKey)?;
+
+        /* run the transactions on a copy of the state and check their accesses,
+        before anything is changed */
+        let mut new_state = state.clone();
+        let mut runs = Vec::with_capacity(txs.len());
+        for tx in txs.iter() {
+            let (y_i, mut raddr, mut rdata, mut waddr, mut wdata, cs_witness) =
+                <VC::TF as TransitionFunction<VC::F>>::run(&mut new_state, tx)?;
+
+            /* pad the accesses to the maximal numbers, the padding is skipped by the gadgets */
+            let num_reads = raddr.len();
+            let ractive = pad_accesses(
+                &mut raddr,
+                &mut rdata,
+                <VC::TF as TransitionFunction<VC::F>>::NUM_READS,
+            )?;
+            let num_writes = waddr.len();
+            let wactive = pad_accesses(
+                &mut waddr,
+                &mut wdata,
+                <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES,
             )?;
 
-            let tree_state = aux_state.tree_state.as_ref().unwrap();
-            read_proof = <VC::MTState as MT<
-                VC::F,
-                <VC::TF as TransitionFunction<VC::F>>::Addr,
-                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-            >>::lookup(&self.pp_mt.0, &tree_state, &raddr)?;
+            runs.push((
+                y_i, raddr, rdata, ractive, num_reads, waddr, wdata, wactive, num_writes,
+                cs_witness,
+            ));
+        }
+
+        let mut z_old: Option<VerifiableTransitionFunctionMsg<VC>> = None;
+        let mut ivc_proof_old: Option<<VC::I as PCD<VC::F>>::Proof> = None;
+        let mut cm_cur: Commitment<VC>;
+        let mut tree_state;
+        let mut tree_history;
 
-            insert_proof =
-                <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::default_modify_proof(1)?;
+        if aux_state.t == 0 {
+            /* the base case */
+            let (tree_state_0, tree_history_0) =
+                AuxState::<VC>::new_trees(&self.pp_mt.0, &self.pp_mt.1)?;
+            tree_state = tree_state_0;
+            tree_history = tree_history_0;
+
+            cm_cur = Commitment::<VC> {
+                state_rh: self.empty_digest.0.clone(),
+                history_rh: self.empty_digest.1.clone(),
+            };
         } else {
             /* not the base case */
-            let t_old = aux_state.t;
-            let cm_old = aux_state.cm.as_ref().unwrap().clone();
+            cm_cur = aux_state
+                .cm
+                .clone()
+                .ok_or(IVLSError::UninitializedAuxState)?;
             z_old = Some(VerifiableTransitionFunctionMsg::<VC> {
-                t: t_old,
-                cm: cm_old.clone(),
+                t: aux_state.t,
+                cm: cm_cur.clone(),
             });
-            ivc_proof_old = Some(aux_state.proof.as_ref().unwrap().clone());
+            ivc_proof_old = Some(aux_state.proof.clone().ok_or(IVLSError::MissingProof)?);
+
+            /* check the trees, and update copies of them */
+            tree_state = aux_state
+                .tree_state
+                .as_ref()
+                .ok_or(IVLSError::UninitializedAuxState)?
+                .clone();
+            tree_history = aux_state
+                .tree_history
+                .as_ref()
+                .ok_or(IVLSError::UninitializedAuxState)?
+                .clone();
+
+            if VC::MTState::root(&self.pp_mt.0, &tree_state)? != cm_cur.state_rh
+                || VC::MTHistory::root(&self.pp_mt.1, &tree_history)? != cm_cur.history_rh
+            {
+                return Err(IVLSError::TreeInconsistency);
+            }
+        }
 
-            t_new = t_old + 1;
+        let mut t_cur = aux_state.t;
+        let mut y = Vec::with_capacity(txs.len());
+        let mut active = Vec::with_capacity(VC::BATCH_SIZE);
+        let mut steps = Vec::with_capacity(VC::BATCH_SIZE);
+
+        for (i, (tx, run)) in txs.iter().zip(runs.into_iter()).enumerate() {
+            let (
+                y_i,
+                raddr,
+                rdata,
+                ractive,
+                num_reads,
+                waddr,
+                wdata,
+                wactive,
+                num_writes,
+                cs_witness,
+            ) = run;
+
+            /* the read proof is generated for the padded number of reads,
+            so that it has the shape of the dummy proof used in the setup */
+            let read_proof = <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::lookup_padded(
+                &self.pp_mt.0,
+                &tree_state,
+                &raddr[..num_reads],
+                <VC::TF as TransitionFunction<VC::F>>::NUM_READS,
+            )?;
+
+            let (rh_state_new, mut write_proof) = <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::_modify_and_apply(
+                &self.pp_mt.0,
+                &mut tree_state,
+                &waddr[..num_writes],
+                &StateLeaf::<VC>::wrap(&wdata[..num_writes]),
+            )?;
 
-            let tree_state = aux_state.tree_state.as_ref().unwrap();
-            read_proof = <VC::MTState as MT<
+            <VC::MTState as MT<
                 VC::F,
                 <VC::TF as TransitionFunction<VC::F>>::Addr,
                 <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-            >>::lookup(&self.pp_mt.0, &tree_state, &raddr)?;
+            >>::pad_modify_proof(
+                &mut write_proof,
+                <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES,
+            )?;
 
-            let mut history_addr_vec = Vec::with_capacity(1);
-            history_addr_vec.push(t_old);
+            /* the state after this transaction is kept as the version of the next step */
+            if VC::STATE_VERSIONS > 0 {
+                <VC::MTState as MT<
+                    VC::F,
+                    <VC::TF as TransitionFunction<VC::F>>::Addr,
+                    <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+                >>::set_version(&self.pp_mt.0, &mut tree_state, t_cur + 1)?;
+            }
 
-            let mut history_data_vec = Vec::with_capacity(1);
-            history_data_vec.push(cm_old);
+            /* the history tree records the commitment before the first transaction in the batch,
+            or before every transaction if so configured */
+            let (rh_history_new, insert_proof) = if t_cur != 0 && (i == 0 || VC::HISTORY_PER_TX) {
+                let mut history_addr_vec = Vec::with_capacity(1);
+                history_addr_vec.push(t_cur);
+
+                let mut history_data_vec = Vec::with_capacity(1);
+                history_data_vec.push(cm_cur.clone());
 
-            let mut tree_history = aux_state.tree_history.as_mut().unwrap();
-            let insert_result =
                 <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::_modify_and_apply(
                     &self.pp_mt.1,
                     &mut tree_history,
                     &history_addr_vec,
                     &history_data_vec,
-                )?;
-            rh_history_new = insert_result.0;
-            insert_proof = insert_result.1;
+                )?
+            } else {
+                (
+                    cm_cur.history_rh.clone(),
+                    <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::default_modify_proof(1)?,
+                )
+            };
+
+            cm_cur = Commitment::<VC> {
+                state_rh: rh_state_new,
+                history_rh: rh_history_new,
+            };
+            t_cur += 1;
+
+            y.push(y_i);
+            active.push(true);
+            steps.push(VerifiableTransitionFunctionWitness {
+                trans: cs_witness,
+                raddr,
+                rdata,
+                ractive,
+                read_proof,
+                tx: tx.clone(),
+                waddr,
+                wdata,
+                wactive,
+                write_proof,
+                insert_proof,
+                cm: cm_cur.clone(),
+            });
         }
 
-        let mut tree_state = aux_state.tree_state.as_mut().unwrap();
-        let (rh_state_new, write_proof) =
+        /* drop the versions older than the last steps kept */
+        if VC::STATE_VERSIONS > 0 {
             <VC::MTState as MT<
                 VC::F,
                 <VC::TF as TransitionFunction<VC::F>>::Addr,
                 <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-            >>::_modify_and_apply(&self.pp_mt.0, &mut tree_state, &waddr, &wdata)?;
+            >>::prune_versions(
+                &self.pp_mt.0,
+                &mut tree_state,
+                t_cur.saturating_sub(VC::STATE_VERSIONS),
+            )?;
+        }
 
-        let cm_new = Commitment::<VC> {
-            state_rh: rh_state_new,
-            history_rh: rh_history_new,
-        };
+        /* pad the batch with the default transaction run on a scratch state,
+        whose accesses satisfy the ledger system's constraints but are not checked against the trees */
+        while steps.len() < VC::BATCH_SIZE {
+            let mut scratch_state = State::<VC::F, VC::TF>::default();
+            let tx = <VC::TF as TransitionFunction<VC::F>>::Tx::default();
+            let (_, mut raddr, mut rdata, mut waddr, mut wdata, cs_witness) =
+                <VC::TF as TransitionFunction<VC::F>>::run(&mut scratch_state, &tx)?;
+
+            let ractive = pad_accesses(
+                &mut raddr,
+                &mut rdata,
+                <VC::TF as TransitionFunction<VC::F>>::NUM_READS,
+            )?;
+            let wactive = pad_accesses(
+                &mut waddr,
+                &mut wdata,
+                <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES,
+            )?;
+
+            active.push(false);
+            steps.push(VerifiableTransitionFunctionWitness {
+                trans: cs_witness,
+                read_proof: <VC::MTState as MT<
+                    VC::F,
+                    <VC::TF as TransitionFunction<VC::F>>::Addr,
+                    <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+                >>::default_lookup_proof(
+                    <VC::TF as TransitionFunction<VC::F>>::NUM_READS
+                )?,
+                write_proof: <VC::MTState as MT<
+                    VC::F,
+                    <VC::TF as TransitionFunction<VC::F>>::Addr,
+                    <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+                >>::default_modify_proof(
+                    <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
+                )?,
+                raddr,
+                rdata,
+                ractive,
+                tx,
+                waddr,
+                wdata,
+                wactive,
+                insert_proof:
+                    <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::default_modify_proof(1)?,
+                cm: cm_cur.clone(),
+            });
+        }
 
         let z_new = VerifiableTransitionFunctionMsg {
-            t: t_new,
-            cm: cm_new.clone(),
+            t: t_cur,
+            cm: cm_cur.clone(),
         };
 
-        let w = VerifiableTransitionFunctionWitness {
-            trans: cs_witness,
-            raddr,
-            rdata,
-            read_proof,
-            tx: tx.clone(),
-            waddr,
-            wdata,
-            write_proof,
-            insert_proof,
-        };
+        let w = VerifiableTransitionFunctionBatchWitness { active, steps };
 
-        let ivc_proof_new = if z_old.is_some() {
-            VC::I::prove::<Self, R>(
-                &self.ipk.clone().unwrap(),
-                &self,
-                &z_new,
-                &w,
-                &[z_old.unwrap()],
-                &[ivc_proof_old.unwrap()],
-                rng,
-            )?
-        } else {
-            VC::I::prove::<Self, R>(&self.ipk.clone().unwrap(), &self, &z_new, &w, &[], &[], rng)?
+        let ivc_proof_new = match (z_old, ivc_proof_old) {
+            (Some(z_old), Some(ivc_proof_old)) => {
+                VC::I::prove::<Self, R>(&ipk, &self, &z_new, &w, &[z_old], &[ivc_proof_old], rng)?
+            }
+            _ => VC::I::prove::<Self, R>(&ipk, &self, &z_new, &w, &[], &[], rng)?,
         };
 
-        aux_state.t = t_new;
-        aux_state.cm = Some(cm_new);
+        *state = new_state;
+        aux_state.tree_state = Some(tree_state);
+        aux_state.tree_history = Some(tree_history);
+        aux_state.batch_starts.push(aux_state.t);
+        aux_state.t = t_cur;
+        aux_state.cm = Some(cm_cur);
         aux_state.proof = Some(ivc_proof_new);
 
         Ok(y)
@@ -212,14 +415,38 @@ impl<VC: VerifiableTransitionFunctionConfig> VerifiableTransitionFunction<VC> {
         t: &u64,
         cm: &Commitment<VC>,
         ivc_proof: &<VC::I as PCD<VC::F>>::Proof,
-    ) -> Result<bool, Error> {
+    ) -> Result<bool, IVLSError> {
         let z = VerifiableTransitionFunctionMsg {
             t: *t,
             cm: cm.clone(),
         };
 
-        VC::I::verify::<Self>(&self.ivk.clone().unwrap(), &z, &ivc_proof)
+        let ivk = self.ivk.as_ref().ok_or(IVLSError::MissingKey)?;
+
+        Ok(VC::I::verify::<Self>(ivk, &z, &ivc_proof)?)
+    }
+}
+
+/// pad the accesses of a transaction to `num` entries, and return the flags of the real ones
+fn pad_accesses<Addr: Default, Data: Default>(
+    addr: &mut Vec<Addr>,
+    data: &mut Vec<Data>,
+    num: usize,
+) -> Result<Vec<bool>, IVLSError> {
+    if addr.len() != data.len() {
+        return Err(IVLSError::LengthMismatch(data.len(), addr.len()));
     }
+    if addr.len() > num {
+        return Err(IVLSError::LengthMismatch(addr.len(), num));
+    }
+
+    let mut active = vec![true; addr.len()];
+    while addr.len() < num {
+        addr.push(Addr::default());
+        data.push(Data::default());
+        active.push(false);
+    }
+    Ok(active)
 }
 
 impl<VC: VerifiableTransitionFunctionConfig> PCDPredicate<VC::F>
@@ -227,8 +454,8 @@ impl<VC: VerifiableTransitionFunctionConfig> PCDPredicate<VC::F>
 {
     type Message = VerifiableTransitionFunctionMsg<VC>;
     type MessageVar = VerifiableTransitionFunctionMsgVar<VC>;
-    type LocalWitness = VerifiableTransitionFunctionWitness<VC>;
-    type LocalWitnessVar = VerifiableTransitionFunctionWitnessVar<VC>;
+    type LocalWitness = VerifiableTransitionFunctionBatchWitness<VC>;
+    type LocalWitnessVar = VerifiableTransitionFunctionBatchWitnessVar<VC>;
 
     const PRIOR_MSG_LEN: usize = 1;
 
@@ -241,54 +468,31 @@ impl<VC: VerifiableTransitionFunctionConfig> PCDPredicate<VC::F>
         _base_bit: &Boolean<VC::F>,
     ) -> Result<(), SynthesisError> {
         // base checks
-        assert_eq!(
-            witness.raddr_g.len(),
-            <VC::TF as TransitionFunction<VC::F>>::NUM_READS
-        );
-        assert_eq!(
-            witness.rdata_g.len(),
-            <VC::TF as TransitionFunction<VC::F>>::NUM_READS
-        );
-
-        assert_eq!(
-            witness.waddr_g.len(),
-            <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
-        );
-        assert_eq!(
-            witness.wdata_g.len(),
-            <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
-        );
-
-        // 1. Check that trans is valid
-        VC::TF::generate_constraints(
-            ark_relations::ns!(cs, "checking_transcript").cs(),
-            &witness.trans_g,
-            &witness.tx_g,
-            &witness.raddr_g,
-            &witness.rdata_g,
-            &witness.waddr_g,
-            &witness.wdata_g,
-        )?;
+        assert_eq!(witness.active_g.len(), VC::BATCH_SIZE);
+        assert_eq!(witness.steps_g.len(), VC::BATCH_SIZE);
+
+        // 1. Check that the active slots form a nonempty prefix
+        witness.active_g[0].enforce_equal(&Boolean::constant(true))?;
+        for i in 1..VC::BATCH_SIZE {
+            witness.active_g[i]
+                .and(&witness.active_g[i - 1].not())?
+                .enforce_equal(&Boolean::constant(false))?;
+        }
 
-        // 2. Check if t_new = t_old + 1
+        // 2. Check if t_old != 0
         let t_old = &prior_msgs[0].t_g;
-        let t_new = &msg.t_g;
-        let t_new_supposed = UInt64::addmany(&[t_old.clone(), UInt64::constant(1u64)])?;
-        t_new.enforce_equal(&t_new_supposed)?;
-
-        // 3. Check if t_old = 0
-        let t_old_is_zero: Boolean<VC::F>;
+        let t_old_is_nonzero: Boolean<VC::F>;
         {
             let res = t_old
                 .to_bits_le()
                 .iter()
                 .fold(Boolean::constant(false), |acc, x| acc.or(&x).unwrap());
-            t_old_is_zero = res;
+            t_old_is_nonzero = res;
         }
 
-        // 4. The lookup proof uses
-        //         the old msg's state_rh_g if t_old_is_zero = 1,
-        //         the pp's empty_digest_state_g if t_old_is_zero = 0,
+        // 3. The first slot starts from
+        //         the old msg's state_rh_g and history_rh_g if t_old_is_nonzero = 1,
+        //         the pp's empty digests if t_old_is_nonzero = 0,
         let empty_state_g = <VC::MTState as MT<
             VC::F,
             <VC::TF as TransitionFunction<VC::F>>::Addr,
@@ -298,79 +502,169 @@ impl<VC: VerifiableTransitionFunctionConfig> PCDPredicate<VC::F>
             self.empty_digest.0.clone(),
         )?;
 
-        let state_rh_g_selected = <VC::MTState as MT<
+        let empty_digest_g =
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::DigestVar::new_constant(
+                ark_relations::ns!(cs, "empty_digest_g"),
+                self.empty_digest.1.clone(),
+            )?;
+
+        let mut cur_state_rh_g = <VC::MTState as MT<
             VC::F,
             <VC::TF as TransitionFunction<VC::F>>::Addr,
             <VC::TF as TransitionFunction<VC::F>>::AddrVar,
         >>::DigestVar::conditionally_select(
-            &t_old_is_zero,
+            &t_old_is_nonzero,
             &prior_msgs[0].cm_g.state_rh_g,
             &empty_state_g,
         )?;
 
-        // 5. Check the lookup proof
+        let mut cur_history_rh_g =
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::DigestVar::conditionally_select(
+                &t_old_is_nonzero,
+                &prior_msgs[0].cm_g.history_rh_g,
+                &empty_digest_g,
+            )?;
+
+        let mut cur_t = t_old.clone();
+        let mut cur_t_is_nonzero = t_old_is_nonzero;
+
         let pp_mt_state_g = self.pp_mt.0.clone();
-        <VC::MTState as MT<
-            VC::F,
-            <VC::TF as TransitionFunction<VC::F>>::Addr,
-            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-        >>::verify_lookup_gadget(
-            ark_relations::ns!(cs, "read_proof").cs(),
-            &pp_mt_state_g,
-            &state_rh_g_selected,
-            &witness.raddr_g,
-            &witness.rdata_g,
-            &witness.read_proof_g,
-        )?;
+        let pp_mt_history_g = self.pp_mt.1.clone();
 
-        // 6. Check the update proof
-        <VC::MTState as MT<
-            VC::F,
-            <VC::TF as TransitionFunction<VC::F>>::Addr,
-            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
-        >>::conditionally_verify_modify_gadget(
-            ark_relations::ns!(cs, "write_proof").cs(),
-            &pp_mt_state_g,
-            &state_rh_g_selected,
-            &msg.cm_g.state_rh_g,
-            &witness.waddr_g,
-            &witness.wdata_g,
-            &witness.write_proof_g,
-            &Boolean::constant(true),
-        )?;
+        for (i, (active, step)) in witness
+            .active_g
+            .iter()
+            .zip(witness.steps_g.iter())
+            .enumerate()
+        {
+            assert_eq!(
+                step.raddr_g.len(),
+                <VC::TF as TransitionFunction<VC::F>>::NUM_READS
+            );
+            assert_eq!(
+                step.rdata_g.len(),
+                <VC::TF as TransitionFunction<VC::F>>::NUM_READS
+            );
+            assert_eq!(
+                step.ractive_g.len(),
+                <VC::TF as TransitionFunction<VC::F>>::NUM_READS
+            );
+
+            assert_eq!(
+                step.waddr_g.len(),
+                <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
+            );
+            assert_eq!(
+                step.wdata_g.len(),
+                <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
+            );
+            assert_eq!(
+                step.wactive_g.len(),
+                <VC::TF as TransitionFunction<VC::F>>::NUM_WRITES
+            );
+
+            // 4. Check that trans is valid
+            VC::TF::generate_constraints(
+                ark_relations::ns!(cs, "checking_transcript").cs(),
+                &step.trans_g,
+                &step.tx_g,
+                &step.raddr_g,
+                &step.rdata_g,
+                &step.ractive_g,
+                &step.waddr_g,
+                &step.wdata_g,
+                &step.wactive_g,
+            )?;
 
-        // 4. The insert proof uses
-        //         the old msg's history_rh_g if t_old_is_zero = 1,
-        //         the pp's empty_digest_history_g if t_old_is_zero = 0,
-        let empty_digest_g =
-            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::DigestVar::new_constant(
-                ark_relations::ns!(cs, "empty_digest_g"),
-                self.empty_digest.1.clone(),
+            // 5. Check the lookup proof
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::conditionally_verify_partial_lookup_gadget(
+                ark_relations::ns!(cs, "read_proof").cs(),
+                &pp_mt_state_g,
+                &cur_state_rh_g,
+                &step.raddr_g,
+                &StateLeafVar::<VC>::wrap(&step.rdata_g),
+                &step.ractive_g,
+                &step.read_proof_g,
+                &active,
             )?;
 
-        let history_rh_g_selected =
-            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::DigestVar::conditionally_select(
-                &t_old_is_zero,
-                &prior_msgs[0].cm_g.history_rh_g,
-                &empty_digest_g,
+            // 6. Check the update proof
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::conditionally_verify_partial_modify_gadget(
+                ark_relations::ns!(cs, "write_proof").cs(),
+                &pp_mt_state_g,
+                &cur_state_rh_g,
+                &step.cm_g.state_rh_g,
+                &step.waddr_g,
+                &StateLeafVar::<VC>::wrap(&step.wdata_g),
+                &step.wactive_g,
+                &step.write_proof_g,
+                &active,
             )?;
 
-        // 6. Check the insert proof
-        let addr_g_vec = vec![t_old.clone()];
-        let data_g_vec = vec![prior_msgs[0].cm_g.clone()];
+            // 7. Check the insert proof, which records the current commitment under the
+            //    current step count before the first slot (or before every slot if so configured)
+            let insert_enabled = if i == 0 || VC::HISTORY_PER_TX {
+                active.and(&cur_t_is_nonzero)?
+            } else {
+                Boolean::constant(false)
+            };
+
+            let addr_g_vec = vec![cur_t.clone()];
+            let data_g_vec = vec![CommitmentVar::<VC> {
+                state_rh_g: cur_state_rh_g.clone(),
+                history_rh_g: cur_history_rh_g.clone(),
+            }];
+
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::conditionally_verify_modify_gadget(
+                ark_relations::ns!(cs, "insert_proof").cs(),
+                &pp_mt_history_g,
+                &cur_history_rh_g,
+                &step.cm_g.history_rh_g,
+                &addr_g_vec,
+                &data_g_vec,
+                &step.insert_proof_g,
+                &insert_enabled,
+            )?;
 
-        let pp_mt_history_g = self.pp_mt.1.clone();
+            // 8. An active slot without an insert keeps the history root
+            let history_unchanged = active.and(&insert_enabled.not())?;
+            step.cm_g
+                .history_rh_g
+                .conditional_enforce_equal(&cur_history_rh_g, &history_unchanged)?;
 
-        <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::conditionally_verify_modify_gadget(
-            ark_relations::ns!(cs, "insert_proof").cs(),
-            &pp_mt_history_g,
-            &history_rh_g_selected,
-            &msg.cm_g.history_rh_g,
-            &addr_g_vec,
-            &data_g_vec,
-            &witness.insert_proof_g,
-            &t_old_is_zero,
-        )?;
+            // 9. Move to the next slot if this one is active
+            cur_state_rh_g = <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::DigestVar::conditionally_select(
+                &active, &step.cm_g.state_rh_g, &cur_state_rh_g
+            )?;
+            cur_history_rh_g =
+                <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::DigestVar::conditionally_select(
+                    &active,
+                    &step.cm_g.history_rh_g,
+                    &cur_history_rh_g,
+                )?;
+
+            let mut active_bits = vec![Boolean::constant(false); 64];
+            active_bits[0] = active.clone();
+            cur_t = UInt64::addmany(&[cur_t, UInt64::from_bits_le(&active_bits)])?;
+            cur_t_is_nonzero = cur_t_is_nonzero.or(&active)?;
+        }
+
+        // 10. Check if the new msg matches the last active slot
+        msg.t_g.enforce_equal(&cur_t)?;
+        msg.cm_g.state_rh_g.enforce_equal(&cur_state_rh_g)?;
+        msg.cm_g.history_rh_g.enforce_equal(&cur_history_rh_g)?;
 
         Ok(())
     }
@@ -0,0 +1,123 @@
+use crate::{
+    building_blocks::mt::MT,
+    gadgets::UInt64,
+    ivls::{
+        data_structures::{Commitment, QueryProof, VerifiableTransitionFunctionMsg},
+        error::IVLSError,
+        history::VerifiableHistory,
+        state::VerifiableState,
+        transition_function::{VerifiableTransitionFunction, VerifiableTransitionFunctionConfig},
+    },
+    ledger_system::{client_function::ClientFunction, transition_function::TransitionFunction},
+};
+use ark_pcd::PCD;
+
+/// the verifier-only handle of an IVLS, which never holds the PCD pk
+pub struct IVLSVerifier<VC: VerifiableTransitionFunctionConfig> {
+    /// verifiable state
+    pub vs: VerifiableState<VC>,
+    /// verifiable history
+    pub vh: VerifiableHistory<VC>,
+    /// digests for empty state and history
+    pub empty_digest: (
+        <VC::MTState as MT<
+            VC::F,
+            <VC::TF as TransitionFunction<VC::F>>::Addr,
+            <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+        >>::Digest,
+        <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::Digest,
+    ),
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> Clone for IVLSVerifier<VC> {
+    fn clone(&self) -> Self {
+        IVLSVerifier::<VC> {
+            vs: self.vs.clone(),
+            vh: self.vh.clone(),
+            empty_digest: self.empty_digest.clone(),
+        }
+    }
+}
+
+impl<VC: VerifiableTransitionFunctionConfig> IVLSVerifier<VC> {
+    /// check that the commitment is reached after t steps, where t = 0 takes no proof
+    pub fn verify(
+        &self,
+        t: u64,
+        cm: &Commitment<VC>,
+        ivc_proof: &Option<<VC::I as PCD<VC::F>>::Proof>,
+    ) -> Result<bool, IVLSError> {
+        if t == 0 {
+            if ivc_proof.is_some() {
+                return Err(IVLSError::UnexpectedProof);
+            }
+
+            Ok(cm.state_rh == self.empty_digest.0 && cm.history_rh == self.empty_digest.1)
+        } else {
+            let ivc_proof = ivc_proof.as_ref().ok_or(IVLSError::MissingProof)?;
+            let z = VerifiableTransitionFunctionMsg { t, cm: cm.clone() };
+
+            Ok(VC::I::verify::<VerifiableTransitionFunction<VC>>(
+                &self.vs.ivk,
+                &z,
+                ivc_proof,
+            )?)
+        }
+    }
+
+    /// check the answer of a client function against the commitment, as vS.verify_query
+    pub fn verify_query<CF: ClientFunction<VC::F, VC::TF>>(
+        &self,
+        cm: &Commitment<VC>,
+        query: &CF::Query,
+        answer: &CF::Answer,
+        proof: &QueryProof<VC>,
+    ) -> Result<bool, IVLSError> {
+        self.vs.verify_query::<CF>(cm, query, answer, proof)
+    }
+
+    /// check that the history behind the commitment has cm_t at step t, as History.verify
+    pub fn verify_history(
+        &self,
+        cm: &Commitment<VC>,
+        t: u64,
+        cm_t: &Option<Commitment<VC>>,
+        proof: &Option<<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof>,
+    ) -> Result<bool, IVLSError> {
+        self.vh.verify(cm, t, cm_t, proof)
+    }
+
+    /// check that the commitments from time `t1` to `t2` (inclusive) are in the history of `cm`,
+    /// as History.verify_range
+    pub fn verify_history_range(
+        &self,
+        cm: &Commitment<VC>,
+        t1: u64,
+        t2: u64,
+        cm_range: &[Commitment<VC>],
+        proof: &<VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::MultiLookupProof,
+    ) -> Result<bool, IVLSError> {
+        self.vh.verify_range(cm, t1, t2, cm_range, proof)
+    }
+
+    /// check the data at the addresses at time `t` in the history of `cm`, as
+    /// History.verify_state_at
+    pub fn verify_history_state_at(
+        &self,
+        cm: &Commitment<VC>,
+        t: u64,
+        cm_t: &Commitment<VC>,
+        addr: &[<VC::TF as TransitionFunction<VC::F>>::Addr],
+        data: &[<VC::TF as TransitionFunction<VC::F>>::Data],
+        proof: &(
+            <VC::MTHistory as MT<VC::F, u64, UInt64<VC::F>>>::LookupProof,
+            <VC::MTState as MT<
+                VC::F,
+                <VC::TF as TransitionFunction<VC::F>>::Addr,
+                <VC::TF as TransitionFunction<VC::F>>::AddrVar,
+            >>::LookupProof,
+        ),
+    ) -> Result<bool, IVLSError> {
+        self.vh.verify_state_at(cm, t, cm_t, addr, data, proof)
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{
+    ledger_system::{state::State, transition_function::TransitionFunction},
+    Error, PrimeField, Sized, Vec,
+};
+
+/// a trait for the client function, which would be implemented by the user
+pub trait ClientFunction<F: PrimeField, TF: TransitionFunction<F>>: Sized {
+    /// type of the query
+    type Query: Clone;
+    /// type of the answer
+    type Answer: Clone;
+
+    /// compute the answer, together with the addresses read and the data obtained
+    fn run(
+        state: &State<F, TF>,
+        query: &Self::Query,
+    ) -> Result<(Self::Answer, Vec<TF::Addr>, Vec<TF::Data>), Error>;
+
+    /// check if the answer is consistent with the query and the data read from the state
+    fn verify(
+        query: &Self::Query,
+        answer: &Self::Answer,
+        raddr: &[TF::Addr],
+        rdata: &[TF::Data],
+    ) -> Result<bool, Error>;
+}
@@ -1,6 +1,11 @@
 use crate::{
-    gadgets::{AllocVar, Assignment, EmptyVar, EqGadget, FpVar, UInt64},
-    ledger_system::{state::State, transition_function::TransitionFunction},
+    building_blocks::mt::{
+        FieldLeaf, FieldLeafVar, LeafEncoding, LeafEncodingVar, MerkleLeaf, MerkleLeafGadget,
+    },
+    gadgets::{AllocVar, Assignment, Boolean, EmptyVar, EqGadget, FpVar, UInt64},
+    ledger_system::{
+        client_function::ClientFunction, state::State, transition_function::TransitionFunction,
+    },
     Borrow, Error, PhantomData, PrimeField, SynthesisError, ToBytes, Vec,
 };
 use ark_r1cs_std::alloc::AllocationMode;
@@ -89,6 +94,14 @@ impl<F: PrimeField> TransitionFunction<F> for ExampleTransitionFunction<F> {
     const NUM_READS: usize = 1;
     const NUM_WRITES: usize = 1;
 
+    fn encode_data(data: &F) -> Result<LeafEncoding<F>, Error> {
+        FieldLeaf(*data).encode_leaf()
+    }
+
+    fn encode_data_gadget(data_g: &FpVar<F>) -> Result<LeafEncodingVar<F>, SynthesisError> {
+        FieldLeafVar(data_g.clone()).encode_leaf()
+    }
+
     fn run(
         state: &mut State<F, Self>,
         tx: &Self::Tx,
@@ -127,13 +140,21 @@ impl<F: PrimeField> TransitionFunction<F> for ExampleTransitionFunction<F> {
         tx_g: &Self::TxVar,
         raddr_g: &[Self::AddrVar],
         rdata_g: &[Self::DataVar],
+        ractive_g: &[Boolean<F>],
         waddr_g: &[Self::AddrVar],
         wdata_g: &[Self::DataVar],
+        wactive_g: &[Boolean<F>],
     ) -> Result<(), SynthesisError> {
         assert_eq!(raddr_g.len(), Self::NUM_READS);
         assert_eq!(rdata_g.len(), Self::NUM_READS);
+        assert_eq!(ractive_g.len(), Self::NUM_READS);
         assert_eq!(waddr_g.len(), Self::NUM_WRITES);
         assert_eq!(wdata_g.len(), Self::NUM_WRITES);
+        assert_eq!(wactive_g.len(), Self::NUM_WRITES);
+
+        // 0. Check if the read and the write are both performed.
+        ractive_g[0].enforce_equal(&Boolean::constant(true))?;
+        wactive_g[0].enforce_equal(&Boolean::constant(true))?;
 
         // 1. Check if the raddr and waddr are correct.
         let tx_key = &tx_g.key_g;
@@ -155,3 +176,41 @@ impl<F: PrimeField> TransitionFunction<F> for ExampleTransitionFunction<F> {
         Ok(())
     }
 }
+
+/// an example client function that reads the value under a key
+pub struct ExampleClientFunction<F: PrimeField> {
+    #[doc(hidden)]
+    f_phantom: PhantomData<F>,
+}
+
+impl<F: PrimeField> ClientFunction<F, ExampleTransitionFunction<F>> for ExampleClientFunction<F> {
+    type Query = u64;
+    type Answer = F;
+
+    fn run(
+        state: &State<F, ExampleTransitionFunction<F>>,
+        query: &Self::Query,
+    ) -> Result<(Self::Answer, Vec<u64>, Vec<F>), Error> {
+        let val: F = *state.read(query)?;
+
+        let mut raddr: Vec<u64> = Vec::with_capacity(1);
+        let mut rdata: Vec<F> = Vec::with_capacity(1);
+        raddr.push(*query);
+        rdata.push(val);
+
+        Ok((val, raddr, rdata))
+    }
+
+    fn verify(
+        query: &Self::Query,
+        answer: &Self::Answer,
+        raddr: &[u64],
+        rdata: &[F],
+    ) -> Result<bool, Error> {
+        if raddr.len() != 1 || rdata.len() != 1 {
+            return Ok(false);
+        }
+
+        Ok(raddr[0] == *query && rdata[0] == *answer)
+    }
+}
@@ -1,3 +1,5 @@
+/// the trait for the client function
+pub mod client_function;
 /// a simple example ledger system
 pub mod example;
 /// ledger system's state model
@@ -1,6 +1,8 @@
 use crate::{ledger_system::transition_function::TransitionFunction, Error, PrimeField};
 use ark_ff::ToBytes;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::collections::BTreeMap;
+use ark_std::io::{Read, Write};
 
 /// the ledger system's state
 pub struct State<F: PrimeField, TF: TransitionFunction<F>>
@@ -32,6 +34,20 @@ where
     }
 }
 
+impl<F: PrimeField, TF: TransitionFunction<F>> Clone for State<F, TF>
+where
+    TF: TransitionFunction<F>,
+    TF::Addr: ToBytes + Default + Eq + Clone + Ord,
+    TF::Data: ToBytes + Clone + Default,
+{
+    fn clone(&self) -> Self {
+        State {
+            map: self.map.clone(),
+            default_data: self.default_data.clone(),
+        }
+    }
+}
+
 impl<F: PrimeField, TF: TransitionFunction<F>> State<F, TF>
 where
     TF: TransitionFunction<F>,
@@ -45,7 +61,7 @@ where
 
     /// read the state
     pub fn read(
-        &mut self,
+        &self,
         addr: &<TF as TransitionFunction<F>>::Addr,
     ) -> Result<&<TF as TransitionFunction<F>>::Data, Error> {
         let data = match self.map.get(addr) {
@@ -72,4 +88,44 @@ where
 
         Ok(())
     }
+
+    /// write a snapshot of the state
+    pub fn snapshot<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        (self.map.len() as u64).serialize(&mut writer)?;
+        for (addr, data) in self.map.iter() {
+            addr.serialize(&mut writer)?;
+            data.serialize(&mut writer)?;
+        }
+        self.default_data.serialize(&mut writer)?;
+
+        Ok(())
+    }
+
+    /// restore the state from a snapshot
+    pub fn restore<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let len = u64::deserialize(&mut reader)?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let addr = <TF as TransitionFunction<F>>::Addr::deserialize(&mut reader)?;
+            let data = <TF as TransitionFunction<F>>::Data::deserialize(&mut reader)?;
+            map.insert(addr, data);
+        }
+        let default_data = <TF as TransitionFunction<F>>::Data::deserialize(&mut reader)?;
+
+        Ok(State { map, default_data })
+    }
+
+    /// write a snapshot of the state to a file
+    #[cfg(feature = "std")]
+    pub fn snapshot_to_file<T: AsRef<std::path::Path>>(&self, path: T) -> Result<(), Error> {
+        let file = std::fs::File::create(path)?;
+        self.snapshot(std::io::BufWriter::new(file))
+    }
+
+    /// restore the state from a snapshot file
+    #[cfg(feature = "std")]
+    pub fn restore_from_file<T: AsRef<std::path::Path>>(path: T) -> Result<Self, Error> {
+        let file = std::fs::File::open(path)?;
+        Self::restore(std::io::BufReader::new(file))
+    }
 }
@@ -1,16 +1,18 @@
 use crate::{
-    gadgets::{AllocVar, ToBytesGadget},
+    building_blocks::mt::{LeafEncoding, LeafEncodingVar, MerkleLeaf, MerkleLeafGadget},
+    gadgets::{AllocVar, Boolean, ToBytesGadget},
     ledger_system::state::State,
     Error, PrimeField, Sized, SynthesisError, ToBytes, Vec,
 };
 use ark_relations::r1cs::ConstraintSystemRef;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 
 /// a trait for the transition function, which would be implemented by the user
 pub trait TransitionFunction<F: PrimeField>: Sized {
     /// type of the address
-    type Addr: ToBytes + Default + Eq + Clone + Ord;
+    type Addr: ToBytes + Default + Eq + Clone + Ord + CanonicalSerialize + CanonicalDeserialize;
     /// type of the data
-    type Data: ToBytes + Clone + Default;
+    type Data: ToBytes + Clone + Default + CanonicalSerialize + CanonicalDeserialize;
     /// witness
     type Witness: Default + Clone;
     /// transaction
@@ -28,12 +30,25 @@ pub trait TransitionFunction<F: PrimeField>: Sized {
     /// output of the transition function
     type Output;
 
-    /// number of reads the function would perform
+    /// maximal number of reads the function would perform
     const NUM_READS: usize;
-    /// number of writes the function would perform
+    /// maximal number of writes the function would perform
     const NUM_WRITES: usize;
 
-    /// compute the transition result
+    /// the encoding of the data in the state tree, by default its bytes
+    ///
+    /// a ledger whose data are field elements can encode them as such, e.g., with `FieldLeaf`,
+    /// so that the state tree hashes them without the conversion to bytes
+    fn encode_data(data: &Self::Data) -> Result<LeafEncoding<F>, Error> {
+        MerkleLeaf::<F>::encode_leaf(data)
+    }
+
+    /// the encoding of the data gadgets, which must match `encode_data`
+    fn encode_data_gadget(data: &Self::DataVar) -> Result<LeafEncodingVar<F>, SynthesisError> {
+        MerkleLeafGadget::<F>::encode_leaf(data)
+    }
+
+    /// compute the transition result, with at most `NUM_READS` reads and `NUM_WRITES` writes
     fn run(
         state: &mut State<F, Self>,
         tx: &Self::Tx,
@@ -50,13 +65,19 @@ pub trait TransitionFunction<F: PrimeField>: Sized {
     >;
 
     /// generate the constraints for the ledger system itself only
+    ///
+    /// the reads and writes are padded to `NUM_READS` and `NUM_WRITES`, and `ractive` / `wactive`
+    /// mark the accesses that `run` actually performed; the function is responsible for
+    /// constraining these flags, since the Merkle tree gadgets skip the inactive accesses
     fn generate_constraints(
         cs: ConstraintSystemRef<F>,
         witness: &Self::WitnessVar,
         tx: &Self::TxVar,
         raddr: &[Self::AddrVar],
         rdata: &[Self::DataVar],
+        ractive: &[Boolean<F>],
         waddr: &[Self::AddrVar],
         wdata: &[Self::DataVar],
+        wactive: &[Boolean<F>],
     ) -> Result<(), SynthesisError>;
 }
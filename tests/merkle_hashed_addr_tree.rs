@@ -0,0 +1,458 @@
+use ark_ed_on_mnt4_298::Fq as Fr;
+use ark_ivls::building_blocks::mt::{
+    hashed_addr_tree::{addr_to_index, HashedAddrMT},
+    merkle_sparse_tree::MerkleSparseTreeConfig,
+    MT,
+};
+
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+use ark_ivls::gadgets::{AllocVar, Boolean, FpVar, UInt8};
+use ark_relations::r1cs::ConstraintSystem;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::collections::BTreeMap;
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+/* a small tree with 16 leaves, so that collisions are easy to find */
+#[derive(Clone, Debug)]
+struct P;
+impl MerkleSparseTreeConfig for P {
+    const HEIGHT: u64 = 5;
+    type H = H;
+}
+
+type M = HashedAddrMT<Fr, P, HG, Fr>;
+type PP = <M as MT<Fr, Fr, FpVar<Fr>>>::PublicParameters;
+
+/* returns two addresses at the same index, and a third address at a different index */
+fn find_addresses(pp: &PP) -> (Fr, Fr, Fr) {
+    let mut seen: BTreeMap<u64, Fr> = BTreeMap::new();
+    let mut collision = None;
+    for i in 0..17u64 {
+        let addr = Fr::from(i);
+        let index = addr_to_index::<P, Fr>(pp, &addr).unwrap();
+        if let Some(other) = seen.get(&index) {
+            collision = Some((*other, addr, index));
+            break;
+        }
+        seen.insert(index, addr);
+    }
+
+    let (addr_1, addr_2, index) = collision.expect("17 addresses must collide in 16 leaves");
+    let addr_3 = seen
+        .iter()
+        .find(|(other_index, _)| **other_index != index)
+        .map(|(_, addr)| *addr)
+        .unwrap();
+
+    (addr_1, addr_2, addr_3)
+}
+
+#[test]
+fn test_merkle_hashed_addr_tree() {
+    let mut rng = ark_std::test_rng();
+
+    /* the tree is too small to resist grinding, so only the explicit setup accepts it */
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::setup(&mut rng).is_err());
+    let pp = M::setup_with_small_height(&mut rng).unwrap();
+    let (addr_1, addr_2, addr_3) = find_addresses(&pp);
+
+    /* tree_1 is empty initially */
+    let mut tree_1 = <M as MT<Fr, Fr, FpVar<Fr>>>::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = <M as MT<Fr, Fr, FpVar<Fr>>>::root(&pp, &tree_1).unwrap();
+
+    /* tree_1 + (addr_1, 2), (addr_3, 4) */
+    let waddr = vec![addr_1, addr_3];
+    let wdata = vec![2u8, 4];
+    let (tree_1_rh, modify_proof) =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut tree_1, &waddr, &wdata).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::validate(&pp, &tree_1).unwrap());
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &modify_proof
+    )
+    .unwrap());
+
+    /* check if the modify proof does NOT work when the data is tampered */
+    assert!(!<M as MT<Fr, Fr, FpVar<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &[2u8, 5],
+        &modify_proof
+    )
+    .unwrap());
+
+    /* test the _new_with_map */
+    let mut data_map: BTreeMap<Fr, u8> = BTreeMap::new();
+    data_map.insert(addr_1, 2u8);
+    data_map.insert(addr_3, 4u8);
+    let tree_2 = <M as MT<Fr, Fr, FpVar<Fr>>>::_new_with_map(&pp, &data_map).unwrap();
+    assert_eq!(
+        tree_1_rh,
+        <M as MT<Fr, Fr, FpVar<Fr>>>::root(&pp, &tree_2).unwrap()
+    );
+
+    /* test lookup, where an unwritten address reads the default data */
+    let unwritten_addr = (100..)
+        .map(|i: u64| Fr::from(i))
+        .find(|addr| {
+            let index = addr_to_index::<P, Fr>(&pp, addr).unwrap();
+            !tree_1.addresses.contains_key(&index)
+        })
+        .unwrap();
+    let raddr = vec![addr_1, addr_3, unwritten_addr];
+    let rdata = vec![2u8, 4, 0];
+    let lookup_proof = <M as MT<Fr, Fr, FpVar<Fr>>>::lookup(&pp, &tree_1, &raddr).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &raddr,
+        &rdata,
+        &lookup_proof
+    )
+    .unwrap());
+
+    /* an unwritten address cannot read non-default data */
+    assert!(!<M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &raddr,
+        &[2u8, 4, 1],
+        &lookup_proof
+    )
+    .unwrap());
+
+    let multi_lookup_proof =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::multi_lookup(&pp, &tree_1, &raddr).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::verify_multi_lookup(
+        &pp,
+        &tree_1_rh,
+        &raddr,
+        &rdata,
+        &multi_lookup_proof
+    )
+    .unwrap());
+
+    /* check if the tree still works after a serialization round trip */
+    let mut tree_bytes = Vec::new();
+    <M as MT<Fr, Fr, FpVar<Fr>>>::serialize_tree(&tree_1, &mut tree_bytes).unwrap();
+    let tree_3 =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::deserialize_tree::<u8, _>(&pp, &tree_bytes[..]).unwrap();
+    assert_eq!(
+        tree_1_rh,
+        <M as MT<Fr, Fr, FpVar<Fr>>>::root(&pp, &tree_3).unwrap()
+    );
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::validate(&pp, &tree_3).unwrap());
+
+    let lookup_proof = <M as MT<Fr, Fr, FpVar<Fr>>>::lookup(&pp, &tree_1, &waddr).unwrap();
+    let mut lookup_proof_bytes = Vec::new();
+    lookup_proof.serialize(&mut lookup_proof_bytes).unwrap();
+    let lookup_proof_deserialized =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::LookupProof::deserialize(&lookup_proof_bytes[..]).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &lookup_proof_deserialized
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_merkle_hashed_addr_tree_collision() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup_with_small_height(&mut rng).unwrap();
+    let (addr_1, addr_2, _) = find_addresses(&pp);
+
+    let mut tree = <M as MT<Fr, Fr, FpVar<Fr>>>::new::<u8>(&pp).unwrap();
+    let (tree_rh, _) =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut tree, &[addr_1], &[7u8]).unwrap();
+
+    /* the colliding address can be neither written, looked up, nor proven absent */
+    assert!(
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut tree, &[addr_2], &[8u8]).is_err()
+    );
+    assert_eq!(
+        tree_rh,
+        <M as MT<Fr, Fr, FpVar<Fr>>>::root(&pp, &tree).unwrap()
+    );
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::lookup(&pp, &tree, &[addr_2]).is_err());
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::prove_absence(&pp, &tree, &[addr_2]).is_err());
+
+    /* colliding addresses in the same batch or map are refused as well */
+    let mut empty_tree = <M as MT<Fr, Fr, FpVar<Fr>>>::new::<u8>(&pp).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut empty_tree,
+        &[addr_1, addr_2],
+        &[7u8, 8]
+    )
+    .is_err());
+
+    let mut data_map: BTreeMap<Fr, u8> = BTreeMap::new();
+    data_map.insert(addr_1, 7u8);
+    data_map.insert(addr_2, 8u8);
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::_new_with_map(&pp, &data_map).is_err());
+
+    /* the proof for one address does not work for the colliding address */
+    let lookup_proof = <M as MT<Fr, Fr, FpVar<Fr>>>::lookup(&pp, &tree, &[addr_1]).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup(
+        &pp,
+        &tree_rh,
+        &[addr_1],
+        &[7u8],
+        &lookup_proof
+    )
+    .unwrap());
+    assert!(!<M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup(
+        &pp,
+        &tree_rh,
+        &[addr_2],
+        &[7u8],
+        &lookup_proof
+    )
+    .unwrap());
+
+    /* nor in the circuit */
+    for &(addr, expected) in [(addr_1, true), (addr_2, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let tree_rh_g = <M as MT<Fr, Fr, FpVar<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh),
+        )
+        .unwrap();
+        let addr_g = vec![FpVar::new_witness(ark_relations::ns!(cs, "addr"), || Ok(addr)).unwrap()];
+        let data_g = vec![UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(7u8)).unwrap()];
+        let lookup_proof_g = <M as MT<Fr, Fr, FpVar<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "lookup_proof"),
+            || Ok(&lookup_proof),
+        )
+        .unwrap();
+
+        <M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &lookup_proof_g,
+        )
+        .unwrap();
+
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_merkle_hashed_addr_tree_colliding_overwrite() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup_with_small_height(&mut rng).unwrap();
+    let (addr_1, addr_2, _) = find_addresses(&pp);
+
+    let mut tree = <M as MT<Fr, Fr, FpVar<Fr>>>::new::<u8>(&pp).unwrap();
+    let (tree_rh, _) =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut tree, &[addr_1], &[7u8]).unwrap();
+
+    /* a prover that forgets addr_1 can produce a proof that evicts it by writing addr_2 */
+    let mut tree_bytes = Vec::new();
+    <M as MT<Fr, Fr, FpVar<Fr>>>::serialize_tree(&tree, &mut tree_bytes).unwrap();
+    let mut forged_tree =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::deserialize_tree::<u8, _>(&pp, &tree_bytes[..]).unwrap();
+    forged_tree.addresses.clear();
+    forged_tree.data.clear();
+    let (forged_tree_rh, forged_proof) =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut forged_tree, &[addr_2], &[8u8])
+            .unwrap();
+
+    /* but the old leaf is not empty, so the proof is rejected */
+    assert!(!<M as MT<Fr, Fr, FpVar<Fr>>>::verify_modify(
+        &pp,
+        &tree_rh,
+        &forged_tree_rh,
+        &[addr_2],
+        &[8u8],
+        &forged_proof
+    )
+    .unwrap());
+
+    /* while overwriting the same address is accepted */
+    let (new_tree_rh, modify_proof) =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut tree, &[addr_1], &[9u8]).unwrap();
+    assert!(<M as MT<Fr, Fr, FpVar<Fr>>>::verify_modify(
+        &pp,
+        &tree_rh,
+        &new_tree_rh,
+        &[addr_1],
+        &[9u8],
+        &modify_proof
+    )
+    .unwrap());
+
+    /* the same holds in the circuit */
+    for &(addr, data, new_rh, proof, expected) in [
+        (addr_2, 8u8, forged_tree_rh, &forged_proof, false),
+        (addr_1, 9u8, new_tree_rh, &modify_proof, true),
+    ]
+    .iter()
+    {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let tree_rh_g = <M as MT<Fr, Fr, FpVar<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh),
+        )
+        .unwrap();
+        let new_rh_g = <M as MT<Fr, Fr, FpVar<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "new_rh"),
+            || Ok(new_rh),
+        )
+        .unwrap();
+        let addr_g = vec![FpVar::new_witness(ark_relations::ns!(cs, "addr"), || Ok(addr)).unwrap()];
+        let data_g = vec![UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(data)).unwrap()];
+        let modify_proof_g = <M as MT<Fr, Fr, FpVar<Fr>>>::ModifyProofVar::new_witness(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(proof),
+        )
+        .unwrap();
+
+        <M as MT<Fr, Fr, FpVar<Fr>>>::conditionally_verify_modify_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &new_rh_g,
+            &addr_g,
+            &data_g,
+            &modify_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_merkle_hashed_addr_tree_gadgets() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup_with_small_height(&mut rng).unwrap();
+    let (addr_1, _, addr_3) = find_addresses(&pp);
+
+    let mut tree = <M as MT<Fr, Fr, FpVar<Fr>>>::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = <M as MT<Fr, Fr, FpVar<Fr>>>::root(&pp, &tree).unwrap();
+
+    /* write addr_1 only, so that addr_3 is still empty */
+    let (tree_rh, modify_proof) =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::_modify_and_apply(&pp, &mut tree, &[addr_1], &[7u8]).unwrap();
+
+    let raddr = vec![addr_1, addr_3];
+    let lookup_proof = <M as MT<Fr, Fr, FpVar<Fr>>>::lookup(&pp, &tree, &raddr).unwrap();
+    let multi_lookup_proof =
+        <M as MT<Fr, Fr, FpVar<Fr>>>::multi_lookup(&pp, &tree, &raddr).unwrap();
+    let absence_proof = <M as MT<Fr, Fr, FpVar<Fr>>>::prove_absence(&pp, &tree, &[addr_3]).unwrap();
+
+    /* the unwritten address passes only with the default data */
+    for &(data_3, expected) in [(0u8, true), (1u8, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let empty_tree_rh_g = <M as MT<Fr, Fr, FpVar<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "empty_tree_rh"),
+            || Ok(empty_tree_rh),
+        )
+        .unwrap();
+        let tree_rh_g = <M as MT<Fr, Fr, FpVar<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh),
+        )
+        .unwrap();
+
+        let addr_g: Vec<FpVar<Fr>> = raddr
+            .iter()
+            .map(|addr| FpVar::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+            .collect();
+        let data_g: Vec<UInt8<Fr>> = [7u8, data_3]
+            .iter()
+            .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+            .collect();
+
+        let modify_proof_g = <M as MT<Fr, Fr, FpVar<Fr>>>::ModifyProofVar::new_witness(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(&modify_proof),
+        )
+        .unwrap();
+        <M as MT<Fr, Fr, FpVar<Fr>>>::conditionally_verify_modify_gadget(
+            cs.clone(),
+            &pp,
+            &empty_tree_rh_g,
+            &tree_rh_g,
+            &addr_g[..1],
+            &data_g[..1],
+            &modify_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let absence_proof_g = <M as MT<Fr, Fr, FpVar<Fr>>>::AbsenceProofVar::new_witness(
+            ark_relations::ns!(cs, "absence_proof"),
+            || Ok(&absence_proof),
+        )
+        .unwrap();
+        <M as MT<Fr, Fr, FpVar<Fr>>>::verify_absence_gadget::<u8>(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g[1..],
+            &absence_proof_g,
+        )
+        .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let lookup_proof_g = <M as MT<Fr, Fr, FpVar<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "lookup_proof"),
+            || Ok(&lookup_proof),
+        )
+        .unwrap();
+        let multi_lookup_proof_g = <M as MT<Fr, Fr, FpVar<Fr>>>::MultiLookupProofVar::new_witness(
+            ark_relations::ns!(cs, "multi_lookup_proof"),
+            || Ok(&multi_lookup_proof),
+        )
+        .unwrap();
+
+        <M as MT<Fr, Fr, FpVar<Fr>>>::verify_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &lookup_proof_g,
+        )
+        .unwrap();
+        <M as MT<Fr, Fr, FpVar<Fr>>>::verify_multi_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &raddr,
+            &data_g,
+            &multi_lookup_proof_g,
+        )
+        .unwrap();
+
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
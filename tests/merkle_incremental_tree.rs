@@ -0,0 +1,384 @@
+use ark_ed_on_mnt4_298::Fq as Fr;
+use ark_ivls::building_blocks::mt::{
+    merkle_incremental_tree::IncrementalMT, merkle_sparse_tree::MerkleSparseTreeConfig, SparseMT,
+    MT,
+};
+
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+use ark_ivls::gadgets::{AllocVar, Boolean, UInt64, UInt8};
+use ark_relations::r1cs::ConstraintSystem;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::collections::BTreeMap;
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+#[derive(Clone, Debug)]
+struct P;
+impl MerkleSparseTreeConfig for P {
+    const HEIGHT: u64 = 10;
+    type H = H;
+}
+
+type M = IncrementalMT<Fr, P, HG>;
+type SM = SparseMT<Fr, P, HG>;
+
+/* returns the number of constraints of the modify gadget for a write at location 6 */
+fn modify_constraints<T: MT<Fr, u64, UInt64<Fr>>>() -> usize {
+    let mut rng = ark_std::test_rng();
+
+    let pp = T::setup(&mut rng).unwrap();
+    let mut tree = T::new::<u8>(&pp).unwrap();
+    let (old_tree_rh, _) = T::_modify_and_apply(&pp, &mut tree, &[1u64, 3], &[2u8, 4]).unwrap();
+    let (tree_rh, modify_proof) = T::_modify_and_apply(&pp, &mut tree, &[6u64], &[5u8]).unwrap();
+
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let old_tree_rh_g =
+        T::DigestVar::new_witness(ark_relations::ns!(cs, "old_tree_rh"), || Ok(old_tree_rh))
+            .unwrap();
+    let tree_rh_g =
+        T::DigestVar::new_witness(ark_relations::ns!(cs, "tree_rh"), || Ok(tree_rh)).unwrap();
+    let waddr_g = vec![UInt64::new_witness(ark_relations::ns!(cs, "waddr"), || Ok(6u64)).unwrap()];
+    let wdata_g = vec![UInt8::new_witness(ark_relations::ns!(cs, "wdata"), || Ok(5u8)).unwrap()];
+    let modify_proof_g =
+        T::ModifyProofVar::new_witness(ark_relations::ns!(cs, "modify_proof"), || {
+            Ok(&modify_proof)
+        })
+        .unwrap();
+
+    T::conditionally_verify_modify_gadget(
+        cs.clone(),
+        &pp,
+        &old_tree_rh_g,
+        &tree_rh_g,
+        &waddr_g,
+        &wdata_g,
+        &modify_proof_g,
+        &Boolean::constant(true),
+    )
+    .unwrap();
+    assert!(cs.is_satisfied().unwrap());
+
+    cs.num_constraints()
+}
+
+#[test]
+fn test_merkle_incremental_tree() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    /* tree_1 is empty initially */
+    let mut tree_1 = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree_1).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree_1).unwrap());
+
+    /* tree_1 + (1, 2), (3, 4), (6, 5), where the locations 0, 2, 4, 5 are skipped */
+    let waddr = vec![1u64, 3, 6];
+    let wdata = vec![2u8, 4, 5];
+    let (tree_1_rh, modify_proof) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree_1, &waddr, &wdata)
+            .unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree_1).unwrap());
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &modify_proof
+    )
+    .unwrap());
+
+    /* check if the modify proof does NOT work when the data is tampered */
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &[2u8, 4, 6],
+        &modify_proof
+    )
+    .unwrap());
+
+    /* test the _new_with_map */
+    let mut data_map: BTreeMap<u64, u8> = BTreeMap::new();
+    data_map.insert(1u64, 2u8);
+    data_map.insert(3u64, 4u8);
+    data_map.insert(6u64, 5u8);
+    let tree_2 = <M as MT<Fr, u64, UInt64<Fr>>>::_new_with_map(&pp, &data_map).unwrap();
+    assert_eq!(
+        tree_1_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree_2).unwrap()
+    );
+
+    /* test lookup and multi-lookup */
+    let lookup_proof = <M as MT<Fr, u64, UInt64<Fr>>>::lookup(&pp, &tree_1, &waddr).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &lookup_proof
+    )
+    .unwrap());
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &[2u8, 4, 6],
+        &lookup_proof
+    )
+    .unwrap());
+
+    let multi_lookup_proof =
+        <M as MT<Fr, u64, UInt64<Fr>>>::multi_lookup(&pp, &tree_1, &waddr).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_multi_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &multi_lookup_proof
+    )
+    .unwrap());
+
+    /* skipped and later locations are absent, written ones are not */
+    let aaddr = vec![0u64, 4, 7, 100];
+    let absence_proof =
+        <M as MT<Fr, u64, UInt64<Fr>>>::prove_absence(&pp, &tree_1, &aaddr).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_absence::<u8>(
+        &pp,
+        &tree_1_rh,
+        &aaddr,
+        &absence_proof
+    )
+    .unwrap());
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::prove_absence(&pp, &tree_1, &[3u64]).is_err());
+
+    /* check if the tree still works after a serialization round trip */
+    let mut tree_bytes = Vec::new();
+    <M as MT<Fr, u64, UInt64<Fr>>>::serialize_tree(&tree_1, &mut tree_bytes).unwrap();
+    let tree_3 =
+        <M as MT<Fr, u64, UInt64<Fr>>>::deserialize_tree::<u8, _>(&pp, &tree_bytes[..]).unwrap();
+    assert_eq!(
+        tree_1_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree_3).unwrap()
+    );
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree_3).unwrap());
+
+    let mut modify_proof_bytes = Vec::new();
+    modify_proof.serialize(&mut modify_proof_bytes).unwrap();
+    let modify_proof_deserialized =
+        <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProof::deserialize(&modify_proof_bytes[..]).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &modify_proof_deserialized
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_merkle_incremental_tree_append_only() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    let mut tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree).unwrap();
+    let (tree_rh, _) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &[1u64, 3], &[2u8, 4])
+            .unwrap();
+
+    /* neither a written nor a skipped location can be written again */
+    for addr in [1u64, 2, 3].iter() {
+        assert!(<M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+            &pp,
+            &mut tree,
+            &[*addr],
+            &[7u8]
+        )
+        .is_err());
+    }
+
+    /* a batch out of order is refused as a whole */
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut tree,
+        &[5u64, 4],
+        &[7u8, 8]
+    )
+    .is_err());
+    assert_eq!(
+        tree_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree).unwrap()
+    );
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree).unwrap());
+
+    /* a proof of writing location 1 in the empty tree does not rewrite it in the current tree */
+    let mut other_tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let (other_tree_rh, overwrite_proof) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut other_tree, &[1u64], &[7u8])
+            .unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &other_tree_rh,
+        &[1u64],
+        &[7u8],
+        &overwrite_proof
+    )
+    .unwrap());
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &tree_rh,
+        &other_tree_rh,
+        &[1u64],
+        &[7u8],
+        &overwrite_proof
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_merkle_incremental_tree_append_constraints() {
+    let append_constraints = modify_constraints::<M>();
+    let sparse_modify_constraints = modify_constraints::<SM>();
+    println!(
+        "append: {} constraints, sparse tree modification: {} constraints",
+        append_constraints, sparse_modify_constraints
+    );
+
+    /* an append hashes the old and the new path like a sparse tree modification, so it is not
+    cheaper in the circuit */
+    assert!(10 * append_constraints >= 9 * sparse_modify_constraints);
+}
+
+#[test]
+fn test_merkle_incremental_tree_gadgets() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    let mut tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let (old_tree_rh, _) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &[1u64, 3], &[2u8, 4])
+            .unwrap();
+    let (tree_rh, modify_proof) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &[6u64], &[5u8]).unwrap();
+
+    let raddr = vec![1u64, 6];
+    let rdata = vec![2u8, 5];
+    let lookup_proof = <M as MT<Fr, u64, UInt64<Fr>>>::lookup(&pp, &tree, &raddr).unwrap();
+    let multi_lookup_proof =
+        <M as MT<Fr, u64, UInt64<Fr>>>::multi_lookup(&pp, &tree, &raddr).unwrap();
+    let absence_proof = <M as MT<Fr, u64, UInt64<Fr>>>::prove_absence(&pp, &tree, &[4u64]).unwrap();
+
+    /* the append passes only from the root it was made for */
+    for &(old_rh, expected) in [(old_tree_rh, true), (tree_rh, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let old_tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "old_tree_rh"),
+            || Ok(old_rh),
+        )
+        .unwrap();
+        let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh),
+        )
+        .unwrap();
+
+        let waddr_g =
+            vec![UInt64::new_witness(ark_relations::ns!(cs, "waddr"), || Ok(6u64)).unwrap()];
+        let wdata_g =
+            vec![UInt8::new_witness(ark_relations::ns!(cs, "wdata"), || Ok(5u8)).unwrap()];
+        let modify_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProofVar::new_witness(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(&modify_proof),
+        )
+        .unwrap();
+        <M as MT<Fr, u64, UInt64<Fr>>>::conditionally_verify_modify_gadget(
+            cs.clone(),
+            &pp,
+            &old_tree_rh_g,
+            &tree_rh_g,
+            &waddr_g,
+            &wdata_g,
+            &modify_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+
+    /* lookups, multi-lookups and absence proofs in the circuit */
+    let cs = ConstraintSystem::<Fr>::new_ref();
+
+    let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "tree_rh"),
+        || Ok(tree_rh),
+    )
+    .unwrap();
+    let raddr_g: Vec<UInt64<Fr>> = raddr
+        .iter()
+        .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "raddr"), || Ok(*addr)).unwrap())
+        .collect();
+    let rdata_g: Vec<UInt8<Fr>> = rdata
+        .iter()
+        .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "rdata"), || Ok(*data)).unwrap())
+        .collect();
+    let aaddr_g = vec![UInt64::new_witness(ark_relations::ns!(cs, "aaddr"), || Ok(4u64)).unwrap()];
+
+    let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "lookup_proof"),
+        || Ok(&lookup_proof),
+    )
+    .unwrap();
+    let multi_lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::MultiLookupProofVar::new_witness(
+        ark_relations::ns!(cs, "multi_lookup_proof"),
+        || Ok(&multi_lookup_proof),
+    )
+    .unwrap();
+    let absence_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::AbsenceProofVar::new_witness(
+        ark_relations::ns!(cs, "absence_proof"),
+        || Ok(&absence_proof),
+    )
+    .unwrap();
+
+    <M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &raddr_g,
+        &rdata_g,
+        &lookup_proof_g,
+    )
+    .unwrap();
+    <M as MT<Fr, u64, UInt64<Fr>>>::verify_multi_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &raddr,
+        &rdata_g,
+        &multi_lookup_proof_g,
+    )
+    .unwrap();
+    <M as MT<Fr, u64, UInt64<Fr>>>::verify_absence_gadget::<u8>(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &aaddr_g,
+        &absence_proof_g,
+    )
+    .unwrap();
+
+    assert!(cs.is_satisfied().unwrap());
+}
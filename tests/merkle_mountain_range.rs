@@ -0,0 +1,417 @@
+use ark_ed_on_mnt4_298::Fq as Fr;
+use ark_ivls::building_blocks::mt::{
+    merkle_mountain_range::MountainRangeMT, merkle_sparse_tree::MerkleSparseTreeConfig, MT,
+};
+
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+use ark_ivls::gadgets::{AllocVar, Boolean, UInt64, UInt8};
+use ark_relations::r1cs::ConstraintSystem;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::collections::BTreeMap;
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+/* a small range with up to 15 leaves */
+#[derive(Clone, Debug)]
+struct P;
+impl MerkleSparseTreeConfig for P {
+    const HEIGHT: u64 = 4;
+    type H = H;
+}
+
+type M = MountainRangeMT<Fr, P, HG>;
+
+#[test]
+fn test_merkle_mountain_range() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    /* tree_1 is empty initially */
+    let mut tree_1 = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree_1).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree_1).unwrap());
+
+    /* tree_1 + (1, 2), (3, 4), (4, 6), where the locations 0 and 2 are skipped */
+    let waddr = vec![1u64, 3, 4];
+    let wdata = vec![2u8, 4, 6];
+    let (tree_1_rh, modify_proof) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree_1, &waddr, &wdata)
+            .unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree_1).unwrap());
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &modify_proof
+    )
+    .unwrap());
+
+    /* check if the modify proof does NOT work when the data or the locations are tampered */
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &[2u8, 4, 7],
+        &modify_proof
+    )
+    .unwrap());
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &[1u64, 3, 5],
+        &wdata,
+        &modify_proof
+    )
+    .unwrap());
+
+    /* test the _new_with_map */
+    let mut data_map: BTreeMap<u64, u8> = BTreeMap::new();
+    data_map.insert(1u64, 2u8);
+    data_map.insert(3u64, 4u8);
+    data_map.insert(4u64, 6u8);
+    let tree_2 = <M as MT<Fr, u64, UInt64<Fr>>>::_new_with_map(&pp, &data_map).unwrap();
+    assert_eq!(
+        tree_1_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree_2).unwrap()
+    );
+
+    /* test lookup and multi-lookup */
+    let lookup_proof = <M as MT<Fr, u64, UInt64<Fr>>>::lookup(&pp, &tree_1, &waddr).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &lookup_proof
+    )
+    .unwrap());
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &[2u8, 4, 7],
+        &lookup_proof
+    )
+    .unwrap());
+
+    /* a proof for one location does not work for another */
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &[2u64],
+        &[2u8],
+        &lookup_proof[..1].to_vec()
+    )
+    .unwrap());
+
+    /* a skipped location cannot be looked up */
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::lookup(&pp, &tree_1, &[2u64]).is_err());
+
+    let multi_lookup_proof =
+        <M as MT<Fr, u64, UInt64<Fr>>>::multi_lookup(&pp, &tree_1, &waddr).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_multi_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &multi_lookup_proof
+    )
+    .unwrap());
+
+    /* later locations are absent, but skipped or written ones cannot be proven absent */
+    let aaddr = vec![5u64, 100];
+    let absence_proof =
+        <M as MT<Fr, u64, UInt64<Fr>>>::prove_absence(&pp, &tree_1, &aaddr).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_absence::<u8>(
+        &pp,
+        &tree_1_rh,
+        &aaddr,
+        &absence_proof
+    )
+    .unwrap());
+    assert!(!<M as MT<Fr, u64, UInt64<Fr>>>::verify_absence::<u8>(
+        &pp,
+        &tree_1_rh,
+        &[4u64, 100],
+        &absence_proof
+    )
+    .unwrap());
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::prove_absence(&pp, &tree_1, &[2u64]).is_err());
+
+    /* check if the tree still works after a serialization round trip */
+    let mut tree_bytes = Vec::new();
+    <M as MT<Fr, u64, UInt64<Fr>>>::serialize_tree(&tree_1, &mut tree_bytes).unwrap();
+    let tree_3 =
+        <M as MT<Fr, u64, UInt64<Fr>>>::deserialize_tree::<u8, _>(&pp, &tree_bytes[..]).unwrap();
+    assert_eq!(
+        tree_1_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree_3).unwrap()
+    );
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree_3).unwrap());
+
+    let mut lookup_proof_bytes = Vec::new();
+    lookup_proof.serialize(&mut lookup_proof_bytes).unwrap();
+    let lookup_proof_deserialized =
+        <M as MT<Fr, u64, UInt64<Fr>>>::LookupProof::deserialize(&lookup_proof_bytes[..]).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &lookup_proof_deserialized
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_merkle_mountain_range_append_only() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    let mut tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let (tree_rh, _) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &[1u64, 3], &[2u8, 4])
+            .unwrap();
+
+    /* neither a written nor a skipped location can be written again */
+    for addr in [1u64, 2, 3].iter() {
+        assert!(<M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+            &pp,
+            &mut tree,
+            &[*addr],
+            &[7u8]
+        )
+        .is_err());
+    }
+
+    /* a batch out of order is refused as a whole */
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut tree,
+        &[5u64, 4],
+        &[7u8, 8]
+    )
+    .is_err());
+    assert_eq!(
+        tree_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree).unwrap()
+    );
+
+    /* the range holds 15 leaves */
+    let waddr: Vec<u64> = (4..17).collect();
+    let wdata = vec![0u8; waddr.len()];
+    let (full_tree_rh, _) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    assert!(<M as MT<Fr, u64, UInt64<Fr>>>::validate(&pp, &tree).unwrap());
+    assert!(
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &[17u64], &[0u8])
+            .is_err()
+    );
+    assert_eq!(
+        full_tree_rh,
+        <M as MT<Fr, u64, UInt64<Fr>>>::root(&pp, &tree).unwrap()
+    );
+}
+
+#[test]
+fn test_merkle_mountain_range_extend_proof() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    let mut tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let (old_tree_rh, _) = <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut tree,
+        &[1u64, 2, 3],
+        &[2u8, 3, 4],
+    )
+    .unwrap();
+    let old_proof = tree.generate_membership_proof(2).unwrap();
+
+    /* the old proof stays valid for the old root, and is extended for the new root */
+    let (tree_rh, _) = <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut tree,
+        &[5u64, 8, 9, 10],
+        &[5u8, 6, 7, 8],
+    )
+    .unwrap();
+    assert!(old_proof
+        .verify_with_location(&pp, &old_tree_rh, &3u8, 2)
+        .unwrap());
+    assert!(!old_proof
+        .verify_with_location(&pp, &tree_rh, &3u8, 2)
+        .unwrap());
+
+    let extended_proof = tree.extend_membership_proof(&old_proof).unwrap();
+    assert!(extended_proof
+        .verify_with_location(&pp, &tree_rh, &3u8, 2)
+        .unwrap());
+    assert!(!extended_proof
+        .verify_with_location(&pp, &tree_rh, &4u8, 2)
+        .unwrap());
+
+    /* a proof from another tree cannot be extended */
+    let mut other_tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut other_tree,
+        &[1u64, 2, 3],
+        &[0u8, 0, 0],
+    )
+    .unwrap();
+    let other_proof = other_tree.generate_membership_proof(2).unwrap();
+    assert!(tree.extend_membership_proof(&other_proof).is_err());
+}
+
+#[test]
+fn test_merkle_mountain_range_gadgets() {
+    let mut rng = ark_std::test_rng();
+
+    let pp = <M as MT<Fr, u64, UInt64<Fr>>>::setup(&mut rng).unwrap();
+
+    let mut tree = <M as MT<Fr, u64, UInt64<Fr>>>::new::<u8>(&pp).unwrap();
+    let (old_tree_rh, _) = <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(
+        &pp,
+        &mut tree,
+        &[1u64, 3, 4],
+        &[2u8, 4, 6],
+    )
+    .unwrap();
+
+    /* appending to 3 leaves merges two peaks */
+    let (tree_rh, modify_proof) =
+        <M as MT<Fr, u64, UInt64<Fr>>>::_modify_and_apply(&pp, &mut tree, &[6u64], &[5u8]).unwrap();
+
+    let raddr = vec![1u64, 4, 6];
+    let rdata = vec![2u8, 6, 5];
+    let lookup_proof = <M as MT<Fr, u64, UInt64<Fr>>>::lookup(&pp, &tree, &raddr).unwrap();
+    let multi_lookup_proof =
+        <M as MT<Fr, u64, UInt64<Fr>>>::multi_lookup(&pp, &tree, &raddr).unwrap();
+    let absence_proof = <M as MT<Fr, u64, UInt64<Fr>>>::prove_absence(&pp, &tree, &[7u64]).unwrap();
+
+    /* the append passes only from the root it was made for, and only at its location */
+    for &(old_rh, waddr, expected) in [
+        (old_tree_rh, 6u64, true),
+        (tree_rh, 6u64, false),
+        (old_tree_rh, 7u64, false),
+    ]
+    .iter()
+    {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let old_tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "old_tree_rh"),
+            || Ok(old_rh),
+        )
+        .unwrap();
+        let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh),
+        )
+        .unwrap();
+
+        let waddr_g =
+            vec![UInt64::new_witness(ark_relations::ns!(cs, "waddr"), || Ok(waddr)).unwrap()];
+        let wdata_g =
+            vec![UInt8::new_witness(ark_relations::ns!(cs, "wdata"), || Ok(5u8)).unwrap()];
+        let modify_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProofVar::new_witness(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(&modify_proof),
+        )
+        .unwrap();
+        <M as MT<Fr, u64, UInt64<Fr>>>::conditionally_verify_modify_gadget(
+            cs.clone(),
+            &pp,
+            &old_tree_rh_g,
+            &tree_rh_g,
+            &waddr_g,
+            &wdata_g,
+            &modify_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+
+    /* lookups, multi-lookups and absence proofs in the circuit */
+    for &(data_6, expected) in [(5u8, true), (4u8, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh),
+        )
+        .unwrap();
+        let raddr_g: Vec<UInt64<Fr>> = raddr
+            .iter()
+            .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "raddr"), || Ok(*addr)).unwrap())
+            .collect();
+        let rdata_g: Vec<UInt8<Fr>> = [rdata[0], rdata[1], data_6]
+            .iter()
+            .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "rdata"), || Ok(*data)).unwrap())
+            .collect();
+        let aaddr_g =
+            vec![UInt64::new_witness(ark_relations::ns!(cs, "aaddr"), || Ok(7u64)).unwrap()];
+
+        let absence_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::AbsenceProofVar::new_witness(
+            ark_relations::ns!(cs, "absence_proof"),
+            || Ok(&absence_proof),
+        )
+        .unwrap();
+        <M as MT<Fr, u64, UInt64<Fr>>>::verify_absence_gadget::<u8>(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &aaddr_g,
+            &absence_proof_g,
+        )
+        .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "lookup_proof"),
+            || Ok(&lookup_proof),
+        )
+        .unwrap();
+        let multi_lookup_proof_g =
+            <M as MT<Fr, u64, UInt64<Fr>>>::MultiLookupProofVar::new_witness(
+                ark_relations::ns!(cs, "multi_lookup_proof"),
+                || Ok(&multi_lookup_proof),
+            )
+            .unwrap();
+
+        <M as MT<Fr, u64, UInt64<Fr>>>::verify_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &raddr_g,
+            &rdata_g,
+            &lookup_proof_g,
+        )
+        .unwrap();
+        <M as MT<Fr, u64, UInt64<Fr>>>::verify_multi_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &raddr,
+            &rdata_g,
+            &multi_lookup_proof_g,
+        )
+        .unwrap();
+
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
@@ -0,0 +1,290 @@
+use ark_ed_on_mnt4_298::Fq as Fr;
+use ark_ivls::building_blocks::mt::{
+    merkle_sparse_quad_tree::MerkleSparseQuadTreeConfig,
+    merkle_sparse_tree::MerkleSparseTreeConfig, SparseMT, SparseQuadMT, MT,
+};
+
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+use ark_ivls::gadgets::{AllocVar, Boolean, UInt64, UInt8};
+use ark_relations::r1cs::ConstraintSystem;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::collections::BTreeMap;
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+/* both trees have 2^30 leaves */
+#[derive(Clone, Debug)]
+struct QuadP;
+impl MerkleSparseQuadTreeConfig for QuadP {
+    const HEIGHT: u64 = 16;
+    type H = H;
+}
+
+#[derive(Clone, Debug)]
+struct BinaryP;
+impl MerkleSparseTreeConfig for BinaryP {
+    const HEIGHT: u64 = 31;
+    type H = H;
+}
+
+#[test]
+fn test_merkle_sparse_quad_tree_poseidon() {
+    type M = SparseQuadMT<Fr, QuadP, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    /* tree_1 is empty initially */
+    let mut tree_1 = M::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = M::root(&pp, &tree_1).unwrap();
+    assert!(M::validate(&pp, &tree_1).unwrap());
+
+    /* tree_1 + (1, 2), (2, 4), (3, 7), (6, 9) */
+    let waddr = vec![1u64, 2, 3, 6];
+    let wdata = vec![2u8, 4, 7, 9];
+    let (tree_1_rh, tree_1_modify_proof) =
+        M::_modify_and_apply(&pp, &mut tree_1, &waddr, &wdata).unwrap();
+
+    /* check if the tree_1_rh matches the new rh */
+    assert_eq!(tree_1_rh, M::root(&pp, &tree_1).unwrap());
+    assert!(M::validate(&pp, &tree_1).unwrap());
+
+    /* check if the modify proof works */
+    assert!(M::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &tree_1_modify_proof
+    )
+    .unwrap());
+
+    /* check if a proof does NOT work when the addr is tampered */
+    let waddr_tampered = vec![2u64, 3, 1, 6];
+    assert!(!M::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr_tampered,
+        &wdata,
+        &tree_1_modify_proof
+    )
+    .unwrap());
+
+    /* check if a proof does NOT work when the data is tampered */
+    let wdata_tampered = vec![1u8, 3, 9, 9];
+    assert!(!M::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata_tampered,
+        &tree_1_modify_proof
+    )
+    .unwrap());
+
+    /* test the _new_with_map */
+    let mut data_map: BTreeMap<u64, u8> = BTreeMap::new();
+    for (addr, data) in waddr.iter().zip(wdata.iter()) {
+        data_map.insert(*addr, *data);
+    }
+
+    let tree_2 = M::_new_with_map(&pp, &data_map).unwrap();
+    assert_eq!(tree_1_rh, M::root(&pp, &tree_2).unwrap());
+
+    /* test lookup */
+    let lookup_proof = M::lookup(&pp, &tree_1, &waddr).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_1_rh, &waddr, &wdata, &lookup_proof).unwrap());
+
+    /* check if a proof does NOT work when the addr or the data is tampered */
+    assert!(!M::verify_lookup(&pp, &tree_1_rh, &waddr_tampered, &wdata, &lookup_proof).unwrap());
+    assert!(!M::verify_lookup(&pp, &tree_1_rh, &waddr, &wdata_tampered, &lookup_proof).unwrap());
+
+    /* test multi-lookup */
+    let multi_lookup_proof = M::multi_lookup(&pp, &tree_1, &waddr).unwrap();
+    assert!(M::verify_multi_lookup(&pp, &tree_1_rh, &waddr, &wdata, &multi_lookup_proof).unwrap());
+    assert!(!M::verify_multi_lookup(
+        &pp,
+        &tree_1_rh,
+        &waddr,
+        &wdata_tampered,
+        &multi_lookup_proof
+    )
+    .unwrap());
+
+    /* check if the proofs still work after a serialization round trip */
+    let mut lookup_proof_bytes = Vec::new();
+    lookup_proof.serialize(&mut lookup_proof_bytes).unwrap();
+    let lookup_proof_deserialized =
+        <M as MT<Fr, u64, UInt64<Fr>>>::LookupProof::deserialize(&lookup_proof_bytes[..]).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_1_rh, &waddr, &wdata, &lookup_proof_deserialized).unwrap());
+
+    let mut tree_bytes = Vec::new();
+    M::serialize_tree(&tree_1, &mut tree_bytes).unwrap();
+    let tree_3 = M::deserialize_tree::<u8, _>(&pp, &tree_bytes[..]).unwrap();
+    assert_eq!(tree_1_rh, M::root(&pp, &tree_3).unwrap());
+
+    /* test absence */
+    let aaddr = vec![4u64, 100];
+    let absence_proof = M::prove_absence(&pp, &tree_1, &aaddr).unwrap();
+    assert!(M::verify_absence::<u8>(&pp, &tree_1_rh, &aaddr, &absence_proof).unwrap());
+
+    /* check if an absence proof does NOT work when the addr is tampered */
+    let aaddr_tampered = vec![6u64, 100];
+    assert!(!M::verify_absence::<u8>(&pp, &tree_1_rh, &aaddr_tampered, &absence_proof).unwrap());
+
+    /* check if a written address cannot be proven absent */
+    assert!(M::prove_absence(&pp, &tree_1, &waddr).is_err());
+
+    M::clear(&pp, &mut tree_1).unwrap();
+}
+
+#[test]
+fn test_merkle_sparse_quad_tree_gadgets() {
+    type M = SparseQuadMT<Fr, QuadP, HG>;
+    type BinaryM = SparseMT<Fr, BinaryP, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    let waddr = vec![1u64, 2, 3];
+    let wdata = vec![2u8, 4, 7];
+
+    let mut tree = M::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = M::root(&pp, &tree).unwrap();
+    let (tree_rh, modify_proof) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    let lookup_proof = M::lookup(&pp, &tree, &waddr).unwrap();
+
+    let mut binary_tree = BinaryM::new::<u8>(&pp).unwrap();
+    let (binary_tree_rh, _) =
+        BinaryM::_modify_and_apply(&pp, &mut binary_tree, &waddr, &wdata).unwrap();
+    let binary_lookup_proof = BinaryM::lookup(&pp, &binary_tree, &waddr).unwrap();
+
+    let cs = ConstraintSystem::<Fr>::new_ref();
+
+    let addr_g: Vec<UInt64<Fr>> = waddr
+        .iter()
+        .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+        .collect();
+    let data_g: Vec<UInt8<Fr>> = wdata
+        .iter()
+        .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+        .collect();
+
+    /* the modifying proof */
+    let empty_tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "empty_tree_rh"),
+        || Ok(empty_tree_rh.clone()),
+    )
+    .unwrap();
+    let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "tree_rh"),
+        || Ok(tree_rh.clone()),
+    )
+    .unwrap();
+    let modify_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProofVar::new_witness(
+        ark_relations::ns!(cs, "modify_proof"),
+        || Ok(&modify_proof),
+    )
+    .unwrap();
+    M::conditionally_verify_modify_gadget(
+        cs.clone(),
+        &pp,
+        &empty_tree_rh_g,
+        &tree_rh_g,
+        &addr_g,
+        &data_g,
+        &modify_proof_g,
+        &Boolean::constant(true),
+    )
+    .unwrap();
+
+    /* the lookup proof in the 4-ary tree */
+    let num_constraints = cs.num_constraints();
+    let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "lookup_proof"),
+        || Ok(&lookup_proof),
+    )
+    .unwrap();
+    M::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &addr_g,
+        &data_g,
+        &lookup_proof_g,
+    )
+    .unwrap();
+    let quad_lookup_constraints = cs.num_constraints() - num_constraints;
+
+    /* the lookup proof in the binary tree of the same capacity */
+    let num_constraints = cs.num_constraints();
+    let binary_tree_rh_g = <BinaryM as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "binary_tree_rh"),
+        || Ok(binary_tree_rh.clone()),
+    )
+    .unwrap();
+    let binary_lookup_proof_g = <BinaryM as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "binary_lookup_proof"),
+        || Ok(&binary_lookup_proof),
+    )
+    .unwrap();
+    BinaryM::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &binary_tree_rh_g,
+        &addr_g,
+        &data_g,
+        &binary_lookup_proof_g,
+    )
+    .unwrap();
+    let binary_lookup_constraints = cs.num_constraints() - num_constraints;
+
+    println!(
+        "number of constraints for {} lookups: 4-ary {}, binary {}",
+        waddr.len(),
+        quad_lookup_constraints,
+        binary_lookup_constraints
+    );
+
+    assert!(cs.is_satisfied().unwrap());
+
+    /* a tampered leaf does not pass */
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "tree_rh"),
+        || Ok(tree_rh.clone()),
+    )
+    .unwrap();
+    let addr_g: Vec<UInt64<Fr>> = waddr
+        .iter()
+        .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+        .collect();
+    let data_g: Vec<UInt8<Fr>> = [2u8, 4, 8]
+        .iter()
+        .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+        .collect();
+    let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "lookup_proof"),
+        || Ok(&lookup_proof),
+    )
+    .unwrap();
+    M::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &addr_g,
+        &data_g,
+        &lookup_proof_g,
+    )
+    .unwrap();
+    assert!(!cs.is_satisfied().unwrap());
+}
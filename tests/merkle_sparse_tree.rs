@@ -1,5 +1,12 @@
 use ark_ed_on_mnt4_298::Fq as Fr;
-use ark_ivls::building_blocks::mt::{merkle_sparse_tree::MerkleSparseTreeConfig, SparseMT, MT};
+use ark_ivls::building_blocks::crh::blake2s::{
+    Blake2sCRHforMerkleTree, Blake2sCRHforMerkleTreeGadget,
+};
+use ark_ivls::building_blocks::crh::CRHforMerkleTree;
+use ark_ivls::building_blocks::mt::{
+    merkle_sparse_tree::{storage::FileNodeStorage, MerkleSparseTree, MerkleSparseTreeConfig},
+    FieldLeaf, FieldLeafVar, SparseMT, MT,
+};
 
 use rand_chacha::ChaChaRng;
 
@@ -10,7 +17,14 @@ use ark_ivls::building_blocks::crh::bowe_hopwood::{
 use ark_ivls::building_blocks::crh::poseidon::{
     PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
 };
+use ark_ivls::building_blocks::crh::rescue::{
+    RescueCRHforMerkleTree, RescueCRHforMerkleTreeGadget,
+};
+use ark_ivls::gadgets::{AllocVar, Boolean, FpVar, UInt64, UInt8};
+use ark_relations::r1cs::ConstraintSystem;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::collections::BTreeMap;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
 
 #[test]
 fn test_merkle_sparse_tree_pedersen() {
@@ -125,6 +139,49 @@ fn test_merkle_sparse_tree_pedersen() {
     )
     .unwrap());
 
+    /* check if the proofs still work after a serialization round trip */
+    let mut lookup_proof_bytes = Vec::new();
+    lookup_proof.serialize(&mut lookup_proof_bytes).unwrap();
+    assert_eq!(lookup_proof_bytes.len(), lookup_proof.serialized_size());
+    let lookup_proof_deserialized =
+        <M as MT<Fr, u64, UInt64<Fr>>>::LookupProof::deserialize(&lookup_proof_bytes[..]).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_1_rh, &raddr, &rdata, &lookup_proof_deserialized).unwrap());
+
+    let mut modify_proof_bytes = Vec::new();
+    tree_1_modify_proof
+        .serialize_uncompressed(&mut modify_proof_bytes)
+        .unwrap();
+    assert_eq!(
+        modify_proof_bytes.len(),
+        tree_1_modify_proof.uncompressed_size()
+    );
+    let modify_proof_deserialized =
+        <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProof::deserialize_uncompressed(
+            &modify_proof_bytes[..],
+        )
+        .unwrap();
+    assert!(M::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_1_rh,
+        &waddr,
+        &wdata,
+        &modify_proof_deserialized
+    )
+    .unwrap());
+
+    /* test absence */
+    let aaddr = vec![4u64, 100];
+    let absence_proof = M::prove_absence(&pp, &tree_1, &aaddr).unwrap();
+    assert!(M::verify_absence::<u8>(&pp, &tree_1_rh, &aaddr, &absence_proof).unwrap());
+
+    /* check if an absence proof does NOT work when the addr is tampered */
+    let aaddr_tampered = vec![5u64, 100];
+    assert!(!M::verify_absence::<u8>(&pp, &tree_1_rh, &aaddr_tampered, &absence_proof).unwrap());
+
+    /* check if a written address cannot be proven absent */
+    assert!(M::prove_absence(&pp, &tree_1, &raddr).is_err());
+
     M::clear(&pp, &mut tree_1).unwrap();
 }
 
@@ -242,5 +299,780 @@ fn test_merkle_sparse_tree_poseidon() {
     )
     .unwrap());
 
+    /* test absence */
+    let aaddr = vec![4u64, 100];
+    let absence_proof = M::prove_absence(&pp, &tree_1, &aaddr).unwrap();
+    assert!(M::verify_absence::<u8>(&pp, &tree_1_rh, &aaddr, &absence_proof).unwrap());
+
+    /* check if an absence proof does NOT work when the addr is tampered */
+    let aaddr_tampered = vec![5u64, 100];
+    assert!(!M::verify_absence::<u8>(&pp, &tree_1_rh, &aaddr_tampered, &absence_proof).unwrap());
+
+    /* check if a written address cannot be proven absent */
+    assert!(M::prove_absence(&pp, &tree_1, &raddr).is_err());
+
     M::clear(&pp, &mut tree_1).unwrap();
 }
+
+#[test]
+fn test_merkle_sparse_tree_partial_gadgets() {
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    let mut tree = M::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = M::root(&pp, &tree).unwrap();
+
+    /* tree + (1, 2), (2, 4), with the proofs padded to three entries */
+    let waddr = vec![1u64, 2];
+    let wdata = vec![2u8, 4];
+    let (tree_rh, mut modify_proof) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    M::pad_modify_proof(&mut modify_proof, 3).unwrap();
+
+    let mut lookup_proof = M::lookup(&pp, &tree, &waddr).unwrap();
+    M::pad_lookup_proof(&mut lookup_proof, 3).unwrap();
+
+    /* the padded entry passes only if it is inactive */
+    for &(padding_active, expected) in [(false, true), (true, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let empty_tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "empty_tree_rh"),
+            || Ok(empty_tree_rh.clone()),
+        )
+        .unwrap();
+        let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh.clone()),
+        )
+        .unwrap();
+
+        let addr_g: Vec<UInt64<Fr>> = [1u64, 2, 0]
+            .iter()
+            .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+            .collect();
+        let data_g: Vec<UInt8<Fr>> = [2u8, 4, 0]
+            .iter()
+            .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+            .collect();
+        let active_g = vec![
+            Boolean::constant(true),
+            Boolean::constant(true),
+            Boolean::new_witness(ark_relations::ns!(cs, "padding_active"), || {
+                Ok(padding_active)
+            })
+            .unwrap(),
+        ];
+
+        let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "lookup_proof"),
+            || Ok(&lookup_proof),
+        )
+        .unwrap();
+        let modify_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProofVar::new_witness(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(&modify_proof),
+        )
+        .unwrap();
+
+        M::conditionally_verify_partial_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &active_g,
+            &lookup_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+
+        M::conditionally_verify_partial_modify_gadget(
+            cs.clone(),
+            &pp,
+            &empty_tree_rh_g,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &active_g,
+            &modify_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_merkle_sparse_tree_field_leaves() {
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    /* the leaves hashed as field elements give a different tree from the leaves hashed as bytes */
+    let mut tree = M::new::<FieldLeaf<Fr>>(&pp).unwrap();
+    let empty_tree_rh = M::root(&pp, &tree).unwrap();
+    assert_ne!(
+        empty_tree_rh,
+        M::root(&pp, &M::new::<Fr>(&pp).unwrap()).unwrap()
+    );
+
+    /* tree + (1, 2), (2, 4), with the proofs padded to three entries */
+    let waddr = vec![1u64, 2];
+    let wdata = vec![FieldLeaf(Fr::from(2u64)), FieldLeaf(Fr::from(4u64))];
+    let (tree_rh, mut modify_proof) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    assert_eq!(tree_rh, M::root(&pp, &tree).unwrap());
+    assert!(M::validate(&pp, &tree).unwrap());
+
+    assert!(
+        M::verify_modify(&pp, &empty_tree_rh, &tree_rh, &waddr, &wdata, &modify_proof).unwrap()
+    );
+    let wdata_bytes: Vec<Fr> = wdata.iter().map(|leaf| leaf.0).collect();
+    assert!(!M::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &tree_rh,
+        &waddr,
+        &wdata_bytes,
+        &modify_proof
+    )
+    .unwrap());
+
+    let mut data_map: BTreeMap<u64, FieldLeaf<Fr>> = BTreeMap::new();
+    data_map.insert(waddr[0], wdata[0].clone());
+    data_map.insert(waddr[1], wdata[1].clone());
+    let tree_2 = M::_new_with_map(&pp, &data_map).unwrap();
+    assert_eq!(tree_rh, M::root(&pp, &tree_2).unwrap());
+
+    let mut lookup_proof = M::lookup(&pp, &tree, &waddr).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_rh, &waddr, &wdata, &lookup_proof).unwrap());
+    let wdata_tampered = vec![FieldLeaf(Fr::from(4u64)), FieldLeaf(Fr::from(2u64))];
+    assert!(!M::verify_lookup(&pp, &tree_rh, &waddr, &wdata_tampered, &lookup_proof).unwrap());
+
+    M::pad_modify_proof(&mut modify_proof, 3).unwrap();
+    M::pad_lookup_proof(&mut lookup_proof, 3).unwrap();
+
+    /* the gadgets accept the proofs only for the right data */
+    for &(data, expected) in [(4u64, true), (5u64, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+
+        let empty_tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "empty_tree_rh"),
+            || Ok(empty_tree_rh.clone()),
+        )
+        .unwrap();
+        let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh.clone()),
+        )
+        .unwrap();
+
+        let addr_g: Vec<UInt64<Fr>> = [1u64, 2, 0]
+            .iter()
+            .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+            .collect();
+        let data_g: Vec<FieldLeafVar<FpVar<Fr>>> = [2u64, data, 0]
+            .iter()
+            .map(|data| {
+                FieldLeafVar(
+                    FpVar::new_witness(ark_relations::ns!(cs, "data"), || Ok(Fr::from(*data)))
+                        .unwrap(),
+                )
+            })
+            .collect();
+        let active_g = vec![
+            Boolean::constant(true),
+            Boolean::constant(true),
+            Boolean::constant(false),
+        ];
+
+        let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "lookup_proof"),
+            || Ok(&lookup_proof),
+        )
+        .unwrap();
+        let modify_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::ModifyProofVar::new_witness(
+            ark_relations::ns!(cs, "modify_proof"),
+            || Ok(&modify_proof),
+        )
+        .unwrap();
+
+        M::conditionally_verify_partial_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &active_g,
+            &lookup_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+
+        M::conditionally_verify_partial_modify_gadget(
+            cs.clone(),
+            &pp,
+            &empty_tree_rh_g,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &active_g,
+            &modify_proof_g,
+            &Boolean::constant(true),
+        )
+        .unwrap();
+
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_merkle_sparse_tree_multi_lookup() {
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    let mut tree = M::new::<u8>(&pp).unwrap();
+
+    let waddr = vec![4u64, 5, 6, 7];
+    let wdata = vec![1u8, 2, 3, 4];
+    let (tree_rh, _) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+
+    /* the multi-opening verifies and is smaller than the separate paths */
+    let lookup_proof = M::lookup(&pp, &tree, &waddr).unwrap();
+    let multi_lookup_proof = M::multi_lookup(&pp, &tree, &waddr).unwrap();
+    assert!(M::verify_multi_lookup(&pp, &tree_rh, &waddr, &wdata, &multi_lookup_proof).unwrap());
+    assert!(
+        !M::verify_multi_lookup(&pp, &tree_rh, &waddr, &[1u8, 2, 3, 5], &multi_lookup_proof)
+            .unwrap()
+    );
+    assert!(
+        multi_lookup_proof.serialized_size() < lookup_proof.serialized_size(),
+        "the multi-opening is not smaller"
+    );
+
+    /* the multi-opening also takes fewer constraints than the separate paths */
+    let cs = ConstraintSystem::<Fr>::new_ref();
+
+    let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "tree_rh"),
+        || Ok(tree_rh.clone()),
+    )
+    .unwrap();
+    let addr_g: Vec<UInt64<Fr>> = waddr
+        .iter()
+        .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+        .collect();
+    let data_g: Vec<UInt8<Fr>> = wdata
+        .iter()
+        .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+        .collect();
+
+    let num_constraints = cs.num_constraints();
+    let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "lookup_proof"),
+        || Ok(&lookup_proof),
+    )
+    .unwrap();
+    M::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &addr_g,
+        &data_g,
+        &lookup_proof_g,
+    )
+    .unwrap();
+    let lookup_constraints = cs.num_constraints() - num_constraints;
+
+    let num_constraints = cs.num_constraints();
+    let multi_lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::MultiLookupProofVar::new_witness(
+        ark_relations::ns!(cs, "multi_lookup_proof"),
+        || Ok(&multi_lookup_proof),
+    )
+    .unwrap();
+    M::verify_multi_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &waddr,
+        &data_g,
+        &multi_lookup_proof_g,
+    )
+    .unwrap();
+    let multi_lookup_constraints = cs.num_constraints() - num_constraints;
+
+    assert!(cs.is_satisfied().unwrap());
+    assert!(multi_lookup_constraints < lookup_constraints);
+}
+
+#[test]
+fn test_merkle_sparse_tree_batched_lookup() {
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    let mut tree = M::new::<u8>(&pp).unwrap();
+
+    let waddr = vec![3u64, 100, 7000, 12345];
+    let wdata = vec![1u8, 2, 3, 4];
+    let (tree_rh, _) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+
+    /* the batched opening verifies for the right data only */
+    let lookup_proof = M::lookup(&pp, &tree, &waddr).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_rh, &waddr, &wdata, &lookup_proof).unwrap());
+    assert!(!M::verify_lookup(&pp, &tree_rh, &waddr, &[1u8, 2, 3, 5], &lookup_proof).unwrap());
+
+    /* with the addresses as witnesses, the batched opening takes fewer constraints than
+    one lookup per address */
+    let cs = ConstraintSystem::<Fr>::new_ref();
+
+    let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "tree_rh"),
+        || Ok(tree_rh.clone()),
+    )
+    .unwrap();
+    let addr_g: Vec<UInt64<Fr>> = waddr
+        .iter()
+        .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+        .collect();
+    let data_g: Vec<UInt8<Fr>> = wdata
+        .iter()
+        .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+        .collect();
+
+    let num_constraints = cs.num_constraints();
+    let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "lookup_proof"),
+        || Ok(&lookup_proof),
+    )
+    .unwrap();
+    M::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &addr_g,
+        &data_g,
+        &lookup_proof_g,
+    )
+    .unwrap();
+    let batched_constraints = cs.num_constraints() - num_constraints;
+
+    let num_constraints = cs.num_constraints();
+    for i in 0..waddr.len() {
+        let single_lookup_proof = M::lookup(&pp, &tree, &waddr[i..i + 1]).unwrap();
+        let single_lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "single_lookup_proof"),
+            || Ok(&single_lookup_proof),
+        )
+        .unwrap();
+        M::verify_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g[i..i + 1],
+            &data_g[i..i + 1],
+            &single_lookup_proof_g,
+        )
+        .unwrap();
+    }
+    let separate_constraints = cs.num_constraints() - num_constraints;
+
+    assert!(cs.is_satisfied().unwrap());
+    assert!(batched_constraints < separate_constraints);
+
+    /* malformed proofs are rejected with an error instead of a panic */
+    assert!(M::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &addr_g[..3],
+        &data_g[..3],
+        &lookup_proof_g,
+    )
+    .is_err());
+
+    let default_multi_lookup_proof_g =
+        <M as MT<Fr, u64, UInt64<Fr>>>::MultiLookupProofVar::new_witness(
+            ark_relations::ns!(cs, "default_multi_lookup_proof"),
+            || Ok(<M as MT<Fr, u64, UInt64<Fr>>>::MultiLookupProof::default()),
+        )
+        .unwrap();
+    assert!(M::verify_multi_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &waddr,
+        &data_g,
+        &default_multi_lookup_proof_g,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_merkle_sparse_tree_file_storage() {
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type S = FileNodeStorage<<H as CRHforMerkleTree>::Output>;
+    type M = SparseMT<Fr, P, HG>;
+    type FileM = SparseMT<Fr, P, HG, S>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    let mut tree = M::new::<u8>(&pp).unwrap();
+    let mut file_tree = FileM::new::<u8>(&pp).unwrap();
+    assert_eq!(
+        M::root(&pp, &tree).unwrap(),
+        FileM::root(&pp, &file_tree).unwrap()
+    );
+
+    /* the file-backed tree follows the in-memory tree, including overwrites */
+    let empty_tree_rh = M::root(&pp, &tree).unwrap();
+    let waddr = vec![1u64, 2, 3, 2];
+    let wdata = vec![2u8, 4, 7, 5];
+    let (tree_rh, _) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    let (file_tree_rh, file_tree_modify_proof) =
+        FileM::_modify_and_apply(&pp, &mut file_tree, &waddr, &wdata).unwrap();
+    assert_eq!(tree_rh, file_tree_rh);
+    assert!(FileM::verify_modify(
+        &pp,
+        &empty_tree_rh,
+        &file_tree_rh,
+        &waddr,
+        &wdata,
+        &file_tree_modify_proof
+    )
+    .unwrap());
+    assert!(FileM::validate(&pp, &file_tree).unwrap());
+
+    let raddr = vec![1u64, 2, 3];
+    let rdata = vec![2u8, 5, 7];
+    let lookup_proof = FileM::lookup(&pp, &file_tree, &raddr).unwrap();
+    assert!(FileM::verify_lookup(&pp, &file_tree_rh, &raddr, &rdata, &lookup_proof).unwrap());
+
+    /* the log can be compacted, and reopened to restore the tree */
+    let path = std::env::temp_dir().join("ivls-test-merkle-sparse-tree-file-storage.log");
+    let _ = std::fs::remove_file(&path);
+    let file_storage = S::open(&path).unwrap();
+    let mut named_tree =
+        MerkleSparseTree::<P, S>::from_storage::<u8>(pp.clone(), file_storage).unwrap();
+    for (addr, data) in waddr.iter().zip(wdata.iter()) {
+        named_tree.update_and_prove(*addr, data).unwrap();
+    }
+    let log_len = std::fs::metadata(&path).unwrap().len();
+    named_tree.tree.compact().unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() < log_len);
+    drop(named_tree);
+
+    let mut reopened_tree = FileM::new_with_storage::<u8>(&pp, S::open(&path).unwrap()).unwrap();
+    assert_eq!(FileM::root(&pp, &reopened_tree).unwrap(), tree_rh);
+    assert!(FileM::validate(&pp, &reopened_tree).unwrap());
+
+    /* a batch cut short by a crash is dropped on reopening, even if the index covers it */
+    let log_len = std::fs::metadata(&path).unwrap().len();
+    let (crashed_rh, _) = FileM::_modify_and_apply(&pp, &mut reopened_tree, &[9], &[1u8]).unwrap();
+    assert_ne!(crashed_rh, tree_rh);
+    let crashed_log_len = std::fs::metadata(&path).unwrap().len();
+    drop(reopened_tree);
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(crashed_log_len - 1)
+        .unwrap();
+    let recovered_tree = FileM::new_with_storage::<u8>(&pp, S::open(&path).unwrap()).unwrap();
+    assert_eq!(FileM::root(&pp, &recovered_tree).unwrap(), tree_rh);
+    assert!(FileM::validate(&pp, &recovered_tree).unwrap());
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), log_len);
+    drop(recovered_tree);
+
+    /* the index is rebuilt from the log if it is lost */
+    let mut index_path = path.clone().into_os_string();
+    index_path.push(".index");
+    std::fs::remove_file(&index_path).unwrap();
+    let rebuilt_tree = FileM::new_with_storage::<u8>(&pp, S::open(&path).unwrap()).unwrap();
+    assert_eq!(FileM::root(&pp, &rebuilt_tree).unwrap(), tree_rh);
+    assert!(FileM::validate(&pp, &rebuilt_tree).unwrap());
+    drop(rebuilt_tree);
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&index_path).unwrap();
+
+    /* a versioned tree keeps its past versions when reopened, also after compaction */
+    let path = std::env::temp_dir().join("ivls-test-merkle-sparse-tree-file-storage-versions.log");
+    let _ = std::fs::remove_file(&path);
+    let mut versioned_tree = FileM::new_with_storage::<u8>(&pp, S::open(&path).unwrap()).unwrap();
+    FileM::enable_versioning(&pp, &mut versioned_tree, 0).unwrap();
+    FileM::_modify_and_apply(&pp, &mut versioned_tree, &waddr, &wdata).unwrap();
+    FileM::set_version(&pp, &mut versioned_tree, 1).unwrap();
+    let (latest_rh, _) = FileM::_modify_and_apply(&pp, &mut versioned_tree, &[1], &[9u8]).unwrap();
+    FileM::set_version(&pp, &mut versioned_tree, 2).unwrap();
+    drop(versioned_tree);
+
+    for compact in [false, true].iter() {
+        let mut reopened_tree =
+            FileM::new_with_storage::<u8>(&pp, S::open(&path).unwrap()).unwrap();
+        if *compact {
+            reopened_tree.tree.compact().unwrap();
+        }
+        assert_eq!(reopened_tree.version(), Some(2));
+        assert_eq!(reopened_tree.root_at(0).unwrap(), empty_tree_rh);
+        assert_eq!(reopened_tree.root_at(1).unwrap(), tree_rh);
+        assert_eq!(reopened_tree.root_at(2).unwrap(), latest_rh);
+
+        let lookup_proof = FileM::lookup_at(&pp, &reopened_tree, 1, &raddr).unwrap();
+        assert!(FileM::verify_lookup(&pp, &tree_rh, &raddr, &rdata, &lookup_proof).unwrap());
+    }
+
+    let mut index_path = path.clone().into_os_string();
+    index_path.push(".index");
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&index_path).unwrap();
+}
+
+#[test]
+fn test_merkle_sparse_tree_poseidon_seeded_parameters() {
+    type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    /* the rounds include the security margin */
+    let alpha = H::sbox_exponent();
+    assert!(alpha >= 3 && alpha % 2 == 1);
+    let (full_rounds, partial_rounds) = H::round_numbers(alpha, 3);
+    assert_eq!(full_rounds, 8);
+    assert!(partial_rounds > 0);
+
+    let waddr = vec![1u64, 2, 3];
+    let wdata = vec![2u8, 4, 7];
+    let root_from_seed = |seed: [u8; 32]| {
+        let pp = M::setup(&mut StdRng::from_seed(seed)).unwrap();
+        let mut data_map: BTreeMap<u64, u8> = BTreeMap::new();
+        for (addr, data) in waddr.iter().zip(wdata.iter()) {
+            data_map.insert(*addr, *data);
+        }
+        let tree = M::_new_with_map(&pp, &data_map).unwrap();
+        M::root(&pp, &tree).unwrap()
+    };
+
+    /* the parameters are reproducible from the seed */
+    assert_eq!(root_from_seed([1u8; 32]), root_from_seed([1u8; 32]));
+    assert_ne!(root_from_seed([1u8; 32]), root_from_seed([2u8; 32]));
+}
+
+#[test]
+fn test_merkle_sparse_tree_rescue() {
+    type H = RescueCRHforMerkleTree<ChaChaRng, Fr>;
+    type HG = RescueCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 32;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    /* tree + (1, 2), (2, 4), (3, 7) */
+    let mut tree = M::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = M::root(&pp, &tree).unwrap();
+    let waddr = vec![1u64, 2, 3];
+    let wdata = vec![2u8, 4, 7];
+    let (tree_rh, modify_proof) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    assert!(
+        M::verify_modify(&pp, &empty_tree_rh, &tree_rh, &waddr, &wdata, &modify_proof).unwrap()
+    );
+
+    let lookup_proof = M::lookup(&pp, &tree, &waddr).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_rh, &waddr, &wdata, &lookup_proof).unwrap());
+
+    /* check if a proof does NOT work when the data is tampered */
+    let wdata_tampered = vec![1u8, 3, 9];
+    assert!(!M::verify_lookup(&pp, &tree_rh, &waddr, &wdata_tampered, &lookup_proof).unwrap());
+
+    /* the lookup proof also works in the circuit */
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+        ark_relations::ns!(cs, "tree_rh"),
+        || Ok(tree_rh.clone()),
+    )
+    .unwrap();
+    let addr_g: Vec<UInt64<Fr>> = waddr
+        .iter()
+        .map(|addr| UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(*addr)).unwrap())
+        .collect();
+    let data_g: Vec<UInt8<Fr>> = wdata
+        .iter()
+        .map(|data| UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(*data)).unwrap())
+        .collect();
+    let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+        ark_relations::ns!(cs, "lookup_proof"),
+        || Ok(&lookup_proof),
+    )
+    .unwrap();
+
+    M::verify_lookup_gadget(
+        cs.clone(),
+        &pp,
+        &tree_rh_g,
+        &addr_g,
+        &data_g,
+        &lookup_proof_g,
+    )
+    .unwrap();
+    assert!(cs.is_satisfied().unwrap());
+    println!("number of constraints: {}", cs.num_constraints());
+}
+
+#[test]
+fn test_merkle_sparse_tree_blake2s() {
+    type H = Blake2sCRHforMerkleTree;
+    type HG = Blake2sCRHforMerkleTreeGadget<Fr>;
+
+    /* Blake2s is the standard unkeyed Blake2s-256 */
+    let abc_digest = H::hash_bytes(&(), b"abc").unwrap();
+    let mut abc_digest_bytes = Vec::new();
+    abc_digest.serialize(&mut abc_digest_bytes).unwrap();
+    assert_eq!(
+        abc_digest_bytes,
+        vec![
+            0x50, 0x8c, 0x5e, 0x8c, 0x32, 0x7c, 0x14, 0xe2, 0xe1, 0xa7, 0x2b, 0xa3, 0x4e, 0xeb,
+            0x45, 0x2f, 0x37, 0x45, 0x8b, 0x20, 0x9e, 0xd6, 0x3a, 0x29, 0x4d, 0x99, 0x9b, 0x4c,
+            0x86, 0x67, 0x59, 0x82
+        ]
+    );
+
+    /* a short tree keeps the number of Blake2s constraints small */
+    #[derive(Clone, Debug)]
+    struct P;
+    impl MerkleSparseTreeConfig for P {
+        const HEIGHT: u64 = 4;
+        type H = H;
+    }
+
+    type M = SparseMT<Fr, P, HG>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = M::setup(&mut rng).unwrap();
+
+    /* tree + (1, 2), (2, 4), (3, 7) */
+    let mut tree = M::new::<u8>(&pp).unwrap();
+    let empty_tree_rh = M::root(&pp, &tree).unwrap();
+    let waddr = vec![1u64, 2, 3];
+    let wdata = vec![2u8, 4, 7];
+    let (tree_rh, modify_proof) = M::_modify_and_apply(&pp, &mut tree, &waddr, &wdata).unwrap();
+    assert!(
+        M::verify_modify(&pp, &empty_tree_rh, &tree_rh, &waddr, &wdata, &modify_proof).unwrap()
+    );
+
+    let raddr = vec![2u64];
+    let rdata = vec![4u8];
+    let lookup_proof = M::lookup(&pp, &tree, &raddr).unwrap();
+    assert!(M::verify_lookup(&pp, &tree_rh, &raddr, &rdata, &lookup_proof).unwrap());
+
+    /* check if a proof does NOT work when the data is tampered */
+    assert!(!M::verify_lookup(&pp, &tree_rh, &raddr, &[5u8], &lookup_proof).unwrap());
+
+    /* the lookup proof also works in the circuit */
+    for &(data, expected) in [(4u8, true), (5u8, false)].iter() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let tree_rh_g = <M as MT<Fr, u64, UInt64<Fr>>>::DigestVar::new_witness(
+            ark_relations::ns!(cs, "tree_rh"),
+            || Ok(tree_rh.clone()),
+        )
+        .unwrap();
+        let addr_g =
+            vec![UInt64::new_witness(ark_relations::ns!(cs, "addr"), || Ok(raddr[0])).unwrap()];
+        let data_g = vec![UInt8::new_witness(ark_relations::ns!(cs, "data"), || Ok(data)).unwrap()];
+        let lookup_proof_g = <M as MT<Fr, u64, UInt64<Fr>>>::LookupProofVar::new_witness(
+            ark_relations::ns!(cs, "lookup_proof"),
+            || Ok(&lookup_proof),
+        )
+        .unwrap();
+
+        M::verify_lookup_gadget(
+            cs.clone(),
+            &pp,
+            &tree_rh_g,
+            &addr_g,
+            &data_g,
+            &lookup_proof_g,
+        )
+        .unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), expected);
+    }
+}
@@ -1,5 +1,6 @@
 use ark_ivls::ledger_system::{
-    example::{ExampleTransitionFunction, ExampleTx},
+    client_function::ClientFunction,
+    example::{ExampleClientFunction, ExampleTransitionFunction, ExampleTx},
     state::State,
     transition_function::TransitionFunction,
 };
@@ -52,3 +53,68 @@ fn test_state() {
     let val_2_new = state.read(&tx_4.key).unwrap().clone();
     assert_eq!(val_2_new, F::from_repr(BigInteger320::from(12u64)).unwrap());
 }
+
+#[test]
+fn test_client_function() {
+    type F = Fq;
+    type TF = ExampleTransitionFunction<F>;
+    type CF = ExampleClientFunction<F>;
+
+    let mut state = State::<F, TF>::default();
+
+    let tx_1 = ExampleTx::<F> {
+        key: 1,
+        val: F::from(7u64),
+    };
+    ExampleTransitionFunction::run(&mut state, &tx_1).unwrap();
+
+    /* query a written key */
+    let (answer, raddr, rdata) = CF::run(&state, &1u64).unwrap();
+    assert_eq!(answer, tx_1.val);
+    assert!(CF::verify(&1u64, &answer, &raddr, &rdata).unwrap());
+
+    /* query an unwritten key, which returns the default data */
+    let (answer_empty, raddr_empty, rdata_empty) = CF::run(&state, &2u64).unwrap();
+    assert_eq!(answer_empty, F::default());
+    assert!(CF::verify(&2u64, &answer_empty, &raddr_empty, &rdata_empty).unwrap());
+
+    /* check that a wrong answer or a wrong query is rejected */
+    assert!(!CF::verify(&1u64, &F::from(8u64), &raddr, &rdata).unwrap());
+    assert!(!CF::verify(&2u64, &answer, &raddr, &rdata).unwrap());
+}
+
+#[test]
+fn test_state_snapshot() {
+    type F = Fq;
+    type TF = ExampleTransitionFunction<F>;
+
+    let mut state = State::<F, TF>::default();
+
+    let tx_1 = ExampleTx::<F> {
+        key: 1,
+        val: F::from(1u64),
+    };
+    let tx_2 = ExampleTx::<F> {
+        key: 2,
+        val: F::from(3u64),
+    };
+
+    ExampleTransitionFunction::run(&mut state, &tx_1).unwrap();
+    ExampleTransitionFunction::run(&mut state, &tx_2).unwrap();
+
+    /* write a snapshot and restore it */
+    let mut snapshot = Vec::new();
+    state.snapshot(&mut snapshot).unwrap();
+    let mut state_restored = State::<F, TF>::restore(&snapshot[..]).unwrap();
+
+    assert_eq!(state_restored.map, state.map);
+    assert_eq!(state_restored.read(&tx_1.key).unwrap(), &tx_1.val);
+    assert_eq!(state_restored.read(&tx_2.key).unwrap(), &tx_2.val);
+
+    /* the restored state can continue to run */
+    ExampleTransitionFunction::run(&mut state_restored, &tx_2).unwrap();
+    assert_eq!(state_restored.read(&tx_2.key).unwrap(), &F::from(6u64));
+
+    /* check that a truncated snapshot is rejected */
+    assert!(State::<F, TF>::restore(&snapshot[..snapshot.len() - 1]).is_err());
+}
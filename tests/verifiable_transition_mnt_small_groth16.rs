@@ -14,15 +14,24 @@ use ark_pcd::{
 };
 
 use ark_crypto_primitives::crh::pedersen::Window as PedersenWindow;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::marker::PhantomData;
 use rand_chacha::ChaChaRng;
 
 use ark_ivls::{
     building_blocks::mt::{merkle_sparse_tree::MerkleSparseTreeConfig, SparseMT},
-    compiler::circuit_specific_setup_compiler::CircuitSpecificSetupIVLSCompiler,
-    ivls::{state::AuxState, transition_function::VerifiableTransitionFunctionConfig},
+    compiler::{
+        circuit_specific_setup_compiler::CircuitSpecificSetupIVLSCompiler, IVLSProverBundle,
+        IVLSVerifierBundle,
+    },
+    ivls::{
+        data_structures::{Commitment, VerifiableTransitionFunctionMsg},
+        error::IVLSError,
+        state::AuxState,
+        transition_function::VerifiableTransitionFunctionConfig,
+    },
     ledger_system::{
-        example::{ExampleTransitionFunction, ExampleTx},
+        example::{ExampleClientFunction, ExampleTransitionFunction, ExampleTx},
         state::State,
     },
 };
@@ -72,6 +81,8 @@ impl<I: PCD<Fr>> VerifiableTransitionFunctionConfig for VCTemplate<I> {
     type MTState = SparseMT<Self::F, P, HG>;
     type MTHistory = SparseMT<Self::F, P, HG>;
     type I = I;
+
+    const STATE_VERSIONS: u64 = 2;
 }
 
 type TestPCD = ECCyclePCD<Fr, Fq, PCDGroth16Mnt4>;
@@ -116,6 +127,15 @@ fn test_verifiable_transition_mnt_small_groth16_cycle_pcd() {
         .unwrap();
     println!("tx_1 time: {}", tx_1_start.elapsed().as_secs());
 
+    let (_, cm_1, _) = ivls.vs.info(&state, &aux_state).unwrap();
+
+    /* the empty state does not match the commitment of a written state */
+    let empty_state = State::<
+        <VC as VerifiableTransitionFunctionConfig>::F,
+        <VC as VerifiableTransitionFunctionConfig>::TF,
+    >::default();
+    assert!(!ivls.vs.verify_cm(&empty_state, &cm_1).unwrap());
+
     let tx_2_start = Instant::now();
     ivls.vf
         .run(&mut state, &mut aux_state, &tx_2, &mut rng)
@@ -143,9 +163,70 @@ fn test_verifiable_transition_mnt_small_groth16_cycle_pcd() {
         )
         .unwrap());
 
+    /* check if the commitment still works after a serialization round trip */
+    let mut cm_mid_bytes = Vec::new();
+    cm_mid
+        .as_ref()
+        .unwrap()
+        .serialize(&mut cm_mid_bytes)
+        .unwrap();
+    let cm_mid_deserialized = Commitment::<VC>::deserialize(&cm_mid_bytes[..]).unwrap();
+    assert!(ivls
+        .vf
+        .verify(&t_mid, &cm_mid_deserialized, proof_mid.as_ref().unwrap())
+        .unwrap());
+
+    let msg_mid = VerifiableTransitionFunctionMsg::<VC> {
+        t: t_mid,
+        cm: cm_mid_deserialized,
+    };
+    let mut msg_mid_bytes = Vec::new();
+    msg_mid.serialize_uncompressed(&mut msg_mid_bytes).unwrap();
+    let msg_mid_deserialized =
+        VerifiableTransitionFunctionMsg::<VC>::deserialize_uncompressed(&msg_mid_bytes[..])
+            .unwrap();
+    assert_eq!(msg_mid_deserialized.t, t_mid);
+    assert!(ivls
+        .vf
+        .verify(
+            &msg_mid_deserialized.t,
+            &msg_mid_deserialized.cm,
+            proof_mid.as_ref().unwrap()
+        )
+        .unwrap());
+
     /* check if state/aux_state is valid */
     assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
 
+    /* restart from a snapshot of state/aux_state */
+    let mut state_snapshot = Vec::new();
+    state.snapshot(&mut state_snapshot).unwrap();
+    let mut aux_state_snapshot = Vec::new();
+    aux_state.snapshot(&mut aux_state_snapshot).unwrap();
+
+    let mut state = State::<
+        <VC as VerifiableTransitionFunctionConfig>::F,
+        <VC as VerifiableTransitionFunctionConfig>::TF,
+    >::restore(&state_snapshot[..])
+    .unwrap();
+    let mut aux_state =
+        AuxState::<VC>::restore(&ivls.vf.pp_mt.0, &ivls.vf.pp_mt.1, &aux_state_snapshot[..])
+            .unwrap();
+    assert_eq!(aux_state.t, t_mid);
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
+    /* restart the prover from a serialized prover bundle */
+    let mut prover_bundle_bytes = Vec::new();
+    ivls.prover_bundle()
+        .unwrap()
+        .serialize(&mut prover_bundle_bytes)
+        .unwrap();
+    let mut ivls = IVLSProverBundle::<VC>::deserialize(&prover_bundle_bytes[..])
+        .unwrap()
+        .load()
+        .unwrap();
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
     let tx_3 = ExampleTx::<Fr> {
         key: 3,
         val: Fr::from_repr(BigInteger320::from(5u64)).unwrap(),
@@ -175,6 +256,33 @@ fn test_verifiable_transition_mnt_small_groth16_cycle_pcd() {
     assert_eq!(val_2, tx_2.val);
     assert_eq!(val_3, tx_3.val);
 
+    /* answer a query with a proof against cm_end */
+    let (answer, query_proof) = ivls
+        .vs
+        .prove_query::<ExampleClientFunction<Fr>>(&state, &aux_state, &tx_2.key)
+        .unwrap();
+    assert_eq!(answer, tx_2.val);
+    assert!(ivls
+        .vs
+        .verify_query::<ExampleClientFunction<Fr>>(
+            cm_end.as_ref().unwrap(),
+            &tx_2.key,
+            &answer,
+            &query_proof
+        )
+        .unwrap());
+
+    /* check that the query proof fails for a wrong answer */
+    assert!(!ivls
+        .vs
+        .verify_query::<ExampleClientFunction<Fr>>(
+            cm_end.as_ref().unwrap(),
+            &tx_2.key,
+            &tx_3.val,
+            &query_proof
+        )
+        .unwrap());
+
     /* obtain a history proof */
     let history_proof = ivls.vh.prove(&state, &aux_state, 2u64).unwrap();
     assert!(history_proof.is_some());
@@ -190,4 +298,239 @@ fn test_verifiable_transition_mnt_small_groth16_cycle_pcd() {
         .vh
         .verify(cm_end.as_ref().unwrap(), 1u64, &cm_mid, &history_proof)
         .unwrap());
+
+    /* obtain a single range proof for the commitments at t = 1 and t = 2 */
+    let cm_range = [cm_1.clone().unwrap(), cm_mid.clone().unwrap()];
+    let range_proof = ivls.vh.prove_range(&state, &aux_state, 1u64, 2u64).unwrap();
+    assert!(ivls
+        .vh
+        .verify_range(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            2u64,
+            &cm_range,
+            &range_proof
+        )
+        .unwrap());
+
+    /* check that the range proof fails for swapped commitments or a shifted range */
+    let cm_range_swapped = [cm_mid.clone().unwrap(), cm_1.clone().unwrap()];
+    assert!(!ivls
+        .vh
+        .verify_range(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            2u64,
+            &cm_range_swapped,
+            &range_proof
+        )
+        .unwrap());
+    assert!(!ivls
+        .vh
+        .verify_range(
+            cm_end.as_ref().unwrap(),
+            2u64,
+            3u64,
+            &cm_range,
+            &range_proof
+        )
+        .unwrap());
+
+    /* check that an invalid range is rejected */
+    let err = ivls
+        .vh
+        .prove_range(&state, &aux_state, 0u64, 2u64)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::InvalidRange(0, 2)));
+    let err = ivls
+        .vh
+        .prove_range(&state, &aux_state, 1u64, u64::MAX)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::InvalidRange(1, u64::MAX)));
+
+    /* check that a huge range is rejected by its length, before anything is allocated */
+    let err = ivls
+        .vh
+        .verify_range(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            u64::MAX,
+            &cm_range,
+            &range_proof,
+        )
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::LengthMismatch(2, _)));
+
+    /* prove the values at t = 1, after the restart from the snapshot, when key 2 was unwritten */
+    let addr_at_1 = [tx_1.key, tx_2.key];
+    let data_at_1 = [tx_1.val, Fr::from(0u64)];
+    let state_proof = ivls
+        .vh
+        .prove_state_at(&state, &aux_state, 1u64, &addr_at_1)
+        .unwrap();
+    assert!(ivls
+        .vh
+        .verify_state_at(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            cm_1.as_ref().unwrap(),
+            &addr_at_1,
+            &data_at_1,
+            &state_proof
+        )
+        .unwrap());
+
+    /* check that the proof fails for the values of a later time or for a wrong t */
+    let data_at_2 = [tx_1.val, tx_2.val];
+    assert!(!ivls
+        .vh
+        .verify_state_at(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            cm_1.as_ref().unwrap(),
+            &addr_at_1,
+            &data_at_2,
+            &state_proof
+        )
+        .unwrap());
+    assert!(!ivls
+        .vh
+        .verify_state_at(
+            cm_end.as_ref().unwrap(),
+            2u64,
+            cm_1.as_ref().unwrap(),
+            &addr_at_1,
+            &data_at_1,
+            &state_proof
+        )
+        .unwrap());
+
+    /* the current step is not in the history yet */
+    assert!(ivls
+        .vh
+        .prove_state_at(&state, &aux_state, 3u64, &addr_at_1)
+        .is_err());
+
+    /* load vS and vH from a serialized verifier bundle */
+    let mut verifier_bundle_bytes = Vec::new();
+    ivls.verifier_bundle()
+        .serialize(&mut verifier_bundle_bytes)
+        .unwrap();
+    let (vs, vh) = IVLSVerifierBundle::<VC>::deserialize(&verifier_bundle_bytes[..])
+        .unwrap()
+        .load()
+        .unwrap();
+    assert!(vs.verify_all(&state, &aux_state).unwrap());
+    assert!(vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &cm_mid, &history_proof)
+        .unwrap());
+
+    /* check everything with the verifier-only handle */
+    let verifier = IVLSVerifierBundle::<VC>::deserialize(&verifier_bundle_bytes[..])
+        .unwrap()
+        .load_verifier()
+        .unwrap();
+    let (t_end, cm_end, proof_end) = vs.info(&state, &aux_state).unwrap();
+    assert!(verifier
+        .verify(t_end, cm_end.as_ref().unwrap(), &proof_end)
+        .unwrap());
+    assert!(!verifier
+        .verify(t_end - 1, cm_end.as_ref().unwrap(), &proof_end)
+        .unwrap());
+    assert!(verifier
+        .verify_query::<ExampleClientFunction<Fr>>(
+            cm_end.as_ref().unwrap(),
+            &tx_2.key,
+            &tx_2.val,
+            &query_proof
+        )
+        .unwrap());
+    assert!(verifier
+        .verify_history(cm_end.as_ref().unwrap(), 2u64, &cm_mid, &history_proof)
+        .unwrap());
+    assert!(!verifier
+        .verify_history(cm_end.as_ref().unwrap(), 1u64, &cm_mid, &history_proof)
+        .unwrap());
+    assert!(verifier
+        .verify_history_range(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            2u64,
+            &cm_range,
+            &range_proof
+        )
+        .unwrap());
+    assert!(verifier
+        .verify_history_state_at(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            cm_1.as_ref().unwrap(),
+            &addr_at_1,
+            &data_at_1,
+            &state_proof
+        )
+        .unwrap());
+
+    /* the empty commitment is valid at t = 0 without a proof */
+    let verifier = ivls.verifier();
+    let cm_empty = Commitment::<VC> {
+        state_rh: verifier.empty_digest.0.clone(),
+        history_rh: verifier.empty_digest.1.clone(),
+    };
+    assert!(verifier.verify(0, &cm_empty, &None).unwrap());
+    assert!(!verifier.verify(0, cm_end.as_ref().unwrap(), &None).unwrap());
+
+    /* check that malformed inputs return typed errors */
+    let mut vf_without_key = ivls.vf.clone();
+    vf_without_key.ipk = None;
+    let tx_4 = ExampleTx::<Fr> {
+        key: 4,
+        val: Fr::from_repr(BigInteger320::from(7u64)).unwrap(),
+    };
+    let err = vf_without_key
+        .run(&mut state, &mut aux_state, &tx_4, &mut rng)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::MissingKey));
+
+    let aux_state_without_proof = AuxState::<VC> {
+        t: aux_state.t,
+        cm: aux_state.cm.clone(),
+        proof: None,
+        batch_starts: aux_state.batch_starts.clone(),
+        tree_state: None,
+        tree_history: None,
+    };
+    let err = ivls
+        .vs
+        .verify_all(&state, &aux_state_without_proof)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::MissingProof));
+
+    let err = ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &cm_mid, &None)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::MissingProof));
+    let err = ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &None, &history_proof)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::MissingCommitment));
+    let err = ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 0u64, &cm_mid, &history_proof)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::UnexpectedProof));
+
+    /* the verifier-only handle follows the same convention */
+    let err = verifier
+        .verify_history(cm_end.as_ref().unwrap(), 2u64, &None, &history_proof)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::MissingCommitment));
+    let err = verifier
+        .verify(t_end, cm_end.as_ref().unwrap(), &None)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::MissingProof));
+    let err = verifier.verify(0, &cm_empty, &proof_end).unwrap_err();
+    assert!(matches!(err, IVLSError::UnexpectedProof));
 }
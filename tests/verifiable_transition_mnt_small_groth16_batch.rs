@@ -0,0 +1,317 @@
+use ark_ed_on_mnt4_298::EdwardsParameters;
+use ark_mnt4_298::{constraints::PairingVar as MNT4PairingVar, Fq, Fr, MNT4_298};
+use ark_mnt6_298::{constraints::PairingVar as MNT6PairingVar, MNT6_298};
+
+use ark_ff::{biginteger::BigInteger320, fields::PrimeField};
+
+use ark_pcd::{
+    ec_cycle_pcd::{ECCyclePCD, ECCyclePCDConfig},
+    variable_length_crh::bowe_hopwood::{
+        constraints::VariableLengthBoweHopwoodCompressedCRHGadget,
+        VariableLengthBoweHopwoodCompressedCRH,
+    },
+    PCD,
+};
+
+use ark_crypto_primitives::crh::pedersen::Window as PedersenWindow;
+use ark_std::marker::PhantomData;
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::{
+    building_blocks::mt::{merkle_sparse_tree::MerkleSparseTreeConfig, SparseMT},
+    compiler::circuit_specific_setup_compiler::CircuitSpecificSetupIVLSCompiler,
+    ivls::{
+        error::IVLSError, state::AuxState, transition_function::VerifiableTransitionFunctionConfig,
+    },
+    ledger_system::{
+        example::{ExampleTransitionFunction, ExampleTx},
+        state::State,
+    },
+};
+
+use ark_groth16::{
+    constraints::Groth16VerifierGadget as Groth16SNARKGadget, Groth16 as Groth16SNARK,
+};
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+use ark_std::time::Instant;
+
+pub struct PCDGroth16Mnt4;
+impl ECCyclePCDConfig<Fr, Fq> for PCDGroth16Mnt4 {
+    type CRH = VariableLengthBoweHopwoodCompressedCRH<ChaChaRng, EdwardsParameters>;
+    type CRHGadget = VariableLengthBoweHopwoodCompressedCRHGadget<ChaChaRng, EdwardsParameters>;
+    type MainSNARK = Groth16SNARK<MNT4_298>;
+    type HelpSNARK = Groth16SNARK<MNT6_298>;
+    type MainSNARKGadget = Groth16SNARKGadget<MNT4_298, MNT4PairingVar>;
+    type HelpSNARKGadget = Groth16SNARKGadget<MNT6_298, MNT6PairingVar>;
+}
+
+#[derive(Clone, Debug)]
+pub struct TestPedersenWindow;
+impl PedersenWindow for TestPedersenWindow {
+    const WINDOW_SIZE: usize = 64;
+    const NUM_WINDOWS: usize = 10;
+}
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+#[derive(Clone, Debug)]
+struct P;
+impl MerkleSparseTreeConfig for P {
+    const HEIGHT: u64 = 32;
+    type H = H;
+}
+
+struct VCTemplate<I: PCD<Fr>> {
+    i_phantom: PhantomData<I>,
+}
+
+impl<I: PCD<Fr>> VerifiableTransitionFunctionConfig for VCTemplate<I> {
+    type F = Fr;
+    type TF = ExampleTransitionFunction<Self::F>;
+    type MTState = SparseMT<Self::F, P, HG>;
+    type MTHistory = SparseMT<Self::F, P, HG>;
+    type I = I;
+
+    const BATCH_SIZE: usize = 2;
+    const HISTORY_PER_TX: bool = true;
+}
+
+type TestPCD = ECCyclePCD<Fr, Fq, PCDGroth16Mnt4>;
+
+#[test]
+fn test_verifiable_transition_mnt_small_groth16_batch() {
+    type VC = VCTemplate<TestPCD>;
+
+    let mut rng = ark_std::test_rng();
+
+    let setup_start = Instant::now();
+    let pp = CircuitSpecificSetupIVLSCompiler::circuit_specific_setup(&mut rng).unwrap();
+    let mut ivls = CircuitSpecificSetupIVLSCompiler::make_sfh(&pp, &mut rng).unwrap();
+    println!("setup time: {}", setup_start.elapsed().as_secs());
+
+    let mut state = State::<
+        <VC as VerifiableTransitionFunctionConfig>::F,
+        <VC as VerifiableTransitionFunctionConfig>::TF,
+    >::default();
+    let mut aux_state = AuxState::<VC>::default();
+
+    let tx_1 = ExampleTx::<Fr> {
+        key: 1,
+        val: Fr::from_repr(BigInteger320::from(1u64)).unwrap(),
+    };
+
+    let tx_2 = ExampleTx::<Fr> {
+        key: 2,
+        val: Fr::from_repr(BigInteger320::from(3u64)).unwrap(),
+    };
+
+    let tx_3 = ExampleTx::<Fr> {
+        key: 3,
+        val: Fr::from_repr(BigInteger320::from(5u64)).unwrap(),
+    };
+
+    /* check that a batch larger than BATCH_SIZE is rejected before running anything */
+    let err = ivls
+        .vf
+        .run_batch(
+            &mut state,
+            &mut aux_state,
+            &[tx_1.clone(), tx_2.clone(), tx_3.clone()],
+            &mut rng,
+        )
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::LengthMismatch(3, 2)));
+    assert!(state.map.is_empty());
+
+    /* run a full batch of two transactions in one step */
+    let batch_1_start = Instant::now();
+    let y = ivls
+        .vf
+        .run_batch(
+            &mut state,
+            &mut aux_state,
+            &[tx_1.clone(), tx_2.clone()],
+            &mut rng,
+        )
+        .unwrap();
+    println!("batch_1 time: {}", batch_1_start.elapsed().as_secs());
+    assert_eq!(y.len(), 2);
+
+    let (t_mid, cm_mid, proof_mid) = ivls.vs.info(&state, &aux_state).unwrap();
+
+    /* check if t = 2 */
+    assert_eq!(t_mid, 2);
+
+    assert!(ivls
+        .vf
+        .verify(
+            &t_mid,
+            cm_mid.as_ref().unwrap(),
+            proof_mid.as_ref().unwrap()
+        )
+        .unwrap());
+
+    /* check if state/aux_state is valid */
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
+    /* run a partial batch with only one transaction */
+    let batch_2_start = Instant::now();
+    let y = ivls
+        .vf
+        .run_batch(&mut state, &mut aux_state, &[tx_3.clone()], &mut rng)
+        .unwrap();
+    println!("batch_2 time: {}", batch_2_start.elapsed().as_secs());
+    assert_eq!(y.len(), 1);
+
+    /* check if t = 3 */
+    let (t_end, cm_end, _) = ivls.vs.info(&state, &aux_state).unwrap();
+    assert_eq!(t_end, 3u64);
+
+    /* check if state/aux_state is valid */
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
+    assert_eq!(state.read(&tx_1.key).unwrap().clone(), tx_1.val);
+    assert_eq!(state.read(&tx_2.key).unwrap().clone(), tx_2.val);
+    assert_eq!(state.read(&tx_3.key).unwrap().clone(), tx_3.val);
+
+    /* the commitment after tx_2 is recorded at t = 2 */
+    let history_proof = ivls.vh.prove(&state, &aux_state, 2u64).unwrap();
+    assert!(ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &cm_mid, &history_proof)
+        .unwrap());
+
+    /* t = 1 records the commitment in the middle of the first batch, not cm_mid */
+    let history_proof = ivls.vh.prove(&state, &aux_state, 1u64).unwrap();
+    assert!(!ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 1u64, &cm_mid, &history_proof)
+        .unwrap());
+
+    /* the past state trees are not kept by default */
+    let err = ivls
+        .vh
+        .prove_state_at(&state, &aux_state, 2u64, &[tx_1.key])
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::StateNotKept(2)));
+}
+
+struct VCBatchStartTemplate<I: PCD<Fr>> {
+    i_phantom: PhantomData<I>,
+}
+
+impl<I: PCD<Fr>> VerifiableTransitionFunctionConfig for VCBatchStartTemplate<I> {
+    type F = Fr;
+    type TF = ExampleTransitionFunction<Self::F>;
+    type MTState = SparseMT<Self::F, P, HG>;
+    type MTHistory = SparseMT<Self::F, P, HG>;
+    type I = I;
+
+    const BATCH_SIZE: usize = 2;
+    const HISTORY_PER_TX: bool = false;
+    const STATE_VERSIONS: u64 = 1;
+}
+
+#[test]
+fn test_verifiable_transition_mnt_small_groth16_batch_start_history() {
+    type VC = VCBatchStartTemplate<TestPCD>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = CircuitSpecificSetupIVLSCompiler::circuit_specific_setup(&mut rng).unwrap();
+    let mut ivls = CircuitSpecificSetupIVLSCompiler::make_sfh(&pp, &mut rng).unwrap();
+
+    let mut state = State::<
+        <VC as VerifiableTransitionFunctionConfig>::F,
+        <VC as VerifiableTransitionFunctionConfig>::TF,
+    >::default();
+    let mut aux_state = AuxState::<VC>::default();
+
+    let tx_1 = ExampleTx::<Fr> {
+        key: 1,
+        val: Fr::from_repr(BigInteger320::from(1u64)).unwrap(),
+    };
+
+    let tx_2 = ExampleTx::<Fr> {
+        key: 2,
+        val: Fr::from_repr(BigInteger320::from(3u64)).unwrap(),
+    };
+
+    let tx_3 = ExampleTx::<Fr> {
+        key: 3,
+        val: Fr::from_repr(BigInteger320::from(5u64)).unwrap(),
+    };
+
+    /* the batches start at t = 0 and t = 2 */
+    ivls.vf
+        .run_batch(
+            &mut state,
+            &mut aux_state,
+            &[tx_1.clone(), tx_2.clone()],
+            &mut rng,
+        )
+        .unwrap();
+    let (_, cm_mid, _) = ivls.vs.info(&state, &aux_state).unwrap();
+
+    ivls.vf
+        .run_batch(&mut state, &mut aux_state, &[tx_3.clone()], &mut rng)
+        .unwrap();
+    let (t_end, cm_end, _) = ivls.vs.info(&state, &aux_state).unwrap();
+    assert_eq!(t_end, 3u64);
+    assert_eq!(aux_state.batch_starts, vec![0u64, 2]);
+
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
+    /* the commitment at the start of the second batch is recorded */
+    let history_proof = ivls.vh.prove(&state, &aux_state, 2u64).unwrap();
+    assert!(ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &cm_mid, &history_proof)
+        .unwrap());
+
+    let addr_at_2 = [tx_1.key, tx_3.key];
+    let data_at_2 = [tx_1.val, Fr::from(0u64)];
+    let state_proof = ivls
+        .vh
+        .prove_state_at(&state, &aux_state, 2u64, &addr_at_2)
+        .unwrap();
+    assert!(ivls
+        .vh
+        .verify_state_at(
+            cm_end.as_ref().unwrap(),
+            2u64,
+            cm_mid.as_ref().unwrap(),
+            &addr_at_2,
+            &data_at_2,
+            &state_proof
+        )
+        .unwrap());
+
+    /* t = 1 is in the middle of the first batch, so it is not recorded */
+    let err = ivls.vh.prove(&state, &aux_state, 1u64).unwrap_err();
+    assert!(matches!(err, IVLSError::UnrecordedTime(1)));
+
+    let err = ivls
+        .vh
+        .prove_range(&state, &aux_state, 1u64, 2u64)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::UnrecordedTime(1)));
+
+    let err = ivls
+        .vh
+        .prove_state_at(&state, &aux_state, 1u64, &addr_at_2)
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::UnrecordedTime(1)));
+
+    /* the batch starts are kept by a snapshot */
+    let mut aux_state_snapshot = Vec::new();
+    aux_state.snapshot(&mut aux_state_snapshot).unwrap();
+    let aux_state_restored =
+        AuxState::<VC>::restore(&ivls.vf.pp_mt.0, &ivls.vf.pp_mt.1, &aux_state_snapshot[..])
+            .unwrap();
+    assert_eq!(aux_state_restored.batch_starts, aux_state.batch_starts);
+    assert!(ivls.vh.prove(&state, &aux_state_restored, 1u64).is_err());
+}
@@ -0,0 +1,166 @@
+use ark_ed_on_mnt4_298::EdwardsParameters;
+use ark_mnt4_298::{constraints::PairingVar as MNT4PairingVar, Fq, Fr, MNT4_298};
+use ark_mnt6_298::{constraints::PairingVar as MNT6PairingVar, MNT6_298};
+
+use ark_ff::{biginteger::BigInteger320, fields::PrimeField};
+
+use ark_pcd::{
+    ec_cycle_pcd::{ECCyclePCD, ECCyclePCDConfig},
+    variable_length_crh::bowe_hopwood::{
+        constraints::VariableLengthBoweHopwoodCompressedCRHGadget,
+        VariableLengthBoweHopwoodCompressedCRH,
+    },
+    PCD,
+};
+
+use ark_crypto_primitives::crh::pedersen::Window as PedersenWindow;
+use ark_std::marker::PhantomData;
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::{
+    building_blocks::mt::{
+        merkle_incremental_tree::IncrementalMT, merkle_sparse_tree::MerkleSparseTreeConfig,
+        SparseMT,
+    },
+    compiler::circuit_specific_setup_compiler::CircuitSpecificSetupIVLSCompiler,
+    ivls::{state::AuxState, transition_function::VerifiableTransitionFunctionConfig},
+    ledger_system::{
+        example::{ExampleTransitionFunction, ExampleTx},
+        state::State,
+    },
+};
+
+use ark_groth16::{
+    constraints::Groth16VerifierGadget as Groth16SNARKGadget, Groth16 as Groth16SNARK,
+};
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+use ark_std::time::Instant;
+
+pub struct PCDGroth16Mnt4;
+impl ECCyclePCDConfig<Fr, Fq> for PCDGroth16Mnt4 {
+    type CRH = VariableLengthBoweHopwoodCompressedCRH<ChaChaRng, EdwardsParameters>;
+    type CRHGadget = VariableLengthBoweHopwoodCompressedCRHGadget<ChaChaRng, EdwardsParameters>;
+    type MainSNARK = Groth16SNARK<MNT4_298>;
+    type HelpSNARK = Groth16SNARK<MNT6_298>;
+    type MainSNARKGadget = Groth16SNARKGadget<MNT4_298, MNT4PairingVar>;
+    type HelpSNARKGadget = Groth16SNARKGadget<MNT6_298, MNT6PairingVar>;
+}
+
+#[derive(Clone, Debug)]
+pub struct TestPedersenWindow;
+impl PedersenWindow for TestPedersenWindow {
+    const WINDOW_SIZE: usize = 64;
+    const NUM_WINDOWS: usize = 10;
+}
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+#[derive(Clone, Debug)]
+struct P;
+impl MerkleSparseTreeConfig for P {
+    const HEIGHT: u64 = 32;
+    type H = H;
+}
+
+struct VCTemplate<I: PCD<Fr>> {
+    i_phantom: PhantomData<I>,
+}
+
+impl<I: PCD<Fr>> VerifiableTransitionFunctionConfig for VCTemplate<I> {
+    type F = Fr;
+    type TF = ExampleTransitionFunction<Self::F>;
+    type MTState = SparseMT<Self::F, P, HG>;
+    type MTHistory = IncrementalMT<Self::F, P, HG>;
+    type I = I;
+}
+
+type TestPCD = ECCyclePCD<Fr, Fq, PCDGroth16Mnt4>;
+
+#[test]
+fn test_verifiable_transition_mnt_small_groth16_incremental_history() {
+    type VC = VCTemplate<TestPCD>;
+
+    let mut rng = ark_std::test_rng();
+
+    let setup_start = Instant::now();
+    let pp = CircuitSpecificSetupIVLSCompiler::circuit_specific_setup(&mut rng).unwrap();
+    let mut ivls = CircuitSpecificSetupIVLSCompiler::make_sfh(&pp, &mut rng).unwrap();
+    println!("setup time: {}", setup_start.elapsed().as_secs());
+
+    let mut state = State::<
+        <VC as VerifiableTransitionFunctionConfig>::F,
+        <VC as VerifiableTransitionFunctionConfig>::TF,
+    >::default();
+    let mut aux_state = AuxState::<VC>::default();
+
+    /* the default state/aux_state is valid */
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
+    /* run three transactions, each appending a commitment to the history */
+    let mut cms = Vec::new();
+    for (key, val) in [(1u64, 1u64), (2, 3), (3, 5)].iter() {
+        let tx = ExampleTx::<Fr> {
+            key: *key,
+            val: Fr::from_repr(BigInteger320::from(*val)).unwrap(),
+        };
+
+        let tx_start = Instant::now();
+        ivls.vf
+            .run(&mut state, &mut aux_state, &tx, &mut rng)
+            .unwrap();
+        println!("tx_{} time: {}", key, tx_start.elapsed().as_secs());
+
+        let (t, cm, proof) = ivls.vs.info(&state, &aux_state).unwrap();
+        assert_eq!(t, *key);
+        assert!(ivls
+            .vf
+            .verify(&t, cm.as_ref().unwrap(), proof.as_ref().unwrap())
+            .unwrap());
+        assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+        cms.push(cm);
+    }
+    let cm_end = cms[2].clone();
+
+    /* the history proofs work against the append-only history tree */
+    let history_proof = ivls.vh.prove(&state, &aux_state, 2u64).unwrap();
+    assert!(ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &cms[1], &history_proof)
+        .unwrap());
+    assert!(!ivls
+        .vh
+        .verify(cm_end.as_ref().unwrap(), 2u64, &cms[0], &history_proof)
+        .unwrap());
+
+    let cm_range = [cms[0].clone().unwrap(), cms[1].clone().unwrap()];
+    let range_proof = ivls.vh.prove_range(&state, &aux_state, 1u64, 2u64).unwrap();
+    assert!(ivls
+        .vh
+        .verify_range(
+            cm_end.as_ref().unwrap(),
+            1u64,
+            2u64,
+            &cm_range,
+            &range_proof
+        )
+        .unwrap());
+
+    /* restart from a snapshot of state/aux_state */
+    let mut state_snapshot = Vec::new();
+    state.snapshot(&mut state_snapshot).unwrap();
+    let mut aux_state_snapshot = Vec::new();
+    aux_state.snapshot(&mut aux_state_snapshot).unwrap();
+
+    let state = State::<
+        <VC as VerifiableTransitionFunctionConfig>::F,
+        <VC as VerifiableTransitionFunctionConfig>::TF,
+    >::restore(&state_snapshot[..])
+    .unwrap();
+    let aux_state =
+        AuxState::<VC>::restore(&ivls.vf.pp_mt.0, &ivls.vf.pp_mt.1, &aux_state_snapshot[..])
+            .unwrap();
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+}
@@ -0,0 +1,266 @@
+use ark_ed_on_mnt4_298::EdwardsParameters;
+use ark_mnt4_298::{constraints::PairingVar as MNT4PairingVar, Fq, Fr, MNT4_298};
+use ark_mnt6_298::{constraints::PairingVar as MNT6PairingVar, MNT6_298};
+
+use ark_ff::{biginteger::BigInteger320, fields::PrimeField, Zero};
+
+use ark_pcd::{
+    ec_cycle_pcd::{ECCyclePCD, ECCyclePCDConfig},
+    variable_length_crh::bowe_hopwood::{
+        constraints::VariableLengthBoweHopwoodCompressedCRHGadget,
+        VariableLengthBoweHopwoodCompressedCRH,
+    },
+    PCD,
+};
+
+use ark_crypto_primitives::crh::pedersen::Window as PedersenWindow;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use ark_std::marker::PhantomData;
+use rand_chacha::ChaChaRng;
+
+use ark_ivls::{
+    building_blocks::mt::{merkle_sparse_tree::MerkleSparseTreeConfig, SparseMT},
+    compiler::circuit_specific_setup_compiler::CircuitSpecificSetupIVLSCompiler,
+    gadgets::{Boolean, EmptyVar, EqGadget, FieldVar, FpVar, UInt64},
+    ivls::{
+        error::IVLSError, state::AuxState, transition_function::VerifiableTransitionFunctionConfig,
+    },
+    ledger_system::{
+        example::{ExampleTx, ExampleTxVar},
+        state::State,
+        transition_function::TransitionFunction,
+    },
+    Error,
+};
+
+use ark_groth16::{
+    constraints::Groth16VerifierGadget as Groth16SNARKGadget, Groth16 as Groth16SNARK,
+};
+use ark_ivls::building_blocks::crh::poseidon::{
+    PoseidonCRHforMerkleTree, PoseidonCRHforMerkleTreeGadget,
+};
+
+pub struct PCDGroth16Mnt4;
+impl ECCyclePCDConfig<Fr, Fq> for PCDGroth16Mnt4 {
+    type CRH = VariableLengthBoweHopwoodCompressedCRH<ChaChaRng, EdwardsParameters>;
+    type CRHGadget = VariableLengthBoweHopwoodCompressedCRHGadget<ChaChaRng, EdwardsParameters>;
+    type MainSNARK = Groth16SNARK<MNT4_298>;
+    type HelpSNARK = Groth16SNARK<MNT6_298>;
+    type MainSNARKGadget = Groth16SNARKGadget<MNT4_298, MNT4PairingVar>;
+    type HelpSNARKGadget = Groth16SNARKGadget<MNT6_298, MNT6PairingVar>;
+}
+
+#[derive(Clone, Debug)]
+pub struct TestPedersenWindow;
+impl PedersenWindow for TestPedersenWindow {
+    const WINDOW_SIZE: usize = 64;
+    const NUM_WINDOWS: usize = 10;
+}
+
+type H = PoseidonCRHforMerkleTree<ChaChaRng, Fr>;
+type HG = PoseidonCRHforMerkleTreeGadget<ChaChaRng, Fr>;
+
+#[derive(Clone, Debug)]
+struct P;
+impl MerkleSparseTreeConfig for P {
+    const HEIGHT: u64 = 32;
+    type H = H;
+}
+
+/* a transaction on this key writes twice, more than NUM_WRITES allows */
+const DOUBLE_WRITE_KEY: u64 = 7;
+
+/* adds the value under the key like the example, but skips the write if the value is zero */
+struct SkipWriteTransitionFunction;
+
+impl TransitionFunction<Fr> for SkipWriteTransitionFunction {
+    type Addr = u64;
+    type Data = Fr;
+    type Tx = ExampleTx<Fr>;
+    type Witness = ();
+    type Output = ();
+
+    type AddrVar = UInt64<Fr>;
+    type DataVar = FpVar<Fr>;
+    type TxVar = ExampleTxVar<Fr>;
+    type WitnessVar = EmptyVar<Fr>;
+
+    const NUM_READS: usize = 1;
+    const NUM_WRITES: usize = 1;
+
+    fn run(
+        state: &mut State<Fr, Self>,
+        tx: &Self::Tx,
+    ) -> Result<((), Vec<u64>, Vec<Fr>, Vec<u64>, Vec<Fr>, ()), Error> {
+        let old_val: Fr = *state.read(&tx.key)?;
+
+        let raddr = vec![tx.key];
+        let rdata = vec![old_val];
+
+        let mut waddr = Vec::new();
+        let mut wdata = Vec::new();
+        if !tx.val.is_zero() {
+            let new_val = old_val + tx.val;
+            state.write(&tx.key, &new_val)?;
+
+            waddr.push(tx.key);
+            wdata.push(new_val);
+            if tx.key == DOUBLE_WRITE_KEY {
+                waddr.push(tx.key);
+                wdata.push(new_val);
+            }
+        }
+
+        Ok(((), raddr, rdata, waddr, wdata, ()))
+    }
+
+    fn generate_constraints(
+        _cs: ConstraintSystemRef<Fr>,
+        _witness_g: &Self::WitnessVar,
+        tx_g: &Self::TxVar,
+        raddr_g: &[Self::AddrVar],
+        rdata_g: &[Self::DataVar],
+        ractive_g: &[Boolean<Fr>],
+        waddr_g: &[Self::AddrVar],
+        wdata_g: &[Self::DataVar],
+        wactive_g: &[Boolean<Fr>],
+    ) -> Result<(), SynthesisError> {
+        /* the read is always performed */
+        ractive_g[0].enforce_equal(&Boolean::constant(true))?;
+        tx_g.key_g.enforce_equal(&raddr_g[0])?;
+
+        /* the write is performed if and only if the value is not zero */
+        let write = tx_g.val_g.is_zero()?.not();
+        wactive_g[0].enforce_equal(&write)?;
+
+        let sum = &tx_g.val_g + &rdata_g[0];
+        tx_g.key_g
+            .conditional_enforce_equal(&waddr_g[0], &wactive_g[0])?;
+        sum.conditional_enforce_equal(&wdata_g[0], &wactive_g[0])?;
+
+        Ok(())
+    }
+}
+
+struct VCTemplate<I: PCD<Fr>> {
+    i_phantom: PhantomData<I>,
+}
+
+impl<I: PCD<Fr>> VerifiableTransitionFunctionConfig for VCTemplate<I> {
+    type F = Fr;
+    type TF = SkipWriteTransitionFunction;
+    type MTState = SparseMT<Self::F, P, HG>;
+    type MTHistory = SparseMT<Self::F, P, HG>;
+    type I = I;
+
+    const BATCH_SIZE: usize = 2;
+    const HISTORY_PER_TX: bool = true;
+}
+
+type TestPCD = ECCyclePCD<Fr, Fq, PCDGroth16Mnt4>;
+
+#[test]
+fn test_verifiable_transition_mnt_small_groth16_skip_write() {
+    type VC = VCTemplate<TestPCD>;
+
+    let mut rng = ark_std::test_rng();
+
+    let pp = CircuitSpecificSetupIVLSCompiler::circuit_specific_setup(&mut rng).unwrap();
+    let mut ivls = CircuitSpecificSetupIVLSCompiler::make_sfh(&pp, &mut rng).unwrap();
+
+    let mut state = State::<Fr, SkipWriteTransitionFunction>::default();
+    let mut aux_state = AuxState::<VC>::default();
+
+    let tx_1 = ExampleTx::<Fr> {
+        key: 1,
+        val: Fr::from_repr(BigInteger320::from(1u64)).unwrap(),
+    };
+
+    /* reads the key but skips the write */
+    let tx_skip_1 = ExampleTx::<Fr> {
+        key: 1,
+        val: Fr::zero(),
+    };
+
+    let tx_skip_2 = ExampleTx::<Fr> {
+        key: 2,
+        val: Fr::zero(),
+    };
+
+    let tx_3 = ExampleTx::<Fr> {
+        key: 3,
+        val: Fr::from_repr(BigInteger320::from(5u64)).unwrap(),
+    };
+
+    let tx_double_write = ExampleTx::<Fr> {
+        key: DOUBLE_WRITE_KEY,
+        val: Fr::from_repr(BigInteger320::from(2u64)).unwrap(),
+    };
+
+    /* a batch with a write and a skipped write */
+    ivls.vf
+        .run_batch(
+            &mut state,
+            &mut aux_state,
+            &[tx_1.clone(), tx_skip_1.clone()],
+            &mut rng,
+        )
+        .unwrap();
+    let (t_1, cm_1, proof_1) = ivls.vs.info(&state, &aux_state).unwrap();
+    assert_eq!(t_1, 2);
+    assert!(ivls
+        .vf
+        .verify(&t_1, cm_1.as_ref().unwrap(), proof_1.as_ref().unwrap())
+        .unwrap());
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+
+    /* a batch whose only transaction skips its write keeps the state root */
+    ivls.vf
+        .run_batch(&mut state, &mut aux_state, &[tx_skip_2.clone()], &mut rng)
+        .unwrap();
+    let (t_2, cm_2, proof_2) = ivls.vs.info(&state, &aux_state).unwrap();
+    assert_eq!(t_2, 3);
+    assert!(ivls
+        .vf
+        .verify(&t_2, cm_2.as_ref().unwrap(), proof_2.as_ref().unwrap())
+        .unwrap());
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+    assert!(cm_2.as_ref().unwrap().state_rh == cm_1.as_ref().unwrap().state_rh);
+
+    assert_eq!(state.read(&tx_1.key).unwrap().clone(), tx_1.val);
+    assert!(!state.map.contains_key(&tx_skip_2.key));
+
+    /* a transaction with too many accesses fails the whole batch, before anything is changed */
+    let mut state_snapshot = Vec::new();
+    state.snapshot(&mut state_snapshot).unwrap();
+    let mut aux_state_snapshot = Vec::new();
+    aux_state.snapshot(&mut aux_state_snapshot).unwrap();
+
+    let err = ivls
+        .vf
+        .run_batch(
+            &mut state,
+            &mut aux_state,
+            &[tx_3.clone(), tx_double_write.clone()],
+            &mut rng,
+        )
+        .unwrap_err();
+    assert!(matches!(err, IVLSError::LengthMismatch(2, 1)));
+
+    let mut state_snapshot_after = Vec::new();
+    state.snapshot(&mut state_snapshot_after).unwrap();
+    let mut aux_state_snapshot_after = Vec::new();
+    aux_state.snapshot(&mut aux_state_snapshot_after).unwrap();
+    assert_eq!(state_snapshot, state_snapshot_after);
+    assert_eq!(aux_state_snapshot, aux_state_snapshot_after);
+    assert!(!state.map.contains_key(&tx_3.key));
+
+    /* the next batch still runs from the untouched state */
+    ivls.vf
+        .run_batch(&mut state, &mut aux_state, &[tx_3.clone()], &mut rng)
+        .unwrap();
+    let (t_3, _, _) = ivls.vs.info(&state, &aux_state).unwrap();
+    assert_eq!(t_3, 4);
+    assert!(ivls.vs.verify_all(&state, &aux_state).unwrap());
+    assert_eq!(state.read(&tx_3.key).unwrap().clone(), tx_3.val);
+}